@@ -11,10 +11,14 @@ pub enum CryptoError {
     EncryptionFailed,
     /// 復号に失敗（認証タグ検証失敗を含む）
     DecryptionFailed,
-    /// リプレイアタック検出（seq が期待値より古い）
+    /// リプレイアタック検出（受信済みの seq、またはリプレイウィンドウより古い seq）
     ReplayAttack,
     /// パケットが短すぎる
     PacketTooShort,
+    /// direction ビットが相手の送信方向と一致しない（反射されたパケット）
+    UnexpectedDirection,
+    /// 乱数源が利用できない（鍵生成に失敗）
+    RandomUnavailable,
 }
 
 impl core::fmt::Display for CryptoError {
@@ -24,8 +28,10 @@ impl core::fmt::Display for CryptoError {
             CryptoError::InvalidBase64 => write!(f, "Invalid Base64 encoding"),
             CryptoError::EncryptionFailed => write!(f, "Encryption failed"),
             CryptoError::DecryptionFailed => write!(f, "Decryption failed (authentication tag mismatch)"),
-            CryptoError::ReplayAttack => write!(f, "Replay attack detected: packet sequence number too old"),
+            CryptoError::ReplayAttack => write!(f, "Replay attack detected: duplicate or too old packet sequence number"),
             CryptoError::PacketTooShort => write!(f, "Packet too short"),
+            CryptoError::UnexpectedDirection => write!(f, "Unexpected packet direction (reflected packet?)"),
+            CryptoError::RandomUnavailable => write!(f, "Random number generator unavailable"),
         }
     }
 }
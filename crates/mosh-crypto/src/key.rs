@@ -0,0 +1,171 @@
+//! mosh セッション鍵
+//!
+//! mosh-server は `MOSH_KEY` を標準アルファベット（`+` `/`）の Base64 で、
+//! 末尾の `==` を除いた 22 文字として出力する。
+//! 手入力や URL 経由で受け渡される鍵にも対応するため、解析時は以下をすべて受け付ける。
+//!
+//! ```text
+//! 標準         4NeCCgvZFe2RnPgrcU1PQw   / 4NeCCgvZFe2RnPgrcU1PQw==
+//! URL-safe     (+ → -, / → _)          / パディングあり・なし
+//! ```
+//!
+//! 出力（[`MoshKey::to_base64`]）は常に mosh と同じ正規形（標準・パディングなし）。
+
+use alloc::string::String;
+use core::fmt;
+use core::str::FromStr;
+
+use base64::Engine as _;
+use zeroize::Zeroize;
+
+use crate::error::CryptoError;
+
+/// AES-128 鍵のバイト長
+pub const KEY_LEN: usize = 16;
+
+/// mosh セッション鍵（AES-128、16 バイト）
+///
+/// `Debug` では鍵の中身を表示せず、破棄時にメモリをゼロクリアする。
+#[derive(Clone)]
+pub struct MoshKey([u8; KEY_LEN]);
+
+impl MoshKey {
+    /// 16 バイトの raw 鍵から生成する
+    pub fn from_bytes(bytes: [u8; KEY_LEN]) -> Self {
+        MoshKey(bytes)
+    }
+
+    /// Base64 文字列を解析する
+    ///
+    /// 標準・URL-safe のどちらのアルファベットも、パディングの有無も受け付ける。
+    /// 前後の空白は無視する。
+    ///
+    /// # エラー
+    /// - `CryptoError::InvalidBase64`: Base64 として不正
+    /// - `CryptoError::InvalidKeyLength`: デコード結果が 16 バイト以外
+    pub fn parse(key_b64: &str) -> Result<Self, CryptoError> {
+        // URL-safe アルファベットを標準に揃え、パディングを除去する
+        let mut normalized: String = key_b64
+            .trim()
+            .trim_end_matches('=')
+            .chars()
+            .map(|c| match c {
+                '-' => '+',
+                '_' => '/',
+                c => c,
+            })
+            .collect();
+
+        let decoded = base64::engine::general_purpose::STANDARD_NO_PAD.decode(normalized.as_bytes());
+        normalized.zeroize();
+        let mut bytes = decoded.map_err(|_| CryptoError::InvalidBase64)?;
+
+        if bytes.len() != KEY_LEN {
+            bytes.zeroize();
+            return Err(CryptoError::InvalidKeyLength);
+        }
+
+        let mut key = [0u8; KEY_LEN];
+        key.copy_from_slice(&bytes);
+        bytes.zeroize();
+        Ok(MoshKey(key))
+    }
+
+    /// OS の乱数源（WASM では `crypto.getRandomValues`）から新しい鍵を生成する
+    ///
+    /// # エラー
+    /// - `CryptoError::RandomUnavailable`: 乱数源が利用できない
+    pub fn generate() -> Result<Self, CryptoError> {
+        let mut key = [0u8; KEY_LEN];
+        getrandom::getrandom(&mut key).map_err(|_| CryptoError::RandomUnavailable)?;
+        Ok(MoshKey(key))
+    }
+
+    /// mosh の正規形（標準アルファベット・パディングなしの 22 文字）で Base64 エンコードする
+    pub fn to_base64(&self) -> String {
+        base64::engine::general_purpose::STANDARD_NO_PAD.encode(self.0)
+    }
+
+    /// 鍵のバイト列
+    pub fn as_bytes(&self) -> &[u8; KEY_LEN] {
+        &self.0
+    }
+}
+
+impl FromStr for MoshKey {
+    type Err = CryptoError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+impl fmt::Debug for MoshKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("MoshKey(<redacted>)")
+    }
+}
+
+impl Drop for MoshKey {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::format;
+
+    /// '+' と '/' を含む鍵（0xfb 0xff ... は標準アルファベットで "+/" になる）
+    const STANDARD_KEY: &str = "+/+/+/+/+/+/+/+/+/+/+w";
+
+    #[test]
+    fn test_parse_standard_alphabet() {
+        let key = MoshKey::parse(STANDARD_KEY).unwrap();
+        assert_eq!(key.to_base64(), STANDARD_KEY);
+    }
+
+    #[test]
+    fn test_parse_url_safe_and_padded() {
+        let expected = MoshKey::parse(STANDARD_KEY).unwrap();
+        for form in [
+            "-_-_-_-_-_-_-_-_-_-_-w",
+            "-_-_-_-_-_-_-_-_-_-_-w==",
+            "+/+/+/+/+/+/+/+/+/+/+w==",
+            "  +/+/+/+/+/+/+/+/+/+/+w\n",
+        ] {
+            let key = MoshKey::parse(form).unwrap();
+            assert_eq!(key.as_bytes(), expected.as_bytes(), "{}", form);
+        }
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(MoshKey::parse("not*base64!").unwrap_err(), CryptoError::InvalidBase64);
+        assert_eq!(MoshKey::parse("AAAAAAAAAAAAAA").unwrap_err(), CryptoError::InvalidKeyLength);
+    }
+
+    #[test]
+    fn test_canonical_round_trip() {
+        let key = MoshKey::from_bytes([0x42; KEY_LEN]);
+        let encoded = key.to_base64();
+        assert_eq!(encoded.len(), 22);
+        assert_eq!(MoshKey::parse(&encoded).unwrap().as_bytes(), key.as_bytes());
+    }
+
+    #[test]
+    fn test_generate_distinct() {
+        let a = MoshKey::generate().unwrap();
+        let b = MoshKey::generate().unwrap();
+        assert_ne!(a.as_bytes(), b.as_bytes());
+    }
+
+    #[test]
+    fn test_debug_redacted() {
+        let key = MoshKey::from_bytes([0x42; KEY_LEN]);
+        let debug = format!("{:?}", key);
+        assert_eq!(debug, "MoshKey(<redacted>)");
+        assert!(!debug.contains(&key.to_base64()));
+    }
+}
@@ -21,17 +21,37 @@
 //! direction_seq:
 //!   seq の MSB (bit 63) = direction (TO_SERVER=0, TO_CLIENT=1)
 //! ```
+//!
+//! ## 鍵
+//!
+//! セッション鍵は [`MoshKey`] で扱う。mosh-server が出力する標準アルファベットの
+//! Base64 に加え、URL-safe・パディングありの形式も受け付ける。
+//!
+//! ## リフレクション保護
+//!
+//! [`CryptoSession`] は生成時に [`Role`] を受け取り、相手の送信方向以外の
+//! direction ビットを持つパケットを `CryptoError::UnexpectedDirection` として拒否する。
+//!
+//! ## リプレイ保護
+//!
+//! 受信 seq はビットマップ方式のスライディングウィンドウ（[`ReplayWindow`]）で検査する。
+//! ウィンドウ内の順序入れ替わりは受理し、重複・古すぎる seq は
+//! `CryptoError::ReplayAttack` として拒否する。
 
 #![no_std]
 extern crate alloc;
 
 mod error;
+mod key;
 mod nonce;
+mod replay;
 mod session;
 
 pub use error::CryptoError;
+pub use key::{MoshKey, KEY_LEN};
 pub use nonce::MoshNonce;
-pub use session::CryptoSession;
+pub use replay::{ReplayStats, ReplayWindow, DEFAULT_REPLAY_WINDOW};
+pub use session::{CryptoSession, DecryptedPacket};
 
 /// mosh パケットの方向（TO_SERVER or TO_CLIENT）
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -61,22 +81,44 @@ impl Direction {
     }
 }
 
-/// Base64 文字列（22文字）を 16 バイトのキーにデコードする
+/// 暗号セッションの役割（クライアント or サーバー）
 ///
-/// mosh-server が出力するキーフォーマット: `4NeCCgvZFe2RnPgrcU1PQw`（22文字）
-pub fn decode_base64_key(key_b64: &str) -> Result<[u8; 16], CryptoError> {
-    use base64::Engine as _;
-    let bytes = base64::engine::general_purpose::URL_SAFE_NO_PAD
-        .decode(key_b64)
-        .map_err(|_| CryptoError::InvalidBase64)?;
+/// 送信時に付ける direction ビットと、受信時に期待する direction ビットを決める。
+/// 自分が送ったパケットを攻撃者に反射（リフレクション）されても、
+/// direction が逆なので受理しない。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    /// クライアント（ToServer で送信し、ToClient を受信する）
+    Client,
+    /// サーバー（ToClient で送信し、ToServer を受信する）
+    Server,
+}
+
+impl Role {
+    /// 送信パケットに付ける方向
+    pub fn send_direction(&self) -> Direction {
+        match self {
+            Role::Client => Direction::ToServer,
+            Role::Server => Direction::ToClient,
+        }
+    }
 
-    if bytes.len() != 16 {
-        return Err(CryptoError::InvalidKeyLength);
+    /// 受信パケットに期待する方向（相手の送信方向）
+    pub fn recv_direction(&self) -> Direction {
+        match self {
+            Role::Client => Direction::ToClient,
+            Role::Server => Direction::ToServer,
+        }
     }
+}
 
-    let mut key = [0u8; 16];
-    key.copy_from_slice(&bytes);
-    Ok(key)
+/// Base64 文字列（22文字）を 16 バイトのキーにデコードする
+///
+/// mosh-server が出力するキーフォーマット: `4NeCCgvZFe2RnPgrcU1PQw`（22文字）
+/// 受け付ける形式は [`MoshKey::parse`] と同じ。戻り値の配列はゼロクリアされないため、
+/// 鍵を保持する場合は [`MoshKey`] を直接使うこと。
+pub fn decode_base64_key(key_b64: &str) -> Result<[u8; 16], CryptoError> {
+    MoshKey::parse(key_b64).map(|key| *key.as_bytes())
 }
 
 #[cfg(test)]
@@ -104,6 +146,14 @@ mod tests {
         assert_eq!(tc >> 63, 1);
     }
 
+    #[test]
+    fn test_role_directions() {
+        assert_eq!(Role::Client.send_direction(), Direction::ToServer);
+        assert_eq!(Role::Client.recv_direction(), Direction::ToClient);
+        assert_eq!(Role::Server.send_direction(), Direction::ToClient);
+        assert_eq!(Role::Server.recv_direction(), Direction::ToServer);
+    }
+
     #[test]
     fn test_decode_base64_key_valid() {
         // 16バイト = 22文字（URL-safe base64 no-pad）
@@ -112,6 +162,13 @@ mod tests {
         assert_eq!(key, [0u8; 16]);
     }
 
+    #[test]
+    fn test_decode_base64_key_standard_alphabet() {
+        // mosh-server は '+' と '/' を含む鍵を出力する
+        let key = decode_base64_key("+/+/+/+/+/+/+/+/+/+/+w").unwrap();
+        assert_eq!(key[0], 0xfb);
+    }
+
     #[test]
     fn test_decode_base64_key_invalid_length() {
         let key_b64 = "AAAAAAAAAAAAAA"; // 短すぎる
@@ -0,0 +1,267 @@
+//! スライディングウィンドウ方式のリプレイ検出
+//!
+//! IPsec (RFC 4303 Appendix A) / WireGuard と同じビットマップ方式。
+//!
+//! ```text
+//!            ← window_size →
+//!  ... [ too old ][ bitmap (受信済みなら 1) ][highest] → 新しい seq は常に受理
+//! ```
+//!
+//! - `highest` より新しい seq: 受理し、ウィンドウを前進させる
+//! - ウィンドウ内の seq: ビットが立っていなければ受理（順序入れ替わり）
+//! - ウィンドウより古い seq / ビットが立っている seq: 拒否
+//!
+//! 判定（`check`）は状態を変えず、ウィンドウと統計の更新は認証タグの検証後にのみ行う
+//! （受理は `update`、拒否は `record_rejection`）。偽造パケットでウィンドウを進められたり、
+//! 壊れたパケットをリプレイとして数えたりしないため。
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::error::CryptoError;
+
+/// デフォルトのリプレイウィンドウ幅（パケット数）
+pub const DEFAULT_REPLAY_WINDOW: u64 = 1024;
+
+/// ビットマップ 1 ワードのビット数
+const WORD_BITS: u64 = u64::BITS as u64;
+
+/// リプレイ検出の統計情報
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReplayStats {
+    /// 受理したパケット数
+    pub accepted: u64,
+    /// 重複（受信済み seq）として拒否した、認証済みのパケット数
+    pub duplicates: u64,
+    /// ウィンドウより古い seq として拒否した、認証済みのパケット数
+    pub too_old: u64,
+}
+
+impl ReplayStats {
+    /// 拒否したパケットの総数（リプレイ攻撃の試行回数）
+    pub fn rejected(&self) -> u64 {
+        self.duplicates + self.too_old
+    }
+}
+
+/// ビットマップ方式のリプレイウィンドウ
+#[derive(Debug, Clone)]
+pub struct ReplayWindow {
+    /// 受信済みフラグ（seq % window_size 番目のビット）
+    bitmap: Vec<u64>,
+    /// ウィンドウ幅（64 の倍数に切り上げ済み）
+    window_size: u64,
+    /// これまでに受理した最大の seq（未受信なら None）
+    highest: Option<u64>,
+    /// 統計
+    stats: ReplayStats,
+}
+
+impl ReplayWindow {
+    /// 指定したウィンドウ幅で生成する
+    ///
+    /// # 引数
+    /// - `window_size`: 受理する順序入れ替わりの最大幅（パケット数）。
+    ///   64 の倍数に切り上げられる（最小 64）。
+    pub fn new(window_size: u64) -> Self {
+        let words = window_size.max(1).div_ceil(WORD_BITS);
+        ReplayWindow {
+            bitmap: vec![0u64; words as usize],
+            window_size: words * WORD_BITS,
+            highest: None,
+            stats: ReplayStats::default(),
+        }
+    }
+
+    /// seq が受理可能か判定する（状態は変更しない）
+    ///
+    /// # エラー
+    /// - `CryptoError::ReplayAttack`: 重複またはウィンドウより古い seq
+    pub fn check(&self, seq: u64) -> Result<(), CryptoError> {
+        let highest = match self.highest {
+            None => return Ok(()),
+            Some(h) => h,
+        };
+
+        if seq > highest {
+            return Ok(());
+        }
+
+        if highest - seq >= self.window_size || self.is_set(seq) {
+            return Err(CryptoError::ReplayAttack);
+        }
+
+        Ok(())
+    }
+
+    /// `check` で拒否した seq を統計に記録する
+    ///
+    /// 認証タグの検証が済んだ（本物のパケットが再送・複製された）後に呼び出すこと。
+    pub fn record_rejection(&mut self, seq: u64) {
+        match self.highest {
+            Some(highest) if seq <= highest && highest - seq >= self.window_size => {
+                self.stats.too_old += 1;
+            }
+            _ => self.stats.duplicates += 1,
+        }
+    }
+
+    /// 認証済みの seq を記録し、必要ならウィンドウを前進させる
+    ///
+    /// `check` が成功し、かつ認証タグの検証が済んだ後に呼び出すこと。
+    pub fn update(&mut self, seq: u64) {
+        match self.highest {
+            Some(highest) if seq <= highest => {}
+            Some(highest) if seq - highest < self.window_size => {
+                // ウィンドウから押し出される区間のビットをクリア
+                for s in (highest + 1)..seq {
+                    self.clear(s);
+                }
+                self.highest = Some(seq);
+            }
+            _ => {
+                // 初回受信またはウィンドウ幅以上のジャンプ
+                self.bitmap.iter_mut().for_each(|w| *w = 0);
+                self.highest = Some(seq);
+            }
+        }
+
+        self.set(seq);
+        self.stats.accepted += 1;
+    }
+
+    /// ウィンドウ幅（パケット数）
+    pub fn window_size(&self) -> u64 {
+        self.window_size
+    }
+
+    /// これまでに受理した最大の seq
+    pub fn highest(&self) -> Option<u64> {
+        self.highest
+    }
+
+    /// 統計情報
+    pub fn stats(&self) -> ReplayStats {
+        self.stats
+    }
+
+    fn bit_position(&self, seq: u64) -> (usize, u64) {
+        let index = seq % self.window_size;
+        ((index / WORD_BITS) as usize, 1u64 << (index % WORD_BITS))
+    }
+
+    fn is_set(&self, seq: u64) -> bool {
+        let (word, mask) = self.bit_position(seq);
+        self.bitmap[word] & mask != 0
+    }
+
+    fn set(&mut self, seq: u64) {
+        let (word, mask) = self.bit_position(seq);
+        self.bitmap[word] |= mask;
+    }
+
+    fn clear(&mut self, seq: u64) {
+        let (word, mask) = self.bit_position(seq);
+        self.bitmap[word] &= !mask;
+    }
+}
+
+impl Default for ReplayWindow {
+    fn default() -> Self {
+        Self::new(DEFAULT_REPLAY_WINDOW)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 認証済みのパケットとして判定・記録する
+    fn accept(window: &mut ReplayWindow, seq: u64) -> Result<(), CryptoError> {
+        if let Err(e) = window.check(seq) {
+            window.record_rejection(seq);
+            return Err(e);
+        }
+        window.update(seq);
+        Ok(())
+    }
+
+    #[test]
+    fn test_window_size_rounded_up() {
+        assert_eq!(ReplayWindow::new(1).window_size(), 64);
+        assert_eq!(ReplayWindow::new(64).window_size(), 64);
+        assert_eq!(ReplayWindow::new(100).window_size(), 128);
+    }
+
+    #[test]
+    fn test_in_order_accepted() {
+        let mut window = ReplayWindow::new(64);
+        for seq in 0..200 {
+            assert!(accept(&mut window, seq).is_ok());
+        }
+        assert_eq!(window.highest(), Some(199));
+        assert_eq!(window.stats().accepted, 200);
+    }
+
+    #[test]
+    fn test_duplicate_rejected() {
+        let mut window = ReplayWindow::new(64);
+        accept(&mut window, 5).unwrap();
+        assert_eq!(accept(&mut window, 5), Err(CryptoError::ReplayAttack));
+        assert_eq!(window.stats().duplicates, 1);
+    }
+
+    #[test]
+    fn test_reordered_within_window_accepted_once() {
+        let mut window = ReplayWindow::new(64);
+        accept(&mut window, 10).unwrap();
+        accept(&mut window, 7).unwrap();
+        accept(&mut window, 9).unwrap();
+        assert_eq!(accept(&mut window, 7), Err(CryptoError::ReplayAttack));
+        assert_eq!(window.highest(), Some(10));
+    }
+
+    #[test]
+    fn test_too_old_rejected() {
+        let mut window = ReplayWindow::new(64);
+        accept(&mut window, 100).unwrap();
+        assert_eq!(accept(&mut window, 36), Err(CryptoError::ReplayAttack));
+        assert!(accept(&mut window, 37).is_ok());
+        assert_eq!(window.stats().too_old, 1);
+        assert_eq!(window.stats().rejected(), 1);
+    }
+
+    #[test]
+    fn test_slide_clears_stale_bits() {
+        let mut window = ReplayWindow::new(64);
+        accept(&mut window, 1).unwrap();
+        accept(&mut window, 60).unwrap();
+        accept(&mut window, 66).unwrap();
+        // 65 は 1 と同じビット位置を使うが、前進時にクリアされているので受理される
+        assert!(accept(&mut window, 65).is_ok());
+        assert_eq!(window.stats().accepted, 4);
+    }
+
+    #[test]
+    fn test_large_jump_resets_bitmap() {
+        let mut window = ReplayWindow::new(64);
+        accept(&mut window, 3).unwrap();
+        accept(&mut window, 10_000).unwrap();
+        assert!(accept(&mut window, 10_000 - 63).is_ok());
+        assert_eq!(accept(&mut window, 3), Err(CryptoError::ReplayAttack));
+    }
+
+    #[test]
+    fn test_check_does_not_mutate() {
+        let mut window = ReplayWindow::new(64);
+        accept(&mut window, 0).unwrap();
+        // check のみでは受信済みにならない
+        assert!(window.check(1).is_ok());
+        assert!(window.check(1).is_ok());
+        assert_eq!(window.highest(), Some(0));
+
+        // 拒否しても、記録するまで統計は変わらない
+        assert_eq!(window.check(0), Err(CryptoError::ReplayAttack));
+        assert_eq!(window.stats().rejected(), 0);
+    }
+}
@@ -10,7 +10,8 @@ use ocb3::Ocb3;
 
 use crate::error::CryptoError;
 use crate::nonce::MoshNonce;
-use crate::{Direction, decode_base64_key};
+use crate::replay::{ReplayStats, ReplayWindow, DEFAULT_REPLAY_WINDOW};
+use crate::{Direction, MoshKey, Role};
 
 /// AES-128-OCB3 (12バイト nonce, 16バイト tag) の型エイリアス
 type Aes128Ocb3 = Ocb3<Aes128>;
@@ -19,40 +20,71 @@ type Aes128Ocb3 = Ocb3<Aes128>;
 ///
 /// mosh プロトコルのパケット暗号化/復号を管理する。
 /// 送信シーケンス番号を自動インクリメントし、Nonce の重複を防ぐ。
+/// 受信側はスライディングウィンドウでリプレイされたパケットを拒否し、
+/// 役割（[`Role`]）から期待される方向以外のパケットも拒否する。
 pub struct CryptoSession {
     cipher: Aes128Ocb3,
+    /// このセッションの役割（受信時に期待する direction を決める）
+    role: Role,
     /// 次の送信シーケンス番号
     send_seq: u64,
-    /// 最後に受信した（有効な）シーケンス番号
-    recv_seq: u64,
+    /// 受信シーケンス番号のリプレイウィンドウ
+    replay: ReplayWindow,
 }
 
 impl CryptoSession {
     /// mosh-server が出力する Base64 鍵（22文字）からセッションを初期化する
     ///
     /// # 引数
-    /// - `key_b64`: Base64 エンコードされた 16 バイト鍵（例: "4NeCCgvZFe2RnPgrcU1PQw"）
+    /// - `key_b64`: Base64 エンコードされた 16 バイト鍵（例: "4NeCCgvZFe2RnPgrcU1PQw"）。
+    ///   受け付ける形式は [`MoshKey::parse`] を参照。
+    /// - `role`: このセッションの役割（クライアント or サーバー）
     ///
     /// # エラー
     /// - `CryptoError::InvalidBase64`: Base64 デコード失敗
     /// - `CryptoError::InvalidKeyLength`: 鍵長が 16 バイト以外
-    pub fn from_base64_key(key_b64: &str) -> Result<Self, CryptoError> {
-        let key = decode_base64_key(key_b64)?;
-        Self::from_key(key)
+    pub fn from_base64_key(key_b64: &str, role: Role) -> Result<Self, CryptoError> {
+        let key = MoshKey::parse(key_b64)?;
+        Ok(Self::from_mosh_key(&key, role))
     }
 
-    /// 16 バイトの raw 鍵からセッションを初期化する
-    pub fn from_key(key: [u8; 16]) -> Result<Self, CryptoError> {
-        let cipher = Aes128Ocb3::new((&key).into());
-        Ok(CryptoSession {
-            cipher,
+    /// [`MoshKey`] からセッションを初期化する
+    pub fn from_mosh_key(key: &MoshKey, role: Role) -> Self {
+        CryptoSession {
+            cipher: Aes128Ocb3::new(key.as_bytes().into()),
+            role,
             send_seq: 0,
-            recv_seq: 0,
-        })
+            replay: ReplayWindow::new(DEFAULT_REPLAY_WINDOW),
+        }
+    }
+
+    /// 16 バイトの raw 鍵からセッションを初期化する
+    pub fn from_key(key: [u8; 16], role: Role) -> Result<Self, CryptoError> {
+        Ok(Self::from_mosh_key(&MoshKey::from_bytes(key), role))
+    }
+
+    /// リプレイウィンドウの幅を変更する（ビルダー）
+    ///
+    /// # 引数
+    /// - `window_size`: 受理する順序入れ替わりの最大幅（パケット数、64 の倍数に切り上げ）
+    ///
+    /// # 例
+    /// ```
+    /// use mosh_crypto::{CryptoSession, Role};
+    /// let session = CryptoSession::from_key([0u8; 16], Role::Client)
+    ///     .unwrap()
+    ///     .with_replay_window(256);
+    /// assert_eq!(session.replay_window_size(), 256);
+    /// ```
+    pub fn with_replay_window(mut self, window_size: u64) -> Self {
+        self.replay = ReplayWindow::new(window_size);
+        self
     }
 
     /// 平文を暗号化して UDP ペイロードを返す
     ///
+    /// パケットの方向はセッションの役割で決まる（[`Role::send_direction`]）。
+    ///
     /// ## UDP ペイロード構造
     /// ```text
     /// [nonce_tail: 8bytes][ciphertext_with_tag: variable]
@@ -64,13 +96,11 @@ impl CryptoSession {
     /// ```
     ///
     /// # 引数
-    /// - `direction`: パケットの方向（ToServer/ToClient）
     /// - `timestamp`: ローカルタイムスタンプ（16bit, ms の下位16ビット）
     /// - `timestamp_reply`: 相手から受け取ったタイムスタンプのエコー
     /// - `payload`: 暗号化するペイロード（Fragment バイト列）
     pub fn encrypt_packet(
         &mut self,
-        direction: Direction,
         timestamp: u16,
         timestamp_reply: u16,
         payload: &[u8],
@@ -78,7 +108,7 @@ impl CryptoSession {
         let seq = self.send_seq;
         self.send_seq += 1;
 
-        let direction_seq = direction.apply_to_seq(seq);
+        let direction_seq = self.role.send_direction().apply_to_seq(seq);
         let nonce = MoshNonce::new(direction_seq);
 
         // 平文の組み立て
@@ -113,8 +143,9 @@ impl CryptoSession {
     ///
     /// # エラー
     /// - `CryptoError::PacketTooShort`: パケットが短すぎる（最低 8 + 16 = 24 バイト必要）
+    /// - `CryptoError::UnexpectedDirection`: 相手の送信方向と一致しない（反射されたパケット）
     /// - `CryptoError::DecryptionFailed`: 認証タグ検証失敗
-    /// - `CryptoError::ReplayAttack`: シーケンス番号が古すぎる
+    /// - `CryptoError::ReplayAttack`: 受信済み、またはウィンドウより古いシーケンス番号
     pub fn decrypt_packet(&mut self, packet: &[u8]) -> Result<DecryptedPacket, CryptoError> {
         // 最低: nonce_tail(8) + empty_plaintext_with_tag(16) = 24 バイト
         if packet.len() < 24 {
@@ -125,6 +156,15 @@ impl CryptoSession {
             .ok_or(CryptoError::PacketTooShort)?;
         let ciphertext = &packet[8..];
 
+        // 反射されたパケット（自分と同じ方向）を拒否する
+        if Direction::from_seq(nonce.seq()) != self.role.recv_direction() {
+            return Err(CryptoError::UnexpectedDirection);
+        }
+
+        // リプレイ判定（状態・統計の更新は認証後）
+        let nonce_seq = nonce.seq() & !(1u64 << 63);
+        let replay = self.replay.check(nonce_seq);
+
         // AES-128-OCB3 復号
         use aead::Aead;
         let plaintext = self
@@ -132,6 +172,12 @@ impl CryptoSession {
             .decrypt(nonce.as_bytes().into(), ciphertext)
             .map_err(|_| CryptoError::DecryptionFailed)?;
 
+        // 認証済みの本物のパケットが再び届いたときだけリプレイとして数える
+        if let Err(e) = replay {
+            self.replay.record_rejection(nonce_seq);
+            return Err(e);
+        }
+
         // 平文は最低 12 バイト（direction_seq:8 + timestamp:2 + timestamp_reply:2）
         if plaintext.len() < 12 {
             return Err(CryptoError::DecryptionFailed);
@@ -151,9 +197,8 @@ impl CryptoSession {
         // ペイロード
         let payload = plaintext[12..].to_vec();
 
-        // recv_seq を更新（簡易的なリプレイ検出）
-        // TODO: ウィンドウベースのより堅牢なリプレイ検出を実装する
-        self.recv_seq = seq;
+        // 認証タグ検証済みなのでリプレイウィンドウを更新
+        self.replay.update(nonce_seq);
 
         Ok(DecryptedPacket {
             seq,
@@ -164,14 +209,29 @@ impl CryptoSession {
         })
     }
 
+    /// このセッションの役割
+    pub fn role(&self) -> Role {
+        self.role
+    }
+
     /// 現在の送信シーケンス番号を返す（テスト用）
     pub fn send_seq(&self) -> u64 {
         self.send_seq
     }
 
-    /// 最後に受信したシーケンス番号を返す（テスト用）
+    /// これまでに受理した最大の受信シーケンス番号を返す（未受信なら 0）
     pub fn recv_seq(&self) -> u64 {
-        self.recv_seq
+        self.replay.highest().unwrap_or(0)
+    }
+
+    /// リプレイウィンドウの幅（パケット数）
+    pub fn replay_window_size(&self) -> u64 {
+        self.replay.window_size()
+    }
+
+    /// リプレイ検出の統計（受理数・拒否数）
+    pub fn replay_stats(&self) -> ReplayStats {
+        self.replay.stats()
     }
 }
 
@@ -194,9 +254,16 @@ pub struct DecryptedPacket {
 mod tests {
     use super::*;
 
+    /// クライアント（送信側）のセッション
     fn make_session() -> CryptoSession {
         let key = [0u8; 16];
-        CryptoSession::from_key(key).unwrap()
+        CryptoSession::from_key(key, Role::Client).unwrap()
+    }
+
+    /// サーバー（ToServer パケットの受信側）のセッション
+    fn make_server_session() -> CryptoSession {
+        let key = [0u8; 16];
+        CryptoSession::from_key(key, Role::Server).unwrap()
     }
 
     #[test]
@@ -205,11 +272,11 @@ mod tests {
         let payload = b"Hello, mosh!";
 
         let packet = session
-            .encrypt_packet(Direction::ToServer, 1000, 0, payload)
+            .encrypt_packet(1000, 0, payload)
             .unwrap();
 
         // 復号には同じ鍵の別セッションを使う
-        let mut recv_session = make_session();
+        let mut recv_session = make_server_session();
         let decrypted = recv_session.decrypt_packet(&packet).unwrap();
 
         assert_eq!(decrypted.payload, payload);
@@ -224,12 +291,12 @@ mod tests {
         assert_eq!(session.send_seq(), 0);
 
         session
-            .encrypt_packet(Direction::ToServer, 0, 0, b"")
+            .encrypt_packet(0, 0, b"")
             .unwrap();
         assert_eq!(session.send_seq(), 1);
 
         session
-            .encrypt_packet(Direction::ToServer, 0, 0, b"")
+            .encrypt_packet(0, 0, b"")
             .unwrap();
         assert_eq!(session.send_seq(), 2);
     }
@@ -238,12 +305,12 @@ mod tests {
     fn test_decrypt_wrong_key_fails() {
         let mut send_session = make_session();
         let packet = send_session
-            .encrypt_packet(Direction::ToServer, 0, 0, b"secret")
+            .encrypt_packet(0, 0, b"secret")
             .unwrap();
 
         // 異なる鍵で復号 → 失敗すべき
         let key = [0xFFu8; 16];
-        let mut recv_session = CryptoSession::from_key(key).unwrap();
+        let mut recv_session = CryptoSession::from_key(key, Role::Server).unwrap();
         let result = recv_session.decrypt_packet(&packet);
         assert!(result.is_err());
     }
@@ -258,7 +325,135 @@ mod tests {
     #[test]
     fn test_from_base64_key() {
         // 16 zero bytes → base64url = "AAAAAAAAAAAAAAAAAAAAAA"
-        let session = CryptoSession::from_base64_key("AAAAAAAAAAAAAAAAAAAAAA");
+        let session = CryptoSession::from_base64_key("AAAAAAAAAAAAAAAAAAAAAA", Role::Client);
         assert!(session.is_ok());
     }
+
+    #[test]
+    fn test_from_base64_key_standard_and_url_safe_interoperate() {
+        // mosh-server の標準アルファベット鍵と、その URL-safe 表記は同じ鍵
+        let mut client = CryptoSession::from_base64_key("+/+/+/+/+/+/+/+/+/+/+w", Role::Client).unwrap();
+        let mut server = CryptoSession::from_base64_key("-_-_-_-_-_-_-_-_-_-_-w==", Role::Server).unwrap();
+
+        let packet = client.encrypt_packet(0, 0, b"hello").unwrap();
+        assert_eq!(server.decrypt_packet(&packet).unwrap().payload, b"hello");
+    }
+
+    #[test]
+    fn test_replayed_packet_rejected() {
+        let mut send_session = make_session();
+        let packet = send_session
+            .encrypt_packet(0, 0, b"once")
+            .unwrap();
+
+        let mut recv_session = make_server_session();
+        assert!(recv_session.decrypt_packet(&packet).is_ok());
+        assert_eq!(
+            recv_session.decrypt_packet(&packet),
+            Err(CryptoError::ReplayAttack)
+        );
+        assert_eq!(recv_session.replay_stats().duplicates, 1);
+    }
+
+    #[test]
+    fn test_reordered_packets_accepted() {
+        let mut send_session = make_session();
+        let packets: Vec<Vec<u8>> = (0..4)
+            .map(|_| {
+                send_session
+                    .encrypt_packet(0, 0, b"x")
+                    .unwrap()
+            })
+            .collect();
+
+        let mut recv_session = make_server_session();
+        for i in [3usize, 0, 2, 1] {
+            assert!(recv_session.decrypt_packet(&packets[i]).is_ok());
+        }
+        assert_eq!(recv_session.recv_seq(), 3);
+        assert_eq!(recv_session.replay_stats().accepted, 4);
+    }
+
+    #[test]
+    fn test_too_old_packet_rejected() {
+        let mut send_session = make_session();
+        let old = send_session
+            .encrypt_packet(0, 0, b"old")
+            .unwrap();
+        let mut latest = Vec::new();
+        for _ in 0..64 {
+            latest = send_session
+                .encrypt_packet(0, 0, b"new")
+                .unwrap();
+        }
+
+        let mut recv_session = make_server_session().with_replay_window(64);
+        recv_session.decrypt_packet(&latest).unwrap();
+        assert_eq!(
+            recv_session.decrypt_packet(&old),
+            Err(CryptoError::ReplayAttack)
+        );
+        assert_eq!(recv_session.replay_stats().too_old, 1);
+    }
+
+    #[test]
+    fn test_forged_packet_does_not_advance_window() {
+        let mut send_session = make_session();
+        let packet = send_session
+            .encrypt_packet(0, 0, b"genuine")
+            .unwrap();
+
+        // seq を大きく書き換えた偽造パケット（認証タグ検証で失敗する）
+        let mut forged = packet.clone();
+        forged[0..8].copy_from_slice(&1_000_000u64.to_be_bytes());
+
+        let mut recv_session = make_server_session().with_replay_window(64);
+        assert_eq!(
+            recv_session.decrypt_packet(&forged),
+            Err(CryptoError::DecryptionFailed)
+        );
+        // ウィンドウが進んでいないので本物のパケットは受理される
+        assert!(recv_session.decrypt_packet(&packet).is_ok());
+
+        // 受信済み seq のまま壊れたパケットはリプレイとして数えない
+        let mut corrupted = packet.clone();
+        let last = corrupted.len() - 1;
+        corrupted[last] ^= 0x01;
+        assert_eq!(
+            recv_session.decrypt_packet(&corrupted),
+            Err(CryptoError::DecryptionFailed)
+        );
+        assert_eq!(recv_session.replay_stats().rejected(), 0);
+    }
+
+    #[test]
+    fn test_reflected_packet_rejected() {
+        let mut client = make_session();
+        let packet = client
+            .encrypt_packet(0, 0, b"to server")
+            .unwrap();
+
+        // 自分が送ったパケットを反射されても受理しない
+        assert_eq!(
+            client.decrypt_packet(&packet),
+            Err(CryptoError::UnexpectedDirection)
+        );
+
+        // 本来の受信者（サーバー）は受理する
+        let mut server = make_server_session();
+        assert!(server.decrypt_packet(&packet).is_ok());
+    }
+
+    #[test]
+    fn test_server_to_client_roundtrip() {
+        let mut server = make_server_session();
+        let packet = server
+            .encrypt_packet(0, 0, b"to client")
+            .unwrap();
+
+        let mut client = make_session();
+        let decrypted = client.decrypt_packet(&packet).unwrap();
+        assert_eq!(decrypted.direction, Direction::ToClient);
+        assert_eq!(decrypted.payload, b"to client");
+    }
 }
@@ -0,0 +1,156 @@
+//! 時刻源を持つエンドポイント
+//!
+//! [`MoshEndpoint`] の API は呼び出し側が `now_ms` を渡す。[`ClockedEndpoint`] は
+//! [`Clock`] から時刻を読むので、呼び出し側が時刻を取り違える（壁時計を渡す、
+//! 呼び出しごとに異なる時刻源を使う）ことがない。時刻源は [`MonotonicClock`] で包まれ、
+//! 逆行しても直前の時刻から進み続ける。
+
+use alloc::vec::Vec;
+
+use mosh_ssp::clock::{Clock, MonotonicClock};
+use mosh_ssp::ConnectionState;
+
+use crate::endpoint::{EndpointStats, MoshEndpoint};
+use crate::error::EndpointError;
+use crate::EndpointRole;
+
+/// 時刻源を持つエンドポイント
+///
+/// [`MoshEndpoint::with_clock`] で生成する。
+///
+/// ```
+/// use mosh_endpoint::{MoshClientEndpoint, MoshServer};
+/// use mosh_ssp::clock::ManualClock;
+///
+/// let clock = ManualClock::new(1000);
+/// let mut client = MoshClientEndpoint::new("AAAAAAAAAAAAAAAAAAAAAA", None)
+///     .unwrap()
+///     .with_clock(clock.clone());
+/// let mut server = MoshServer::new("AAAAAAAAAAAAAAAAAAAAAA", None).unwrap();
+///
+/// assert!(client.send_data(b"hi").unwrap().is_empty()); // 送信ペーシングで保留
+/// clock.advance(client.next_timeout_ms());
+/// for pkt in client.tick().unwrap() {
+///     assert_eq!(server.recv_udp_packet(&pkt, 1010).unwrap(), b"hi");
+/// }
+/// ```
+pub struct ClockedEndpoint<R: EndpointRole, C: Clock> {
+    /// 時刻を渡して操作するエンドポイント
+    endpoint: MoshEndpoint<R>,
+    /// 逆行を吸収する時刻源
+    clock: MonotonicClock<C>,
+}
+
+impl<R: EndpointRole, C: Clock> ClockedEndpoint<R, C> {
+    pub(crate) fn new(endpoint: MoshEndpoint<R>, clock: C) -> Self {
+        ClockedEndpoint {
+            endpoint,
+            clock: MonotonicClock::new(clock),
+        }
+    }
+
+    /// 現在時刻（ミリ秒、単調増加）
+    pub fn now_ms(&self) -> u64 {
+        self.clock.now_ms()
+    }
+
+    /// 受信した UDP ペイロードを処理する（[`MoshEndpoint::recv_udp_packet`]）
+    pub fn recv_udp_packet(&mut self, udp_bytes: &[u8]) -> Result<Vec<u8>, EndpointError> {
+        let now_ms = self.now_ms();
+        self.endpoint.recv_udp_packet(udp_bytes, now_ms)
+    }
+
+    /// 上位レイヤーからのデータを送信する（[`MoshEndpoint::send_data`]）
+    pub fn send_data(&mut self, data: &[u8]) -> Result<Vec<Vec<u8>>, EndpointError> {
+        let now_ms = self.now_ms();
+        self.endpoint.send_data(data, now_ms)
+    }
+
+    /// タイマー tick（[`MoshEndpoint::tick`]）
+    pub fn tick(&mut self) -> Result<Vec<Vec<u8>>, EndpointError> {
+        let now_ms = self.now_ms();
+        self.endpoint.tick(now_ms)
+    }
+
+    /// 次に `tick` を呼ぶべきまでの時間（ミリ秒）。既に期限が来ていれば 0
+    pub fn next_timeout_ms(&self) -> u64 {
+        let now_ms = self.now_ms();
+        self.endpoint.next_deadline_ms(now_ms).saturating_sub(now_ms)
+    }
+
+    /// 現在の接続状態を返す
+    pub fn connection_state(&self) -> ConnectionState {
+        self.endpoint.connection_state(self.now_ms())
+    }
+
+    /// 前回の呼び出しから接続状態が変わっていれば、新しい状態を返す
+    pub fn poll_state_change(&mut self) -> Option<ConnectionState> {
+        let now_ms = self.now_ms();
+        self.endpoint.poll_state_change(now_ms)
+    }
+
+    /// 時刻源の逆行を検出した回数
+    pub fn clock_backward_jumps(&self) -> u32 {
+        self.clock.backward_jumps()
+    }
+
+    /// エンドポイントの統計情報を返す
+    pub fn stats(&self) -> EndpointStats {
+        self.endpoint.stats()
+    }
+
+    /// 内側のエンドポイント（時刻を取らない操作に使う）
+    pub fn endpoint(&self) -> &MoshEndpoint<R> {
+        &self.endpoint
+    }
+
+    /// 内側のエンドポイント（可変）
+    pub fn endpoint_mut(&mut self) -> &mut MoshEndpoint<R> {
+        &mut self.endpoint
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{MoshClientEndpoint, MoshServer};
+    use mosh_ssp::clock::ManualClock;
+    use mosh_ssp::HEARTBEAT_INTERVAL_MS;
+
+    const KEY: [u8; 16] = [0x42u8; 16];
+
+    #[test]
+    fn test_clock_drives_timers() {
+        let clock = ManualClock::new(10_000);
+        let mut client = MoshClientEndpoint::from_key(KEY, None)
+            .unwrap()
+            .with_clock(clock.clone());
+        let mut server = MoshServer::from_key(KEY, None).unwrap().with_clock(clock.clone());
+
+        let _ = client.tick().unwrap(); // 初回ハートビート
+        assert_eq!(client.next_timeout_ms(), HEARTBEAT_INTERVAL_MS);
+
+        assert!(client.send_data(b"tick tock").unwrap().is_empty());
+        clock.advance(client.next_timeout_ms());
+        let packets = client.tick().unwrap();
+        assert_eq!(packets.len(), 1);
+        assert_eq!(server.recv_udp_packet(&packets[0]).unwrap(), b"tick tock");
+    }
+
+    #[test]
+    fn test_backward_jump_does_not_rewind_timers() {
+        let clock = ManualClock::new(10_000_000);
+        let mut client = MoshClientEndpoint::from_key(KEY, None)
+            .unwrap()
+            .with_clock(clock.clone());
+        let _ = client.tick().unwrap();
+
+        // 時刻が 1 時間巻き戻っても、ハートビートの期限は延びない
+        clock.set(10_000_000 - 3_600_000);
+        assert_eq!(client.next_timeout_ms(), HEARTBEAT_INTERVAL_MS);
+        assert_eq!(client.clock_backward_jumps(), 1);
+
+        clock.advance(HEARTBEAT_INTERVAL_MS);
+        assert_eq!(client.next_timeout_ms(), 0);
+        assert_eq!(client.tick().unwrap().len(), 1);
+    }
+}
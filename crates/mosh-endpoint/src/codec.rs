@@ -0,0 +1,145 @@
+//! UDP ペイロード ↔ Instruction の変換
+//!
+//! 暗号化・圧縮・Fragment 分割/再組み立てをまとめたもの。
+//! バイトストリームの [`MoshEndpoint`](crate::MoshEndpoint) と
+//! 端末モードの [`MoshTerminalEndpoint`](crate::MoshTerminalEndpoint) が共有する。
+//!
+//! ```text
+//! encode: Instruction → 圧縮 → Fragment 分割 → 暗号化 → UDP ペイロード列
+//! decode: UDP ペイロード → 復号 → Fragment 再組み立て → 展開 → Instruction
+//! ```
+
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+
+use mosh_crypto::{CryptoSession, ReplayStats};
+use mosh_proto::Instruction;
+use mosh_ssp::SspSession;
+use mosh_transport::{Compression, Fragment, FragmentAssembly, Fragmenter, Reassembly, Timestamp16};
+
+use crate::error::EndpointError;
+use crate::{EndpointRole, CRYPTO_OVERHEAD, DEFAULT_MTU, MIN_APP_PAYLOAD_MTU};
+
+/// UDP ペイロードと Instruction の相互変換器
+pub(crate) struct PacketCodec<R: EndpointRole> {
+    /// 暗号セッション（AES-128-OCB3）
+    crypto: CryptoSession,
+    /// Instruction の圧縮方式
+    compression: Compression,
+    /// Fragment 分割器
+    fragmenter: Fragmenter,
+    /// Fragment 再組み立て器
+    assembly: FragmentAssembly,
+    _role: PhantomData<R>,
+}
+
+impl<R: EndpointRole> PacketCodec<R> {
+    /// 暗号セッションと UDP の実効 MTU（`None` なら [`DEFAULT_MTU`]）から生成する
+    pub(crate) fn new(crypto: CryptoSession, mtu: Option<usize>) -> Self {
+        let effective_mtu = mtu.unwrap_or(DEFAULT_MTU);
+        // Fragment のペイロード MTU = UDP MTU - 暗号オーバーヘッド
+        let app_payload_mtu = effective_mtu
+            .saturating_sub(CRYPTO_OVERHEAD)
+            .max(MIN_APP_PAYLOAD_MTU);
+
+        PacketCodec {
+            crypto,
+            compression: Compression::default(),
+            fragmenter: Fragmenter::new(app_payload_mtu),
+            assembly: FragmentAssembly::new(),
+            _role: PhantomData,
+        }
+    }
+
+    /// Instruction の圧縮方式を変更する
+    pub(crate) fn set_compression(&mut self, compression: Compression) {
+        self.compression = compression;
+    }
+
+    /// 受信した UDP ペイロードを復号し、Instruction が揃ったら返す
+    ///
+    /// 復号できたパケットのタイムスタンプは（Instruction が揃う前でも）`ssp` に渡す。
+    ///
+    /// # 戻り値
+    /// 再組み立てが完了した Instruction。まだ Fragment が揃っていない、
+    /// または組み立て終えた Instruction の再送（遅延 ACK だけを設定する）なら `None`
+    pub(crate) fn decode(
+        &mut self,
+        udp_bytes: &[u8],
+        ssp: &mut SspSession,
+        now_ms: u64,
+    ) -> Result<Option<Instruction>, EndpointError> {
+        let decrypted = self
+            .crypto
+            .decrypt_packet(udp_bytes)
+            .map_err(EndpointError::Decrypt)?;
+
+        // タイムスタンプを記録（エコーバック用）し、エコーから RTT を計測
+        ssp.recv_timestamps(
+            Timestamp16(decrypted.timestamp),
+            Timestamp16(decrypted.timestamp_reply),
+            now_ms,
+        );
+
+        let frag = Fragment::from_bytes(&decrypted.payload).map_err(EndpointError::Fragment)?;
+
+        let assembled = match self
+            .assembly
+            .add_fragment(frag, now_ms)
+            .map_err(EndpointError::Fragment)?
+        {
+            Reassembly::Complete(bytes) => bytes,
+            // まだ Fragment が揃っていない
+            Reassembly::Incomplete => return Ok(None),
+            // 組み立て終えた Instruction の再送は相手が ACK を受け取れていない合図なので、
+            // 重複した Instruction と同じく ACK を送り直す
+            Reassembly::Duplicate => {
+                ssp.schedule_ack(now_ms);
+                return Ok(None);
+            }
+        };
+
+        let instruction_bytes = self
+            .compression
+            .decompress(&assembled)
+            .map_err(EndpointError::Decompress)?;
+
+        Instruction::decode_from_bytes(&instruction_bytes)
+            .map(Some)
+            .map_err(EndpointError::Instruction)
+    }
+
+    /// Instruction バイト列を圧縮 → Fragment 分割 → 暗号化し、UDP ペイロードを `out` に追加する
+    pub(crate) fn encode(
+        &mut self,
+        instruction_bytes: &[u8],
+        ssp: &mut SspSession,
+        now_ms: u64,
+        out: &mut Vec<Vec<u8>>,
+    ) -> Result<(), EndpointError> {
+        let compressed = self.compression.compress(instruction_bytes);
+        let fragments = self.fragmenter.make_fragments(&compressed);
+        let timestamp = Timestamp16::now_from_ms(now_ms).raw();
+
+        for frag in fragments {
+            let timestamp_reply = ssp.timestamp_reply(now_ms).raw();
+            let packet = self
+                .crypto
+                .encrypt_packet(timestamp, timestamp_reply, &frag.to_bytes())
+                .map_err(EndpointError::Encrypt)?;
+            out.push(packet);
+        }
+
+        Ok(())
+    }
+
+    /// リプレイウィンドウの幅（パケット数）
+    pub(crate) fn replay_window_size(&self) -> u64 {
+        self.crypto.replay_window_size()
+    }
+
+    /// リプレイ検出の統計
+    pub(crate) fn replay_stats(&self) -> ReplayStats {
+        self.crypto.replay_stats()
+    }
+}
@@ -0,0 +1,565 @@
+//! MoshEndpoint 実装
+//!
+//! 暗号化・SSP プロトコル・Fragment 管理を統合した送受信パイプライン。
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use mosh_crypto::{CryptoSession, MoshKey, ReplayStats};
+use mosh_ssp::session::SspStats;
+use mosh_ssp::{
+    Clock, CongestionControl, ConnectionState, ConnectionThresholds, SendPacing, SspSession,
+    SEND_WINDOW_MAX_BYTES,
+};
+use mosh_stream::{StreamChannel, StreamError, SEND_BUFFER_MAX_BYTES};
+use mosh_transport::Compression;
+
+use crate::clocked::ClockedEndpoint;
+use crate::codec::PacketCodec;
+use crate::error::EndpointError;
+use crate::EndpointRole;
+
+/// mosh トンネルの片端
+///
+/// ## 内部アーキテクチャ
+///
+/// ```text
+/// MoshEndpoint<R>
+///   ├── PacketCodec<R>
+///   │     ├── CryptoSession    (mosh-crypto) - AES-128-OCB3 暗号化/復号（R::ROLE で方向を決定）
+///   │     ├── Compression      (mosh-transport) - Instruction の zlib 圧縮/展開
+///   │     ├── Fragmenter       (mosh-transport) - Instruction を Fragment に分割
+///   │     └── FragmentAssembly (mosh-transport) - Fragment を再組み立て
+///   ├── SspSession       (mosh-ssp) - SSP 状態機械
+///   └── StreamChannel    (mosh-stream) - バイトストリームバッファ
+/// ```
+pub struct MoshEndpoint<R: EndpointRole> {
+    /// 暗号化・圧縮・Fragment 分割/再組み立て
+    codec: PacketCodec<R>,
+    /// SSP 状態機械
+    ssp: SspSession,
+    /// バイトストリームチャンネル
+    stream: StreamChannel,
+    /// 送信バッファが満杯で書き込みを断った後、まだ `poll_writable` で通知していない
+    write_blocked: bool,
+}
+
+impl<R: EndpointRole> MoshEndpoint<R> {
+    /// Base64 鍵（mosh-server が出力する 22 文字）からエンドポイントを初期化する
+    ///
+    /// # 引数
+    /// - `key_base64`: mosh の Base64 鍵（標準・URL-safe、パディングの有無を問わない）
+    /// - `mtu`: UDP の実効 MTU（バイト）。`None` の場合は [`DEFAULT_MTU`]。
+    ///
+    /// # エラー
+    /// - `EndpointError::InvalidKey`: Base64 デコード失敗・鍵長不正
+    pub fn new(key_base64: &str, mtu: Option<usize>) -> Result<Self, EndpointError> {
+        let key = MoshKey::parse(key_base64).map_err(EndpointError::InvalidKey)?;
+        Ok(Self::from_mosh_key(&key, mtu))
+    }
+
+    /// [`MoshKey`] からエンドポイントを初期化する
+    pub fn from_mosh_key(key: &MoshKey, mtu: Option<usize>) -> Self {
+        Self::from_crypto(CryptoSession::from_mosh_key(key, R::ROLE), mtu)
+    }
+
+    /// 16 バイトの raw 鍵からエンドポイントを初期化する
+    pub fn from_key(key: [u8; 16], mtu: Option<usize>) -> Result<Self, EndpointError> {
+        let crypto = CryptoSession::from_key(key, R::ROLE).map_err(EndpointError::InvalidKey)?;
+        Ok(Self::from_crypto(crypto, mtu))
+    }
+
+    fn from_crypto(crypto: CryptoSession, mtu: Option<usize>) -> Self {
+        MoshEndpoint {
+            codec: PacketCodec::new(crypto, mtu),
+            ssp: SspSession::new(),
+            stream: StreamChannel::new(),
+            write_blocked: false,
+        }
+    }
+
+    /// Instruction の圧縮方式を変更する（ビルダー）
+    ///
+    /// デフォルトは mosh C++ 実装と互換の [`Compression::Zlib`]。
+    /// [`Compression::Raw`] は両端が mosh-endpoint の場合のみ使える。
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.codec.set_compression(compression);
+        self
+    }
+
+    /// 送信ペーシングを変更する（ビルダー）
+    ///
+    /// デフォルトでは mosh と同じく書き込みを少し待ってまとめて送る。
+    /// [`SendPacing::DISABLED`] にすると `send_data` ごとに即座に送信する。
+    pub fn with_send_pacing(mut self, pacing: SendPacing) -> Self {
+        self.ssp = self.ssp.with_send_pacing(pacing);
+        self
+    }
+
+    /// 輻輳制御アルゴリズムを差し替える（ビルダー）
+    ///
+    /// デフォルトは [`NewReno`](mosh_ssp::NewReno)。
+    pub fn with_congestion_control(mut self, congestion: Box<dyn CongestionControl>) -> Self {
+        self.ssp = self.ssp.with_congestion_control(congestion);
+        self
+    }
+
+    /// 時刻源を持たせる
+    ///
+    /// 以降は `now_ms` を渡す代わりに `clock` から時刻を読む（[`ClockedEndpoint`]）。
+    pub fn with_clock<C: Clock>(self, clock: C) -> ClockedEndpoint<R, C> {
+        ClockedEndpoint::new(self, clock)
+    }
+
+    /// 受信した UDP ペイロードを処理する
+    ///
+    /// 処理フロー:
+    /// 1. AES-128-OCB3 復号（リプレイ・反射パケットの拒否を含む）
+    /// 2. Fragment ヘッダーを解析
+    /// 3. Fragment が揃ったら再組み立てし、展開して Instruction をデコード
+    /// 4. SSP プロトコル処理（ACK、状態更新）
+    /// 5. ペイロードをストリームバッファに積む
+    ///
+    /// # 戻り値
+    /// 上位レイヤーに渡すバイト列（データがなければ空）
+    pub fn recv_udp_packet(&mut self, udp_bytes: &[u8], now_ms: u64) -> Result<Vec<u8>, EndpointError> {
+        let Some(instr) = self.codec.decode(udp_bytes, &mut self.ssp, now_ms)? else {
+            return Ok(Vec::new());
+        };
+
+        if let Some(data) = self.ssp.recv_instruction(&instr, now_ms) {
+            self.stream.apply_diff(&data);
+        }
+
+        Ok(self.stream.read_available())
+    }
+
+    /// 上位レイヤーからのデータを送信する
+    ///
+    /// データは送信ペーシングにより次の送信枠まで保留されることがある。
+    /// 保留中のデータは [`next_deadline_ms`](Self::next_deadline_ms) の時刻に
+    /// `tick` を呼ぶと送信される。
+    ///
+    /// `data` 全体が送信バッファの空き（[`writable_bytes`](Self::writable_bytes)）に
+    /// 収まらなければ何も積まずに `EndpointError::Write(StreamError::WouldBlock)` を返す。
+    /// 空きができたことは [`poll_writable`](Self::poll_writable) で分かる。
+    ///
+    /// # 戻り値
+    /// 送信すべき UDP ペイロードのリスト
+    pub fn send_data(&mut self, data: &[u8], now_ms: u64) -> Result<Vec<Vec<u8>>, EndpointError> {
+        if data.len() > self.stream.writable_bytes() {
+            self.write_blocked = true;
+            return Err(EndpointError::Write(StreamError::WouldBlock));
+        }
+        self.write(data)?;
+        self.tick(now_ms)
+    }
+
+    /// 上位レイヤーからのデータを送信バッファに積む（送信は次の `tick` で行う）
+    ///
+    /// 送信バッファの空きを超える分は積まない。
+    ///
+    /// # 戻り値
+    /// - `Ok(n)`: 先頭から `n` バイトを積んだ（`n < data.len()` なら残りは後で書き直す）
+    /// - `Err(EndpointError::Write(StreamError::WouldBlock))`: 1 バイトも積めなかった
+    pub fn write(&mut self, data: &[u8]) -> Result<usize, EndpointError> {
+        let result = self.stream.write(data);
+        if result.as_ref().map_or(true, |&n| n < data.len()) {
+            self.write_blocked = true;
+        }
+        result.map_err(EndpointError::Write)
+    }
+
+    /// 送信バッファにあと何バイト積めるか
+    pub fn writable_bytes(&self) -> usize {
+        self.stream.writable_bytes()
+    }
+
+    /// 書き込みを断った後、送信バッファが空になっていれば `true` を返す（1 度だけ）
+    ///
+    /// Node.js の `'drain'` イベントに相当する。ホストは `write` / `send_data` が
+    /// 受け付けなかったら生産側を止め、`tick` の後にこれが `true` になったら再開する。
+    pub fn poll_writable(&mut self) -> bool {
+        if self.write_blocked && !self.stream.has_pending_write() {
+            self.write_blocked = false;
+            return true;
+        }
+        false
+    }
+
+    /// 送信ウィンドウ（フロー制御）を設定する
+    pub fn set_send_window(&mut self, window: SendWindow) {
+        self.ssp.set_max_in_flight_bytes(window.max_in_flight_bytes);
+        self.stream.set_send_capacity(window.max_queued_bytes);
+    }
+
+    /// 定期タイマー tick（送信待ちデータの送信・再送・ハートビート）
+    ///
+    /// # 戻り値
+    /// 送信すべき UDP ペイロードのリスト（空の場合もある）
+    pub fn tick(&mut self, now_ms: u64) -> Result<Vec<Vec<u8>>, EndpointError> {
+        // ストリームバッファに溜まっているデータを、SSP の送信ウィンドウに収まる分だけ渡す
+        let pending_diff = self.stream.take_pending_diff_up_to(self.ssp.send_window_room());
+        if !pending_diff.is_empty() {
+            self.ssp.push_payload(pending_diff);
+        }
+
+        let mut packets = Vec::new();
+        for instr_bytes in self.ssp.tick(now_ms) {
+            self.codec.encode(&instr_bytes, &mut self.ssp, now_ms, &mut packets)?;
+        }
+
+        Ok(packets)
+    }
+
+    /// 次に `tick` を呼ぶべき時刻（ミリ秒、絶対時刻）を返す
+    ///
+    /// ストリームに SSP へ未投入の送信データがあり、送信ウィンドウに空きがあれば `now_ms`（即時）。
+    /// それ以外は [`SspSession::next_deadline_ms`] に従う（ウィンドウの空きは ACK の受信で増える）。
+    pub fn next_deadline_ms(&self, now_ms: u64) -> u64 {
+        if self.stream.has_pending_write() && self.ssp.send_window_room() > 0 {
+            return now_ms;
+        }
+        self.ssp.next_deadline_ms(now_ms)
+    }
+
+    /// 現在の接続状態を返す
+    pub fn connection_state(&self, now_ms: u64) -> ConnectionState {
+        self.ssp.state(now_ms)
+    }
+
+    /// 前回の呼び出しから接続状態が変わっていれば、新しい状態を返す
+    pub fn poll_state_change(&mut self, now_ms: u64) -> Option<ConnectionState> {
+        self.ssp.poll_state_change(now_ms)
+    }
+
+    /// 接続状態の判定しきい値を設定する
+    pub fn set_connection_thresholds(&mut self, thresholds: ConnectionThresholds) {
+        self.ssp.set_connection_thresholds(thresholds);
+    }
+
+    /// 最後に相手から有効なパケットを受信した時刻（ミリ秒）。未受信なら `None`
+    pub fn last_recv_ms(&self) -> Option<u64> {
+        self.ssp.last_recv_ms()
+    }
+
+    /// 上位レイヤーが読み取れるデータがあるか
+    pub fn has_pending_read(&self) -> bool {
+        self.stream.has_pending_read()
+    }
+
+    /// バッファのデータをすべて読み出す
+    pub fn read_pending(&mut self) -> Vec<u8> {
+        self.stream.read_available()
+    }
+
+    /// エンドポイントの統計情報を返す
+    pub fn stats(&self) -> EndpointStats {
+        EndpointStats {
+            ssp: self.ssp.stats(),
+            total_sent_bytes: self.stream.total_sent_bytes(),
+            total_recv_bytes: self.stream.total_received_bytes(),
+            queued_bytes: self.stream.send_buffer_len() + self.ssp.queued_bytes(),
+            replay_window: self.codec.replay_window_size(),
+            replay: self.codec.replay_stats(),
+        }
+    }
+}
+
+/// エンドポイントの統計情報
+#[derive(Debug, Clone)]
+pub struct EndpointStats {
+    /// SSP セッション統計
+    pub ssp: SspStats,
+    /// セッション開始からの送信総バイト数
+    pub total_sent_bytes: u64,
+    /// セッション開始からの受信総バイト数
+    pub total_recv_bytes: u64,
+    /// 送信待ち（まだ Instruction にしていない）バイト数
+    pub queued_bytes: usize,
+    /// リプレイウィンドウの幅（パケット数）
+    pub replay_window: u64,
+    /// リプレイ検出の統計
+    pub replay: ReplayStats,
+}
+
+/// 送信ウィンドウ（フロー制御）の設定
+///
+/// 送信済み・未 ACK のバイト数と、送信待ちのバイト数をそれぞれ制限する。
+/// 遅い回線に大量のデータを書き込んでも、メモリ使用量は両者の合計で頭打ちになる。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SendWindow {
+    /// 送信済み・未 ACK のバイト数の上限
+    pub max_in_flight_bytes: usize,
+    /// 送信待ちのバイト数の上限。これを超える書き込みは受け付けない
+    pub max_queued_bytes: usize,
+}
+
+impl Default for SendWindow {
+    fn default() -> Self {
+        SendWindow {
+            max_in_flight_bytes: SEND_WINDOW_MAX_BYTES,
+            max_queued_bytes: SEND_BUFFER_MAX_BYTES,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MoshClientEndpoint, MoshServer};
+    use mosh_crypto::CryptoError;
+    use mosh_ssp::{ACK_DELAY_MS, SEND_MINDELAY_MS};
+
+    const KEY: [u8; 16] = [0x42u8; 16];
+
+    fn make_pair() -> (MoshClientEndpoint, MoshServer) {
+        (
+            MoshClientEndpoint::from_key(KEY, None).unwrap(),
+            MoshServer::from_key(KEY, None).unwrap(),
+        )
+    }
+
+    /// `send_data` の後、送信ペーシングの待ち時間が過ぎるまで tick する
+    fn send_paced<R: EndpointRole>(
+        endpoint: &mut MoshEndpoint<R>,
+        data: &[u8],
+        now_ms: u64,
+    ) -> Vec<Vec<u8>> {
+        let mut packets = endpoint.send_data(data, now_ms).unwrap();
+        packets.extend(endpoint.tick(now_ms + SEND_MINDELAY_MS).unwrap());
+        packets
+    }
+
+    #[test]
+    fn test_client_to_server() {
+        let (mut client, mut server) = make_pair();
+
+        let packets = send_paced(&mut client, b"hello server", 1000);
+        assert_eq!(packets.len(), 1);
+
+        let data = server.recv_udp_packet(&packets[0], 1010).unwrap();
+        assert_eq!(data, b"hello server");
+    }
+
+    #[test]
+    fn test_server_to_client_with_ack() {
+        let (mut client, mut server) = make_pair();
+
+        for pkt in send_paced(&mut client, b"ping", 1000) {
+            server.recv_udp_packet(&pkt, 1010).unwrap();
+        }
+
+        let mut received = Vec::new();
+        for pkt in send_paced(&mut server, b"pong", 1020) {
+            received.extend(client.recv_udp_packet(&pkt, 1030).unwrap());
+        }
+
+        assert_eq!(received, b"pong");
+        assert_eq!(client.stats().ssp.pending_count, 0, "サーバーの ACK で pending が解消される");
+    }
+
+    #[test]
+    fn test_large_payload_fragmented() {
+        let mut client = MoshClientEndpoint::from_key(KEY, Some(200)).unwrap();
+        let mut server = MoshServer::from_key(KEY, Some(200)).unwrap();
+
+        let data: Vec<u8> = (0u8..=255).cycle().take(3000).collect();
+        let packets = send_paced(&mut client, &data, 1000);
+        assert!(packets.len() > 1);
+
+        let mut received = Vec::new();
+        for pkt in &packets {
+            received.extend(server.recv_udp_packet(pkt, 1010).unwrap());
+        }
+        assert_eq!(received, data);
+    }
+
+    #[test]
+    fn test_lost_ack_of_fragmented_instruction_is_resent() {
+        let mut client = MoshClientEndpoint::from_key(KEY, Some(200)).unwrap();
+        let mut server = MoshServer::from_key(KEY, Some(200)).unwrap();
+
+        // 圧縮で 1 Fragment に収まらないよう、疑似乱数のバイト列を送る
+        let mut seed = 1u32;
+        let data: Vec<u8> = (0..3000)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                (seed >> 16) as u8
+            })
+            .collect();
+        let packets = send_paced(&mut client, &data, 1000);
+        assert!(packets.len() > 1);
+        let mut received = Vec::new();
+        for pkt in &packets {
+            received.extend(server.recv_udp_packet(pkt, 1030).unwrap());
+        }
+        assert_eq!(received, data);
+
+        // サーバーの ACK が失われる
+        let ack_at = server.next_deadline_ms(1030);
+        assert!(!server.tick(ack_at).unwrap().is_empty());
+
+        // 同じ Instruction の再送は組み立て済みの重複だが、ACK は送り直される
+        let retransmit_at = client.next_deadline_ms(ack_at);
+        let retransmit = client.tick(retransmit_at).unwrap();
+        assert!(retransmit.len() > 1);
+        let arrive = retransmit_at + 30;
+        for pkt in &retransmit {
+            assert!(server.recv_udp_packet(pkt, arrive).unwrap().is_empty());
+        }
+        let ack_at = server.next_deadline_ms(arrive);
+        assert!(ack_at <= arrive + ACK_DELAY_MS, "ハートビートを待たずに ACK する");
+        for pkt in server.tick(ack_at).unwrap() {
+            client.recv_udp_packet(&pkt, ack_at + 30).unwrap();
+        }
+        assert_eq!(client.stats().ssp.pending_count, 0);
+    }
+
+    #[test]
+    fn test_reflected_packet_rejected() {
+        let (mut client, _) = make_pair();
+
+        let packets = send_paced(&mut client, b"reflect me", 1000);
+        let result = client.recv_udp_packet(&packets[0], 1010);
+        assert!(matches!(
+            result,
+            Err(EndpointError::Decrypt(CryptoError::UnexpectedDirection))
+        ));
+    }
+
+    #[test]
+    fn test_invalid_key() {
+        let result = MoshServer::new("not a key", None);
+        assert!(matches!(result, Err(EndpointError::InvalidKey(_))));
+    }
+
+    #[test]
+    fn test_next_deadline_follows_retransmit_timer() {
+        let (mut client, _) = make_pair();
+        let _ = send_paced(&mut client, b"data", 10_000);
+
+        let now = 10_000 + SEND_MINDELAY_MS;
+        let deadline = client.next_deadline_ms(now);
+        assert!(deadline > now);
+        assert!(client.tick(deadline - 1).unwrap().is_empty());
+        assert!(!client.tick(deadline).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_connection_state_after_exchange() {
+        let (mut client, mut server) = make_pair();
+        assert_eq!(client.connection_state(1000), ConnectionState::Connecting);
+
+        for pkt in send_paced(&mut client, b"hi", 1000) {
+            server.recv_udp_packet(&pkt, 1010).unwrap();
+        }
+        for pkt in server.tick(1200).unwrap() {
+            client.recv_udp_packet(&pkt, 1210).unwrap();
+        }
+
+        assert_eq!(client.poll_state_change(1210), Some(ConnectionState::Connected));
+        assert_eq!(client.last_recv_ms(), Some(1210));
+    }
+
+    #[test]
+    fn test_raw_compression_between_own_peers() {
+        let mut client = MoshClientEndpoint::from_key(KEY, None)
+            .unwrap()
+            .with_compression(Compression::Raw);
+        let mut server = MoshServer::from_key(KEY, None)
+            .unwrap()
+            .with_compression(Compression::Raw);
+
+        let packets = send_paced(&mut client, b"uncompressed", 1000);
+        assert_eq!(server.recv_udp_packet(&packets[0], 1010).unwrap(), b"uncompressed");
+    }
+
+    #[test]
+    fn test_compression_mismatch_is_detected() {
+        let mut client = MoshClientEndpoint::from_key(KEY, None)
+            .unwrap()
+            .with_compression(Compression::Raw);
+        let mut server = MoshServer::from_key(KEY, None).unwrap();
+
+        let packets = send_paced(&mut client, b"uncompressed", 1000);
+        assert!(matches!(
+            server.recv_udp_packet(&packets[0], 1010),
+            Err(EndpointError::Decompress(_))
+        ));
+    }
+
+    #[test]
+    fn test_rtt_measured_from_heartbeats() {
+        let (mut client, mut server) = make_pair();
+
+        // データのやり取りなし（ハートビートのみ）でも RTT が計測される
+        for pkt in client.tick(10_000).unwrap() {
+            server.recv_udp_packet(&pkt, 10_030).unwrap();
+        }
+        for pkt in server.tick(10_040).unwrap() {
+            client.recv_udp_packet(&pkt, 10_070).unwrap();
+        }
+
+        // 往復 70ms - サーバーでの保持 10ms
+        assert_eq!(client.stats().ssp.srtt_ms, 60.0);
+    }
+
+    #[test]
+    fn test_send_data_batched_until_slot() {
+        let (mut client, mut server) = make_pair();
+
+        assert!(client.send_data(b"l", 1000).unwrap().is_empty());
+        assert!(client.send_data(b"s", 1003).unwrap().is_empty());
+        assert_eq!(client.next_deadline_ms(1003), 1000 + SEND_MINDELAY_MS);
+
+        let packets = client.tick(1000 + SEND_MINDELAY_MS).unwrap();
+        assert_eq!(packets.len(), 1, "2 回の書き込みが 1 パケットにまとまる");
+        assert_eq!(server.recv_udp_packet(&packets[0], 1020).unwrap(), b"ls");
+    }
+
+    #[test]
+    fn test_pacing_disabled_sends_immediately() {
+        let mut client = MoshClientEndpoint::from_key(KEY, None)
+            .unwrap()
+            .with_send_pacing(SendPacing::DISABLED);
+
+        assert_eq!(client.send_data(b"now", 1000).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_send_window_backpressure() {
+        let (mut client, mut server) = make_pair();
+        client.set_send_window(SendWindow {
+            max_in_flight_bytes: 4,
+            max_queued_bytes: 8,
+        });
+
+        assert_eq!(client.write(b"0123456789").unwrap(), 8);
+        assert!(matches!(
+            client.write(b"x"),
+            Err(EndpointError::Write(StreamError::WouldBlock))
+        ));
+        assert!(matches!(
+            client.send_data(b"x", 1000),
+            Err(EndpointError::Write(StreamError::WouldBlock))
+        ));
+
+        // ウィンドウ分だけ送信し、残りは ACK を待つ
+        let packets = send_paced(&mut client, b"", 1000);
+        assert_eq!(server.recv_udp_packet(&packets[0], 1010).unwrap(), b"0123");
+        assert_eq!(client.stats().queued_bytes, 4);
+        assert!(client.next_deadline_ms(1010) > 1010, "ウィンドウが満杯の間は即時 tick しない");
+        assert!(!client.poll_writable());
+
+        // ACK を受け取ると残りを送り、送信バッファが空いたことを 1 度だけ通知する
+        for pkt in server.tick(1200).unwrap() {
+            client.recv_udp_packet(&pkt, 1210).unwrap();
+        }
+        let packets = send_paced(&mut client, b"", 1210);
+        assert_eq!(server.recv_udp_packet(&packets[0], 1220).unwrap(), b"4567");
+        assert!(client.poll_writable());
+        assert!(!client.poll_writable());
+        assert_eq!(client.writable_bytes(), 8);
+    }
+}
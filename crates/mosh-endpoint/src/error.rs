@@ -0,0 +1,44 @@
+//! mosh-endpoint エラー型
+
+use mosh_crypto::CryptoError;
+use mosh_proto::ProtoError;
+use mosh_stream::StreamError;
+use mosh_transport::TransportError;
+
+/// エンドポイント（送受信パイプライン全体）のエラー
+///
+/// どの層で失敗したかを区別できるよう、下位クレートのエラーをラップする。
+#[derive(Debug)]
+pub enum EndpointError {
+    /// mosh 鍵が不正（Base64 デコード失敗・鍵長不正）
+    InvalidKey(CryptoError),
+    /// 暗号化に失敗
+    Encrypt(CryptoError),
+    /// 復号に失敗（認証タグ不一致・リプレイ・反射パケットを含む）
+    Decrypt(CryptoError),
+    /// Fragment の解析・再組み立てに失敗（バッファ上限の超過を含む）
+    Fragment(TransportError),
+    /// 再組み立てた Instruction の展開に失敗
+    Decompress(TransportError),
+    /// Instruction のデコードに失敗
+    Instruction(ProtoError),
+    /// 送信データを受け付けられない（送信バッファが満杯）
+    Write(StreamError),
+    /// 端末モードで受信した差分（HostMessage）を適用できない
+    StateDiff(ProtoError),
+}
+
+impl core::fmt::Display for EndpointError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            EndpointError::InvalidKey(e) => write!(f, "Invalid mosh key: {}", e),
+            EndpointError::Encrypt(e) => write!(f, "Encryption failed: {}", e),
+            EndpointError::Decrypt(e) => write!(f, "Decryption failed: {}", e),
+            EndpointError::Fragment(e) => write!(f, "Fragment parse failed: {}", e),
+            EndpointError::Decompress(e) => write!(f, "Decompression failed: {}", e),
+            EndpointError::Instruction(e) => write!(f, "Instruction decode failed: {}", e),
+            EndpointError::Write(e) => write!(f, "Write failed: {}", e),
+            EndpointError::StateDiff(e) => write!(f, "State diff apply failed: {}", e),
+        }
+    }
+}
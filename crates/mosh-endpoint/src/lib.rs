@@ -0,0 +1,119 @@
+//! # mosh-endpoint
+//!
+//! mosh トンネルの片端（クライアント or サーバー）。
+//!
+//! 暗号化・Fragment 分割/再組み立て・SSP・バイトストリームを一つにまとめ、
+//! UDP ペイロードの送受信 API（send / recv / tick）を提供する。
+//! wasm-bindgen に依存しないため、native のサーバー実装やテストからも使える。
+//! `mosh-wasm` の `MoshClient` もこのクレートの上に実装されている。
+//!
+//! 無改造の mosh-server と対話する端末モードのクライアントは [`MoshTerminalEndpoint`]
+//! （キー入力・端末サイズを送り、画面の更新を受け取る）。
+//!
+//! ## 役割
+//!
+//! 役割は型パラメータで指定する。送信時の direction ビットと、
+//! 受信時に期待する direction ビットが役割から決まる。
+//!
+//! ```text
+//! MoshEndpoint<ClientRole>  : ToServer で送信、ToClient のみ受信
+//! MoshEndpoint<ServerRole>  : ToClient で送信、ToServer のみ受信 (= MoshServer)
+//! ```
+//!
+//! ## 使用例
+//!
+//! ```
+//! use mosh_endpoint::{MoshClientEndpoint, MoshServer};
+//! use mosh_ssp::SEND_MINDELAY_MS;
+//!
+//! let key = "AAAAAAAAAAAAAAAAAAAAAA";
+//! let mut client = MoshClientEndpoint::new(key, None).unwrap();
+//! let mut server = MoshServer::new(key, None).unwrap();
+//!
+//! // 書き込みは SEND_MINDELAY_MS の間まとめてから送るので、期限の tick で出るパケットも集める
+//! let mut packets = client.send_data(b"hello", 1000).unwrap();
+//! packets.extend(client.tick(1000 + SEND_MINDELAY_MS).unwrap());
+//! assert!(!packets.is_empty());
+//!
+//! let mut received = Vec::new();
+//! for pkt in &packets {
+//!     received.extend(server.recv_udp_packet(pkt, 1010).unwrap());
+//! }
+//! assert_eq!(received, b"hello");
+//! ```
+
+#![no_std]
+extern crate alloc;
+
+pub mod clocked;
+mod codec;
+pub mod endpoint;
+pub mod error;
+pub mod terminal;
+
+pub use clocked::ClockedEndpoint;
+pub use endpoint::{EndpointStats, MoshEndpoint, SendWindow};
+pub use error::EndpointError;
+pub use terminal::{
+    HostTranscript, MoshTerminalEndpoint, ScreenUpdate, TerminalScreen, TerminalState,
+};
+
+use mosh_crypto::Role;
+
+/// mosh プロトコルのデフォルト MTU（バイト）
+/// モバイル環境向けの保守的な設定
+pub const DEFAULT_MTU: usize = 500;
+
+/// Fragment ヘッダーのオーバーヘッド（バイト）
+/// - nonce_tail: 8
+/// - auth_tag: 16
+/// - direction_seq: 8
+/// - timestamp: 2
+/// - timestamp_reply: 2
+/// - fragment_header: 10
+pub const CRYPTO_OVERHEAD: usize = 46;
+
+/// Fragment ペイロード MTU の下限（バイト）
+pub const MIN_APP_PAYLOAD_MTU: usize = 64;
+
+/// 端末モードで 1 つの Keystroke に載せるキー入力の最大バイト数
+/// 貼り付けなどの長い入力はこの単位で分けて送る
+pub const USER_INPUT_CHUNK_BYTES: usize = 1024;
+
+/// [`HostTranscript`] が保持するホスト出力の最大バイト数（末尾を残す）
+pub const HOST_TRANSCRIPT_MAX_BYTES: usize = 64 * 1024;
+
+/// [`TerminalScreen`] の初期の画面の幅（桁数）
+/// サーバーから端末サイズが届くまでの仮の値（mosh C++ 実装と同じ 80×24）
+pub const TERMINAL_DEFAULT_WIDTH: u16 = 80;
+
+/// [`TerminalScreen`] の初期の画面の高さ（行数）
+pub const TERMINAL_DEFAULT_HEIGHT: u16 = 24;
+
+/// エンドポイントの役割を表す型レベルのマーカー
+pub trait EndpointRole {
+    /// 暗号セッションの役割
+    const ROLE: Role;
+}
+
+/// クライアント役割のマーカー
+#[derive(Debug)]
+pub enum ClientRole {}
+
+/// サーバー役割のマーカー
+#[derive(Debug)]
+pub enum ServerRole {}
+
+impl EndpointRole for ClientRole {
+    const ROLE: Role = Role::Client;
+}
+
+impl EndpointRole for ServerRole {
+    const ROLE: Role = Role::Server;
+}
+
+/// クライアント側のエンドポイント
+pub type MoshClientEndpoint = MoshEndpoint<ClientRole>;
+
+/// サーバー側のエンドポイント
+pub type MoshServer = MoshEndpoint<ServerRole>;
@@ -0,0 +1,759 @@
+//! 端末モードのクライアント（無改造の mosh-server と対話する）
+//!
+//! バイトストリームの [`MoshEndpoint`](crate::MoshEndpoint) と暗号化・Fragment・SSP の層を
+//! 共有し、ペイロードだけを mosh C++ 実装の端末用メッセージに置き換える。
+//!
+//! ```text
+//! 送信: キー入力・端末サイズ → UserEvent → UserMessage
+//!       （UserMessage は連結できるので、バイトストリームと同じく差分を積み重ねて送る）
+//! 受信: Instruction.diff = HostMessage（状態 old_num の画面 → 状態 new_num の画面）
+//!       → ReceivedStates<S> で起点の状態に適用 → 表示中の状態との差を ScreenUpdate で渡す
+//! ```
+//!
+//! 画面の状態は既定では [`TerminalScreen`]（端末エミュレーターで描いたフレームバッファ）で、
+//! 表示中の画面との差分を最小限のエスケープシーケンスにして渡す。
+//! キー入力のエコーは [`PredictionEngine`] で予測して画面に重ね、サーバーの `EchoAck` で確かめる。
+
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+
+use mosh_crypto::{CryptoSession, MoshKey};
+use mosh_proto::{HostEvent, HostMessage, ProtoError, UserEvent, UserMessage};
+use mosh_ssp::{
+    ConnectionState, ConnectionThresholds, ReceivedStates, SendPacing, SspSession, SyncState,
+};
+use mosh_prediction::{PredictionEngine, PredictionMode};
+use mosh_terminal::{Display, Emulator, Framebuffer};
+use mosh_transport::Compression;
+
+use crate::codec::PacketCodec;
+use crate::endpoint::EndpointStats;
+use crate::error::EndpointError;
+use crate::{
+    ClientRole, EndpointRole, HOST_TRANSCRIPT_MAX_BYTES, TERMINAL_DEFAULT_HEIGHT,
+    TERMINAL_DEFAULT_WIDTH, USER_INPUT_CHUNK_BYTES,
+};
+
+/// 端末モードで受信側が保持する画面の状態
+///
+/// 差分（エンコード済み HostMessage）の適用は [`SyncState::apply_diff`] で行う。
+pub trait TerminalState: SyncState<Error = ProtoError> + Default {
+    /// サーバーが通知した端末サイズ（幅, 高さ）。未通知なら `None`
+    fn size(&self) -> Option<(u16, u16)>;
+
+    /// サーバーがエコーを画面に反映済みのクライアント状態番号
+    fn echo_ack(&self) -> u64;
+
+    /// 表示中の状態 `displayed` からこの状態へ描き替えるバイト列（端末に流す）
+    ///
+    /// `displayed` が `None` なら、まだ何も表示していない（端末の内容は不明）。
+    fn repaint_from(&self, displayed: Option<&Self>) -> Vec<u8>;
+
+    /// 予測エンジンが予測の検証に使う画面（画面を持たない状態は `None` で、予測しない）
+    fn frame(&self) -> Option<&Framebuffer> {
+        None
+    }
+
+    /// 予測したエコーを画面に重ねる（画面を持たない状態は何もしない）
+    fn apply_predictions(&mut self, _predictions: &PredictionEngine) {}
+}
+
+/// 端末エミュレーターで描いた画面の状態（mosh C++ 実装の `Terminal::Complete` 相当）
+///
+/// ホスト出力を [`Emulator`] に流してフレームバッファを作り、表示中の画面との差分を
+/// [`Display`] で最小限のエスケープシーケンスにする。差分が途中の状態を飛ばしても、
+/// 画面同士を比べるので描き替えは小さいまま。
+#[derive(Debug, Clone)]
+pub struct TerminalScreen {
+    /// ホスト出力を描く端末エミュレーター
+    emulator: Emulator,
+    /// サーバーが通知した端末サイズ（幅, 高さ）
+    size: Option<(u16, u16)>,
+    /// エコーを反映済みのクライアント状態番号
+    echo_ack: u64,
+}
+
+impl Default for TerminalScreen {
+    fn default() -> Self {
+        TerminalScreen {
+            emulator: Emulator::new(
+                TERMINAL_DEFAULT_WIDTH.into(),
+                TERMINAL_DEFAULT_HEIGHT.into(),
+            ),
+            size: None,
+            echo_ack: 0,
+        }
+    }
+}
+
+impl TerminalScreen {
+    /// 現在の画面
+    pub fn framebuffer(&self) -> &Framebuffer {
+        self.emulator.framebuffer()
+    }
+}
+
+impl SyncState for TerminalScreen {
+    type Error = ProtoError;
+
+    fn apply_diff(&mut self, diff: &[u8]) -> Result<(), ProtoError> {
+        for event in HostMessage::decode_from_bytes(diff)?.events() {
+            match event {
+                HostEvent::HostBytes(bytes) => self.emulator.write(&bytes),
+                HostEvent::Resize { width, height } => {
+                    let size = (clamp_dimension(width), clamp_dimension(height));
+                    self.emulator.resize(size.0.into(), size.1.into());
+                    self.size = Some(size);
+                }
+                HostEvent::EchoAck(num) => self.echo_ack = num,
+            }
+        }
+        // 端末への問い合わせにはサーバー側の端末が応答するので、こちらの応答は捨てる
+        self.emulator.take_replies();
+        Ok(())
+    }
+}
+
+impl TerminalState for TerminalScreen {
+    fn size(&self) -> Option<(u16, u16)> {
+        self.size
+    }
+
+    fn echo_ack(&self) -> u64 {
+        self.echo_ack
+    }
+
+    fn repaint_from(&self, displayed: Option<&Self>) -> Vec<u8> {
+        let display = Display::default();
+        let frame = match displayed {
+            Some(displayed) => display.new_frame(true, displayed.framebuffer(), self.framebuffer()),
+            None => display.new_frame(false, self.framebuffer(), self.framebuffer()),
+        };
+        frame.into_bytes()
+    }
+
+    fn frame(&self) -> Option<&Framebuffer> {
+        Some(self.framebuffer())
+    }
+
+    fn apply_predictions(&mut self, predictions: &PredictionEngine) {
+        predictions.apply(self.emulator.framebuffer_mut());
+    }
+}
+
+/// ホスト出力の履歴をそのまま保持する画面の状態
+///
+/// 端末エミュレーターを使わない実装（ホスト出力をそのまま端末に流す）。
+/// 表示中の状態の履歴がこの状態の履歴の先頭部分なら続きだけを、そうでなければ
+/// （差分が途中の状態を飛ばした場合など）端末をリセットして保持している履歴を流し直す。
+/// 履歴は末尾 [`HOST_TRANSCRIPT_MAX_BYTES`] バイトだけを保持する。
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HostTranscript {
+    /// 保持しているホスト出力（末尾 `HOST_TRANSCRIPT_MAX_BYTES` バイト）
+    bytes: Vec<u8>,
+    /// セッション開始からのホスト出力の総バイト数
+    total: u64,
+    /// 端末サイズ（幅, 高さ）
+    size: Option<(u16, u16)>,
+    /// エコーを反映済みのクライアント状態番号
+    echo_ack: u64,
+}
+
+impl HostTranscript {
+    /// 保持しているホスト出力
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// セッション開始からのホスト出力の総バイト数
+    pub fn total_bytes(&self) -> u64 {
+        self.total
+    }
+
+    /// 保持している履歴の先頭が、セッション開始から何バイト目か
+    fn start(&self) -> u64 {
+        self.total - self.bytes.len() as u64
+    }
+
+    /// 総バイト数で数えた `from..to` の履歴（保持している範囲内であること）
+    fn range(&self, from: u64, to: u64) -> &[u8] {
+        let start = self.start();
+        &self.bytes[(from - start) as usize..(to - start) as usize]
+    }
+}
+
+impl SyncState for HostTranscript {
+    type Error = ProtoError;
+
+    fn apply_diff(&mut self, diff: &[u8]) -> Result<(), ProtoError> {
+        for event in HostMessage::decode_from_bytes(diff)?.events() {
+            match event {
+                HostEvent::HostBytes(bytes) => {
+                    self.bytes.extend_from_slice(&bytes);
+                    self.total += bytes.len() as u64;
+                }
+                HostEvent::Resize { width, height } => {
+                    self.size = Some((clamp_dimension(width), clamp_dimension(height)));
+                }
+                HostEvent::EchoAck(num) => self.echo_ack = num,
+            }
+        }
+        if self.bytes.len() > HOST_TRANSCRIPT_MAX_BYTES {
+            self.bytes.drain(..self.bytes.len() - HOST_TRANSCRIPT_MAX_BYTES);
+        }
+        Ok(())
+    }
+}
+
+impl TerminalState for HostTranscript {
+    fn size(&self) -> Option<(u16, u16)> {
+        self.size
+    }
+
+    fn echo_ack(&self) -> u64 {
+        self.echo_ack
+    }
+
+    fn repaint_from(&self, displayed: Option<&Self>) -> Vec<u8> {
+        let initial = HostTranscript::default();
+        let displayed = displayed.unwrap_or(&initial);
+        // 表示中の履歴の末尾がこちらの保持範囲にあり、重なる部分が一致すれば続きだけを流す
+        if displayed.total <= self.total && displayed.total >= self.start() {
+            let overlap_start = self.start().max(displayed.start());
+            if self.range(overlap_start, displayed.total)
+                == displayed.range(overlap_start, displayed.total)
+            {
+                return self.range(displayed.total, self.total).to_vec();
+            }
+        }
+
+        // RIS（端末のリセット）の後に保持している履歴を流し直す
+        let mut repaint = b"\x1bc".to_vec();
+        repaint.extend_from_slice(&self.bytes);
+        repaint
+    }
+}
+
+/// 端末サイズ（protobuf では int32）を u16 に収める
+fn clamp_dimension(value: i32) -> u16 {
+    value.clamp(0, u16::MAX as i32) as u16
+}
+
+/// 表示中の画面から最新の画面への更新
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScreenUpdate {
+    /// 端末サイズが変わっていれば新しいサイズ（幅, 高さ）
+    pub resize: Option<(u16, u16)>,
+    /// 端末に流すバイト列（空なら描き替え不要）
+    pub bytes: Vec<u8>,
+}
+
+impl ScreenUpdate {
+    /// 更新がないか
+    pub fn is_empty(&self) -> bool {
+        self.resize.is_none() && self.bytes.is_empty()
+    }
+}
+
+/// 端末モードの mosh クライアント
+///
+/// ## 内部アーキテクチャ
+///
+/// ```text
+/// MoshTerminalEndpoint<S>   (S は既定で TerminalScreen)
+///   ├── PacketCodec<ClientRole>  - 暗号化・圧縮・Fragment 分割/再組み立て
+///   ├── SspSession      (mosh-ssp) - SSP 状態機械（送信は push_message、受信は状態同期）
+///   ├── ReceivedStates<S> (mosh-ssp) - 受信した画面の状態（状態番号ごと）
+///   ├── PredictionEngine (mosh-prediction) - キー入力のエコーの予測
+///   └── displayed: S    - 上位レイヤーに渡し済みの画面の状態（予測を重ねたもの）
+/// ```
+pub struct MoshTerminalEndpoint<S: TerminalState = TerminalScreen> {
+    /// 暗号化・圧縮・Fragment 分割/再組み立て
+    codec: PacketCodec<ClientRole>,
+    /// SSP 状態機械
+    ssp: SspSession,
+    /// 受信した画面の状態
+    states: ReceivedStates<S>,
+    /// 上位レイヤーに渡し済みの画面の状態と、その状態番号
+    displayed: (u64, S),
+    /// キー入力のエコーの予測
+    prediction: PredictionEngine,
+    /// 予測が変わったので、同じ状態でも画面を描き直す
+    predictions_changed: bool,
+    /// まだ SSP に積んでいないユーザー操作
+    input: VecDeque<UserEvent>,
+    /// SSP に積んだ UserMessage の総バイト数
+    total_sent_bytes: u64,
+    /// 適用した HostMessage の総バイト数
+    total_recv_bytes: u64,
+}
+
+impl<S: TerminalState> MoshTerminalEndpoint<S> {
+    /// Base64 鍵（mosh-server が出力する 22 文字）から初期化する
+    ///
+    /// # 引数
+    /// - `key_base64`: mosh の Base64 鍵（標準・URL-safe、パディングの有無を問わない）
+    /// - `mtu`: UDP の実効 MTU（バイト）。`None` の場合は [`DEFAULT_MTU`](crate::DEFAULT_MTU)。
+    ///
+    /// # エラー
+    /// - `EndpointError::InvalidKey`: Base64 デコード失敗・鍵長不正
+    pub fn new(key_base64: &str, mtu: Option<usize>) -> Result<Self, EndpointError> {
+        let key = MoshKey::parse(key_base64).map_err(EndpointError::InvalidKey)?;
+        Ok(Self::from_mosh_key(&key, mtu))
+    }
+
+    /// [`MoshKey`] から初期化する
+    pub fn from_mosh_key(key: &MoshKey, mtu: Option<usize>) -> Self {
+        Self::from_crypto(CryptoSession::from_mosh_key(key, ClientRole::ROLE), mtu)
+    }
+
+    /// 16 バイトの raw 鍵から初期化する
+    pub fn from_key(key: [u8; 16], mtu: Option<usize>) -> Result<Self, EndpointError> {
+        let crypto =
+            CryptoSession::from_key(key, ClientRole::ROLE).map_err(EndpointError::InvalidKey)?;
+        Ok(Self::from_crypto(crypto, mtu))
+    }
+
+    fn from_crypto(crypto: CryptoSession, mtu: Option<usize>) -> Self {
+        MoshTerminalEndpoint {
+            codec: PacketCodec::new(crypto, mtu),
+            ssp: SspSession::new(),
+            states: ReceivedStates::new(S::default()),
+            displayed: (0, S::default()),
+            prediction: PredictionEngine::default(),
+            predictions_changed: false,
+            input: VecDeque::new(),
+            total_sent_bytes: 0,
+            total_recv_bytes: 0,
+        }
+    }
+
+    /// Instruction の圧縮方式を変更する（ビルダー）
+    ///
+    /// mosh-server と対話するならデフォルトの [`Compression::Zlib`] のまま使う。
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.codec.set_compression(compression);
+        self
+    }
+
+    /// 送信ペーシングを変更する（ビルダー）
+    pub fn with_send_pacing(mut self, pacing: SendPacing) -> Self {
+        self.ssp = self.ssp.with_send_pacing(pacing);
+        self
+    }
+
+    /// 予測の表示モードを変更する（ビルダー）
+    pub fn with_prediction_mode(mut self, mode: PredictionMode) -> Self {
+        self.set_prediction_mode(mode);
+        self
+    }
+
+    /// 予測の表示モードを変更する
+    pub fn set_prediction_mode(&mut self, mode: PredictionMode) {
+        self.prediction.set_mode(mode);
+        self.predictions_changed = true;
+    }
+
+    /// キー入力のエコーの予測
+    pub fn prediction(&self) -> &PredictionEngine {
+        &self.prediction
+    }
+
+    /// キー入力を送信待ちに積む（送信は次の `tick` で行う）
+    ///
+    /// エコーを予測して、次の [`take_screen_update`](Self::take_screen_update) から画面に重ねる。
+    /// 長い入力（貼り付けなど）は [`USER_INPUT_CHUNK_BYTES`] ごとに分けて積む。
+    pub fn keystroke(&mut self, keys: &[u8]) {
+        if let Some(fb) = self.states.latest().frame() {
+            self.prediction.new_user_input(keys, fb);
+            self.predictions_changed = true;
+        }
+        for chunk in keys.chunks(USER_INPUT_CHUNK_BYTES) {
+            self.input.push_back(UserEvent::Keystroke(chunk.to_vec()));
+        }
+    }
+
+    /// 端末サイズの変更を送信待ちに積む（送信は次の `tick` で行う）
+    pub fn resize(&mut self, width: u16, height: u16) {
+        self.input.push_back(UserEvent::Resize {
+            width: width.into(),
+            height: height.into(),
+        });
+    }
+
+    /// 受信した UDP ペイロードを処理する
+    ///
+    /// 処理フロー:
+    /// 1. 復号・Fragment 再組み立て・展開して Instruction をデコード
+    /// 2. SSP プロトコル処理（ACK）
+    /// 3. 差分（HostMessage）を起点の画面の状態に適用して保持する
+    /// 4. 予測をエコー確認済みの画面と比べて確定・取り消しする
+    ///
+    /// 画面の更新は [`take_screen_update`](Self::take_screen_update) で取り出す。
+    ///
+    /// # 戻り値
+    /// 新しい画面の状態を受信したら `true`
+    ///
+    /// # エラー
+    /// - `EndpointError::StateDiff`: 差分をデコードできない（ACK しないので相手が送り直す）
+    /// - その他はバイトストリームの [`MoshEndpoint`](crate::MoshEndpoint) と同じ
+    pub fn recv_udp_packet(&mut self, udp_bytes: &[u8], now_ms: u64) -> Result<bool, EndpointError> {
+        let Some(instr) = self.codec.decode(udp_bytes, &mut self.ssp, now_ms)? else {
+            return Ok(false);
+        };
+
+        let applied = self
+            .ssp
+            .recv_state_instruction(&instr, &mut self.states, now_ms)
+            .map_err(EndpointError::StateDiff)?;
+        if applied.is_some() {
+            self.total_recv_bytes += instr.diff_bytes().len() as u64;
+            self.cull_predictions(now_ms);
+        }
+        Ok(applied.is_some())
+    }
+
+    /// 表示中の画面から最新の画面への更新を取り出す
+    ///
+    /// 取り出した時点の最新の画面（予測を重ねたもの）を表示中とみなす。
+    /// 新しい画面も予測の変化もなければ空の更新を返す。
+    pub fn take_screen_update(&mut self) -> ScreenUpdate {
+        let latest_num = self.states.latest_num();
+        if latest_num == 0 || (latest_num == self.displayed.0 && !self.predictions_changed) {
+            return ScreenUpdate::default();
+        }
+        self.predictions_changed = false;
+
+        let mut latest = self.states.latest().clone();
+        latest.apply_predictions(&self.prediction);
+        let (displayed_num, displayed) = &self.displayed;
+        // 状態 0 は初期状態なので、まだ何も表示していない
+        let shown = (*displayed_num != 0).then_some(displayed);
+        let update = ScreenUpdate {
+            resize: latest.size().filter(|&size| displayed.size() != Some(size)),
+            bytes: latest.repaint_from(shown),
+        };
+        self.displayed = (latest_num, latest);
+        update
+    }
+
+    /// 最新の画面の状態
+    pub fn screen(&self) -> &S {
+        self.states.latest()
+    }
+
+    /// サーバーがエコーを画面に反映済みのクライアント状態番号
+    pub fn echo_ack(&self) -> u64 {
+        self.states.latest().echo_ack()
+    }
+
+    /// 定期タイマー tick（ユーザー操作の送信・再送・ハートビート）
+    ///
+    /// ユーザー操作は送信ウィンドウに収まる分だけ UserMessage にして SSP に積む。
+    /// 1 つの UserMessage が 2 つの Instruction にまたがることはない
+    /// （[`SspSession::push_message`]）。
+    /// キー入力をすべて Instruction にしたら、その状態番号を予測に記録する
+    /// （サーバーの `EchoAck` がこの番号に達したら予測を確かめる）。
+    ///
+    /// # 戻り値
+    /// 送信すべき UDP ペイロードのリスト（空の場合もある）
+    pub fn tick(&mut self, now_ms: u64) -> Result<Vec<Vec<u8>>, EndpointError> {
+        let mut room = self.ssp.send_window_room();
+        while let Some(event) = self.input.front() {
+            let message = UserMessage::from_events([event.clone()]).encode_to_bytes();
+            if message.len() > room {
+                break;
+            }
+            room -= message.len();
+            self.total_sent_bytes += message.len() as u64;
+            self.input.pop_front();
+            self.ssp.push_message(message);
+        }
+
+        let mut packets = Vec::new();
+        for instr_bytes in self.ssp.tick(now_ms) {
+            self.codec.encode(&instr_bytes, &mut self.ssp, now_ms, &mut packets)?;
+        }
+
+        if self.input.is_empty() && self.ssp.queued_bytes() == 0 {
+            self.prediction.set_local_frame_sent(self.ssp.last_sent_num(), now_ms);
+        }
+        self.cull_predictions(now_ms);
+
+        Ok(packets)
+    }
+
+    /// 次に `tick` を呼ぶべき時刻（ミリ秒、絶対時刻）を返す
+    ///
+    /// SSP へ未投入のユーザー操作があり、送信ウィンドウに空きがあれば `now_ms`（即時）。
+    /// それ以外は [`SspSession::next_deadline_ms`] に従う。
+    pub fn next_deadline_ms(&self, now_ms: u64) -> u64 {
+        if !self.input.is_empty() && self.ssp.send_window_room() > 0 {
+            return now_ms;
+        }
+        self.ssp.next_deadline_ms(now_ms)
+    }
+
+    /// 現在の接続状態を返す
+    pub fn connection_state(&self, now_ms: u64) -> ConnectionState {
+        self.ssp.state(now_ms)
+    }
+
+    /// 前回の呼び出しから接続状態が変わっていれば、新しい状態を返す
+    pub fn poll_state_change(&mut self, now_ms: u64) -> Option<ConnectionState> {
+        self.ssp.poll_state_change(now_ms)
+    }
+
+    /// 接続状態の判定しきい値を設定する
+    pub fn set_connection_thresholds(&mut self, thresholds: ConnectionThresholds) {
+        self.ssp.set_connection_thresholds(thresholds);
+    }
+
+    /// 最後にサーバーから有効なパケットを受信した時刻（ミリ秒）。未受信なら `None`
+    pub fn last_recv_ms(&self) -> Option<u64> {
+        self.ssp.last_recv_ms()
+    }
+
+    /// エンドポイントの統計情報を返す
+    ///
+    /// 送受信の総バイト数は UserMessage / HostMessage のエンコード後のバイト数。
+    pub fn stats(&self) -> EndpointStats {
+        let unsent_input: usize = self
+            .input
+            .iter()
+            .map(|event| UserMessage::from_events([event.clone()]).encode_to_bytes().len())
+            .sum();
+        EndpointStats {
+            ssp: self.ssp.stats(),
+            total_sent_bytes: self.total_sent_bytes,
+            total_recv_bytes: self.total_recv_bytes,
+            queued_bytes: unsent_input + self.ssp.queued_bytes(),
+            replay_window: self.codec.replay_window_size(),
+            replay: self.codec.replay_stats(),
+        }
+    }
+
+    /// 予測を最新の画面・エコー確認・往復時間で確かめる
+    fn cull_predictions(&mut self, now_ms: u64) {
+        let latest = self.states.latest();
+        let Some(fb) = latest.frame() else {
+            return;
+        };
+        // 予測があれば、確定・取り消し・表示の切り替えで画面が変わりうる
+        self.predictions_changed |= self.prediction.is_active();
+        self.prediction.set_local_frame_late_acked(latest.echo_ack());
+        self.prediction.set_srtt(self.ssp.stats().srtt_ms);
+        self.prediction.cull(fb, now_ms);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ServerRole;
+    use alloc::vec;
+    use mosh_proto::Instruction;
+    use mosh_ssp::SEND_MINDELAY_MS;
+
+    const KEY: [u8; 16] = [0x42u8; 16];
+
+    /// Instruction を直接組み立てて送る mosh-server 役
+    struct FakeServer {
+        codec: PacketCodec<ServerRole>,
+        ssp: SspSession,
+    }
+
+    impl FakeServer {
+        fn new() -> Self {
+            FakeServer {
+                codec: PacketCodec::new(CryptoSession::from_key(KEY, ServerRole::ROLE).unwrap(), None),
+                ssp: SspSession::new(),
+            }
+        }
+
+        /// 状態 `old` → `new` の差分として `msg` を送る
+        fn send(&mut self, old: u64, new: u64, throwaway: u64, msg: HostMessage) -> Vec<Vec<u8>> {
+            let instr = Instruction::new_send(old, new, 0, throwaway, msg.encode_to_bytes());
+            let mut packets = Vec::new();
+            self.codec
+                .encode(&instr.encode_to_bytes(), &mut self.ssp, 0, &mut packets)
+                .unwrap();
+            packets
+        }
+
+        /// クライアントのパケットから Instruction を取り出す
+        fn recv(&mut self, packets: &[Vec<u8>]) -> Vec<Instruction> {
+            packets
+                .iter()
+                .filter_map(|pkt| self.codec.decode(pkt, &mut self.ssp, 0).unwrap())
+                .collect()
+        }
+    }
+
+    fn deliver<S: TerminalState>(client: &mut MoshTerminalEndpoint<S>, packets: &[Vec<u8>]) -> bool {
+        packets
+            .iter()
+            .fold(false, |changed, pkt| client.recv_udp_packet(pkt, 1000).unwrap() | changed)
+    }
+
+    #[test]
+    fn test_keystrokes_and_resize_sent_as_user_message() {
+        let mut client = MoshTerminalEndpoint::<HostTranscript>::from_key(KEY, None).unwrap();
+        let mut server = FakeServer::new();
+
+        client.resize(80, 24);
+        client.keystroke(b"ls\r");
+        assert_eq!(client.next_deadline_ms(1000), 1000);
+        let mut packets = client.tick(1000).unwrap();
+        packets.extend(client.tick(1000 + SEND_MINDELAY_MS).unwrap());
+
+        let instrs = server.recv(&packets);
+        assert_eq!(instrs.len(), 1);
+        let msg = UserMessage::decode_from_bytes(instrs[0].diff_bytes()).unwrap();
+        assert_eq!(
+            msg.events().collect::<Vec<_>>(),
+            vec![
+                UserEvent::Resize { width: 80, height: 24 },
+                UserEvent::Keystroke(b"ls\r".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_long_paste_chunked() {
+        let mut client = MoshTerminalEndpoint::<HostTranscript>::from_key(KEY, None).unwrap();
+        client.keystroke(&[b'x'; USER_INPUT_CHUNK_BYTES * 2 + 1]);
+        assert_eq!(client.input.len(), 3);
+    }
+
+    #[test]
+    fn test_screen_updates_follow_host_output() {
+        let mut client = MoshTerminalEndpoint::<HostTranscript>::from_key(KEY, None).unwrap();
+        let mut server = FakeServer::new();
+
+        let first = HostMessage::default().with_resize(80, 24).with_host_bytes(b"$ ");
+        assert!(deliver(&mut client, &server.send(0, 1, 0, first)));
+        assert_eq!(
+            client.take_screen_update(),
+            ScreenUpdate {
+                resize: Some((80, 24)),
+                bytes: b"$ ".to_vec(),
+            }
+        );
+        assert!(client.take_screen_update().is_empty());
+
+        // 重複は画面を変えない
+        let second = HostMessage::default().with_host_bytes(b"ls").with_echo_ack(3);
+        assert!(deliver(&mut client, &server.send(1, 2, 0, second.clone())));
+        assert!(!deliver(&mut client, &server.send(1, 2, 0, second)));
+        assert_eq!(client.take_screen_update().bytes, b"ls");
+        assert_eq!(client.echo_ack(), 3);
+    }
+
+    #[test]
+    fn test_diff_skipping_displayed_state_repaints() {
+        let mut client = MoshTerminalEndpoint::<HostTranscript>::from_key(KEY, None).unwrap();
+        let mut server = FakeServer::new();
+
+        deliver(&mut client, &server.send(0, 4, 0, HostMessage::default().with_host_bytes(b"a")));
+        deliver(&mut client, &server.send(4, 5, 4, HostMessage::default().with_host_bytes(b"b")));
+        assert_eq!(client.take_screen_update().bytes, b"ab");
+
+        // 5 の ACK が届く前に、サーバーが 4 を起点に 6 を作った
+        deliver(&mut client, &server.send(4, 6, 4, HostMessage::default().with_host_bytes(b"c")));
+        assert_eq!(client.screen().bytes(), b"ac");
+        assert_eq!(client.take_screen_update().bytes, b"\x1bcac");
+    }
+
+    #[test]
+    fn test_invalid_host_message_rejected() {
+        let mut client = MoshTerminalEndpoint::<HostTranscript>::from_key(KEY, None).unwrap();
+        let mut server = FakeServer::new();
+
+        let instr = Instruction::new_send(0, 1, 0, 0, vec![0x0a, 0x05, 0x12]);
+        let mut packets = Vec::new();
+        server
+            .codec
+            .encode(&instr.encode_to_bytes(), &mut server.ssp, 0, &mut packets)
+            .unwrap();
+        assert!(matches!(
+            client.recv_udp_packet(&packets[0], 1000),
+            Err(EndpointError::StateDiff(_))
+        ));
+        assert_eq!(client.stats().ssp.recv_num, 0);
+    }
+
+    #[test]
+    fn test_transcript_keeps_tail() {
+        let mut state = HostTranscript::default();
+        let big = HostMessage::default().with_host_bytes(&vec![b'x'; HOST_TRANSCRIPT_MAX_BYTES]);
+        state.apply_diff(&big.encode_to_bytes()).unwrap();
+        let displayed = state.clone();
+        state
+            .apply_diff(&HostMessage::default().with_host_bytes(b"yz").encode_to_bytes())
+            .unwrap();
+
+        assert_eq!(state.bytes().len(), HOST_TRANSCRIPT_MAX_BYTES);
+        assert_eq!(state.total_bytes(), HOST_TRANSCRIPT_MAX_BYTES as u64 + 2);
+        assert_eq!(state.repaint_from(Some(&displayed)), b"yz");
+    }
+
+    #[test]
+    fn test_screen_repaints_minimal_diff() {
+        let mut client = MoshTerminalEndpoint::<TerminalScreen>::from_key(KEY, None).unwrap();
+        let mut server = FakeServer::new();
+
+        let first = HostMessage::default().with_resize(20, 5).with_host_bytes(b"$ ");
+        deliver(&mut client, &server.send(0, 1, 0, first));
+        let update = client.take_screen_update();
+        assert_eq!(update.resize, Some((20, 5)));
+        // 最初は画面を消去して全体を描く
+        let clear = b"\x1b[r\x1b[0m\x1b[H\x1b[2J";
+        assert!(update.bytes.windows(clear.len()).any(|w| w == clear));
+        assert_eq!(client.screen().framebuffer().row(0).text(), "$");
+
+        deliver(&mut client, &server.send(1, 2, 0, HostMessage::default().with_host_bytes(b"ab")));
+        assert_eq!(client.take_screen_update().bytes, b"ab");
+
+        // 2 の ACK が届く前に、サーバーが 1 を起点に 3 を作った: 画面同士の差分だけを描く
+        deliver(&mut client, &server.send(1, 3, 1, HostMessage::default().with_host_bytes(b"ac")));
+        assert_eq!(client.screen().framebuffer().row(0).text(), "$ ac");
+        assert_eq!(client.take_screen_update().bytes, b"\x1b[?25l\x08c\x1b[?25h");
+    }
+
+    #[test]
+    fn test_keystroke_echo_predicted() {
+        let mut client = MoshTerminalEndpoint::<TerminalScreen>::from_key(KEY, None)
+            .unwrap()
+            .with_prediction_mode(PredictionMode::Always);
+        let mut server = FakeServer::new();
+        // キー入力を Instruction にして送る（サーバーへは届けない）
+        let mut now_ms = 1000;
+        let mut type_keys = |client: &mut MoshTerminalEndpoint, keys: &[u8]| {
+            now_ms += 1000;
+            client.keystroke(keys);
+            client.tick(now_ms).unwrap();
+            client.tick(now_ms + SEND_MINDELAY_MS).unwrap();
+        };
+
+        let first = HostMessage::default().with_resize(20, 5).with_host_bytes(b"$ ");
+        deliver(&mut client, &server.send(0, 1, 0, first));
+        client.take_screen_update();
+
+        // 最初の予測は仮なので、エコー（状態 1）が届くまで表示しない
+        type_keys(&mut client, b"a");
+        assert!(client.take_screen_update().bytes.is_empty());
+        let echo = HostMessage::default().with_host_bytes(b"a").with_echo_ack(1);
+        deliver(&mut client, &server.send(1, 2, 0, echo));
+        assert_eq!(client.take_screen_update().bytes, b"a");
+
+        // 以降は送信前から画面に重なり、同じエコーが届いても描き直さない
+        client.keystroke(b"b");
+        assert_eq!(client.take_screen_update().bytes, b"b");
+        type_keys(&mut client, b"");
+        let echo = HostMessage::default().with_host_bytes(b"b").with_echo_ack(2);
+        deliver(&mut client, &server.send(2, 3, 0, echo));
+        assert!(!client.prediction().is_active());
+        assert!(client.take_screen_update().bytes.is_empty());
+    }
+}
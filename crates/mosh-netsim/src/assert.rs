@@ -0,0 +1,70 @@
+//! バイト列のアサーション
+//!
+//! 大きな転送でも `assert_eq!` のように全体をダンプせず、
+//! 長さと最初に食い違ったオフセットだけを報告する。
+
+/// 2 つのバイト列が最初に食い違うオフセット（一方が他方の接頭辞なら短い方の長さ）
+///
+/// 完全に一致すれば `None`。
+pub fn first_mismatch(a: &[u8], b: &[u8]) -> Option<usize> {
+    match a.iter().zip(b).position(|(x, y)| x != y) {
+        Some(offset) => Some(offset),
+        None if a.len() == b.len() => None,
+        None => Some(a.len().min(b.len())),
+    }
+}
+
+/// `actual` が `expected` とバイト単位で一致することを確かめる
+#[track_caller]
+pub fn assert_bytes_eq(label: &str, expected: &[u8], actual: &[u8]) {
+    if let Some(offset) = first_mismatch(expected, actual) {
+        panic!(
+            "{}: 期待 {} バイト、実際 {} バイト、オフセット {} で不一致（期待 {:?}、実際 {:?}）",
+            label,
+            expected.len(),
+            actual.len(),
+            offset,
+            expected.get(offset),
+            actual.get(offset),
+        );
+    }
+}
+
+/// `actual` が `expected` の接頭辞であることを確かめる（転送途中の破損・余分なデータの検出）
+#[track_caller]
+pub fn assert_prefix(label: &str, expected: &[u8], actual: &[u8]) {
+    if actual.len() > expected.len() {
+        panic!(
+            "{}: 送った {} バイトより多い {} バイトを受信した",
+            label,
+            expected.len(),
+            actual.len(),
+        );
+    }
+    assert_bytes_eq(label, &expected[..actual.len()], actual);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_first_mismatch() {
+        assert_eq!(first_mismatch(b"abc", b"abc"), None);
+        assert_eq!(first_mismatch(b"abc", b"abd"), Some(2));
+        assert_eq!(first_mismatch(b"abc", b"ab"), Some(2));
+        assert_eq!(first_mismatch(b"", b"x"), Some(0));
+    }
+
+    #[test]
+    #[should_panic(expected = "オフセット 1 で不一致")]
+    fn test_assert_bytes_eq_reports_offset() {
+        assert_bytes_eq("test", b"hello", b"hallo");
+    }
+
+    #[test]
+    fn test_assert_prefix() {
+        assert_prefix("test", b"hello", b"hel");
+        assert_prefix("test", b"hello", b"");
+    }
+}
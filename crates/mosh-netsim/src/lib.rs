@@ -0,0 +1,56 @@
+//! # mosh-netsim
+//!
+//! 決定的なネットワークシミュレーター（エンドツーエンドテスト用）
+//!
+//! クライアントとサーバーの `MoshEndpoint`（暗号化 + Fragment + SSP + ストリーム）を
+//! 仮想時計と障害モデル付きのリンクでつなぐ。障害はシード付き乱数で決まるので、
+//! モバイル回線で起きた不具合を `cargo test` で何度でも同じように再現できる。
+//!
+//! ## 障害モデル
+//!
+//! | 項目 | [`Impairment`] のフィールド |
+//! |------|------|
+//! | ランダム損失 | `loss` |
+//! | バースト損失（Gilbert-Elliott） | `burst` |
+//! | 遅延・揺らぎ | `latency_ms`, `jitter_ms` |
+//! | 順序入れ替え | `reorder`, `reorder_delay_ms` |
+//! | 複製 | `duplicate` |
+//! | 経路 MTU | `mtu` |
+//! | 停電期間 | `blackouts` |
+//!
+//! ## 使用例
+//!
+//! ```
+//! use mosh_netsim::{Blackout, Impairment, NetSim, SimRng};
+//!
+//! let data = SimRng::new(42).bytes(50_000);
+//! let mut sim = NetSim::new(42).with_impairment(Impairment {
+//!     loss: 0.05,
+//!     latency_ms: 60,
+//!     jitter_ms: 20,
+//!     blackouts: vec![Blackout::new(500, 2_000)],
+//!     ..Default::default()
+//! });
+//! sim.client_write(&data);
+//! sim.assert_delivered_within(60_000);
+//! ```
+
+#![no_std]
+extern crate alloc;
+
+pub mod assert;
+pub mod link;
+pub mod rng;
+pub mod sim;
+
+pub use assert::{assert_bytes_eq, assert_prefix, first_mismatch};
+pub use link::{Blackout, BurstLoss, Impairment, Link, LinkStats};
+pub use rng::SimRng;
+pub use sim::{NetSim, PeerIo};
+
+/// [`NetSim::new`] が両エンドポイントに使う鍵
+pub const SIM_KEY: [u8; 16] = [0x42; 16];
+
+/// 同じ時刻でイベント処理を繰り返す上限
+/// 受信 → tick → 遅延 0 の配送 … と連鎖しても、この回数で打ち切って時刻を 1 ms 進める
+pub const MAX_ROUNDS_PER_STEP: usize = 64;
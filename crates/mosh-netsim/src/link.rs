@@ -0,0 +1,319 @@
+//! 片方向の仮想リンク
+//!
+//! UDP ペイロードを受け取り、[`Impairment`] に従って捨てる・複製する・遅らせてから届ける。
+//! 障害の判定は送信時に行う。
+//!
+//! ```text
+//! send(packet, now)
+//!   ├── 停電期間中 ─────────────────→ 破棄（dropped_blackout）
+//!   ├── MTU 超過 ──────────────────→ 破棄（dropped_mtu）
+//!   ├── バースト損失（Bad 状態）──→ 破棄（dropped_burst）
+//!   ├── ランダム損失 ──────────────→ 破棄（dropped_loss）
+//!   └── 配送キューへ: now + latency + jitter (+ reorder_delay)
+//!         └── 複製（duplicate）なら独立した遅延でもう 1 つ
+//! ```
+
+use alloc::collections::BinaryHeap;
+use alloc::vec::Vec;
+use core::cmp::Reverse;
+
+use crate::rng::SimRng;
+
+/// リンクの障害モデル
+///
+/// デフォルトは障害のない理想的なリンク（遅延 0）。必要な項目だけを設定して使う。
+///
+/// ```
+/// use mosh_netsim::{BurstLoss, Impairment};
+///
+/// let mobile = Impairment {
+///     latency_ms: 80,
+///     jitter_ms: 40,
+///     burst: Some(BurstLoss { enter_bad: 0.02, exit_bad: 0.3, bad_loss: 0.8 }),
+///     ..Default::default()
+/// };
+/// assert_eq!(mobile.loss, 0.0);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Impairment {
+    /// パケットごとの独立な損失確率（0.0〜1.0）
+    pub loss: f64,
+    /// バースト損失（Gilbert-Elliott モデル）。`None` なら使わない
+    pub burst: Option<BurstLoss>,
+    /// 基本の片道遅延（ミリ秒）
+    pub latency_ms: u64,
+    /// 遅延の揺らぎ（ミリ秒）。0〜`jitter_ms` の一様乱数を遅延に加える
+    pub jitter_ms: u64,
+    /// パケットを追加で遅らせ、後続に追い越させる確率（0.0〜1.0）
+    pub reorder: f64,
+    /// 順序入れ替え対象のパケットに加える遅延（ミリ秒）
+    pub reorder_delay_ms: u64,
+    /// パケットを複製して 2 回届ける確率（0.0〜1.0）
+    pub duplicate: f64,
+    /// 経路 MTU（バイト）。これを超える UDP ペイロードは黙って捨てる（PMTU ブラックホール）
+    pub mtu: Option<usize>,
+    /// 停電期間（すべてのパケットを捨てる時間帯）
+    pub blackouts: Vec<Blackout>,
+}
+
+/// Gilbert-Elliott モデルによるバースト損失
+///
+/// リンクは Good と Bad の 2 状態を持ち、パケットごとに状態遷移してから、
+/// Bad 状態なら `bad_loss` の確率で捨てる（Good 状態の損失は [`Impairment::loss`]）。
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BurstLoss {
+    /// Good → Bad に遷移する確率
+    pub enter_bad: f64,
+    /// Bad → Good に戻る確率
+    pub exit_bad: f64,
+    /// Bad 状態での損失確率
+    pub bad_loss: f64,
+}
+
+/// 停電期間（シミュレーション時刻、ミリ秒）
+///
+/// `start_ms <= t < end_ms` に送られたパケットはすべて失われる。
+/// Wi-Fi からモバイル回線への切り替えやトンネル内の圏外を再現する。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Blackout {
+    /// 開始時刻（この時刻を含む）
+    pub start_ms: u64,
+    /// 終了時刻（この時刻を含まない）
+    pub end_ms: u64,
+}
+
+impl Blackout {
+    /// `start_ms` から `duration_ms` だけ続く停電期間
+    pub fn new(start_ms: u64, duration_ms: u64) -> Self {
+        Blackout {
+            start_ms,
+            end_ms: start_ms.saturating_add(duration_ms),
+        }
+    }
+
+    /// `now_ms` が停電期間に含まれるか
+    pub fn contains(&self, now_ms: u64) -> bool {
+        (self.start_ms..self.end_ms).contains(&now_ms)
+    }
+}
+
+/// リンクの統計情報
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LinkStats {
+    /// リンクに渡されたパケット数
+    pub sent: u64,
+    /// 相手に届けたパケット数（複製を含む）
+    pub delivered: u64,
+    /// ランダム損失で捨てたパケット数
+    pub dropped_loss: u64,
+    /// バースト損失で捨てたパケット数
+    pub dropped_burst: u64,
+    /// MTU 超過で捨てたパケット数
+    pub dropped_mtu: u64,
+    /// 停電期間中に捨てたパケット数
+    pub dropped_blackout: u64,
+    /// 複製したパケット数
+    pub duplicated: u64,
+    /// 順序入れ替えのために追加で遅らせたパケット数
+    pub reordered: u64,
+}
+
+impl LinkStats {
+    /// 捨てたパケットの合計
+    pub fn dropped(&self) -> u64 {
+        self.dropped_loss + self.dropped_burst + self.dropped_mtu + self.dropped_blackout
+    }
+}
+
+/// 片方向の仮想リンク
+#[derive(Debug, Clone)]
+pub struct Link {
+    /// 障害モデル
+    impairment: Impairment,
+    /// 障害の判定に使う乱数
+    rng: SimRng,
+    /// Gilbert-Elliott モデルの状態（`true` なら Bad）
+    bad_state: bool,
+    /// 配送待ちのパケット（配送時刻, 送信順, ペイロード）
+    in_flight: BinaryHeap<Reverse<(u64, u64, Vec<u8>)>>,
+    /// 送信順の通し番号（同じ配送時刻のパケットは送信順に届ける）
+    next_seq: u64,
+    /// 統計情報
+    stats: LinkStats,
+}
+
+impl Link {
+    /// 障害モデルと乱数からリンクを生成する
+    pub fn new(impairment: Impairment, rng: SimRng) -> Self {
+        Link {
+            impairment,
+            rng,
+            bad_state: false,
+            in_flight: BinaryHeap::new(),
+            next_seq: 0,
+            stats: LinkStats::default(),
+        }
+    }
+
+    /// 障害モデル
+    pub fn impairment(&self) -> &Impairment {
+        &self.impairment
+    }
+
+    /// 障害モデルを差し替える（配送待ちのパケットには影響しない）
+    pub fn set_impairment(&mut self, impairment: Impairment) {
+        self.impairment = impairment;
+    }
+
+    /// パケットをリンクに送り出す
+    pub fn send(&mut self, packet: Vec<u8>, now_ms: u64) {
+        self.stats.sent += 1;
+
+        if self.impairment.blackouts.iter().any(|b| b.contains(now_ms)) {
+            self.stats.dropped_blackout += 1;
+            return;
+        }
+        if self.impairment.mtu.is_some_and(|mtu| packet.len() > mtu) {
+            self.stats.dropped_mtu += 1;
+            return;
+        }
+        if let Some(burst) = self.impairment.burst {
+            let flip = if self.bad_state { burst.exit_bad } else { burst.enter_bad };
+            if self.rng.chance(flip) {
+                self.bad_state = !self.bad_state;
+            }
+            if self.bad_state && self.rng.chance(burst.bad_loss) {
+                self.stats.dropped_burst += 1;
+                return;
+            }
+        }
+        if self.rng.chance(self.impairment.loss) {
+            self.stats.dropped_loss += 1;
+            return;
+        }
+
+        if self.rng.chance(self.impairment.duplicate) {
+            self.stats.duplicated += 1;
+            let deliver_at = now_ms + self.delay_ms();
+            self.enqueue(packet.clone(), deliver_at);
+        }
+        let deliver_at = now_ms + self.delay_ms();
+        self.enqueue(packet, deliver_at);
+    }
+
+    /// `now_ms` までに届くパケットを配送時刻順に取り出す
+    pub fn poll(&mut self, now_ms: u64) -> Vec<Vec<u8>> {
+        let mut delivered = Vec::new();
+        while let Some(Reverse((deliver_at, _, _))) = self.in_flight.peek() {
+            if *deliver_at > now_ms {
+                break;
+            }
+            let Reverse((_, _, packet)) = self.in_flight.pop().unwrap();
+            delivered.push(packet);
+        }
+        self.stats.delivered += delivered.len() as u64;
+        delivered
+    }
+
+    /// 次のパケットが届く時刻。配送待ちがなければ `None`
+    pub fn next_delivery_ms(&self) -> Option<u64> {
+        self.in_flight.peek().map(|Reverse((deliver_at, _, _))| *deliver_at)
+    }
+
+    /// 配送待ちのパケット数
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.len()
+    }
+
+    /// 統計情報
+    pub fn stats(&self) -> LinkStats {
+        self.stats
+    }
+
+    /// 1 パケット分の片道遅延（基本遅延 + 揺らぎ + 順序入れ替え）
+    fn delay_ms(&mut self) -> u64 {
+        let mut delay = self.impairment.latency_ms + self.rng.up_to(self.impairment.jitter_ms);
+        if self.rng.chance(self.impairment.reorder) {
+            self.stats.reordered += 1;
+            delay += self.impairment.reorder_delay_ms;
+        }
+        delay
+    }
+
+    fn enqueue(&mut self, packet: Vec<u8>, deliver_at: u64) {
+        self.in_flight.push(Reverse((deliver_at, self.next_seq, packet)));
+        self.next_seq += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    fn link(impairment: Impairment) -> Link {
+        Link::new(impairment, SimRng::new(1))
+    }
+
+    #[test]
+    fn test_perfect_link_delivers_in_order() {
+        let mut l = link(Impairment { latency_ms: 30, ..Default::default() });
+        l.send(vec![1], 0);
+        l.send(vec![2], 5);
+        assert!(l.poll(29).is_empty());
+        assert_eq!(l.next_delivery_ms(), Some(30));
+        assert_eq!(l.poll(35), vec![vec![1], vec![2]]);
+        assert_eq!(l.stats().delivered, 2);
+    }
+
+    #[test]
+    fn test_mtu_and_blackout_drop() {
+        let mut l = link(Impairment {
+            mtu: Some(100),
+            blackouts: vec![Blackout::new(1000, 500)],
+            ..Default::default()
+        });
+        l.send(vec![0; 101], 0);
+        l.send(vec![0; 100], 0);
+        l.send(vec![0; 10], 1000);
+        l.send(vec![0; 10], 1499);
+        l.send(vec![0; 10], 1500);
+        assert_eq!(l.poll(2000).len(), 2);
+        let stats = l.stats();
+        assert_eq!(stats.dropped_mtu, 1);
+        assert_eq!(stats.dropped_blackout, 2);
+    }
+
+    #[test]
+    fn test_reorder_lets_later_packets_overtake() {
+        let mut l = link(Impairment {
+            latency_ms: 10,
+            reorder: 1.0,
+            reorder_delay_ms: 50,
+            ..Default::default()
+        });
+        l.send(vec![1], 0);
+        l.set_impairment(Impairment { latency_ms: 10, ..Default::default() });
+        l.send(vec![2], 1);
+        assert_eq!(l.poll(100), vec![vec![2], vec![1]]);
+        assert_eq!(l.stats().reordered, 1);
+    }
+
+    #[test]
+    fn test_duplicate_and_burst_loss() {
+        let mut l = link(Impairment { duplicate: 1.0, ..Default::default() });
+        l.send(vec![7], 0);
+        assert_eq!(l.poll(0), vec![vec![7], vec![7]]);
+
+        // Bad 状態に入ったら抜けずにすべて失う
+        let mut l = link(Impairment {
+            burst: Some(BurstLoss { enter_bad: 1.0, exit_bad: 0.0, bad_loss: 1.0 }),
+            ..Default::default()
+        });
+        for i in 0..10 {
+            l.send(vec![i], 0);
+        }
+        assert!(l.poll(0).is_empty());
+        assert_eq!(l.stats().dropped_burst, 10);
+    }
+}
@@ -0,0 +1,419 @@
+//! 2 つのエンドポイントをつなぐシミュレーター
+//!
+//! クライアントとサーバーの [`MoshEndpoint`](mosh_endpoint::MoshEndpoint) を
+//! 上り・下りの [`Link`] でつなぎ、共有の [`ManualClock`] をイベント駆動で進める。
+//!
+//! ```text
+//!             uplink (client → server)
+//!   client ──────────────[Link]──────────────→ server
+//!          ←─────────────[Link]───────────────
+//!             downlink (server → client)
+//!
+//! step():
+//!   1. 書き込み待ちのデータを送信バッファの空きだけエンドポイントに渡す
+//!   2. 配送時刻が来たパケットを受信させる
+//!   3. 期限が来たエンドポイントを tick し、出てきたパケットをリンクに流す
+//!   4. 次のイベント（tick の期限・パケットの配送）の時刻まで時計を進める
+//! ```
+
+use alloc::vec::Vec;
+
+use mosh_endpoint::{
+    ClientRole, ClockedEndpoint, EndpointRole, MoshClientEndpoint, MoshEndpoint, MoshServer, ServerRole,
+};
+use mosh_ssp::clock::{Clock, ManualClock};
+
+use crate::assert::{assert_bytes_eq, assert_prefix};
+use crate::link::{Impairment, Link, LinkStats};
+use crate::rng::SimRng;
+use crate::{MAX_ROUNDS_PER_STEP, SIM_KEY};
+
+/// 片側のエンドポイントに出入りしたデータの記録
+#[derive(Debug, Clone, Default)]
+pub struct PeerIo {
+    /// 書き込みを頼まれたがまだエンドポイントの送信バッファに入っていないデータ
+    outbox: Vec<u8>,
+    /// 書き込みを頼まれたデータの全体
+    written: Vec<u8>,
+    /// 受信したデータの全体
+    received: Vec<u8>,
+    /// 受信処理でエラーになったパケット数（複製パケットのリプレイ拒否など）
+    recv_errors: u64,
+}
+
+impl PeerIo {
+    /// 書き込みを頼まれたデータの全体
+    pub fn written(&self) -> &[u8] {
+        &self.written
+    }
+
+    /// 受信したデータの全体
+    pub fn received(&self) -> &[u8] {
+        &self.received
+    }
+
+    /// まだ送信バッファに入っていないバイト数
+    pub fn unwritten_bytes(&self) -> usize {
+        self.outbox.len()
+    }
+
+    /// 受信処理でエラーになったパケット数
+    pub fn recv_errors(&self) -> u64 {
+        self.recv_errors
+    }
+}
+
+/// 決定的なネットワークシミュレーター
+///
+/// 同じシード・同じ障害モデル・同じ操作列からは、常に同じ結果が得られる。
+///
+/// ```
+/// use mosh_netsim::{Impairment, NetSim};
+///
+/// let mut sim = NetSim::new(1).with_impairment(Impairment {
+///     loss: 0.1,
+///     latency_ms: 50,
+///     ..Default::default()
+/// });
+/// sim.client_write(b"hello, server");
+/// sim.server_write(b"hello, client");
+/// sim.assert_delivered_within(10_000);
+/// ```
+pub struct NetSim {
+    /// 両エンドポイントが共有する仮想時計
+    clock: ManualClock,
+    /// クライアント側エンドポイント
+    client: ClockedEndpoint<ClientRole, ManualClock>,
+    /// サーバー側エンドポイント
+    server: ClockedEndpoint<ServerRole, ManualClock>,
+    /// クライアント → サーバーのリンク
+    uplink: Link,
+    /// サーバー → クライアントのリンク
+    downlink: Link,
+    /// クライアント側の入出力
+    client_io: PeerIo,
+    /// サーバー側の入出力
+    server_io: PeerIo,
+}
+
+impl NetSim {
+    /// デフォルト設定のエンドポイント（鍵は [`SIM_KEY`]）を障害のないリンクでつなぐ
+    pub fn new(seed: u64) -> Self {
+        let client = MoshClientEndpoint::from_key(SIM_KEY, None).unwrap();
+        let server = MoshServer::from_key(SIM_KEY, None).unwrap();
+        Self::with_endpoints(seed, client, server)
+    }
+
+    /// 設定済みのエンドポイント（MTU・ペーシング・輻輳制御など）を障害のないリンクでつなぐ
+    ///
+    /// 両者は同じ鍵で初期化されている必要がある。
+    pub fn with_endpoints(seed: u64, client: MoshClientEndpoint, server: MoshServer) -> Self {
+        let clock = ManualClock::new(0);
+        let mut rng = SimRng::new(seed);
+        NetSim {
+            client: client.with_clock(clock.clone()),
+            server: server.with_clock(clock.clone()),
+            clock,
+            uplink: Link::new(Impairment::default(), rng.fork()),
+            downlink: Link::new(Impairment::default(), rng.fork()),
+            client_io: PeerIo::default(),
+            server_io: PeerIo::default(),
+        }
+    }
+
+    /// 上りと下りに同じ障害モデルを設定する（ビルダー）
+    ///
+    /// 乱数は方向ごとに独立している。
+    pub fn with_impairment(self, impairment: Impairment) -> Self {
+        self.with_uplink(impairment.clone()).with_downlink(impairment)
+    }
+
+    /// 上り（クライアント → サーバー）の障害モデルを設定する（ビルダー）
+    pub fn with_uplink(mut self, impairment: Impairment) -> Self {
+        self.uplink.set_impairment(impairment);
+        self
+    }
+
+    /// 下り（サーバー → クライアント）の障害モデルを設定する（ビルダー）
+    pub fn with_downlink(mut self, impairment: Impairment) -> Self {
+        self.downlink.set_impairment(impairment);
+        self
+    }
+
+    /// 現在のシミュレーション時刻（ミリ秒、0 から始まる）
+    pub fn now_ms(&self) -> u64 {
+        self.clock.now_ms()
+    }
+
+    /// クライアントからサーバーへ送るデータを書き込む
+    ///
+    /// 送信バッファが満杯なら、空きができるまでシミュレーター側で保持する。
+    pub fn client_write(&mut self, data: &[u8]) {
+        self.client_io.outbox.extend_from_slice(data);
+        self.client_io.written.extend_from_slice(data);
+    }
+
+    /// サーバーからクライアントへ送るデータを書き込む
+    pub fn server_write(&mut self, data: &[u8]) {
+        self.server_io.outbox.extend_from_slice(data);
+        self.server_io.written.extend_from_slice(data);
+    }
+
+    /// 次のイベントまで時刻を進める
+    pub fn step(&mut self) {
+        self.advance(u64::MAX);
+    }
+
+    /// `duration_ms` だけ時刻を進める
+    pub fn run_for(&mut self, duration_ms: u64) {
+        let end_ms = self.now_ms() + duration_ms;
+        while self.now_ms() < end_ms {
+            self.advance(end_ms);
+        }
+    }
+
+    /// `done` が `true` を返すまで、最大 `timeout_ms` だけ時刻を進める
+    ///
+    /// # 戻り値
+    /// 期限内に `done` が成り立てば `true`
+    pub fn run_until(&mut self, timeout_ms: u64, mut done: impl FnMut(&NetSim) -> bool) -> bool {
+        let end_ms = self.now_ms() + timeout_ms;
+        loop {
+            if done(self) {
+                return true;
+            }
+            if self.now_ms() >= end_ms {
+                return false;
+            }
+            self.advance(end_ms);
+        }
+    }
+
+    /// 書き込んだデータが両方向とも届き切るまで、最大 `timeout_ms` だけ時刻を進める
+    pub fn run_until_delivered(&mut self, timeout_ms: u64) -> bool {
+        self.run_until(timeout_ms, NetSim::is_delivered)
+    }
+
+    /// 書き込んだデータが両方向とも届き切っているか
+    pub fn is_delivered(&self) -> bool {
+        self.server_io.received == self.client_io.written
+            && self.client_io.received == self.server_io.written
+    }
+
+    /// 両方向の受信データがバイト単位で送信データと一致することを確かめる
+    #[track_caller]
+    pub fn assert_delivered(&self) {
+        assert_bytes_eq("client → server", &self.client_io.written, &self.server_io.received);
+        assert_bytes_eq("server → client", &self.server_io.written, &self.client_io.received);
+    }
+
+    /// 両方向の受信データが送信データの接頭辞であること（破損・重複がないこと）を確かめる
+    #[track_caller]
+    pub fn assert_consistent(&self) {
+        assert_prefix("client → server", &self.client_io.written, &self.server_io.received);
+        assert_prefix("server → client", &self.server_io.written, &self.client_io.received);
+    }
+
+    /// 最大 `timeout_ms` だけ時刻を進め、両方向のデータがバイト単位で届き切ることを確かめる
+    #[track_caller]
+    pub fn assert_delivered_within(&mut self, timeout_ms: u64) {
+        let delivered = self.run_until_delivered(timeout_ms);
+        self.assert_consistent();
+        if !delivered {
+            panic!(
+                "{} ms 以内に届かなかった: client → server {}/{} バイト、server → client {}/{} バイト\n\
+                 uplink: {:?}\ndownlink: {:?}",
+                timeout_ms,
+                self.server_io.received.len(),
+                self.client_io.written.len(),
+                self.client_io.received.len(),
+                self.server_io.written.len(),
+                self.uplink.stats(),
+                self.downlink.stats(),
+            );
+        }
+    }
+
+    /// クライアント側エンドポイント
+    pub fn client(&self) -> &ClockedEndpoint<ClientRole, ManualClock> {
+        &self.client
+    }
+
+    /// クライアント側エンドポイント（可変）
+    pub fn client_mut(&mut self) -> &mut ClockedEndpoint<ClientRole, ManualClock> {
+        &mut self.client
+    }
+
+    /// サーバー側エンドポイント
+    pub fn server(&self) -> &ClockedEndpoint<ServerRole, ManualClock> {
+        &self.server
+    }
+
+    /// サーバー側エンドポイント（可変）
+    pub fn server_mut(&mut self) -> &mut ClockedEndpoint<ServerRole, ManualClock> {
+        &mut self.server
+    }
+
+    /// クライアント側の入出力
+    pub fn client_io(&self) -> &PeerIo {
+        &self.client_io
+    }
+
+    /// サーバー側の入出力
+    pub fn server_io(&self) -> &PeerIo {
+        &self.server_io
+    }
+
+    /// 上りリンク（途中で障害モデルを変えるのに使う）
+    pub fn uplink_mut(&mut self) -> &mut Link {
+        &mut self.uplink
+    }
+
+    /// 下りリンク（途中で障害モデルを変えるのに使う）
+    pub fn downlink_mut(&mut self) -> &mut Link {
+        &mut self.downlink
+    }
+
+    /// 上りリンクの統計情報
+    pub fn uplink_stats(&self) -> LinkStats {
+        self.uplink.stats()
+    }
+
+    /// 下りリンクの統計情報
+    pub fn downlink_stats(&self) -> LinkStats {
+        self.downlink.stats()
+    }
+
+    /// 現在時刻のイベントを処理し、次のイベント（`limit_ms` を超えない）まで時刻を進める
+    fn advance(&mut self, limit_ms: u64) {
+        let now_ms = self.now_ms();
+        for _ in 0..MAX_ROUNDS_PER_STEP {
+            if !self.process(now_ms) {
+                break;
+            }
+        }
+        let next_ms = self.next_event_ms().max(now_ms + 1).min(limit_ms.max(now_ms + 1));
+        self.clock.set(next_ms);
+    }
+
+    /// 現在時刻に起きるべきことを 1 巡処理する。何か起きたら `true`
+    fn process(&mut self, now_ms: u64) -> bool {
+        let mut progressed = false;
+
+        progressed |= feed(&mut self.client_io, self.client.endpoint_mut());
+        progressed |= feed(&mut self.server_io, self.server.endpoint_mut());
+
+        for packet in self.uplink.poll(now_ms) {
+            progressed = true;
+            match self.server.recv_udp_packet(&packet) {
+                Ok(data) => self.server_io.received.extend_from_slice(&data),
+                Err(_) => self.server_io.recv_errors += 1,
+            }
+        }
+        for packet in self.downlink.poll(now_ms) {
+            progressed = true;
+            match self.client.recv_udp_packet(&packet) {
+                Ok(data) => self.client_io.received.extend_from_slice(&data),
+                Err(_) => self.client_io.recv_errors += 1,
+            }
+        }
+
+        if self.client.next_timeout_ms() == 0 {
+            progressed = true;
+            for packet in self.client.tick().expect("クライアントの tick に失敗") {
+                self.uplink.send(packet, now_ms);
+            }
+        }
+        if self.server.next_timeout_ms() == 0 {
+            progressed = true;
+            for packet in self.server.tick().expect("サーバーの tick に失敗") {
+                self.downlink.send(packet, now_ms);
+            }
+        }
+
+        progressed
+    }
+
+    /// 次のイベントの時刻
+    fn next_event_ms(&self) -> u64 {
+        let now_ms = self.now_ms();
+        [
+            Some(now_ms + self.client.next_timeout_ms()),
+            Some(now_ms + self.server.next_timeout_ms()),
+            self.uplink.next_delivery_ms(),
+            self.downlink.next_delivery_ms(),
+        ]
+        .into_iter()
+        .flatten()
+        .min()
+        .unwrap_or(now_ms)
+    }
+}
+
+/// 書き込み待ちのデータを送信バッファの空きだけエンドポイントに渡す。渡せたら `true`
+fn feed<R: EndpointRole>(io: &mut PeerIo, endpoint: &mut MoshEndpoint<R>) -> bool {
+    let n = io.outbox.len().min(endpoint.writable_bytes());
+    if n == 0 {
+        return false;
+    }
+    let written = endpoint.write(&io.outbox[..n]).unwrap_or(0);
+    io.outbox.drain(..written);
+    written > 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::link::Blackout;
+    use alloc::vec;
+
+    #[test]
+    fn test_perfect_network_delivers_both_ways() {
+        let mut sim = NetSim::new(0);
+        sim.client_write(b"keystrokes");
+        sim.server_write(b"terminal output");
+        sim.assert_delivered_within(1_000);
+        assert_eq!(sim.uplink_stats().dropped(), 0);
+    }
+
+    #[test]
+    fn test_same_seed_is_deterministic() {
+        let run = |seed| {
+            let mut sim = NetSim::new(seed).with_impairment(Impairment {
+                loss: 0.05,
+                latency_ms: 40,
+                jitter_ms: 30,
+                duplicate: 0.05,
+                ..Default::default()
+            });
+            sim.client_write(&SimRng::new(9).bytes(20_000));
+            sim.assert_delivered_within(60_000);
+            (sim.now_ms(), sim.uplink_stats(), sim.downlink_stats())
+        };
+        assert_eq!(run(3), run(3));
+        assert_ne!(run(3), run(4));
+    }
+
+    #[test]
+    fn test_blackout_recovers() {
+        let mut sim = NetSim::new(5).with_impairment(Impairment {
+            latency_ms: 20,
+            blackouts: vec![Blackout::new(100, 5_000)],
+            ..Default::default()
+        });
+        sim.run_for(100);
+        sim.client_write(b"sent into the void");
+        sim.run_for(4_000);
+        assert!(sim.server_io().received().is_empty());
+        sim.assert_delivered_within(10_000);
+        assert!(sim.uplink_stats().dropped_blackout > 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "以内に届かなかった")]
+    fn test_assert_delivered_within_reports_timeout() {
+        let mut sim = NetSim::new(0).with_uplink(Impairment { loss: 1.0, ..Default::default() });
+        sim.client_write(b"never arrives");
+        sim.assert_delivered_within(2_000);
+    }
+}
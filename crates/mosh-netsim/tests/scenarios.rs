@@ -0,0 +1,169 @@
+//! モバイル回線を想定したエンドツーエンドのシナリオテスト
+//!
+//! 暗号化 → Fragment → SSP → ストリームの完全なパイプラインを、
+//! 障害のある仮想ネットワーク越しに動かしてバイト単位の到達を確かめる。
+
+use mosh_endpoint::{MoshClientEndpoint, MoshServer};
+use mosh_netsim::{Blackout, BurstLoss, Impairment, NetSim, SimRng, SIM_KEY};
+use mosh_ssp::{CWND_INITIAL_SEGMENTS, CWND_SEGMENT_BYTES};
+
+/// LTE 程度の遅延と揺らぎ
+fn lte() -> Impairment {
+    Impairment {
+        latency_ms: 40,
+        jitter_ms: 30,
+        ..Default::default()
+    }
+}
+
+/// 双方向の大量転送がランダム損失と複製を越えて届く
+#[test]
+fn test_bulk_transfer_over_lossy_link() {
+    let mut rng = SimRng::new(100);
+    let upload = rng.bytes(200_000);
+    let download = rng.bytes(200_000);
+
+    let mut sim = NetSim::new(100).with_impairment(Impairment {
+        loss: 0.05,
+        duplicate: 0.02,
+        ..lte()
+    });
+    sim.client_write(&upload);
+    sim.server_write(&download);
+    sim.assert_delivered_within(120_000);
+
+    assert!(sim.uplink_stats().dropped_loss > 0);
+    assert!(sim.server_io().recv_errors() > 0, "複製パケットはリプレイとして拒否される");
+}
+
+/// バースト損失（Gilbert-Elliott）と順序入れ替えの下でも破損なく届く
+#[test]
+fn test_burst_loss_and_reorder() {
+    let data = SimRng::new(7).bytes(100_000);
+    let mut sim = NetSim::new(7).with_impairment(Impairment {
+        burst: Some(BurstLoss {
+            enter_bad: 0.02,
+            exit_bad: 0.3,
+            bad_loss: 0.8,
+        }),
+        reorder: 0.1,
+        reorder_delay_ms: 80,
+        ..lte()
+    });
+    sim.client_write(&data);
+    sim.assert_delivered_within(60_000);
+
+    let stats = sim.uplink_stats();
+    assert!(stats.dropped_burst > 0);
+    assert!(stats.reordered > 0);
+}
+
+/// 長い圏外（トンネル）の後、書き込んだデータがすべて届く
+#[test]
+fn test_long_blackout_then_resume() {
+    let mut sim = NetSim::new(11).with_impairment(Impairment {
+        blackouts: vec![Blackout::new(1_000, 20_000)],
+        ..lte()
+    });
+    sim.client_write(b"before");
+    sim.run_for(1_000);
+    assert_eq!(sim.server_io().received(), b"before");
+
+    // 圏外の間に書き込んだデータは届かない
+    sim.client_write(b" during");
+    sim.server_write(b"reply");
+    sim.run_for(15_000);
+    assert_eq!(sim.server_io().received(), b"before");
+    sim.assert_consistent();
+
+    sim.assert_delivered_within(30_000);
+}
+
+/// 経路 MTU がエンドポイントの MTU より小さいとすべて捨てられ、
+/// エンドポイントの MTU を合わせれば届く
+#[test]
+fn test_mtu_clamp() {
+    let clamp = Impairment {
+        mtu: Some(400),
+        ..lte()
+    };
+    let data = SimRng::new(3).bytes(5_000);
+
+    let mut sim = NetSim::new(3).with_impairment(clamp.clone());
+    sim.client_write(&data);
+    assert!(!sim.run_until_delivered(10_000));
+    assert!(sim.server_io().received().is_empty());
+    assert!(sim.uplink_stats().dropped_mtu > 0);
+
+    let client = MoshClientEndpoint::from_key(SIM_KEY, Some(400)).unwrap();
+    let server = MoshServer::from_key(SIM_KEY, Some(400)).unwrap();
+    let mut sim = NetSim::with_endpoints(3, client, server).with_impairment(clamp);
+    sim.client_write(&data);
+    sim.assert_delivered_within(10_000);
+    assert_eq!(sim.uplink_stats().dropped_mtu, 0);
+}
+
+/// 途中で回線品質が変わっても（Wi-Fi → モバイル）届く
+#[test]
+fn test_link_change_mid_transfer() {
+    let data = SimRng::new(21).bytes(100_000);
+    let mut sim = NetSim::new(21);
+    sim.client_write(&data);
+    sim.run_for(50);
+
+    let mobile = Impairment {
+        latency_ms: 150,
+        jitter_ms: 100,
+        loss: 0.03,
+        ..Default::default()
+    };
+    sim.uplink_mut().set_impairment(mobile.clone());
+    sim.downlink_mut().set_impairment(mobile);
+    sim.assert_delivered_within(60_000);
+}
+
+/// SRTT は遅延 ACK の保持時間を含まず、往復の伝搬遅延（2 × 片道遅延）に近づく
+#[test]
+fn test_srtt_tracks_path_latency() {
+    for latency_ms in [1, 10, 50] {
+        let mut sim = NetSim::new(latency_ms).with_impairment(Impairment {
+            latency_ms,
+            ..Default::default()
+        });
+        // 打鍵とエコーを 200ms ごとに繰り返す
+        for _ in 0..20 {
+            sim.client_write(b"k");
+            sim.run_for(latency_ms);
+            sim.server_write(b"k");
+            sim.run_for(200 - latency_ms);
+        }
+        sim.assert_delivered_within(1_000);
+
+        let expected = 2.0 * latency_ms as f64;
+        for srtt in [sim.client().stats().ssp.srtt_ms, sim.server().stats().ssp.srtt_ms] {
+            assert!(
+                (srtt - expected).abs() <= 2.0 + expected * 0.1,
+                "片道 {latency_ms}ms の SRTT が {srtt}ms（期待値 {expected}ms）"
+            );
+        }
+    }
+}
+
+/// 遅延の小さい回線では輻輳ウィンドウが初期値より広がり、大量転送がすぐに終わる
+#[test]
+fn test_bulk_transfer_grows_congestion_window() {
+    let data = SimRng::new(5).bytes(4 * 1024 * 1024);
+    let mut sim = NetSim::new(5).with_impairment(Impairment {
+        latency_ms: 1,
+        ..Default::default()
+    });
+    sim.client_write(&data);
+
+    let mut max_window = 0;
+    let delivered = sim.run_until(10_000, |sim| {
+        max_window = max_window.max(sim.client().stats().ssp.congestion.window_bytes);
+        sim.is_delivered()
+    });
+    assert!(delivered, "4MB が 10 秒以内に届かない（ウィンドウ最大 {max_window} バイト）");
+    assert!(max_window > CWND_INITIAL_SEGMENTS * CWND_SEGMENT_BYTES);
+}
@@ -0,0 +1,763 @@
+//! 予測エンジン
+//!
+//! mosh C++ 実装の `Overlay::PredictionEngine`（terminaloverlay.cc）に対応する。
+//!
+//! 予測はセルごとに 1 つと、カーソルに 1 つ持つ。それぞれに次の 2 つを記録する。
+//!
+//! - 状態番号: 予測のもとになった入力を運ぶクライアントの状態番号。サーバーの
+//!   エコー確認（`EchoAck`）がこの番号に達したら、画面と比べて成否を決める。
+//! - エポック: 効果を確信できない入力（制御文字・改行・折り返し）のたびに進める番号。
+//!   確定したエポック（画面と一致した予測のエポック）以下の予測だけを表示する。
+//!
+//! 行の途中に打った文字はシェルの行編集と同じく挿入（右側をずらす）と予測し、
+//! 後退は左側へ詰めると予測する。ずらして右端からはみ出したセルは内容不明として表示しない。
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+
+use mosh_terminal::{Cell, Framebuffer};
+use unicode_width::UnicodeWidthChar;
+
+use crate::input::{InputParser, Key};
+use crate::{
+    FLAG_TRIGGER_HIGH_MS, FLAG_TRIGGER_LOW_MS, GLITCH_FLAG_THRESHOLD_MS, GLITCH_REPAIR_COUNT,
+    GLITCH_REPAIR_MININTERVAL_MS, GLITCH_THRESHOLD_MS, SRTT_TRIGGER_HIGH_MS, SRTT_TRIGGER_LOW_MS,
+};
+
+/// 予測を表示するか（mosh の `--predict` オプションに対応）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PredictionMode {
+    /// 往復時間が長いとき・エコーが遅れたときだけ表示する（mosh の既定）
+    #[default]
+    Adaptive,
+    /// 常に表示する
+    Always,
+    /// 予測しない
+    Never,
+}
+
+impl PredictionMode {
+    /// 設定値としての名前（`"adaptive"` / `"always"` / `"never"`）
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PredictionMode::Adaptive => "adaptive",
+            PredictionMode::Always => "always",
+            PredictionMode::Never => "never",
+        }
+    }
+
+    /// 名前から変換する（大文字・小文字は区別しない）
+    pub fn from_name(name: &str) -> Option<Self> {
+        [
+            PredictionMode::Adaptive,
+            PredictionMode::Always,
+            PredictionMode::Never,
+        ]
+        .into_iter()
+        .find(|mode| mode.as_str().eq_ignore_ascii_case(name))
+    }
+}
+
+impl core::fmt::Display for PredictionMode {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// 予測の成否
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Validity {
+    /// エコーがまだ画面に反映されていない
+    Pending,
+    /// 画面と一致した
+    Correct,
+    /// 画面と一致したが、予測する前から同じ内容だった（エポックを確定しない）
+    CorrectNoCredit,
+    /// 画面と一致しない
+    Incorrect,
+}
+
+/// 予測の期限（どの入力のエコーで確かめるか）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Expiry {
+    /// 予測を作ったときのエポック
+    epoch: u64,
+    /// 入力を運ぶクライアントの状態番号（まだ送っていなければ `None`）
+    frame: Option<u64>,
+    /// 入力を送った時刻（ミリ秒）
+    sent_ms: Option<u64>,
+}
+
+impl Expiry {
+    fn new(epoch: u64) -> Self {
+        Expiry {
+            epoch,
+            frame: None,
+            sent_ms: None,
+        }
+    }
+
+    /// 入力を状態 `num` で送った
+    fn sent(&mut self, num: u64, now_ms: u64) {
+        if self.frame.is_none() {
+            self.frame = Some(num);
+            self.sent_ms = Some(now_ms);
+        }
+    }
+
+    /// 状態 `late_acked` までのエコーが画面に反映済みなら、確かめる時期
+    fn is_due(&self, late_acked: u64) -> bool {
+        self.frame.is_some_and(|frame| frame <= late_acked)
+    }
+
+    /// 入力を送ってからの経過時間（ミリ秒）
+    fn age_ms(&self, now_ms: u64) -> Option<u64> {
+        self.sent_ms.map(|sent| now_ms.saturating_sub(sent))
+    }
+}
+
+/// セルの予測
+#[derive(Debug, Clone)]
+struct PredictedCell {
+    /// 予測した内容（`None` は右端からはみ出して内容が分からない）
+    replacement: Option<Cell>,
+    /// 予測したときに画面にあった内容（画面がこのままなら成否を数えない）
+    original: Vec<Cell>,
+    expiry: Expiry,
+}
+
+impl PredictedCell {
+    fn validity(&self, fb: &Framebuffer, row: usize, col: usize, late_acked: u64) -> Validity {
+        if row >= fb.height() || col >= fb.width() {
+            return Validity::Incorrect;
+        }
+        if !self.expiry.is_due(late_acked) {
+            return Validity::Pending;
+        }
+        let Some(replacement) = &self.replacement else {
+            return Validity::CorrectNoCredit;
+        };
+        let current = fb.cell(row, col);
+        if !current.same_contents(replacement) {
+            Validity::Incorrect
+        } else if self.original.iter().any(|cell| cell.same_contents(current)) {
+            Validity::CorrectNoCredit
+        } else {
+            Validity::Correct
+        }
+    }
+}
+
+/// カーソル位置の予測
+#[derive(Debug, Clone)]
+struct PredictedCursor {
+    row: usize,
+    col: usize,
+    /// 最後の桁に文字を書いた直後（次の文字は次の行の先頭に書く）
+    pending_wrap: bool,
+    expiry: Expiry,
+}
+
+impl PredictedCursor {
+    fn validity(&self, fb: &Framebuffer, late_acked: u64) -> Validity {
+        if !self.expiry.is_due(late_acked) {
+            Validity::Pending
+        } else if fb.cursor() == (self.row, self.col) {
+            Validity::Correct
+        } else {
+            Validity::Incorrect
+        }
+    }
+}
+
+/// キー入力のエコーを予測して画面に重ねる
+///
+/// サーバーから受け取った画面は変更せず、[`apply`](Self::apply) で表示用の複製に重ねる。
+#[derive(Debug, Clone)]
+pub struct PredictionEngine {
+    mode: PredictionMode,
+    /// キー入力の分類（エスケープシーケンスの途中の状態を持つ）
+    input: InputParser,
+    /// セルの予測（行, 桁）
+    cells: BTreeMap<(usize, usize), PredictedCell>,
+    /// カーソル位置の予測
+    cursor: Option<PredictedCursor>,
+    /// これから作る予測のエポック
+    prediction_epoch: u64,
+    /// 画面と一致した予測のエポックの最大値（これ以下のエポックの予測を表示する）
+    confirmed_epoch: u64,
+    /// サーバーがエコーを画面に反映済みのクライアント状態番号
+    late_acked: u64,
+    /// 往復時間の平滑化推定値（ミリ秒）
+    srtt_ms: f64,
+    /// SRTT が長いので予測を表示する（Adaptive）
+    srtt_trigger: bool,
+    /// 予測に下線を引く
+    flagging: bool,
+    /// エコーの遅れの度合い（0 より大きければ予測を表示する）
+    glitch_trigger: u32,
+    /// 最後に速い確認を数えた時刻（ミリ秒）
+    last_quick_confirmation_ms: u64,
+    /// 予測を作った画面のサイズ（幅, 高さ）
+    size: (usize, usize),
+}
+
+impl Default for PredictionEngine {
+    fn default() -> Self {
+        Self::new(PredictionMode::default())
+    }
+}
+
+impl PredictionEngine {
+    /// 予測エンジンを生成する
+    ///
+    /// 最初の予測は仮（画面と一致するのを確かめてから表示する）。
+    pub fn new(mode: PredictionMode) -> Self {
+        PredictionEngine {
+            mode,
+            input: InputParser::new(),
+            cells: BTreeMap::new(),
+            cursor: None,
+            prediction_epoch: 1,
+            confirmed_epoch: 0,
+            late_acked: 0,
+            srtt_ms: 0.0,
+            srtt_trigger: false,
+            flagging: false,
+            glitch_trigger: 0,
+            last_quick_confirmation_ms: 0,
+            size: (0, 0),
+        }
+    }
+
+    /// 表示モード
+    pub fn mode(&self) -> PredictionMode {
+        self.mode
+    }
+
+    /// 表示モードを変更する（`Never` にしたら予測を捨てる）
+    pub fn set_mode(&mut self, mode: PredictionMode) {
+        self.mode = mode;
+        if mode == PredictionMode::Never {
+            self.reset();
+        }
+    }
+
+    /// 予測を表示する状態か
+    pub fn is_showing(&self) -> bool {
+        match self.mode {
+            PredictionMode::Adaptive => self.srtt_trigger || self.glitch_trigger > 0,
+            PredictionMode::Always => true,
+            PredictionMode::Never => false,
+        }
+    }
+
+    /// 予測に下線を引く状態か
+    pub fn is_flagging(&self) -> bool {
+        self.flagging
+    }
+
+    /// まだ確かめていない予測があるか
+    pub fn is_active(&self) -> bool {
+        !self.cells.is_empty() || self.cursor.is_some()
+    }
+
+    /// 予測をすべて捨てる（以降の予測は仮になる）
+    pub fn reset(&mut self) {
+        self.cells.clear();
+        self.cursor = None;
+        self.become_tentative();
+    }
+
+    /// キー入力 `bytes` のエコーを予測する
+    ///
+    /// `fb` はサーバーから受け取った最新の画面。予測は、入力を運ぶ状態番号が
+    /// [`set_local_frame_sent`](Self::set_local_frame_sent) で決まるまで確かめない。
+    pub fn new_user_input(&mut self, bytes: &[u8], fb: &Framebuffer) {
+        if self.mode == PredictionMode::Never {
+            return;
+        }
+        self.check_size(fb);
+
+        let mut input = core::mem::take(&mut self.input);
+        input.feed(bytes, |key| self.predict(key, fb));
+        self.input = input;
+    }
+
+    /// ここまでのキー入力をすべて状態 `num` 以前の状態で送った
+    pub fn set_local_frame_sent(&mut self, num: u64, now_ms: u64) {
+        for cell in self.cells.values_mut() {
+            cell.expiry.sent(num, now_ms);
+        }
+        if let Some(cursor) = &mut self.cursor {
+            cursor.expiry.sent(num, now_ms);
+        }
+    }
+
+    /// サーバーがエコーを画面に反映済みのクライアント状態番号（`EchoAck`）を設定する
+    pub fn set_local_frame_late_acked(&mut self, num: u64) {
+        self.late_acked = self.late_acked.max(num);
+    }
+
+    /// 往復時間の推定値（ミリ秒）を設定する
+    pub fn set_srtt(&mut self, srtt_ms: f64) {
+        self.srtt_ms = srtt_ms;
+    }
+
+    /// エコーが反映済みの予測を画面 `fb` と比べて、確定・取り消しする
+    ///
+    /// 画面を受け取るたびと定期的に呼ぶ（エコーの遅れは時刻 `now_ms` で判定する）。
+    pub fn cull(&mut self, fb: &Framebuffer, now_ms: u64) {
+        if self.mode == PredictionMode::Never {
+            return;
+        }
+        self.check_size(fb);
+
+        if self.srtt_ms > SRTT_TRIGGER_HIGH_MS {
+            self.srtt_trigger = true;
+        } else if self.srtt_trigger && self.srtt_ms <= SRTT_TRIGGER_LOW_MS && !self.is_active() {
+            self.srtt_trigger = false;
+        }
+        if self.srtt_ms > FLAG_TRIGGER_HIGH_MS {
+            self.flagging = true;
+        } else if self.srtt_ms <= FLAG_TRIGGER_LOW_MS {
+            self.flagging = false;
+        }
+        // 大きな遅れがあったときも下線を引く
+        if self.glitch_trigger > GLITCH_REPAIR_COUNT {
+            self.flagging = true;
+        }
+
+        let late_acked = self.late_acked;
+        let mut confirmed = self.confirmed_epoch;
+        let mut glitch = self.glitch_trigger;
+        let mut last_quick = self.last_quick_confirmation_ms;
+        let mut kill_epoch: Option<u64> = None;
+        let mut reset = false;
+        let mut incorrect = |epoch: u64, confirmed: u64| {
+            if epoch > confirmed {
+                // 仮の予測が外れた: そのエポック以降の予測を捨てる
+                kill_epoch = Some(kill_epoch.map_or(epoch, |kill| kill.min(epoch)));
+            } else {
+                reset = true;
+            }
+        };
+
+        self.cells.retain(|&(row, col), cell| {
+            match cell.validity(fb, row, col, late_acked) {
+                Validity::Pending => {
+                    // なかなか確かめられない予測があれば、SRTT が短くても表示する
+                    match cell.expiry.age_ms(now_ms) {
+                        Some(age) if age >= GLITCH_FLAG_THRESHOLD_MS => {
+                            glitch = GLITCH_REPAIR_COUNT * 2;
+                        }
+                        Some(age) if age >= GLITCH_THRESHOLD_MS => {
+                            glitch = glitch.max(GLITCH_REPAIR_COUNT);
+                        }
+                        _ => {}
+                    }
+                    true
+                }
+                Validity::Correct => {
+                    confirmed = confirmed.max(cell.expiry.epoch);
+                    // 速く確かめられたら、遅れの度合いを少しずつ戻す
+                    let quick = cell
+                        .expiry
+                        .age_ms(now_ms)
+                        .is_some_and(|age| age < GLITCH_THRESHOLD_MS);
+                    if quick
+                        && glitch > 0
+                        && now_ms.saturating_sub(last_quick) >= GLITCH_REPAIR_MININTERVAL_MS
+                    {
+                        glitch -= 1;
+                        last_quick = now_ms;
+                    }
+                    false
+                }
+                Validity::CorrectNoCredit => false,
+                Validity::Incorrect => {
+                    incorrect(cell.expiry.epoch, confirmed);
+                    true
+                }
+            }
+        });
+        if let Some(cursor) = &self.cursor {
+            match cursor.validity(fb, late_acked) {
+                Validity::Pending => {}
+                Validity::Correct | Validity::CorrectNoCredit => self.cursor = None,
+                Validity::Incorrect => incorrect(cursor.expiry.epoch, confirmed),
+            }
+        }
+
+        self.confirmed_epoch = confirmed;
+        self.glitch_trigger = glitch;
+        self.last_quick_confirmation_ms = last_quick;
+        if reset {
+            self.reset();
+        } else if let Some(epoch) = kill_epoch {
+            self.kill_epoch(epoch);
+        }
+    }
+
+    /// 表示してよい予測を `fb`（サーバーから受け取った画面の複製）に重ねる
+    pub fn apply(&self, fb: &mut Framebuffer) {
+        if !self.is_showing() {
+            return;
+        }
+        for (&(row, col), cell) in &self.cells {
+            if cell.expiry.epoch > self.confirmed_epoch {
+                continue;
+            }
+            let Some(replacement) = &cell.replacement else {
+                continue;
+            };
+            let mut replacement = replacement.clone();
+            if self.flagging && !replacement.is_blank() {
+                replacement.renditions.underline = true;
+            }
+            fb.put_cell(row, col, replacement);
+        }
+        if let Some(cursor) = &self.cursor {
+            if cursor.expiry.epoch <= self.confirmed_epoch {
+                fb.set_cursor(cursor.row, cursor.col);
+            }
+        }
+    }
+
+    /// 以降の予測を仮にする
+    fn become_tentative(&mut self) {
+        self.prediction_epoch += 1;
+    }
+
+    /// エポック `epoch` 以降の予測を捨てる
+    fn kill_epoch(&mut self, epoch: u64) {
+        self.cells.retain(|_, cell| cell.expiry.epoch < epoch);
+        if self
+            .cursor
+            .as_ref()
+            .is_some_and(|cursor| cursor.expiry.epoch >= epoch)
+        {
+            self.cursor = None;
+        }
+        self.become_tentative();
+    }
+
+    /// 画面のサイズが変わっていたら予測を捨てる
+    fn check_size(&mut self, fb: &Framebuffer) {
+        let size = (fb.width(), fb.height());
+        if self.size != size {
+            self.size = size;
+            self.reset();
+        }
+    }
+
+    /// キー入力 1 つの効果を予測する
+    fn predict(&mut self, key: Key, fb: &Framebuffer) {
+        let (width, height) = (fb.width(), fb.height());
+        let mut cursor = self.cursor.take().unwrap_or_else(|| {
+            let (row, col) = fb.cursor();
+            PredictedCursor {
+                row,
+                col,
+                pending_wrap: false,
+                expiry: Expiry::new(self.prediction_epoch),
+            }
+        });
+
+        match key {
+            Key::Char(ch) if ch.width() == Some(1) => {
+                if cursor.pending_wrap {
+                    self.become_tentative();
+                    // 画面のスクロールは予測しない
+                    if cursor.row + 1 < height {
+                        cursor.row += 1;
+                        cursor.col = 0;
+                        cursor.pending_wrap = false;
+                    }
+                }
+                if !cursor.pending_wrap {
+                    self.predict_char(fb, &mut cursor, ch);
+                }
+            }
+            Key::Backspace if !cursor.pending_wrap && cursor.col > 0 => {
+                cursor.col -= 1;
+                let (row, col) = (cursor.row, cursor.col);
+                let end = self.row_end(fb, row, col + 1).max(col + 1);
+                for i in col..end {
+                    let replacement = if i + 2 < width {
+                        self.current(fb, row, i + 1)
+                    } else {
+                        None
+                    };
+                    self.predict_cell(fb, row, i, replacement);
+                }
+            }
+            Key::Left if !cursor.pending_wrap && cursor.col > 0 => cursor.col -= 1,
+            // 行の内容の上でだけ右へ動く（行末より右へはシェルが動かさない）
+            Key::Right
+                if cursor.col + 1 < width
+                    && !self
+                        .current(fb, cursor.row, cursor.col)
+                        .is_some_and(|cell| cell.is_blank()) =>
+            {
+                cursor.col += 1;
+            }
+            Key::CarriageReturn => {
+                self.become_tentative();
+                cursor.row = (cursor.row + 1).min(height - 1);
+                cursor.col = 0;
+                cursor.pending_wrap = false;
+            }
+            _ => self.become_tentative(),
+        }
+
+        cursor.expiry = Expiry::new(self.prediction_epoch);
+        self.cursor = Some(cursor);
+    }
+
+    /// カーソル位置に半角文字 `ch` を挿入すると予測する
+    fn predict_char(&mut self, fb: &Framebuffer, cursor: &mut PredictedCursor, ch: char) {
+        let width = fb.width();
+        let (row, col) = (cursor.row, cursor.col);
+        // 行の続きを右へずらす（右端からはみ出すセルは内容不明）
+        let end = self.row_end(fb, row, col);
+        for i in (col + 1..(end + 1).min(width)).rev() {
+            let replacement = if i + 1 < width {
+                self.current(fb, row, i - 1)
+            } else {
+                None
+            };
+            self.predict_cell(fb, row, i, replacement);
+        }
+        self.predict_cell(fb, row, col, Some(Cell::new(ch, *fb.renditions())));
+
+        if col + 1 < width {
+            cursor.col += 1;
+        } else {
+            cursor.pending_wrap = true;
+        }
+    }
+
+    /// セル `(row, col)` が `replacement` になると予測する
+    fn predict_cell(
+        &mut self,
+        fb: &Framebuffer,
+        row: usize,
+        col: usize,
+        replacement: Option<Cell>,
+    ) {
+        let original = fb.cell(row, col);
+        let expiry = Expiry::new(self.prediction_epoch);
+        let cell = self
+            .cells
+            .entry((row, col))
+            .or_insert_with(|| PredictedCell {
+                replacement: None,
+                original: Vec::new(),
+                expiry,
+            });
+        cell.replacement = replacement;
+        cell.expiry = expiry;
+        if !cell.original.iter().any(|o| o.same_contents(original)) {
+            cell.original.push(original.clone());
+        }
+    }
+
+    /// 予測を重ねたセル `(row, col)` の内容（内容不明なら `None`）
+    fn current(&self, fb: &Framebuffer, row: usize, col: usize) -> Option<Cell> {
+        match self.cells.get(&(row, col)) {
+            Some(cell) => cell.replacement.clone(),
+            None => Some(fb.cell(row, col).clone()),
+        }
+    }
+
+    /// 予測を重ねた行 `row` の `from` 桁以降で、最後の空白でないセルの次の桁
+    /// （空白しかなければ `from`）
+    fn row_end(&self, fb: &Framebuffer, row: usize, from: usize) -> usize {
+        (from..fb.width())
+            .rev()
+            .find(|&col| {
+                !self
+                    .current(fb, row, col)
+                    .is_some_and(|cell| cell.is_blank())
+            })
+            .map_or(from, |col| col + 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mosh_terminal::Emulator;
+
+    /// サーバー側の画面と、入力を 1 回ずつ別の状態で送るクライアント
+    struct Harness {
+        server: Emulator,
+        engine: PredictionEngine,
+        sent: u64,
+        now_ms: u64,
+    }
+
+    impl Harness {
+        fn new(mode: PredictionMode, width: usize, height: usize, initial: &[u8]) -> Self {
+            let mut server = Emulator::new(width, height);
+            server.write(initial);
+            Harness {
+                server,
+                engine: PredictionEngine::new(mode),
+                sent: 0,
+                now_ms: 0,
+            }
+        }
+
+        /// キー入力を予測して、次の状態で送る
+        fn type_keys(&mut self, keys: &[u8]) {
+            self.engine.new_user_input(keys, self.server.framebuffer());
+            self.sent += 1;
+            self.engine.set_local_frame_sent(self.sent, self.now_ms);
+        }
+
+        /// サーバーが `output` を出力し、ここまでの入力のエコーを確認した
+        fn echo(&mut self, output: &[u8]) {
+            self.server.write(output);
+            self.engine.set_local_frame_late_acked(self.sent);
+            self.now_ms += 50;
+            self.engine.cull(self.server.framebuffer(), self.now_ms);
+        }
+
+        /// 予測を重ねた画面
+        fn shown(&self) -> Framebuffer {
+            let mut shown = self.server.clone();
+            self.engine.apply(shown.framebuffer_mut());
+            shown.framebuffer().clone()
+        }
+    }
+
+    #[test]
+    fn test_first_prediction_tentative_until_echoed() {
+        let mut h = Harness::new(PredictionMode::Always, 20, 3, b"$ ");
+        h.type_keys(b"e");
+        assert_eq!(h.shown().row(0).text(), "$", "仮の予測は表示しない");
+
+        h.echo(b"e");
+        assert!(!h.engine.is_active());
+        h.type_keys(b"ch");
+        assert_eq!(h.shown().row(0).text(), "$ ech");
+        assert_eq!(h.shown().cursor(), (0, 5));
+
+        h.echo(b"ch");
+        assert!(!h.engine.is_active());
+        assert_eq!(h.shown(), *h.server.framebuffer());
+    }
+
+    #[test]
+    fn test_unechoed_tentative_prediction_discarded() {
+        // パスワード入力: エコーされない
+        let mut h = Harness::new(PredictionMode::Always, 20, 3, b"Password: ");
+        h.type_keys(b"secret");
+        h.echo(b"");
+        assert!(!h.engine.is_active());
+        assert_eq!(h.shown().row(0).text(), "Password:");
+    }
+
+    #[test]
+    fn test_incorrect_confirmed_prediction_resets() {
+        let mut h = Harness::new(PredictionMode::Always, 20, 3, b"$ ");
+        h.type_keys(b"a");
+        h.echo(b"a");
+        h.type_keys(b"b");
+        assert_eq!(h.shown().row(0).text(), "$ ab");
+
+        // 予想と違うエコー
+        h.echo(b"B");
+        assert!(!h.engine.is_active());
+        h.type_keys(b"c");
+        assert_eq!(h.shown().row(0).text(), "$ aB", "取り消した後の予測は仮");
+    }
+
+    #[test]
+    fn test_insert_and_backspace_in_line() {
+        let mut h = Harness::new(PredictionMode::Always, 20, 3, b"$ ");
+        h.type_keys(b"a");
+        h.echo(b"a");
+        h.type_keys(b"c\x1b[D");
+        assert_eq!(h.shown().cursor(), (0, 3));
+        h.echo(b"c\x08");
+
+        // 行の途中の文字は挿入（右側をずらす）
+        h.type_keys(b"b");
+        assert_eq!(h.shown().row(0).text(), "$ abc");
+        assert_eq!(h.shown().cursor(), (0, 4));
+        h.echo(b"\x1b[@b");
+
+        // 後退は左へ詰める
+        h.type_keys(b"\x7f\x7f");
+        assert_eq!(h.shown().row(0).text(), "$ c");
+        assert_eq!(h.shown().cursor(), (0, 2));
+        h.echo(b"\x08\x1b[P\x08\x1b[P");
+        assert!(!h.engine.is_active());
+    }
+
+    #[test]
+    fn test_wrap_and_newline_tentative() {
+        let mut h = Harness::new(PredictionMode::Always, 5, 3, b"$ ");
+        h.type_keys(b"a");
+        h.echo(b"a");
+        h.type_keys(b"bc");
+        assert_eq!(h.shown().row(0).text(), "$ abc");
+        assert_eq!(h.shown().cursor(), (0, 4));
+
+        // 折り返しと改行の後は仮
+        h.type_keys(b"d\rx");
+        assert_eq!(h.shown().text(), "$ abc\n\n");
+        h.echo(b"bcd\r\n");
+        h.echo(b"x");
+        assert!(!h.engine.is_active());
+    }
+
+    #[test]
+    fn test_adaptive_mode_follows_srtt_and_glitches() {
+        let mut h = Harness::new(PredictionMode::Adaptive, 20, 3, b"$ ");
+        h.type_keys(b"a");
+        h.echo(b"a");
+
+        // 速い回線では表示しない
+        h.engine.set_srtt(10.0);
+        h.type_keys(b"b");
+        h.engine.cull(h.server.framebuffer(), h.now_ms);
+        assert!(!h.engine.is_showing());
+        assert_eq!(h.shown().row(0).text(), "$ a");
+
+        // エコーが遅れたら表示する（下線はまだ引かない）
+        h.now_ms += GLITCH_THRESHOLD_MS;
+        h.engine.cull(h.server.framebuffer(), h.now_ms);
+        assert!(h.engine.is_showing());
+        assert!(!h.engine.is_flagging());
+        assert_eq!(h.shown().row(0).text(), "$ ab");
+
+        // 遅い回線では下線を引く
+        h.engine.set_srtt(FLAG_TRIGGER_HIGH_MS + 1.0);
+        h.engine.cull(h.server.framebuffer(), h.now_ms);
+        assert!(h.engine.is_flagging());
+        assert!(h.shown().cell(0, 3).renditions.underline);
+        h.echo(b"b");
+        assert!(!h.shown().cell(0, 3).renditions.underline);
+    }
+
+    #[test]
+    fn test_never_mode_predicts_nothing() {
+        let mut h = Harness::new(PredictionMode::Never, 20, 3, b"$ ");
+        h.type_keys(b"a");
+        assert!(!h.engine.is_active());
+
+        h.engine.set_mode(PredictionMode::Always);
+        h.type_keys(b"a");
+        h.engine.set_mode(PredictionMode::Never);
+        assert!(!h.engine.is_active());
+        assert_eq!(
+            PredictionMode::from_name("ALWAYS"),
+            Some(PredictionMode::Always)
+        );
+        assert_eq!(PredictionMode::from_name("sometimes"), None);
+    }
+}
@@ -0,0 +1,188 @@
+//! キー入力（端末に送るバイト列）の分類
+//!
+//! 予測エンジンはキー入力を画面への効果で分類する。文字・後退・左右のカーソルキー・
+//! 復帰（Enter）以外の入力（制御文字・その他のエスケープシーケンス・不正な UTF-8）は
+//! 効果を予測できないので [`Key::Other`] にする。
+//! エスケープシーケンスや UTF-8 の途中で入力が分かれても続きから解釈する。
+
+/// 画面への効果で分類したキー入力
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    /// 表示される文字
+    Char(char),
+    /// 後退（DEL / BS）
+    Backspace,
+    /// 左カーソルキー（`ESC [ D` / `ESC O D`）
+    Left,
+    /// 右カーソルキー（`ESC [ C` / `ESC O C`）
+    Right,
+    /// 復帰（CR）
+    CarriageReturn,
+    /// 効果を予測できない入力
+    Other,
+}
+
+/// [`InputParser`] の状態
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum State {
+    #[default]
+    Ground,
+    /// ESC の直後
+    Escape,
+    /// CSI の途中（`plain` はパラメーター・中間文字がまだない）
+    Csi { plain: bool },
+    /// SS3（`ESC O`、アプリケーションカーソルキー）の直後
+    Ss3,
+}
+
+/// キー入力のバイト列を [`Key`] に分ける
+#[derive(Debug, Clone, Default)]
+pub struct InputParser {
+    state: State,
+    /// デコード中の UTF-8 のバイト列
+    utf8: [u8; 4],
+    /// `utf8` に溜めたバイト数
+    utf8_len: usize,
+    /// デコード中の文字の UTF-8 のバイト数（0 = デコード中でない）
+    utf8_need: usize,
+}
+
+impl InputParser {
+    /// 初期状態のパーサー
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// バイト列を解釈し、分類したキー入力ごとに `f` を呼ぶ
+    pub fn feed(&mut self, bytes: &[u8], mut f: impl FnMut(Key)) {
+        for &byte in bytes {
+            self.advance(byte, &mut f);
+        }
+    }
+
+    fn advance(&mut self, byte: u8, f: &mut impl FnMut(Key)) {
+        if self.utf8_need > 0 {
+            if byte & 0xC0 == 0x80 {
+                self.utf8[self.utf8_len] = byte;
+                self.utf8_len += 1;
+                if self.utf8_len == self.utf8_need {
+                    let ch = core::str::from_utf8(&self.utf8[..self.utf8_len])
+                        .ok()
+                        .and_then(|s| s.chars().next());
+                    self.utf8_need = 0;
+                    f(ch.map_or(Key::Other, Key::Char));
+                }
+                return;
+            }
+            // 途中で終わった UTF-8
+            self.utf8_need = 0;
+            f(Key::Other);
+        }
+
+        self.state = match self.state {
+            State::Ground if byte == 0x1B => State::Escape,
+            State::Ground => {
+                match byte {
+                    0x7F | 0x08 => f(Key::Backspace),
+                    b'\r' => f(Key::CarriageReturn),
+                    0x20..=0x7E => f(Key::Char(byte.into())),
+                    0xC2..=0xF4 => {
+                        self.utf8[0] = byte;
+                        self.utf8_len = 1;
+                        self.utf8_need = match byte {
+                            0xC2..=0xDF => 2,
+                            0xE0..=0xEF => 3,
+                            _ => 4,
+                        };
+                    }
+                    _ => f(Key::Other),
+                }
+                State::Ground
+            }
+            State::Escape => match byte {
+                b'[' => State::Csi { plain: true },
+                b'O' => State::Ss3,
+                _ => {
+                    // Alt + キーなど
+                    f(Key::Other);
+                    State::Ground
+                }
+            },
+            State::Csi { plain } => match byte {
+                0x20..=0x3F => State::Csi { plain: false },
+                0x40..=0x7E => {
+                    f(match (plain, byte) {
+                        (true, b'C') => Key::Right,
+                        (true, b'D') => Key::Left,
+                        _ => Key::Other,
+                    });
+                    State::Ground
+                }
+                _ => {
+                    f(Key::Other);
+                    State::Ground
+                }
+            },
+            State::Ss3 => {
+                f(match byte {
+                    b'C' => Key::Right,
+                    b'D' => Key::Left,
+                    _ => Key::Other,
+                });
+                State::Ground
+            }
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    fn keys(parser: &mut InputParser, bytes: &[u8]) -> Vec<Key> {
+        let mut keys = Vec::new();
+        parser.feed(bytes, |key| keys.push(key));
+        keys
+    }
+
+    #[test]
+    fn test_classify_keys() {
+        let mut parser = InputParser::new();
+        assert_eq!(
+            keys(
+                &mut parser,
+                b"a\x7f\x08\r\x03\x1b[C\x1bOD\x1b[A\x1b[1;5C\x1bx"
+            ),
+            [
+                Key::Char('a'),
+                Key::Backspace,
+                Key::Backspace,
+                Key::CarriageReturn,
+                Key::Other,
+                Key::Right,
+                Key::Left,
+                Key::Other,
+                Key::Other,
+                Key::Other,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_split_utf8_and_escape() {
+        let mut parser = InputParser::new();
+        let bytes = "あ".as_bytes();
+        assert!(keys(&mut parser, &bytes[..2]).is_empty());
+        assert_eq!(keys(&mut parser, &bytes[2..]), [Key::Char('あ')]);
+
+        assert!(keys(&mut parser, b"\x1b[").is_empty());
+        assert_eq!(keys(&mut parser, b"D"), [Key::Left]);
+
+        // 途中で終わった UTF-8 と不正なバイト
+        assert_eq!(
+            keys(&mut parser, b"\xe3\x81a\xff"),
+            [Key::Other, Key::Char('a'), Key::Other]
+        );
+    }
+}
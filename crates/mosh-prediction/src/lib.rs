@@ -0,0 +1,86 @@
+//! # mosh-prediction
+//!
+//! 端末モードの予測エコー（speculative local echo、no_std + alloc）。
+//!
+//! mosh C++ 実装の `Overlay::PredictionEngine` に対応する。キー入力の画面への効果
+//! （文字の表示・後退・左右のカーソル移動）をサーバーの応答を待たずに予測して画面に重ね、
+//! サーバーがエコーを反映した画面（`HostMessage` の `EchoAck`）と照らし合わせて
+//! 確定または取り消す。遅い回線でも打った文字がすぐに見える。
+//!
+//! ## 流れ
+//!
+//! ```text
+//! キー入力 → new_user_input（予測を作る。どの状態で送るかはまだ未定）
+//!          → set_local_frame_sent（入力を運ぶ SSP の状態番号が決まった）
+//! 画面受信 → set_local_frame_late_acked（EchoAck）+ set_srtt
+//!          → cull（エコーが反映済みの予測を画面と比べて確定・取り消し）
+//! 表示     → apply（表示してよい予測をフレームバッファの複製に重ねる）
+//! ```
+//!
+//! ## 表示する予測
+//!
+//! - 制御文字・改行・行の折り返しなど、効果を確信できない入力の後の予測は「仮」になり、
+//!   その後の予測のどれかが画面と一致するまで表示しない（パスワード入力などで外れた予測を見せない）。
+//! - 予測が外れたら、仮の予測ならそれ以降の仮の予測を、確定した予測なら全部を捨てる。
+//! - [`PredictionMode::Adaptive`] では往復時間（SRTT）が長いときと、
+//!   エコーが遅れたときだけ表示する。さらに遅いときは予測に下線を引いて区別する。
+//!
+//! ## 使用例
+//!
+//! ```
+//! use mosh_prediction::{PredictionEngine, PredictionMode};
+//! use mosh_terminal::Emulator;
+//!
+//! let mut server = Emulator::new(20, 5);
+//! server.write(b"$ ");
+//! let mut engine = PredictionEngine::new(PredictionMode::Always);
+//!
+//! // 最初の予測は仮: サーバーのエコー（状態 1）で確かめてから表示する
+//! engine.new_user_input(b"l", server.framebuffer());
+//! engine.set_local_frame_sent(1, 0);
+//! server.write(b"l");
+//! engine.set_local_frame_late_acked(1);
+//! engine.cull(server.framebuffer(), 100);
+//!
+//! // 以降の入力はすぐに画面に重なる
+//! engine.new_user_input(b"s", server.framebuffer());
+//! let mut shown = server.clone();
+//! engine.apply(shown.framebuffer_mut());
+//! assert_eq!(shown.framebuffer().row(0).text(), "$ ls");
+//! assert_eq!(shown.framebuffer().cursor(), (0, 4));
+//! ```
+
+#![no_std]
+extern crate alloc;
+
+pub mod engine;
+pub mod input;
+
+pub use engine::{PredictionEngine, PredictionMode};
+pub use input::{InputParser, Key};
+
+/// [`PredictionMode::Adaptive`] で予測の表示を始める SRTT（ミリ秒）
+/// これを超えたら表示する（mosh C++ 実装の `SRTT_TRIGGER_HIGH` と同じ）
+pub const SRTT_TRIGGER_HIGH_MS: f64 = 30.0;
+
+/// [`PredictionMode::Adaptive`] で予測の表示をやめる SRTT（ミリ秒）
+/// これ以下になり、未確定の予測がなくなったら表示をやめる
+pub const SRTT_TRIGGER_LOW_MS: f64 = 20.0;
+
+/// 予測に下線を引き始める SRTT（ミリ秒）
+pub const FLAG_TRIGGER_HIGH_MS: f64 = 80.0;
+
+/// 予測の下線をやめる SRTT（ミリ秒）
+pub const FLAG_TRIGGER_LOW_MS: f64 = 50.0;
+
+/// 送信からこの時間（ミリ秒）が経っても確かめられない予測があれば、SRTT が短くても表示する
+pub const GLITCH_THRESHOLD_MS: u64 = 250;
+
+/// 送信からこの時間（ミリ秒）が経っても確かめられない予測があれば、下線も引く
+pub const GLITCH_FLAG_THRESHOLD_MS: u64 = 5_000;
+
+/// エコーの遅れ（glitch）の後、予測の表示をやめるまでに必要な速い確認の回数
+pub const GLITCH_REPAIR_COUNT: u32 = 10;
+
+/// 速い確認を 1 回と数える最小の間隔（ミリ秒）
+pub const GLITCH_REPAIR_MININTERVAL_MS: u64 = 150;
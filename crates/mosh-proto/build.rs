@@ -6,7 +6,11 @@
 fn main() {
     prost_build::Config::new()
         .compile_protos(
-            &["proto/transportinstruction.proto"],
+            &[
+                "proto/transportinstruction.proto",
+                "proto/userinput.proto",
+                "proto/hostinput.proto",
+            ],
             &["proto/"],
         )
         .expect("Failed to compile proto files");
@@ -0,0 +1,180 @@
+//! HostMessage（サーバー → クライアントの端末出力）
+//!
+//! 端末モードのサーバーは、画面差分のエスケープシーケンス・端末サイズ・エコー確認を
+//! [`HostMessage`] にまとめ、エンコードしたバイト列を SSP Instruction の `diff` に格納して送る。
+//!
+//! ```text
+//! HostMessage
+//!   └── instruction[]
+//!         ├── hostbytes.hoststring     (端末に流すバイト列)
+//!         ├── resize.width / .height   (端末サイズ)
+//!         └── echoack.echo_ack_num     (エコーを反映済みのクライアント状態番号)
+//! ```
+
+use alloc::vec::Vec;
+
+use crate::error::ProtoError;
+use crate::host_buffers::{EchoAck, HostBytes, HostMessage, Instruction, ResizeMessage};
+
+/// ホスト側の操作（HostMessage の Instruction 1 つ分）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HostEvent {
+    /// 端末エミュレーターに流すバイト列
+    HostBytes(Vec<u8>),
+    /// 端末サイズの変更
+    Resize {
+        /// 幅（桁数）
+        width: i32,
+        /// 高さ（行数）
+        height: i32,
+    },
+    /// サーバーがこの状態番号までのキー入力のエコーを画面に反映した
+    EchoAck(u64),
+}
+
+impl HostEvent {
+    fn into_instruction(self) -> Instruction {
+        let mut instr = Instruction::default();
+        match self {
+            HostEvent::HostBytes(bytes) => {
+                instr.hostbytes = Some(HostBytes { hoststring: Some(bytes) });
+            }
+            HostEvent::Resize { width, height } => {
+                instr.resize = Some(ResizeMessage {
+                    width: Some(width),
+                    height: Some(height),
+                });
+            }
+            HostEvent::EchoAck(num) => {
+                instr.echoack = Some(EchoAck { echo_ack_num: Some(num) });
+            }
+        }
+        instr
+    }
+}
+
+/// HostMessage の構築・エンコード・デコードユーティリティ
+impl HostMessage {
+    /// ホスト側の操作の列から組み立てる
+    pub fn from_events(events: impl IntoIterator<Item = HostEvent>) -> Self {
+        HostMessage {
+            instruction: events.into_iter().map(HostEvent::into_instruction).collect(),
+        }
+    }
+
+    /// ホスト側の操作を末尾に追加する
+    pub fn push(&mut self, event: HostEvent) {
+        self.instruction.push(event.into_instruction());
+    }
+
+    /// 端末に流すバイト列を追加する（ビルダー）
+    pub fn with_host_bytes(mut self, bytes: &[u8]) -> Self {
+        self.push(HostEvent::HostBytes(bytes.to_vec()));
+        self
+    }
+
+    /// 端末サイズの変更を追加する（ビルダー）
+    pub fn with_resize(mut self, width: i32, height: i32) -> Self {
+        self.push(HostEvent::Resize { width, height });
+        self
+    }
+
+    /// エコー確認を追加する（ビルダー）
+    pub fn with_echo_ack(mut self, echo_ack_num: u64) -> Self {
+        self.push(HostEvent::EchoAck(echo_ack_num));
+        self
+    }
+
+    /// ホスト側の操作を古い順に返す
+    ///
+    /// 複数のフィールドを持つ Instruction は hostbytes → resize → echoack の順に展開し、
+    /// どれも持たないもの（未知の extension）は読み飛ばす。
+    pub fn events(&self) -> impl Iterator<Item = HostEvent> + '_ {
+        self.instruction.iter().flat_map(|instr| {
+            let bytes = instr
+                .hostbytes
+                .as_ref()
+                .map(|h| HostEvent::HostBytes(h.hoststring.clone().unwrap_or_default()));
+            let resize = instr.resize.as_ref().map(|r| HostEvent::Resize {
+                width: r.width.unwrap_or(0),
+                height: r.height.unwrap_or(0),
+            });
+            let echo_ack = instr
+                .echoack
+                .as_ref()
+                .map(|e| HostEvent::EchoAck(e.echo_ack_num.unwrap_or(0)));
+            bytes.into_iter().chain(resize).chain(echo_ack)
+        })
+    }
+
+    /// 最後のエコー確認の状態番号。エコー確認がなければ `None`
+    pub fn echo_ack_num(&self) -> Option<u64> {
+        self.instruction
+            .iter()
+            .rev()
+            .find_map(|instr| instr.echoack.as_ref())
+            .map(|e| e.echo_ack_num.unwrap_or(0))
+    }
+
+    /// バイト列（Instruction の `diff`）から HostMessage をデコードする
+    ///
+    /// # エラー
+    /// - `ProtoError::DecodeFailed`: protobuf デコード失敗
+    pub fn decode_from_bytes(bytes: &[u8]) -> Result<Self, ProtoError> {
+        use prost::Message;
+        HostMessage::decode(bytes).map_err(ProtoError::DecodeFailed)
+    }
+
+    /// HostMessage をバイト列にエンコードする
+    pub fn encode_to_bytes(&self) -> Vec<u8> {
+        use prost::Message;
+        self.encode_to_vec()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    #[test]
+    fn test_roundtrip() {
+        let msg = HostMessage::default()
+            .with_resize(80, 24)
+            .with_host_bytes(b"\x1b[H\x1b[2Jhello")
+            .with_echo_ack(7);
+
+        let decoded = HostMessage::decode_from_bytes(&msg.encode_to_bytes()).unwrap();
+        let events: Vec<_> = decoded.events().collect();
+        assert_eq!(
+            events,
+            vec![
+                HostEvent::Resize { width: 80, height: 24 },
+                HostEvent::HostBytes(b"\x1b[H\x1b[2Jhello".to_vec()),
+                HostEvent::EchoAck(7),
+            ]
+        );
+        assert_eq!(decoded.echo_ack_num(), Some(7));
+    }
+
+    #[test]
+    fn test_wire_format_matches_mosh() {
+        // mosh の extension（hostbytes = 2, echoack = 7）と同じバイト列になる
+        let msg = HostMessage::default().with_host_bytes(b"x");
+        assert_eq!(msg.encode_to_bytes(), [0x0a, 0x05, 0x12, 0x03, 0x22, 0x01, b'x']);
+
+        let msg = HostMessage::default().with_echo_ack(5);
+        assert_eq!(msg.encode_to_bytes(), [0x0a, 0x04, 0x3a, 0x02, 0x40, 0x05]);
+    }
+
+    #[test]
+    fn test_echo_ack_takes_latest() {
+        let msg = HostMessage::from_events([
+            HostEvent::EchoAck(3),
+            HostEvent::HostBytes(b"a".to_vec()),
+            HostEvent::EchoAck(4),
+        ]);
+        assert_eq!(msg.echo_ack_num(), Some(4));
+        assert_eq!(HostMessage::default().echo_ack_num(), None);
+    }
+}
@@ -11,6 +11,13 @@
 //! - `throwaway_num`: これより古い状態は破棄可能
 //! - `diff`: 状態差分データ（バイトストリームモードでは raw bytes）
 //!
+//! ## 端末モードの diff
+//!
+//! バイトストリームモードでは `diff` に raw bytes を格納するが、
+//! 端末モード（mosh-server と直接話す場合）では以下のメッセージをエンコードして格納する：
+//! - クライアント → サーバー: [`UserMessage`]（キー入力・リサイズ）
+//! - サーバー → クライアント: [`HostMessage`]（ホスト出力・リサイズ・エコー確認）
+//!
 //! ## プロトコルバージョン
 //!
 //! mosh のプロトコルバージョンは 2 (MOSH_PROTOCOL_VERSION)。
@@ -21,8 +28,12 @@ extern crate alloc;
 use alloc::vec::Vec;
 
 pub mod error;
+pub mod host_message;
+pub mod user_message;
 
 pub use error::ProtoError;
+pub use host_message::HostEvent;
+pub use user_message::UserEvent;
 
 /// mosh SSP プロトコルバージョン
 pub const MOSH_PROTOCOL_VERSION: u32 = 2;
@@ -32,6 +43,18 @@ pub mod transport_buffers {
     include!(concat!(env!("OUT_DIR"), "/transport_buffers.rs"));
 }
 
+/// クライアント → サーバーの端末入力（mosh の `ClientBuffers`）
+pub mod client_buffers {
+    include!(concat!(env!("OUT_DIR"), "/client_buffers.rs"));
+}
+
+/// サーバー → クライアントの端末出力（mosh の `HostBuffers`）
+pub mod host_buffers {
+    include!(concat!(env!("OUT_DIR"), "/host_buffers.rs"));
+}
+
+pub use client_buffers::UserMessage;
+pub use host_buffers::HostMessage;
 pub use transport_buffers::Instruction;
 
 /// Instruction の構築・エンコード・デコードユーティリティ
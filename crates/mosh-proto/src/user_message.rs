@@ -0,0 +1,154 @@
+//! UserMessage（クライアント → サーバーの端末入力）
+//!
+//! 端末モードのクライアントは、キー入力と端末サイズの変更を [`UserMessage`] にまとめ、
+//! エンコードしたバイト列を SSP Instruction の `diff` に格納して送る。
+//!
+//! ```text
+//! UserMessage
+//!   └── instruction[]
+//!         ├── keystroke.keys          (キー入力のバイト列)
+//!         └── resize.width / .height  (端末サイズ)
+//! ```
+
+use alloc::vec::Vec;
+
+use crate::client_buffers::{Instruction, Keystroke, ResizeMessage, UserMessage};
+use crate::error::ProtoError;
+
+/// ユーザー操作（UserMessage の Instruction 1 つ分）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UserEvent {
+    /// キー入力（UTF-8、エスケープシーケンスを含む）
+    Keystroke(Vec<u8>),
+    /// 端末サイズの変更
+    Resize {
+        /// 幅（桁数）
+        width: i32,
+        /// 高さ（行数）
+        height: i32,
+    },
+}
+
+impl UserEvent {
+    fn into_instruction(self) -> Instruction {
+        match self {
+            UserEvent::Keystroke(keys) => Instruction {
+                keystroke: Some(Keystroke { keys: Some(keys) }),
+                resize: None,
+            },
+            UserEvent::Resize { width, height } => Instruction {
+                keystroke: None,
+                resize: Some(ResizeMessage {
+                    width: Some(width),
+                    height: Some(height),
+                }),
+            },
+        }
+    }
+}
+
+/// UserMessage の構築・エンコード・デコードユーティリティ
+impl UserMessage {
+    /// ユーザー操作の列から組み立てる
+    pub fn from_events(events: impl IntoIterator<Item = UserEvent>) -> Self {
+        UserMessage {
+            instruction: events.into_iter().map(UserEvent::into_instruction).collect(),
+        }
+    }
+
+    /// ユーザー操作を末尾に追加する
+    pub fn push(&mut self, event: UserEvent) {
+        self.instruction.push(event.into_instruction());
+    }
+
+    /// キー入力を追加する（ビルダー）
+    pub fn with_keystroke(mut self, keys: &[u8]) -> Self {
+        self.push(UserEvent::Keystroke(keys.to_vec()));
+        self
+    }
+
+    /// 端末サイズの変更を追加する（ビルダー）
+    pub fn with_resize(mut self, width: i32, height: i32) -> Self {
+        self.push(UserEvent::Resize { width, height });
+        self
+    }
+
+    /// ユーザー操作を古い順に返す
+    ///
+    /// keystroke と resize の両方を持つ Instruction は keystroke → resize の順に展開し、
+    /// どちらも持たないもの（未知の extension）は読み飛ばす。
+    pub fn events(&self) -> impl Iterator<Item = UserEvent> + '_ {
+        self.instruction.iter().flat_map(|instr| {
+            let keystroke = instr
+                .keystroke
+                .as_ref()
+                .map(|k| UserEvent::Keystroke(k.keys.clone().unwrap_or_default()));
+            let resize = instr.resize.as_ref().map(|r| UserEvent::Resize {
+                width: r.width.unwrap_or(0),
+                height: r.height.unwrap_or(0),
+            });
+            keystroke.into_iter().chain(resize)
+        })
+    }
+
+    /// バイト列（Instruction の `diff`）から UserMessage をデコードする
+    ///
+    /// # エラー
+    /// - `ProtoError::DecodeFailed`: protobuf デコード失敗
+    pub fn decode_from_bytes(bytes: &[u8]) -> Result<Self, ProtoError> {
+        use prost::Message;
+        UserMessage::decode(bytes).map_err(ProtoError::DecodeFailed)
+    }
+
+    /// UserMessage をバイト列にエンコードする
+    pub fn encode_to_bytes(&self) -> Vec<u8> {
+        use prost::Message;
+        self.encode_to_vec()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    #[test]
+    fn test_roundtrip() {
+        let msg = UserMessage::default()
+            .with_keystroke(b"ls -l\r")
+            .with_resize(120, 40)
+            .with_keystroke(b"\x1b[A");
+
+        let decoded = UserMessage::decode_from_bytes(&msg.encode_to_bytes()).unwrap();
+        let events: Vec<_> = decoded.events().collect();
+        assert_eq!(
+            events,
+            vec![
+                UserEvent::Keystroke(b"ls -l\r".to_vec()),
+                UserEvent::Resize { width: 120, height: 40 },
+                UserEvent::Keystroke(b"\x1b[A".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_wire_format_matches_mosh() {
+        // mosh の extension（keystroke = 2, resize = 3）と同じバイト列になる
+        let keystroke = UserMessage::default().with_keystroke(b"a");
+        assert_eq!(
+            keystroke.encode_to_bytes(),
+            [0x0a, 0x05, 0x12, 0x03, 0x22, 0x01, b'a']
+        );
+
+        let resize = UserMessage::default().with_resize(80, 24);
+        assert_eq!(
+            resize.encode_to_bytes(),
+            [0x0a, 0x06, 0x1a, 0x04, 0x28, 80, 0x30, 24]
+        );
+    }
+
+    #[test]
+    fn test_decode_garbage_fails() {
+        assert!(UserMessage::decode_from_bytes(&[0x0a, 0x05, 0x12]).is_err());
+    }
+}
@@ -0,0 +1,188 @@
+//! 時刻源
+//!
+//! SSP のタイマー（再送・ハートビート・遅延 ACK・ペーシング）は単調増加する
+//! ミリ秒の時刻を前提とする。各 API は `now_ms` を受け取るが、呼び出し側が
+//! 壁時計（`Date.now()` など）を渡すと、時刻合わせで時間が巻き戻ったときに
+//! タイマーが狂う。[`Clock`] を実装した時刻源を [`MonotonicClock`] で包むと、
+//! 逆行を検出して直前の時刻から進め直し、常に単調な時刻を得られる。
+//!
+//! | 実装 | 用途 |
+//! |------|------|
+//! | [`ManualClock`] | テスト（時刻を手で進める） |
+//! | [`StdClock`] | native（`std::time::Instant`、`std` feature） |
+//! | `PerformanceClock` | WASM（`performance.now()`、`mosh-wasm` クレート） |
+
+use alloc::sync::Arc;
+use core::cell::Cell;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// ミリ秒単位の時刻源
+pub trait Clock: Send {
+    /// 現在時刻（ミリ秒）。起点は実装ごとに任意
+    fn now_ms(&self) -> u64;
+}
+
+/// 手動で進める時刻源（テスト用）
+///
+/// `clone` したものは同じ時刻を共有する。セッションに 1 つを渡し、
+/// 手元に残したもので時刻を進めれば、時間経過を決定的に再現できる。
+#[derive(Debug, Clone, Default)]
+pub struct ManualClock {
+    now_ms: Arc<AtomicU64>,
+}
+
+impl ManualClock {
+    /// `start_ms` から始まる時刻源を生成する
+    pub fn new(start_ms: u64) -> Self {
+        ManualClock {
+            now_ms: Arc::new(AtomicU64::new(start_ms)),
+        }
+    }
+
+    /// 時刻を `ms` だけ進める
+    pub fn advance(&self, ms: u64) {
+        self.now_ms.fetch_add(ms, Ordering::Relaxed);
+    }
+
+    /// 時刻を設定する（巻き戻しも可能。逆行の再現に使う）
+    pub fn set(&self, now_ms: u64) {
+        self.now_ms.store(now_ms, Ordering::Relaxed);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now_ms(&self) -> u64 {
+        self.now_ms.load(Ordering::Relaxed)
+    }
+}
+
+/// `std::time::Instant` による時刻源（生成時刻を 0 とする）
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy)]
+pub struct StdClock {
+    origin: std::time::Instant,
+}
+
+#[cfg(feature = "std")]
+impl StdClock {
+    /// 現在を起点とする時刻源を生成する
+    pub fn new() -> Self {
+        StdClock {
+            origin: std::time::Instant::now(),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Default for StdClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "std")]
+impl Clock for StdClock {
+    fn now_ms(&self) -> u64 {
+        self.origin.elapsed().as_millis() as u64
+    }
+}
+
+/// 逆行を吸収する時刻源
+///
+/// 内側の時刻源が前回より小さい値を返したら、その差を補正値に加えて前回の値を返し、
+/// 逆行の回数を数える。以降は補正した時刻で前回の値から進み続けるので、
+/// 逆行した分だけタイマーが止まることはない。
+#[derive(Debug)]
+pub struct MonotonicClock<C> {
+    /// 内側の時刻源
+    inner: C,
+    /// これまでに返した最大の時刻
+    last_ms: Cell<u64>,
+    /// 逆行の累計（内側の時刻に加える補正値）
+    offset_ms: Cell<u64>,
+    /// 逆行を検出した回数
+    backward_jumps: Cell<u32>,
+}
+
+impl<C: Clock> MonotonicClock<C> {
+    /// 時刻源を包む
+    pub fn new(inner: C) -> Self {
+        MonotonicClock {
+            inner,
+            last_ms: Cell::new(0),
+            offset_ms: Cell::new(0),
+            backward_jumps: Cell::new(0),
+        }
+    }
+
+    /// 外部から得た時刻を単調な時刻に変換する
+    ///
+    /// 内側の時刻源を使わず、呼び出し側が渡す時刻（JS の `now_ms` 引数など）にも同じ保護をかける。
+    pub fn observe(&self, raw_ms: u64) -> u64 {
+        let last = self.last_ms.get();
+        let adjusted = raw_ms.saturating_add(self.offset_ms.get());
+        if adjusted < last {
+            self.offset_ms.set(self.offset_ms.get() + (last - adjusted));
+            self.backward_jumps.set(self.backward_jumps.get().saturating_add(1));
+            return last;
+        }
+        self.last_ms.set(adjusted);
+        adjusted
+    }
+
+    /// 逆行を検出した回数
+    pub fn backward_jumps(&self) -> u32 {
+        self.backward_jumps.get()
+    }
+
+    /// 内側の時刻源
+    pub fn inner(&self) -> &C {
+        &self.inner
+    }
+}
+
+impl<C: Clock> Clock for MonotonicClock<C> {
+    fn now_ms(&self) -> u64 {
+        self.observe(self.inner.now_ms())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manual_clock_shared() {
+        let clock = ManualClock::new(1000);
+        let handle = clock.clone();
+        handle.advance(250);
+        assert_eq!(clock.now_ms(), 1250);
+    }
+
+    #[test]
+    fn test_monotonic_clock_survives_backward_jump() {
+        let source = ManualClock::new(5000);
+        let clock = MonotonicClock::new(source.clone());
+        assert_eq!(clock.now_ms(), 5000);
+
+        source.set(3000);
+        assert_eq!(clock.now_ms(), 5000, "巻き戻った時刻は返さない");
+        assert_eq!(clock.backward_jumps(), 1);
+
+        // 逆行後も止まらずに進む
+        source.advance(100);
+        assert_eq!(clock.now_ms(), 5100);
+        assert_eq!(clock.observe(2000), 5100);
+        assert_eq!(clock.backward_jumps(), 2);
+        assert_eq!(clock.observe(2050), 5150);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_std_clock_is_monotonic() {
+        let clock = crate::StdClock::new();
+        let first = clock.now_ms();
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        assert!(clock.now_ms() >= first + 2);
+    }
+}
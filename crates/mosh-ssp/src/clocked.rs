@@ -0,0 +1,183 @@
+//! 時刻源を持つ SSP セッション
+//!
+//! [`SspSession`] の API は呼び出し側が `now_ms` を渡す。[`ClockedSession`] は
+//! [`Clock`] から時刻を読むので、呼び出し側が時刻を取り違える（壁時計を渡す、
+//! 呼び出しごとに異なる時刻源を使う）ことがない。時刻源は [`MonotonicClock`] で包まれ、
+//! 逆行しても直前の時刻から進み続ける。
+
+use alloc::vec::Vec;
+
+use mosh_proto::Instruction;
+use mosh_transport::Timestamp16;
+
+use crate::clock::{Clock, MonotonicClock};
+use crate::session::{SspSession, SspStats};
+use crate::state::ConnectionState;
+use crate::sync::{ReceivedStates, SyncState};
+
+/// 時刻源を持つ SSP セッション
+///
+/// [`SspSession::with_clock`] で生成する。
+///
+/// ```
+/// use mosh_proto::Instruction;
+/// use mosh_ssp::{ManualClock, SspSession};
+///
+/// let clock = ManualClock::new(1000);
+/// let mut sender = SspSession::new().with_clock(clock.clone());
+/// let mut receiver = SspSession::new().with_clock(clock.clone());
+///
+/// sender.session_mut().push_payload(b"hi".to_vec());
+/// assert!(sender.tick().is_empty()); // 送信ペーシングで保留
+/// clock.advance(sender.next_timeout_ms());
+/// for bytes in sender.tick() {
+///     let instr = Instruction::decode_from_bytes(&bytes).unwrap();
+///     assert_eq!(receiver.recv_instruction(&instr), Some(b"hi".to_vec()));
+/// }
+/// ```
+pub struct ClockedSession<C: Clock> {
+    /// 時刻を渡して操作するセッション
+    session: SspSession,
+    /// 逆行を吸収する時刻源
+    clock: MonotonicClock<C>,
+}
+
+impl<C: Clock> ClockedSession<C> {
+    pub(crate) fn new(session: SspSession, clock: C) -> Self {
+        ClockedSession {
+            session,
+            clock: MonotonicClock::new(clock),
+        }
+    }
+
+    /// 現在時刻（ミリ秒、単調増加）
+    pub fn now_ms(&self) -> u64 {
+        self.clock.now_ms()
+    }
+
+    /// タイマー tick（[`SspSession::tick`]）
+    pub fn tick(&mut self) -> Vec<Vec<u8>> {
+        let now_ms = self.now_ms();
+        self.session.tick(now_ms)
+    }
+
+    /// 受信した Instruction を処理する（[`SspSession::recv_instruction`]）
+    pub fn recv_instruction(&mut self, instr: &Instruction) -> Option<Vec<u8>> {
+        let now_ms = self.now_ms();
+        self.session.recv_instruction(instr, now_ms)
+    }
+
+    /// 受信した Instruction を状態同期として処理する（[`SspSession::recv_state_instruction`]）
+    pub fn recv_state_instruction<S: SyncState>(
+        &mut self,
+        instr: &Instruction,
+        states: &mut ReceivedStates<S>,
+    ) -> Result<Option<u64>, S::Error> {
+        let now_ms = self.now_ms();
+        self.session.recv_state_instruction(instr, states, now_ms)
+    }
+
+    /// 受信パケットのヘッダーのタイムスタンプを処理する（[`SspSession::recv_timestamps`]）
+    pub fn recv_timestamps(&mut self, timestamp: Timestamp16, timestamp_reply: Timestamp16) {
+        let now_ms = self.now_ms();
+        self.session.recv_timestamps(timestamp, timestamp_reply, now_ms)
+    }
+
+    /// 送信パケットに載せる timestamp_reply を返す（[`SspSession::timestamp_reply`]）
+    pub fn timestamp_reply(&mut self) -> Timestamp16 {
+        let now_ms = self.now_ms();
+        self.session.timestamp_reply(now_ms)
+    }
+
+    /// 次に `tick` を呼ぶべきまでの時間（ミリ秒）。既に期限が来ていれば 0
+    pub fn next_timeout_ms(&self) -> u64 {
+        let now_ms = self.now_ms();
+        self.session.next_deadline_ms(now_ms).saturating_sub(now_ms)
+    }
+
+    /// 現在の接続状態を返す
+    pub fn state(&self) -> ConnectionState {
+        self.session.state(self.now_ms())
+    }
+
+    /// 前回の呼び出しから接続状態が変わっていれば、新しい状態を返す
+    pub fn poll_state_change(&mut self) -> Option<ConnectionState> {
+        let now_ms = self.now_ms();
+        self.session.poll_state_change(now_ms)
+    }
+
+    /// 時刻源の逆行を検出した回数
+    pub fn clock_backward_jumps(&self) -> u32 {
+        self.clock.backward_jumps()
+    }
+
+    /// セッションの統計情報を返す
+    pub fn stats(&self) -> SspStats {
+        self.session.stats()
+    }
+
+    /// 内側のセッション（時刻を取らない操作に使う）
+    pub fn session(&self) -> &SspSession {
+        &self.session
+    }
+
+    /// 内側のセッション（可変）
+    pub fn session_mut(&mut self) -> &mut SspSession {
+        &mut self.session
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::{HEARTBEAT_INTERVAL_MS, SEND_MINDELAY_MS};
+
+    #[test]
+    fn test_clock_drives_timers() {
+        let clock = ManualClock::new(10_000);
+        let mut session = SspSession::new().with_clock(clock.clone());
+
+        let _ = session.tick(); // 初回ハートビート
+        assert_eq!(session.next_timeout_ms(), HEARTBEAT_INTERVAL_MS);
+
+        session.session_mut().push_payload(b"tick tock".to_vec());
+        assert!(session.tick().is_empty());
+        assert_eq!(session.next_timeout_ms(), SEND_MINDELAY_MS);
+        clock.advance(SEND_MINDELAY_MS);
+        assert_eq!(session.tick().len(), 1);
+    }
+
+    #[test]
+    fn test_backward_jump_does_not_rewind_timers() {
+        let clock = ManualClock::new(10_000_000);
+        let mut session = SspSession::new().with_clock(clock.clone());
+        let _ = session.tick();
+
+        // 時刻が 1 時間巻き戻っても、ハートビートの期限は延びない
+        clock.set(10_000_000 - 3_600_000);
+        assert_eq!(session.next_timeout_ms(), HEARTBEAT_INTERVAL_MS);
+        assert_eq!(session.clock_backward_jumps(), 1);
+
+        clock.advance(HEARTBEAT_INTERVAL_MS);
+        assert_eq!(session.next_timeout_ms(), 0);
+        assert_eq!(session.tick().len(), 1);
+    }
+
+    #[test]
+    fn test_timestamp_echo_uses_clock() {
+        let clock = ManualClock::new(5000);
+        let mut a = SspSession::new().with_clock(clock.clone());
+        let mut b = SspSession::new().with_clock(clock.clone());
+
+        let sent = Timestamp16::now_from_ms(a.now_ms());
+        clock.advance(20);
+        b.recv_timestamps(sent, Timestamp16::INIT);
+        clock.advance(30); // b の保持時間
+        let reply = b.timestamp_reply();
+        clock.advance(20);
+        a.recv_timestamps(Timestamp16::INIT, reply);
+
+        assert_eq!(a.stats().srtt_ms, 40.0);
+    }
+}
@@ -0,0 +1,255 @@
+//! 輻輳制御
+//!
+//! 未 ACK の Instruction が運ぶバイト数を輻輳ウィンドウ（cwnd）以下に抑える。
+//! mosh C++ 実装は端末画面の差分しか送らないので輻輳制御を持たないが、
+//! このトンネルは拡張機能のダウンロードやファイル同期などの大量転送も運ぶ。
+//!
+//! アルゴリズムは [`CongestionControl`] トレイトで差し替えられる。
+//! デフォルトの [`NewReno`] は以下のように動く。
+//!
+//! ```text
+//! SlowStart ──(ssthresh 到達 / RTT の増加)──→ CongestionAvoidance
+//!     ↑                                              │
+//!     └──────────────(再送タイムアウト)──────────────┘
+//!
+//! SlowStart:           ACK されたバイト数だけ cwnd を増やす
+//! CongestionAvoidance: 1 RTT あたり 1 セグメント増やす。RTT が最小 RTT の 2 倍を超えたら
+//!                      1 RTT に 1 回だけ cwnd を 7/8 に減らす（キューの成長を抑える）
+//! 再送タイムアウト:    ssthresh = max(未 ACK / 2, 最小ウィンドウ)、cwnd = 1 セグメント
+//! ```
+
+use crate::{CWND_INITIAL_SEGMENTS, CWND_MIN_SEGMENTS, CWND_SEGMENT_BYTES};
+
+/// 輻輳制御アルゴリズム
+///
+/// `SspSession` は ACK・RTT サンプル・再送タイムアウトを通知し、
+/// [`window_bytes`](CongestionControl::window_bytes) を超えて未 ACK のデータを送らない。
+pub trait CongestionControl: Send {
+    /// 現在の輻輳ウィンドウ（バイト）
+    fn window_bytes(&self) -> usize;
+
+    /// 新しい ACK で `acked_bytes` バイトが確認された
+    fn on_ack(&mut self, acked_bytes: usize, now_ms: u64);
+
+    /// RTT サンプルを得た（タイムスタンプのエコーから、相手の保持時間を補正済み）
+    fn on_rtt_sample(&mut self, rtt_ms: u64, now_ms: u64);
+
+    /// 再送タイムアウトが起きた（`in_flight_bytes` はタイムアウト時点の未 ACK バイト数）
+    fn on_timeout(&mut self, in_flight_bytes: usize, now_ms: u64);
+
+    /// 現在の状態
+    fn stats(&self) -> CongestionStats;
+}
+
+/// 輻輳制御のフェーズ
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CongestionPhase {
+    /// スロースタート（cwnd を指数的に増やす）
+    SlowStart,
+    /// 輻輳回避（cwnd を線形に増やす）
+    CongestionAvoidance,
+}
+
+impl CongestionPhase {
+    /// 小文字のフェーズ名（JS への受け渡し用）
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CongestionPhase::SlowStart => "slow_start",
+            CongestionPhase::CongestionAvoidance => "congestion_avoidance",
+        }
+    }
+}
+
+/// 輻輳制御の状態（統計用）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CongestionStats {
+    /// 輻輳ウィンドウ（バイト）
+    pub window_bytes: usize,
+    /// スロースタートのしきい値（バイト）。まだ決まっていなければ `None`
+    pub ssthresh_bytes: Option<usize>,
+    /// 現在のフェーズ
+    pub phase: CongestionPhase,
+}
+
+/// NewReno 風の輻輳制御（RTT の増加にも反応する）
+///
+/// SSP には重複 ACK がないので、損失の合図は再送タイムアウトだけ。
+/// その代わり RTT の増加（キューの成長）を早めの輻輳の合図として使う。
+#[derive(Debug, Clone)]
+pub struct NewReno {
+    /// 輻輳ウィンドウ（バイト）
+    cwnd: usize,
+    /// スロースタートのしきい値（バイト）
+    ssthresh: Option<usize>,
+    /// 輻輳回避中に ACK されたバイト数の累計（cwnd に達するごとに 1 セグメント増やす）
+    acked_accum: usize,
+    /// 観測した最小 RTT（ミリ秒）
+    min_rtt_ms: Option<u64>,
+    /// 最新の RTT サンプル（ミリ秒）
+    latest_rtt_ms: u64,
+    /// RTT の増加で最後に cwnd を減らした時刻（1 RTT に 1 回までに抑える）
+    last_delay_reduction_ms: Option<u64>,
+}
+
+impl NewReno {
+    /// 初期ウィンドウ（`CWND_INITIAL_SEGMENTS` セグメント）で生成する
+    pub fn new() -> Self {
+        NewReno {
+            cwnd: CWND_INITIAL_SEGMENTS * CWND_SEGMENT_BYTES,
+            ssthresh: None,
+            acked_accum: 0,
+            min_rtt_ms: None,
+            latest_rtt_ms: 0,
+            last_delay_reduction_ms: None,
+        }
+    }
+
+    fn phase(&self) -> CongestionPhase {
+        match self.ssthresh {
+            Some(ssthresh) if self.cwnd >= ssthresh => CongestionPhase::CongestionAvoidance,
+            _ => CongestionPhase::SlowStart,
+        }
+    }
+}
+
+impl Default for NewReno {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CongestionControl for NewReno {
+    fn window_bytes(&self) -> usize {
+        self.cwnd
+    }
+
+    fn on_ack(&mut self, acked_bytes: usize, _now_ms: u64) {
+        match self.phase() {
+            CongestionPhase::SlowStart => {
+                self.cwnd += acked_bytes;
+                if let Some(ssthresh) = self.ssthresh {
+                    self.cwnd = self.cwnd.min(ssthresh);
+                }
+            }
+            CongestionPhase::CongestionAvoidance => {
+                self.acked_accum += acked_bytes;
+                if self.acked_accum >= self.cwnd {
+                    self.acked_accum -= self.cwnd;
+                    self.cwnd += CWND_SEGMENT_BYTES;
+                }
+            }
+        }
+    }
+
+    fn on_rtt_sample(&mut self, rtt_ms: u64, now_ms: u64) {
+        self.latest_rtt_ms = rtt_ms;
+        let min_rtt = *self.min_rtt_ms.get_or_insert(rtt_ms);
+        if rtt_ms < min_rtt {
+            self.min_rtt_ms = Some(rtt_ms);
+            return;
+        }
+
+        match self.phase() {
+            CongestionPhase::SlowStart => {
+                // HyStart 風: RTT が最小 RTT より明らかに増えたらスロースタートを抜ける
+                let threshold = (min_rtt / 8).clamp(4, 16);
+                if rtt_ms >= min_rtt + threshold {
+                    self.ssthresh = Some(self.cwnd);
+                }
+            }
+            CongestionPhase::CongestionAvoidance => {
+                let min_window = CWND_MIN_SEGMENTS * CWND_SEGMENT_BYTES;
+                let recently_reduced = self
+                    .last_delay_reduction_ms
+                    .is_some_and(|last| now_ms < last + self.latest_rtt_ms);
+                if rtt_ms >= 2 * min_rtt.max(1) && !recently_reduced {
+                    self.cwnd = (self.cwnd / 8 * 7).max(min_window);
+                    self.ssthresh = Some(self.cwnd);
+                    self.acked_accum = 0;
+                    self.last_delay_reduction_ms = Some(now_ms);
+                }
+            }
+        }
+    }
+
+    fn on_timeout(&mut self, in_flight_bytes: usize, _now_ms: u64) {
+        let min_window = CWND_MIN_SEGMENTS * CWND_SEGMENT_BYTES;
+        self.ssthresh = Some((in_flight_bytes / 2).max(min_window));
+        self.cwnd = CWND_SEGMENT_BYTES;
+        self.acked_accum = 0;
+    }
+
+    fn stats(&self) -> CongestionStats {
+        CongestionStats {
+            window_bytes: self.cwnd,
+            ssthresh_bytes: self.ssthresh,
+            phase: self.phase(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SEG: usize = CWND_SEGMENT_BYTES;
+
+    #[test]
+    fn test_slow_start_then_avoidance() {
+        let mut cc = NewReno::new();
+        assert_eq!(cc.window_bytes(), CWND_INITIAL_SEGMENTS * SEG);
+
+        cc.on_ack(4 * SEG, 0);
+        assert_eq!(cc.window_bytes(), (CWND_INITIAL_SEGMENTS + 4) * SEG);
+
+        // タイムアウトで 1 セグメントまで縮み、ssthresh までスロースタート
+        cc.on_timeout(8 * SEG, 100);
+        assert_eq!(cc.window_bytes(), SEG);
+        assert_eq!(cc.stats().ssthresh_bytes, Some(4 * SEG));
+        cc.on_ack(10 * SEG, 200);
+        assert_eq!(cc.window_bytes(), 4 * SEG);
+        assert_eq!(cc.stats().phase, CongestionPhase::CongestionAvoidance);
+
+        // 輻輳回避ではウィンドウ分の ACK で 1 セグメント増える
+        cc.on_ack(2 * SEG, 300);
+        assert_eq!(cc.window_bytes(), 4 * SEG);
+        cc.on_ack(2 * SEG, 400);
+        assert_eq!(cc.window_bytes(), 5 * SEG);
+    }
+
+    #[test]
+    fn test_timeout_respects_min_ssthresh() {
+        let mut cc = NewReno::new();
+        cc.on_timeout(SEG, 0);
+        assert_eq!(cc.stats().ssthresh_bytes, Some(CWND_MIN_SEGMENTS * SEG));
+    }
+
+    #[test]
+    fn test_rtt_growth_exits_slow_start() {
+        let mut cc = NewReno::new();
+        cc.on_rtt_sample(100, 0);
+        cc.on_rtt_sample(105, 10);
+        assert_eq!(cc.stats().phase, CongestionPhase::SlowStart);
+
+        cc.on_rtt_sample(120, 20);
+        assert_eq!(cc.stats().phase, CongestionPhase::CongestionAvoidance);
+        assert_eq!(cc.stats().ssthresh_bytes, Some(CWND_INITIAL_SEGMENTS * SEG));
+    }
+
+    #[test]
+    fn test_rtt_growth_reduces_window_once_per_rtt() {
+        let mut cc = NewReno::new();
+        cc.on_rtt_sample(50, 0);
+        cc.on_rtt_sample(70, 10); // スロースタートを抜ける
+        let cwnd = cc.window_bytes();
+
+        cc.on_rtt_sample(120, 20);
+        let reduced = cc.window_bytes();
+        assert_eq!(reduced, cwnd / 8 * 7);
+
+        cc.on_rtt_sample(120, 60);
+        assert_eq!(cc.window_bytes(), reduced, "1 RTT 以内には再び減らさない");
+        cc.on_rtt_sample(120, 140);
+        assert!(cc.window_bytes() < reduced);
+    }
+}
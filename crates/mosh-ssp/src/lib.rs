@@ -13,23 +13,59 @@
 //! - **ACK**: ack_num で受信確認を通知する
 //! - **throwaway_num**: これより古い Instruction はもう不要（メモリ解放の合図）
 //! - **ハートビート**: 3000ms ごとに ACK を送って接続を維持する
-//! - **RTT 推定**: Jacobson アルゴリズムで Smoothed RTT を推定
-//! - **再送**: RTO 経過後に未 ACK の Instruction を再送
+//! - **送信ペーシング**: 書き込みを SEND_MINDELAY_MS まとめ、新しい Instruction は
+//!   SRTT / 2（20〜250ms）の間隔で作る（[`SendPacing`]）
+//! - **フロー制御**: 未 ACK のバイト数を SEND_WINDOW_MAX_BYTES 以下に抑え、
+//!   超える分は ACK が進むまで送信待ちのまま保持する
+//! - **輻輳制御**: 未 ACK のバイト数を輻輳ウィンドウ以下にも抑える。再送タイムアウトと
+//!   RTT の増加でウィンドウを縮め、失われた分はウィンドウに収まる単位で再送する（[`CongestionControl`]）
+//! - **遅延 ACK**: データ受信から 100ms 以内に送信データがなければ ACK のみを送る
+//! - **RTT 推定**: Jacobson アルゴリズムで Smoothed RTT を推定。サンプルは
+//!   パケットヘッダーのタイムスタンプのエコー（保持時間を補正済み）だけから得る
+//!   （ACK までの時間は遅延 ACK の保持時間を含むので使わない）
+//! - **再送**: RTO（+ 相手の遅延 ACK の猶予）経過後、未 ACK 分をまとめた 1 つの Instruction
+//!   （old_num = last_acked → new_num = 最新）を再送する。
+//!   タイムアウトが続くたびに待ち時間を倍にし（指数バックオフ、上限あり）、ACK が進むと元に戻す
+//! - **時刻源**: 各 API は `now_ms` を受け取る。[`SspSession::with_clock`] で [`Clock`] を持たせると
+//!   時刻を自分で読み、逆行しても単調に進む（[`ClockedSession`]）
+//! - **順序保証**: 番号が飛んで届いた Instruction は並べ替えバッファに保持し、
+//!   欠落分が届いた時点で番号順に上位レイヤーへ渡す（バイトストリームの欠落・重複なし）
+//! - **状態同期**: 端末モードでは差分を連結できないので、受信した状態を番号ごとに保持し、
+//!   差分を起点の状態に適用する（[`sync`] モジュール）
 //!
-//! ## セッションの状態遷移
+//! ## 接続状態
+//!
+//! 最終受信時刻と連続再送タイムアウト回数から [`ConnectionState`] を判定する
+//! （詳細は [`state`] モジュール）。
 //!
 //! ```text
-//! Initial → Connected (初回 Instruction 送受信後)
-//!         → Connected (ハートビート継続)
-//!         → Timeout (長時間 ACK なし、アプリ層が判断)
+//! Connecting → Connected (初回受信後、ハートビートで維持)
+//!            → Stalled   (STALLED_AFTER_MS 受信なし、または再送が続く)
+//!            → Lost      (LOST_AFTER_MS 受信なし)
 //! ```
 
 #![no_std]
 extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
 
+pub mod clock;
+pub mod clocked;
+pub mod congestion;
+pub mod pacing;
 pub mod session;
+pub mod state;
+pub mod sync;
 
+pub use clock::{Clock, ManualClock, MonotonicClock};
+#[cfg(feature = "std")]
+pub use clock::StdClock;
+pub use clocked::ClockedSession;
+pub use congestion::{CongestionControl, CongestionPhase, CongestionStats, NewReno};
+pub use pacing::SendPacing;
 pub use session::SspSession;
+pub use state::{ConnectionState, ConnectionThresholds};
+pub use sync::{ReceivedStates, SyncState};
 
 pub use mosh_proto::MOSH_PROTOCOL_VERSION;
 
@@ -37,6 +73,23 @@ pub use mosh_proto::MOSH_PROTOCOL_VERSION;
 /// mosh C++ 実装では 3000ms
 pub const HEARTBEAT_INTERVAL_MS: u64 = 3000;
 
+/// 遅延 ACK の待ち時間（ミリ秒）
+/// mosh C++ 実装の ACK_DELAY (100ms) に相当。データ受信後、この時間内に
+/// 送信データがなければ ACK のみの Instruction を送る
+pub const ACK_DELAY_MS: u64 = 100;
+
+/// 書き込みをまとめる最小の待ち時間（ミリ秒）
+/// mosh C++ 実装の SEND_MINDELAY (8ms) に相当
+pub const SEND_MINDELAY_MS: u64 = 8;
+
+/// 新しい Instruction を作る間隔の下限（ミリ秒）
+/// mosh C++ 実装の SEND_INTERVAL_MIN (20ms) に相当
+pub const SEND_INTERVAL_MIN_MS: u64 = 20;
+
+/// 新しい Instruction を作る間隔の上限（ミリ秒）
+/// mosh C++ 実装の SEND_INTERVAL_MAX (250ms) に相当
+pub const SEND_INTERVAL_MAX_MS: u64 = 250;
+
 /// 再送タイムアウト最小値（ミリ秒）
 pub const RTO_MIN_MS: u64 = 50;
 
@@ -45,3 +98,52 @@ pub const RTO_MAX_MS: u64 = 1000;
 
 /// 初期 RTO（ミリ秒）
 pub const RTO_INITIAL_MS: u64 = 1000;
+
+/// 受信したタイムスタンプをエコーバックできる最大の保持時間（ミリ秒）
+/// これより長く保持したタイムスタンプは RTT 計測に使えないので捨てる（mosh C++ 実装と同じ）
+pub const TIMESTAMP_ECHO_MAX_HOLD_MS: u64 = 1000;
+
+/// タイムスタンプのエコーから得た RTT サンプルの上限（ミリ秒）
+/// これ以上の値は 16 ビットのラップアラウンドや古いエコーによる誤計測とみなして捨てる
+pub const RTT_SAMPLE_MAX_MS: u64 = 5000;
+
+/// 指数バックオフ後の再送間隔の上限（ミリ秒）のデフォルト値
+/// 経路断の間も、再送はこの間隔より頻繁には行わない
+pub const RTO_BACKOFF_MAX_MS: u64 = 10_000;
+
+/// ACK 待ちにできる Instruction の最大数
+/// これに達している間は新しい Instruction を作らず、送信データは ACK が進むまで保持する
+pub const MAX_PENDING_INSTRUCTIONS: usize = 32;
+
+/// 送信済み・未 ACK の Instruction が運ぶバイト数の上限（デフォルト値）
+/// 遅い回線に大量のデータを流し込んでもメモリに積み上がらず、累積再送の
+/// Instruction も再組み立て上限（4 MiB）より十分小さく保たれる
+pub const SEND_WINDOW_MAX_BYTES: usize = 256 * 1024;
+
+/// 輻輳ウィンドウの単位となるセグメントのバイト数
+/// Instruction は任意長のバイト列なので、TCP の MSS に相当する目安の値
+pub const CWND_SEGMENT_BYTES: usize = 1400;
+
+/// 輻輳ウィンドウの初期値（セグメント数）。RFC 6928 の初期ウィンドウに合わせる
+pub const CWND_INITIAL_SEGMENTS: usize = 10;
+
+/// 再送タイムアウト後の ssthresh の下限（セグメント数）
+pub const CWND_MIN_SEGMENTS: usize = 2;
+
+/// 並べ替えバッファに保持する Instruction の最大数
+/// これを超えて順序が飛んだ Instruction は破棄し、相手の再送を待つ
+pub const REORDER_BUFFER_MAX: usize = 256;
+
+/// 状態同期で受信側が保持する状態の最大数
+/// mosh C++ 実装の受信状態キューの上限（1024）に合わせる。これに達している間は新しい状態を受け付けない
+pub const RECEIVED_STATES_MAX: usize = 1024;
+
+/// 最終受信からこの時間（ミリ秒）が経過したら Stalled とみなす（デフォルト値）
+/// mosh C++ 実装で「Last contact」通知を出し始める 6.5 秒に合わせる
+pub const STALLED_AFTER_MS: u64 = 6500;
+
+/// 最終受信からこの時間（ミリ秒）が経過したら Lost とみなす（デフォルト値）
+pub const LOST_AFTER_MS: u64 = 60_000;
+
+/// ACK が進まないままの連続再送タイムアウトがこの回数に達したら Stalled とみなす（デフォルト値）
+pub const STALLED_AFTER_TIMEOUTS: u32 = 3;
@@ -0,0 +1,68 @@
+//! 送信ペーシング
+//!
+//! mosh C++ 実装の TransportSender と同じく、上位レイヤーからの細かい書き込みを
+//! まとめてから新しい Instruction を作る。
+//!
+//! ```text
+//! 次の送信枠 = max(最初の未送信データを検出した時刻 + min_delay_ms,
+//!                  前回データ Instruction を作った時刻 + 送信間隔)
+//! 送信間隔   = clamp(SRTT / 2, interval_min_ms, interval_max_ms)
+//! ```
+//!
+//! キー入力の連打やストリームへの小さな書き込みが、それぞれ 46 バイト超の
+//! オーバーヘッドを持つパケットになるのを防ぐ。
+
+use crate::{SEND_INTERVAL_MAX_MS, SEND_INTERVAL_MIN_MS, SEND_MINDELAY_MS};
+
+/// 送信ペーシングの設定
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SendPacing {
+    /// 書き込みをまとめる最小の待ち時間（ミリ秒）
+    pub min_delay_ms: u64,
+    /// 新しい Instruction を作る間隔の下限（ミリ秒）
+    pub interval_min_ms: u64,
+    /// 新しい Instruction を作る間隔の上限（ミリ秒）
+    pub interval_max_ms: u64,
+}
+
+impl SendPacing {
+    /// ペーシングなし（書き込みごとに即座に Instruction を作る）
+    pub const DISABLED: SendPacing = SendPacing {
+        min_delay_ms: 0,
+        interval_min_ms: 0,
+        interval_max_ms: 0,
+    };
+
+    /// SRTT から送信間隔（ミリ秒）を求める
+    ///
+    /// RTT 未計測（`srtt_ms == 0`）の間は下限を使う。
+    pub fn send_interval_ms(&self, srtt_ms: f64) -> u64 {
+        let half_rtt = (srtt_ms / 2.0) as u64;
+        half_rtt.clamp(self.interval_min_ms, self.interval_max_ms.max(self.interval_min_ms))
+    }
+}
+
+impl Default for SendPacing {
+    fn default() -> Self {
+        SendPacing {
+            min_delay_ms: SEND_MINDELAY_MS,
+            interval_min_ms: SEND_INTERVAL_MIN_MS,
+            interval_max_ms: SEND_INTERVAL_MAX_MS,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_send_interval_clamped() {
+        let pacing = SendPacing::default();
+        assert_eq!(pacing.send_interval_ms(0.0), SEND_INTERVAL_MIN_MS);
+        assert_eq!(pacing.send_interval_ms(100.0), 50);
+        assert_eq!(pacing.send_interval_ms(10.0), SEND_INTERVAL_MIN_MS);
+        assert_eq!(pacing.send_interval_ms(2000.0), SEND_INTERVAL_MAX_MS);
+        assert_eq!(SendPacing::DISABLED.send_interval_ms(100.0), 0);
+    }
+}
@@ -3,24 +3,41 @@
 //! mosh の Transport クラス相当の実装。
 //! 送受信状態の管理、ACK 処理、ハートビート、RTT 推定、再送を担当する。
 
-use alloc::collections::VecDeque;
+use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, VecDeque};
 use alloc::vec::Vec;
 
 use mosh_proto::Instruction;
-
-use crate::{HEARTBEAT_INTERVAL_MS, RTO_INITIAL_MS, RTO_MAX_MS, RTO_MIN_MS};
+use mosh_transport::Timestamp16;
+
+use crate::clock::Clock;
+use crate::clocked::ClockedSession;
+use crate::congestion::{CongestionControl, CongestionStats, NewReno};
+use crate::pacing::SendPacing;
+use crate::state::{ConnectionState, ConnectionThresholds};
+use crate::sync::{ReceivedStates, SyncState};
+#[cfg(test)]
+use crate::{CWND_MIN_SEGMENTS, CWND_SEGMENT_BYTES, SEND_INTERVAL_MIN_MS, SEND_MINDELAY_MS};
+use crate::{
+    ACK_DELAY_MS, HEARTBEAT_INTERVAL_MS, MAX_PENDING_INSTRUCTIONS, REORDER_BUFFER_MAX,
+    RTO_BACKOFF_MAX_MS, RTO_INITIAL_MS, RTO_MAX_MS, RTO_MIN_MS, RTT_SAMPLE_MAX_MS,
+    SEND_WINDOW_MAX_BYTES, TIMESTAMP_ECHO_MAX_HOLD_MS,
+};
 
 /// ACK 前の送信済み Instruction
+///
+/// 状態 `num - 1` → `num` の差分（この Instruction で新たに積まれたバイト列）を保持する。
+/// 再送時は未 ACK 分の差分を連結し、`last_acked` → 最新番号の 1 つの Instruction にまとめる。
 #[derive(Debug, Clone)]
 struct PendingInstruction {
     /// Instruction の new_num（識別用）
     num: u64,
-    /// エンコード済み Instruction バイト列
-    payload: Vec<u8>,
+    /// 状態 num - 1 → num の差分バイト列
+    diff: Vec<u8>,
     /// 送信時刻（ミリ秒）
     sent_at_ms: u64,
-    /// 再送回数
-    retransmit_count: u32,
+    /// バックオフの段数（再送待ち時間 = RTO × 2^backoff）。ACK が進むと 0 に戻す
+    backoff: u32,
 }
 
 /// SSP 送信側の状態
@@ -33,23 +50,42 @@ struct SendState {
     pending: VecDeque<PendingInstruction>,
     /// 送信待ちペイロード（push_payload で積まれたデータ）
     outgoing_diff: Vec<u8>,
+    /// push_message で積んだメッセージの終端（outgoing_diff 内のオフセット、昇順）
+    /// 空でなければ、Instruction はメッセージの途中で分割しない
+    message_ends: VecDeque<usize>,
+    /// 送信待ちペイロードを tick が最初に検出した時刻（書き込みをまとめる待ち時間の起点）
+    mindelay_clock_ms: Option<u64>,
+    /// 最後にデータを運ぶ新しい Instruction を作った時刻
+    last_data_send_ms: Option<u64>,
     /// 最後に送信した時刻（ミリ秒）
     last_send_ms: u64,
+    /// 再送タイムアウトで失われたとみなした番号の上限
+    lost_through: u64,
+    /// 失われた Instruction のうち再送し終えた番号（`lost_through` に追いつけば回復完了）
+    resent_through: u64,
+    /// ACK が進まないまま連続で再送タイムアウトした回数
+    consecutive_timeouts: u32,
 }
 
 /// SSP 受信側の状態
 struct RecvState {
-    /// 最後に受信した Instruction の番号
+    /// 最後に（欠落なく順番通りに）受信した Instruction の番号
     last_recv_num: u64,
+    /// 順序が飛んで届いた Instruction（new_num → (old_num, diff)）
+    /// 起点の状態（old_num）に追いついた時点で番号順に上位レイヤーへ渡す
+    reorder: BTreeMap<u64, (u64, Vec<u8>)>,
+    /// 受信済みの状態番号 → その状態までに受信したストリームの総バイト数
+    /// 累積再送（old_num が受信済みの番号より古い）で既受信部分を読み飛ばすのに使う
+    offsets: BTreeMap<u64, u64>,
     /// throwaway_num（これより古いものは破棄可能）
     throwaway_num: u64,
-    /// 最後に受信した時刻（ミリ秒）
-    last_recv_ms: u64,
-    /// エコーバック用のタイムスタンプ（受信パケットの timestamp をエコー）
-    /// 将来の RTT 計算精度向上のために保持
-    _last_timestamp: u16,
-    /// タイムスタンプを受信した時刻（将来の RTT 計算用）
-    _last_timestamp_recv_ms: u64,
+    /// 最後に受信した時刻（ミリ秒）。未受信なら None
+    last_recv_ms: Option<u64>,
+    /// 遅延 ACK の期限（ミリ秒）。データ受信で設定され、何かを送信するとクリアされる
+    ack_deadline_ms: Option<u64>,
+    /// エコーバック待ちのタイムスタンプ（受信パケットの timestamp, 受信時刻）
+    /// 次の送信パケットの timestamp_reply に保持時間を加えて 1 度だけ載せる
+    saved_timestamp: Option<(Timestamp16, u64)>,
 }
 
 /// SSP セッション
@@ -67,6 +103,18 @@ pub struct SspSession {
     rttvar_ms: f64,
     /// RTO（Retransmission Timeout）
     rto_ms: u64,
+    /// 指数バックオフ後の再送間隔の上限（ミリ秒）
+    max_backoff_ms: u64,
+    /// 送信ペーシングの設定
+    pacing: SendPacing,
+    /// 送信済み・未 ACK のバイト数の上限
+    max_in_flight_bytes: usize,
+    /// 輻輳制御アルゴリズム
+    congestion: Box<dyn CongestionControl>,
+    /// 接続状態の判定しきい値
+    thresholds: ConnectionThresholds,
+    /// 最後に `poll_state_change` で通知した接続状態
+    reported_state: ConnectionState,
 }
 
 impl SspSession {
@@ -78,35 +126,119 @@ impl SspSession {
                 last_acked: 0,
                 pending: VecDeque::new(),
                 outgoing_diff: Vec::new(),
+                message_ends: VecDeque::new(),
+                mindelay_clock_ms: None,
+                last_data_send_ms: None,
                 last_send_ms: 0,
+                lost_through: 0,
+                resent_through: 0,
+                consecutive_timeouts: 0,
             },
             recv: RecvState {
                 last_recv_num: 0,
+                reorder: BTreeMap::new(),
+                offsets: BTreeMap::from([(0, 0)]),
                 throwaway_num: 0,
-                last_recv_ms: 0,
-                _last_timestamp: u16::MAX,
-                _last_timestamp_recv_ms: 0,
+                last_recv_ms: None,
+                ack_deadline_ms: None,
+                saved_timestamp: None,
             },
             srtt_ms: 0.0,
             rttvar_ms: 0.0,
             rto_ms: RTO_INITIAL_MS,
+            max_backoff_ms: RTO_BACKOFF_MAX_MS,
+            pacing: SendPacing::default(),
+            max_in_flight_bytes: SEND_WINDOW_MAX_BYTES,
+            congestion: Box::new(NewReno::new()),
+            thresholds: ConnectionThresholds::default(),
+            reported_state: ConnectionState::Connecting,
         }
     }
 
+    /// 指数バックオフ後の再送間隔の上限を設定する（ビルダー）
+    ///
+    /// # 引数
+    /// - `max_backoff_ms`: 再送間隔の上限（ミリ秒）。RTO より短い値を指定しても、
+    ///   RTO より頻繁に再送することはない。
+    pub fn with_max_backoff(mut self, max_backoff_ms: u64) -> Self {
+        self.max_backoff_ms = max_backoff_ms;
+        self
+    }
+
+    /// 送信ペーシングを設定する（ビルダー）
+    ///
+    /// [`SendPacing::DISABLED`] を指定すると、tick ごとに即座に Instruction を作る。
+    pub fn with_send_pacing(mut self, pacing: SendPacing) -> Self {
+        self.pacing = pacing;
+        self
+    }
+
+    /// 輻輳制御アルゴリズムを差し替える（ビルダー）
+    ///
+    /// デフォルトは [`NewReno`]。
+    pub fn with_congestion_control(mut self, congestion: Box<dyn CongestionControl>) -> Self {
+        self.congestion = congestion;
+        self
+    }
+
+    /// 時刻源を持たせる
+    ///
+    /// 以降は `now_ms` を渡す代わりに `clock` から時刻を読む（[`ClockedSession`]）。
+    pub fn with_clock<C: Clock>(self, clock: C) -> ClockedSession<C> {
+        ClockedSession::new(self, clock)
+    }
+
+    /// 送信済み・未 ACK のバイト数の上限を設定する
+    ///
+    /// 上限に達している間は新しい Instruction を作らず、送信待ちのデータは ACK が進むまで保持する。
+    /// 1 つの Instruction に載せるバイト数も、上限までの残りに制限される。
+    pub fn set_max_in_flight_bytes(&mut self, max_in_flight_bytes: usize) {
+        self.max_in_flight_bytes = max_in_flight_bytes;
+    }
+
     /// 上位レイヤーからの送信データを積む
     ///
+    /// データは次の送信枠（[`SspSession::next_send_slot_ms`]）まで保持され、
+    /// その間に積まれたデータと 1 つの Instruction にまとめられる。
+    /// 待ち時間の計時は、積んだ後の最初の `tick` から始まる。
+    ///
+    /// 積めるバイト数に上限はない。送信待ちを増やしすぎないよう、
+    /// 呼び出し側は [`SspSession::send_window_room`] の範囲で積むこと。
+    ///
     /// # 引数
     /// - `diff`: 送信するバイト列（バイトストリームモードでは VS Code RPC データ）
     pub fn push_payload(&mut self, diff: Vec<u8>) {
         self.send.outgoing_diff.extend_from_slice(&diff);
     }
 
+    /// 途中で分割してはならない送信データ（メッセージ）を積む
+    ///
+    /// 端末モードの UserMessage のように、受信側が差分を丸ごとデコードする場合に使う。
+    /// 連結したメッセージは 1 つの Instruction にまとめるが、1 つのメッセージが
+    /// 2 つの Instruction にまたがることはない。ウィンドウに収まらないメッセージは ACK を待ち、
+    /// 何も送信中でなければウィンドウを超えても 1 つは送る（停止を防ぐ）。
+    ///
+    /// 同じセッションで [`push_payload`](Self::push_payload) と混ぜて使わないこと。
+    pub fn push_message(&mut self, message: Vec<u8>) {
+        self.send.outgoing_diff.extend_from_slice(&message);
+        self.send.message_ends.push_back(self.send.outgoing_diff.len());
+    }
+
     /// タイマー tick を処理し、送信すべき Instruction バイト列のリストを返す
     ///
     /// Node.js の setInterval(50ms) から定期的に呼び出す。
-    /// - ペイロードがあれば送信 Instruction を生成
-    /// - 再送が必要な Instruction があれば再送
-    /// - ハートビートが必要なら ACK のみの Instruction を生成
+    /// - ペイロードがあり、送信枠（ペーシング）が来ていて、輻輳ウィンドウに空きがあれば送信 Instruction を生成
+    /// - 最古の未 ACK Instruction が再送待ち時間を超えたら、未 ACK 分すべてを失われたとみなし、
+    ///   輻輳ウィンドウに収まる分を 1 つの Instruction（old_num = last_acked, new_num = 収まる最新）に
+    ///   まとめて再送する。残りは ACK でウィンドウが空くたびに続きから再送する
+    /// - 再送待ち時間は RTO × 2^(連続タイムアウト回数) で、上限は `with_max_backoff` の値
+    /// - 遅延 ACK の期限が来たら、またはハートビートが必要なら ACK のみの Instruction を生成
+    ///
+    /// 送信する Instruction はすべて ack_num を運ぶので、何かを送った時点で遅延 ACK は不要になる。
+    ///
+    /// 未 ACK の Instruction が `MAX_PENDING_INSTRUCTIONS` に達している間、または
+    /// 未 ACK のバイト数が上限（`set_max_in_flight_bytes`）に達している間は
+    /// 新しい Instruction を作らず、ペイロードは ACK が進むまで保持する。
     ///
     /// # 引数
     /// - `now_ms`: 現在時刻（JS Date.now()）
@@ -116,30 +248,70 @@ impl SspSession {
     pub fn tick(&mut self, now_ms: u64) -> Vec<Vec<u8>> {
         let mut to_send = Vec::new();
 
-        // 送信待ちペイロードがあれば送信 Instruction を生成
-        if !self.send.outgoing_diff.is_empty() {
-            let diff = core::mem::take(&mut self.send.outgoing_diff);
-            let instr = self.make_send_instruction(diff, now_ms);
-            let bytes = instr.encode_to_bytes();
-            self.enqueue_pending(instr.new_num_or_zero(), bytes.clone(), now_ms);
-            to_send.push(bytes);
+        // 再送チェック: 最古の pending Instruction が再送待ち時間（バックオフ込み）を超過したか
+        let retransmit_due = self
+            .send
+            .pending
+            .front()
+            .is_some_and(|p| now_ms.saturating_sub(p.sent_at_ms) >= self.retransmit_timeout_ms(p));
+
+        // 再送対象（既送信の pending）の送信時刻・バックオフ段数を更新
+        if retransmit_due {
+            let in_flight = self.in_flight_bytes();
+            for pending in &mut self.send.pending {
+                pending.sent_at_ms = now_ms;
+                pending.backoff = pending.backoff.saturating_add(1);
+            }
+            self.send.consecutive_timeouts = self.send.consecutive_timeouts.saturating_add(1);
+            self.congestion.on_timeout(in_flight, now_ms);
         }
 
-        // 再送チェック: RTO 超過した pending Instruction を再送
-        let rto = self.rto_ms;
-        for pending in &mut self.send.pending {
-            if now_ms.saturating_sub(pending.sent_at_ms) >= rto {
-                pending.sent_at_ms = now_ms;
-                pending.retransmit_count += 1;
-                to_send.push(pending.payload.clone());
+        // 送信待ちペイロードの待ち時間の計時を始める
+        if !self.send.outgoing_diff.is_empty() && self.send.mindelay_clock_ms.is_none() {
+            self.send.mindelay_clock_ms = Some(now_ms);
+        }
+
+        if retransmit_due {
+            // 送信待ちのデータも枠を待たずに同乗させ、未 ACK 分と一緒に失われたものとして再送する
+            let room = self.max_in_flight_bytes.saturating_sub(self.in_flight_bytes());
+            self.enqueue_outgoing(room, now_ms);
+            self.send.lost_through = self.send.pending.back().map_or(self.send.last_acked, |p| p.num);
+            self.send.resent_through = self.send.last_acked;
+        }
+
+        // 失われた Instruction を輻輳ウィンドウに収まる分だけまとめて再送する
+        if let Some(new_num) = self.resend_range() {
+            let old_num = self.send.resent_through;
+            for pending in &mut self.send.pending {
+                if pending.num > old_num && pending.num <= new_num {
+                    pending.sent_at_ms = now_ms;
+                }
+            }
+            self.send.resent_through = new_num;
+            let instr = self.make_send_instruction(old_num, new_num);
+            to_send.push(instr.encode_to_bytes());
+        }
+
+        // 送信枠が来ていれば新しい番号を割り当てる（ウィンドウに収まる分だけ載せ、残りは ACK を待つ）
+        let slot_open = self
+            .next_send_slot_ms(now_ms)
+            .is_some_and(|slot| now_ms >= slot);
+        if !retransmit_due && slot_open {
+            if let Some(num) = self.enqueue_outgoing(self.window_room(), now_ms) {
+                let instr = self.make_send_instruction(num - 1, num);
+                to_send.push(instr.encode_to_bytes());
             }
         }
 
-        // ハートビートが必要なら送信
-        if to_send.is_empty() && self.needs_heartbeat(now_ms) {
+        // 遅延 ACK の期限切れ、またはハートビートが必要なら ACK のみを送信
+        if to_send.is_empty() && (self.ack_due(now_ms) || self.needs_heartbeat(now_ms)) {
             let ack_instr = self.make_ack(now_ms);
             to_send.push(ack_instr.encode_to_bytes());
+        }
+
+        if !to_send.is_empty() {
             self.send.last_send_ms = now_ms;
+            self.recv.ack_deadline_ms = None;
         }
 
         to_send
@@ -147,48 +319,133 @@ impl SspSession {
 
     /// 受信した Instruction を処理し、上位レイヤーに渡すペイロードを返す
     ///
+    /// ペイロードは必ず状態番号順に、欠落・重複なく返す。
+    /// - 起点（old_num）がまだ受信していない状態の Instruction は並べ替えバッファに保持し、
+    ///   欠落分が（再送で）届いた時点でまとめて返す。
+    /// - 起点が受信済みの状態より古い累積再送は、既に受信した部分を読み飛ばして返す。
+    ///
     /// # 引数
     /// - `instr`: 受信・復号・再組み立て済みの Instruction
     /// - `now_ms`: 受信時刻（RTT 計算用）
     ///
     /// # 戻り値
-    /// - `Some(bytes)`: 有効なペイロード（上位レイヤーに渡す）
-    /// - `None`: 重複・古すぎるパケット（破棄）
+    /// - `Some(bytes)`: 順番通りに揃った有効なペイロード（上位レイヤーに渡す）
+    /// - `None`: 重複・順序待ち・ハートビート（渡すデータなし）
     pub fn recv_instruction(&mut self, instr: &Instruction, now_ms: u64) -> Option<Vec<u8>> {
-        let new_num = instr.new_num_or_zero();
-        let ack_num = instr.ack_num_or_zero();
-        let throwaway_num = instr.throwaway_num_or_zero();
+        let (old_num, new_num) = self.recv_header(instr, now_ms)?;
 
-        // ACK 処理: 相手が ACK した番号までの pending を解放
-        self.process_ack(ack_num, now_ms);
+        // 既に受信済みの Instruction は無視（重複）
+        if new_num <= self.recv.last_recv_num {
+            return None;
+        }
 
-        // throwaway_num 更新
-        if throwaway_num > self.recv.throwaway_num {
-            self.recv.throwaway_num = throwaway_num;
+        // 起点の状態をまだ受信していない: 欠落分の到着まで並べ替えバッファに保持する
+        if old_num > self.recv.last_recv_num {
+            let buffer_full = self.recv.reorder.len() >= REORDER_BUFFER_MAX;
+            if !buffer_full || self.recv.reorder.contains_key(&new_num) {
+                self.recv
+                    .reorder
+                    .insert(new_num, (old_num, instr.diff_bytes().to_vec()));
+            }
+            // バッファが満杯なら破棄（ACK していないので相手が再送する）
+            return None;
         }
 
-        // 受信時刻を更新
-        self.recv.last_recv_ms = now_ms;
+        let mut payload = Vec::new();
+        self.apply_diff(old_num, new_num, instr.diff_bytes(), &mut payload);
 
-        // 重複・古いパケットのチェック
-        // new_num == 0 はハートビート（ACK のみ）なのでペイロードなし
-        if new_num == 0 {
-            return None;
+        // 追いついた並べ替えバッファ分もまとめて渡す
+        loop {
+            let last = self.recv.last_recv_num;
+            self.recv.reorder.retain(|&num, _| num > last);
+
+            let next = self
+                .recv
+                .reorder
+                .iter()
+                .find(|(_, (old, _))| *old <= last)
+                .map(|(&num, _)| num);
+            let Some(num) = next else { break };
+
+            let (old, diff) = self.recv.reorder.remove(&num).unwrap();
+            self.apply_diff(old, num, &diff, &mut payload);
         }
 
-        // 既に受信済みの Instruction は無視（重複）
-        if new_num <= self.recv.last_recv_num {
-            return None;
+        if payload.is_empty() {
+            None
+        } else {
+            Some(payload)
         }
+    }
 
-        // 受信番号を更新
-        self.recv.last_recv_num = new_num;
+    /// 受信した Instruction を状態同期（端末モード）として処理する
+    ///
+    /// 差分は起点の状態（old_num）の複製に適用し、新しい状態として `states` に保持する
+    /// （[`sync`](crate::sync) モジュール）。起点の状態を持っていない・受信済みの Instruction は
+    /// 無視する。ACK には保持している最新の状態番号を返す。
+    ///
+    /// # 戻り値
+    /// - `Ok(Some(new_num))`: 新しい状態を保持した
+    /// - `Ok(None)`: 重複・起点なし・ハートビート（状態は変わらない）
+    /// - `Err(e)`: 差分を適用できなかった（ACK しないので相手が送り直す）
+    pub fn recv_state_instruction<S: SyncState>(
+        &mut self,
+        instr: &Instruction,
+        states: &mut ReceivedStates<S>,
+        now_ms: u64,
+    ) -> Result<Option<u64>, S::Error> {
+        let Some((old_num, new_num)) = self.recv_header(instr, now_ms) else {
+            return Ok(None);
+        };
+
+        let applied = states.apply(old_num, new_num, instr.diff_bytes())?;
+        states.discard_before(self.recv.throwaway_num);
+        self.recv.last_recv_num = states.latest_num();
+        Ok(applied.then_some(new_num))
+    }
 
-        // ペイロードを返す
-        if instr.has_diff() {
-            Some(instr.diff.clone().unwrap_or_default())
-        } else {
-            None
+    /// 遅延 ACK を設定する（既に設定済みなら期限を延ばさない）
+    ///
+    /// 組み立て終えた Instruction の Fragment が再送されてきたときなど、
+    /// Instruction を復元せずに ACK だけを送り直したい場合に呼ぶ。
+    pub fn schedule_ack(&mut self, now_ms: u64) {
+        if self.recv.ack_deadline_ms.is_none() {
+            self.recv.ack_deadline_ms = Some(now_ms + ACK_DELAY_MS);
+        }
+    }
+
+    /// 受信パケットのヘッダーのタイムスタンプを処理する
+    ///
+    /// Instruction が完成したかどうかに関係なく、復号できたすべてのパケットについて呼ぶ。
+    /// - `timestamp`: 次の送信でエコーバックするために受信時刻と共に保持する
+    /// - `timestamp_reply`: 自分が送ったタイムスタンプのエコー。現在時刻との差を RTT サンプルにする
+    ///
+    /// どちらも [`Timestamp16::INIT`] なら無視する。16 ビットのラップアラウンドは差分計算で吸収し、
+    /// `RTT_SAMPLE_MAX_MS` 以上のサンプルは捨てる。
+    pub fn recv_timestamps(&mut self, timestamp: Timestamp16, timestamp_reply: Timestamp16, now_ms: u64) {
+        if timestamp.is_initialized() {
+            self.recv.saved_timestamp = Some((timestamp, now_ms));
+        }
+
+        if timestamp_reply.is_initialized() {
+            let rtt = Timestamp16::diff(Timestamp16::now_from_ms(now_ms), timestamp_reply) as u64;
+            if rtt < RTT_SAMPLE_MAX_MS {
+                self.update_rtt(rtt, now_ms);
+            }
+        }
+    }
+
+    /// 送信パケットに載せる timestamp_reply を返す
+    ///
+    /// 保持しているタイムスタンプに保持時間（受信から現在まで）を加えて返し、破棄する
+    /// （エコーは 1 度だけ）。相手は自分の時刻との差から、こちらの処理待ちを除いた RTT を得る。
+    /// 保持していない、または保持時間が `TIMESTAMP_ECHO_MAX_HOLD_MS` 以上なら [`Timestamp16::INIT`]。
+    pub fn timestamp_reply(&mut self, now_ms: u64) -> Timestamp16 {
+        match self.recv.saved_timestamp.take() {
+            Some((timestamp, recv_ms)) if now_ms.saturating_sub(recv_ms) < TIMESTAMP_ECHO_MAX_HOLD_MS => {
+                timestamp.add_ms(now_ms.saturating_sub(recv_ms))
+            }
+            _ => Timestamp16::INIT,
         }
     }
 
@@ -196,15 +453,146 @@ impl SspSession {
     pub fn make_ack(&self, _now_ms: u64) -> Instruction {
         Instruction::new_ack(
             self.recv.last_recv_num,
-            self.recv.throwaway_num,
+            self.send.last_acked,
         )
     }
 
+    /// 遅延 ACK の期限が来ているか
+    pub fn ack_due(&self, now_ms: u64) -> bool {
+        self.recv.ack_deadline_ms.is_some_and(|deadline| now_ms >= deadline)
+    }
+
+    /// 次に `tick` を呼ぶべき時刻（ミリ秒、絶対時刻）を返す
+    ///
+    /// 以下の期限のうち最も早いものを返す。既に期限が来ているものがあれば `now_ms`。
+    /// - 最古の未 ACK Instruction の再送（送信時刻 + バックオフ込みの再送待ち時間）
+    /// - ハートビート（最終送信時刻 + HEARTBEAT_INTERVAL_MS）
+    /// - 遅延 ACK の期限
+    /// - 送信待ちペイロードの送信枠（`tick` 前に積まれたデータは計時開始のため即時）
+    /// - 再送タイムアウト後、輻輳ウィンドウが空いて失われた分の続きを再送できるなら即時
+    /// - 受信がないまま接続状態が変わる時刻（Stalled / Lost への遷移）
+    ///
+    /// ホストはこの時刻に 1 回だけタイマーを設定すればよく、固定間隔のポーリングは不要。
+    pub fn next_deadline_ms(&self, now_ms: u64) -> u64 {
+        let mut deadline = self.send.last_send_ms + HEARTBEAT_INTERVAL_MS;
+
+        if let Some(front) = self.send.pending.front() {
+            deadline = deadline.min(front.sent_at_ms + self.retransmit_timeout_ms(front));
+        }
+
+        if let Some(ack_deadline) = self.recv.ack_deadline_ms {
+            deadline = deadline.min(ack_deadline);
+        }
+
+        if let Some(transition) = self.thresholds.next_transition_ms(self.recv.last_recv_ms, now_ms) {
+            deadline = deadline.min(transition);
+        }
+
+        if let Some(slot) = self.next_send_slot_ms(now_ms) {
+            let slot = if self.send.mindelay_clock_ms.is_none() { now_ms } else { slot };
+            deadline = deadline.min(slot);
+        }
+
+        // ACK で輻輳ウィンドウが空き、失われた分の続きを再送できる
+        if self.resend_range().is_some() {
+            deadline = now_ms;
+        }
+
+        deadline.max(now_ms)
+    }
+
+    /// 送信待ちペイロードを新しい Instruction として送信できる時刻（ミリ秒）
+    ///
+    /// `max(計時開始 + min_delay_ms, 前回のデータ送信 + 送信間隔)`。
+    /// 計時がまだ始まっていなければ `now_ms` に `tick` した場合の時刻を返す。
+    /// 送信待ちペイロードがない、ACK 待ち（Instruction 数・バイト数）が上限に達している、
+    /// または輻輳ウィンドウに空きがなければ `None`。
+    pub fn next_send_slot_ms(&self, now_ms: u64) -> Option<u64> {
+        if self.send.outgoing_diff.is_empty()
+            || self.send.pending.len() >= MAX_PENDING_INSTRUCTIONS
+            || self.window_room() == 0
+        {
+            return None;
+        }
+
+        let clock = self.send.mindelay_clock_ms.unwrap_or(now_ms);
+        let mut slot = clock + self.pacing.min_delay_ms;
+        if let Some(last) = self.send.last_data_send_ms {
+            slot = slot.max(last + self.send_interval_ms());
+        }
+        Some(slot)
+    }
+
+    /// 最後に Instruction にした状態の番号（まだなければ 0）
+    ///
+    /// `queued_bytes() == 0` なら、それまでに積んだデータはすべてこの番号までの状態に入っている。
+    pub fn last_sent_num(&self) -> u64 {
+        self.send.next_send_num - 1
+    }
+
+    /// 送信済み・未 ACK の Instruction が運ぶバイト数
+    pub fn in_flight_bytes(&self) -> usize {
+        self.send.pending.iter().map(|p| p.diff.len()).sum()
+    }
+
+    /// 送信待ちペイロード（まだ Instruction にしていないデータ）のバイト数
+    pub fn queued_bytes(&self) -> usize {
+        self.send.outgoing_diff.len()
+    }
+
+    /// 未 ACK のバイト数の上限と輻輳ウィンドウの両方に収まるよう、あと何バイト積めるか
+    ///
+    /// 送信待ちのデータも数える。`push_payload` をこの範囲に抑えれば、
+    /// 積んだデータは ACK を待たずに次の送信枠で送られる。
+    pub fn send_window_room(&self) -> usize {
+        self.window_room().saturating_sub(self.queued_bytes())
+    }
+
+    /// 現在の送信間隔（ミリ秒）。SRTT / 2 をペーシング設定の範囲に収めた値
+    pub fn send_interval_ms(&self) -> u64 {
+        self.pacing.send_interval_ms(self.srtt_ms)
+    }
+
+    /// 現在の接続状態を返す
+    pub fn state(&self, now_ms: u64) -> ConnectionState {
+        self.thresholds
+            .classify(self.recv.last_recv_ms, self.send.consecutive_timeouts, now_ms)
+    }
+
+    /// 前回の呼び出しから接続状態が変わっていれば、新しい状態を返す
+    ///
+    /// ホストは `tick` / `recv_instruction` の後に呼び出し、`Some` なら UI 等に通知する。
+    pub fn poll_state_change(&mut self, now_ms: u64) -> Option<ConnectionState> {
+        let state = self.state(now_ms);
+        if state == self.reported_state {
+            return None;
+        }
+        self.reported_state = state;
+        Some(state)
+    }
+
+    /// 接続状態の判定しきい値を設定する
+    pub fn set_connection_thresholds(&mut self, thresholds: ConnectionThresholds) {
+        self.thresholds = thresholds;
+    }
+
+    /// 最後に相手から受信した時刻（ミリ秒）。未受信なら `None`
+    pub fn last_recv_ms(&self) -> Option<u64> {
+        self.recv.last_recv_ms
+    }
+
     /// ハートビートが必要か（前回送信から HEARTBEAT_INTERVAL_MS 経過）
     pub fn needs_heartbeat(&self, now_ms: u64) -> bool {
         now_ms.saturating_sub(self.send.last_send_ms) >= HEARTBEAT_INTERVAL_MS
     }
 
+    /// ACK が進まないまま連続で再送タイムアウトした回数
+    ///
+    /// 新しい ACK を受信すると 0 に戻る。値が大きいほど経路が切れている可能性が高い。
+    pub fn consecutive_timeouts(&self) -> u32 {
+        self.send.consecutive_timeouts
+    }
+
     /// セッション統計を返す
     pub fn stats(&self) -> SspStats {
         SspStats {
@@ -213,32 +601,201 @@ impl SspSession {
             send_num: self.send.next_send_num,
             recv_num: self.recv.last_recv_num,
             pending_count: self.send.pending.len(),
+            reorder_count: self.recv.reorder.len(),
+            consecutive_timeouts: self.send.consecutive_timeouts,
+            send_interval_ms: self.send_interval_ms(),
+            in_flight_bytes: self.in_flight_bytes(),
+            congestion: self.congestion.stats(),
         }
     }
 
     // ===== Private メソッド =====
 
-    /// 送信用 Instruction を組み立てる
-    fn make_send_instruction(&mut self, diff: Vec<u8>, _now_ms: u64) -> Instruction {
-        let old_num = self.send.last_acked;
-        let new_num = self.send.next_send_num;
-        self.send.next_send_num += 1;
+    /// 受信した Instruction のヘッダー（ACK・throwaway_num）を処理する
+    ///
+    /// 受信時刻を記録し、データを運ぶ Instruction なら遅延 ACK を設定する。
+    ///
+    /// # 戻り値
+    /// `(old_num, new_num)`。ハートビート（new_num == 0）なら `None`
+    fn recv_header(&mut self, instr: &Instruction, now_ms: u64) -> Option<(u64, u64)> {
+        let old_num = instr.old_num_or_zero();
+        let new_num = instr.new_num_or_zero();
+        let ack_num = instr.ack_num_or_zero();
+        let throwaway_num = instr.throwaway_num_or_zero();
+
+        // ACK 処理: 相手が ACK した番号までの pending を解放
+        self.process_ack(ack_num, now_ms);
+
+        // throwaway_num 更新（相手がもう起点に使わない状態のオフセットを解放）
+        if throwaway_num > self.recv.throwaway_num {
+            self.recv.throwaway_num = throwaway_num;
+            self.prune_offsets();
+        }
+
+        // 受信時刻を更新
+        self.recv.last_recv_ms = Some(now_ms);
+
+        // new_num == 0 はハートビート（ACK のみ）なのでペイロードなし
+        if new_num == 0 {
+            return None;
+        }
 
-        let throwaway_num = self.recv.throwaway_num;
-        let ack_num = self.recv.last_recv_num;
+        // データを運ぶ Instruction には（重複・順序待ちでも）遅延 ACK で応答する
+        // 重複は相手が ACK を受け取れていない合図なので、改めて ACK する
+        self.schedule_ack(now_ms);
 
-        Instruction::new_send(old_num, new_num, ack_num, throwaway_num, diff)
+        Some((old_num, new_num))
+    }
+
+    /// 送信用 Instruction を組み立てる
+    ///
+    /// pending の `old_num + 1 ..= new_num` の差分を連結して diff とする。
+    /// throwaway_num には `last_acked` を設定する（これより古い状態は起点に使わない）。
+    fn make_send_instruction(&self, old_num: u64, new_num: u64) -> Instruction {
+        let diff: Vec<u8> = self
+            .send
+            .pending
+            .iter()
+            .filter(|p| p.num > old_num && p.num <= new_num)
+            .flat_map(|p| p.diff.iter().copied())
+            .collect();
+
+        Instruction::new_send(
+            old_num,
+            new_num,
+            self.recv.last_recv_num,
+            self.send.last_acked,
+            diff,
+        )
     }
 
-    /// Pending キューに Instruction を追加する
-    fn enqueue_pending(&mut self, num: u64, payload: Vec<u8>, now_ms: u64) {
+    /// 新しい番号を割り当てて Pending キューに追加する
+    ///
+    /// # 戻り値
+    /// 割り当てた Instruction 番号
+    fn enqueue_pending(&mut self, diff: Vec<u8>, now_ms: u64) -> u64 {
+        let num = self.send.next_send_num;
+        self.send.next_send_num += 1;
         self.send.pending.push_back(PendingInstruction {
             num,
-            payload,
+            diff,
             sent_at_ms: now_ms,
-            retransmit_count: 0,
+            backoff: 0,
         });
-        self.send.last_send_ms = now_ms;
+        num
+    }
+
+    /// 送信待ちペイロードの先頭から最大 `max_len` バイトを新しい番号の Instruction にする
+    ///
+    /// `push_message` で積んだデータはメッセージの境界で区切る。
+    ///
+    /// # 戻り値
+    /// 割り当てた Instruction 番号。送信待ちがない、または `max_len` が 0 なら `None`
+    fn enqueue_outgoing(&mut self, max_len: usize, now_ms: u64) -> Option<u64> {
+        let mut len = max_len.min(self.send.outgoing_diff.len());
+        if let Some(&first_end) = self.send.message_ends.front() {
+            len = match self.send.message_ends.iter().rev().find(|&&end| end <= len) {
+                Some(&end) => end,
+                // 先頭のメッセージがウィンドウを超える: 何も送信中でなければそれだけ送る
+                None if self.send.pending.is_empty() => first_end,
+                None => 0,
+            };
+            while self.send.message_ends.front().is_some_and(|&end| end <= len) {
+                self.send.message_ends.pop_front();
+            }
+            for end in &mut self.send.message_ends {
+                *end -= len;
+            }
+        }
+        if len == 0 {
+            return None;
+        }
+
+        let rest = self.send.outgoing_diff.split_off(len);
+        let diff = core::mem::replace(&mut self.send.outgoing_diff, rest);
+        let num = self.enqueue_pending(diff, now_ms);
+        self.send.mindelay_clock_ms = None;
+        self.send.last_data_send_ms = Some(now_ms);
+        Some(num)
+    }
+
+    /// 輻輳ウィンドウの対象となる未 ACK のバイト数
+    ///
+    /// 失われたとみなして、まだ再送していない Instruction は含めない。
+    fn outstanding_bytes(&self) -> usize {
+        self.send
+            .pending
+            .iter()
+            .filter(|p| p.num <= self.send.resent_through || p.num > self.send.lost_through)
+            .map(|p| p.diff.len())
+            .sum()
+    }
+
+    /// 未 ACK のバイト数の上限と輻輳ウィンドウの両方に対する空き（バイト）
+    fn window_room(&self) -> usize {
+        let flow_room = self.max_in_flight_bytes.saturating_sub(self.in_flight_bytes());
+        let cwnd_room = self
+            .congestion
+            .window_bytes()
+            .saturating_sub(self.outstanding_bytes());
+        flow_room.min(cwnd_room)
+    }
+
+    /// 失われた Instruction のうち、次に輻輳ウィンドウに収まる範囲の末尾の番号
+    ///
+    /// 何も送信中でなければ、ウィンドウを超える Instruction でも 1 つは再送する（停止を防ぐ）。
+    /// 再送するものがなければ `None`。
+    fn resend_range(&self) -> Option<u64> {
+        let window = self.congestion.window_bytes();
+        let mut outstanding = self.outstanding_bytes();
+        let mut new_num = None;
+        for pending in &self.send.pending {
+            if pending.num <= self.send.resent_through || pending.num > self.send.lost_through {
+                continue;
+            }
+            if outstanding > 0 && outstanding + pending.diff.len() > window {
+                break;
+            }
+            outstanding += pending.diff.len();
+            new_num = Some(pending.num);
+        }
+        new_num
+    }
+
+    /// 受信した差分（状態 old_num → new_num）を適用する
+    ///
+    /// 既に受信済みの部分（old_num → last_recv_num）は読み飛ばし、残りを `out` に追加する。
+    /// 起点の状態のオフセットが不明（解放済み）な場合は破棄する。
+    fn apply_diff(&mut self, old_num: u64, new_num: u64, diff: &[u8], out: &mut Vec<u8>) {
+        let last = self.recv.last_recv_num;
+        let (Some(&base), Some(&current)) =
+            (self.recv.offsets.get(&old_num), self.recv.offsets.get(&last))
+        else {
+            return;
+        };
+
+        let skip = (current - base) as usize;
+        if skip > diff.len() {
+            return; // 不整合（既受信分より短い差分）
+        }
+
+        out.extend_from_slice(&diff[skip..]);
+        self.recv.offsets.insert(new_num, base + diff.len() as u64);
+        self.recv.last_recv_num = new_num;
+        self.prune_offsets();
+    }
+
+    /// 起点として使われなくなった状態のオフセットを解放する
+    ///
+    /// throwaway_num より古いものと、上限（REORDER_BUFFER_MAX）を超えた古いものを削除する。
+    /// 最後に受信した状態のオフセットは常に残す。
+    fn prune_offsets(&mut self) {
+        let keep_from = self.recv.throwaway_num.min(self.recv.last_recv_num);
+        self.recv.offsets.retain(|&num, _| num >= keep_from);
+
+        while self.recv.offsets.len() > REORDER_BUFFER_MAX {
+            self.recv.offsets.pop_first();
+        }
     }
 
     /// ACK を処理する（pending キューから ACK 済みを削除）
@@ -248,27 +805,49 @@ impl SspSession {
         }
 
         // ACK されたものを pending から削除
+        let mut acked_bytes = 0;
         while let Some(front) = self.send.pending.front() {
             if front.num <= ack_num {
                 let pending = self.send.pending.pop_front().unwrap();
-
-                // RTT サンプルを更新（初回送信のものだけ使う、再送は使わない）
-                if pending.retransmit_count == 0 {
-                    let rtt_sample = now_ms.saturating_sub(pending.sent_at_ms);
-                    self.update_rtt(rtt_sample);
-                }
+                acked_bytes += pending.diff.len();
             } else {
                 break;
             }
         }
 
         self.send.last_acked = ack_num;
+        self.send.resent_through = self.send.resent_through.max(ack_num);
+        if acked_bytes > 0 {
+            self.congestion.on_ack(acked_bytes, now_ms);
+        }
+
+        // 経路が生きているのでバックオフを解除する
+        self.send.consecutive_timeouts = 0;
+        for pending in &mut self.send.pending {
+            pending.backoff = 0;
+        }
+    }
+
+    /// Pending Instruction の再送待ち時間（RTO × 2^backoff、上限 max_backoff_ms）+ ACK_DELAY_MS
+    ///
+    /// RTT には相手の遅延 ACK の保持時間が含まれないので、その分を足して待つ
+    /// （mosh C++ 実装の `timeout() + ACK_DELAY` と同じ）。
+    fn retransmit_timeout_ms(&self, pending: &PendingInstruction) -> u64 {
+        let factor = 1u64 << pending.backoff.min(16);
+        self.rto_ms
+            .saturating_mul(factor)
+            .min(self.max_backoff_ms.max(self.rto_ms))
+            + ACK_DELAY_MS
     }
 
     /// Jacobson/Karels アルゴリズムで RTT を更新する
     ///
-    /// RFC 6298 に基づく実装。
-    fn update_rtt(&mut self, rtt_sample_ms: u64) {
+    /// RFC 6298 に基づく実装。サンプルは輻輳制御にも渡す。
+    ///
+    /// サンプルはタイムスタンプのエコー（相手の保持時間を補正済み、[`recv_timestamps`](Self::recv_timestamps)）
+    /// だけから取る。送信から ACK までの時間は遅延 ACK の保持時間を含むので使わない。
+    fn update_rtt(&mut self, rtt_sample_ms: u64, now_ms: u64) {
+        self.congestion.on_rtt_sample(rtt_sample_ms, now_ms);
         let rtt = rtt_sample_ms as f64;
 
         if self.srtt_ms == 0.0 {
@@ -314,15 +893,32 @@ pub struct SspStats {
     pub recv_num: u64,
     /// ACK 待ちの Instruction 数
     pub pending_count: usize,
+    /// 並べ替えバッファで順番待ちの Instruction 数
+    pub reorder_count: usize,
+    /// ACK が進まないまま連続で再送タイムアウトした回数
+    pub consecutive_timeouts: u32,
+    /// 新しい Instruction を作る間隔（ミリ秒）
+    pub send_interval_ms: u64,
+    /// 送信済み・未 ACK のバイト数
+    pub in_flight_bytes: usize,
+    /// 輻輳制御の状態
+    pub congestion: CongestionStats,
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    /// ペーシングなしのセッション（tick ごとに即座に Instruction を作る）
+    ///
+    /// ペーシング以外の挙動のテストで、送信タイミングを単純にするために使う。
+    fn unpaced() -> SspSession {
+        SspSession::new().with_send_pacing(SendPacing::DISABLED)
+    }
+
     #[test]
     fn test_ssp_session_new() {
-        let session = SspSession::new();
+        let session = unpaced();
         let stats = session.stats();
         assert_eq!(stats.send_num, 1);
         assert_eq!(stats.recv_num, 0);
@@ -331,7 +927,7 @@ mod tests {
 
     #[test]
     fn test_push_payload_and_tick() {
-        let mut session = SspSession::new();
+        let mut session = unpaced();
         session.push_payload(alloc::vec![1, 2, 3, 4]);
 
         let packets = session.tick(1000);
@@ -345,7 +941,7 @@ mod tests {
 
     #[test]
     fn test_recv_instruction_updates_state() {
-        let mut session = SspSession::new();
+        let mut session = unpaced();
 
         let instr = Instruction::new_send(0, 1, 0, 0, alloc::vec![9, 8, 7]);
         let payload = session.recv_instruction(&instr, 1000);
@@ -356,7 +952,7 @@ mod tests {
 
     #[test]
     fn test_ack_handling() {
-        let mut session = SspSession::new();
+        let mut session = unpaced();
 
         // データを送信
         session.push_payload(alloc::vec![1, 2, 3]);
@@ -373,7 +969,7 @@ mod tests {
 
     #[test]
     fn test_heartbeat_needed() {
-        let mut session = SspSession::new();
+        let mut session = unpaced();
 
         // 最初はハートビートが必要（last_send_ms = 0）
         assert!(session.needs_heartbeat(HEARTBEAT_INTERVAL_MS));
@@ -388,7 +984,7 @@ mod tests {
 
     #[test]
     fn test_duplicate_recv_ignored() {
-        let mut session = SspSession::new();
+        let mut session = unpaced();
 
         let instr = Instruction::new_send(0, 1, 0, 0, alloc::vec![1]);
         let payload1 = session.recv_instruction(&instr, 1000);
@@ -400,18 +996,22 @@ mod tests {
 
     #[test]
     fn test_rtt_update() {
-        let mut session = SspSession::new();
+        let mut session = unpaced();
 
         // データ送信
         session.push_payload(alloc::vec![0]);
         let _ = session.tick(0);
 
-        // ACK で RTT 計算
+        // ACK だけでは RTT を計算しない（遅延 ACK の保持時間を含むため）
         let ack = Instruction::new_ack(1, 0);
         session.recv_instruction(&ack, 150); // 150ms 後に ACK
+        assert_eq!(session.srtt_ms, 0.0);
+
+        // タイムスタンプのエコーで RTT 計算
+        session.recv_timestamps(Timestamp16::INIT, Timestamp16::now_from_ms(0), 150);
 
         // SRTT が更新されているはず
-        assert!(session.srtt_ms > 0.0);
+        assert_eq!(session.srtt_ms, 150.0);
         assert!(session.rto_ms >= RTO_MIN_MS);
         assert!(session.rto_ms <= RTO_MAX_MS);
     }
@@ -420,8 +1020,8 @@ mod tests {
     /// クライアント↔サーバー間の完全な送受信フローを検証する
     #[test]
     fn test_bidirectional_ssp_communication() {
-        let mut client = SspSession::new();
-        let mut server = SspSession::new();
+        let mut client = unpaced();
+        let mut server = unpaced();
 
         let mut now_ms: u64 = 1000;
 
@@ -461,8 +1061,8 @@ mod tests {
     /// 複数の連続したペイロード送信テスト
     #[test]
     fn test_multiple_sequential_payloads() {
-        let mut sender = SspSession::new();
-        let mut receiver = SspSession::new();
+        let mut sender = unpaced();
+        let mut receiver = unpaced();
 
         let payloads: alloc::vec::Vec<alloc::vec::Vec<u8>> = alloc::vec![
             b"First message".to_vec(),
@@ -495,7 +1095,7 @@ mod tests {
     /// 再送タイムアウトのテスト
     #[test]
     fn test_retransmission_on_timeout() {
-        let mut session = SspSession::new();
+        let mut session = unpaced();
 
         // データ送信
         session.push_payload(b"retransmit me".to_vec());
@@ -515,7 +1115,7 @@ mod tests {
     /// ACK 後の再送停止テスト
     #[test]
     fn test_no_retransmit_after_ack() {
-        let mut session = SspSession::new();
+        let mut session = unpaced();
 
         // データ送信
         session.push_payload(b"ack me".to_vec());
@@ -540,8 +1140,8 @@ mod tests {
     /// throwaway_num の伝播テスト
     #[test]
     fn test_throwaway_num_propagation() {
-        let mut client = SspSession::new();
-        let mut server = SspSession::new();
+        let mut client = unpaced();
+        let mut server = unpaced();
 
         // クライアントが複数のパケットを送信
         for i in 0..3u8 {
@@ -566,7 +1166,7 @@ mod tests {
     /// RTT 収束テスト - 複数サンプルで SRTT が収束することを確認
     #[test]
     fn test_rtt_convergence() {
-        let mut session = SspSession::new();
+        let mut session = unpaced();
 
         // 10回の RTT サンプルを収集
         for i in 0..10u64 {
@@ -574,13 +1174,14 @@ mod tests {
             let _ = session.tick(i * 200);
 
             let ack = Instruction::new_ack(i + 1, 0);
-            session.recv_instruction(&ack, i * 200 + 100); // 100ms RTT
+            let echo = Timestamp16::now_from_ms(i * 200);
+            session.recv_timestamps(Timestamp16::INIT, echo, i * 200 + 100); // 100ms RTT
+            session.recv_instruction(&ack, i * 200 + 100);
         }
 
-        // SRTT が 100ms 付近に収束するはず（完全には収束しないが合理的な範囲）
+        // SRTT が 100ms に収束するはず
         let stats = session.stats();
-        assert!(stats.srtt_ms > 0.0, "SRTT は正値であるべき");
-        assert!(stats.srtt_ms < 200.0, "SRTT は 200ms 以下のはず");
+        assert!((stats.srtt_ms - 100.0).abs() < 1.0, "SRTT は 100ms 付近のはず: {}", stats.srtt_ms);
         assert!(stats.rto_ms >= RTO_MIN_MS, "RTO は最小値以上");
         assert!(stats.rto_ms <= RTO_MAX_MS, "RTO は最大値以下");
     }
@@ -588,7 +1189,7 @@ mod tests {
     /// ハートビート間隔検証テスト
     #[test]
     fn test_heartbeat_interval_exact() {
-        let mut session = SspSession::new();
+        let mut session = unpaced();
         let base_ms: u64 = 10_000;
 
         // ハートビートを送信（last_send_ms が更新される）
@@ -606,4 +1207,564 @@ mod tests {
             "インターバル経過後はハートビート必要"
         );
     }
+
+    /// 欠落した Instruction が再送で届いたら、保持していた後続と合わせて順番通りに渡す
+    #[test]
+    fn test_lost_instruction_recovered_in_order() {
+        let mut receiver = unpaced();
+
+        let i1 = Instruction::new_send(0, 1, 0, 0, b"one ".to_vec());
+        let i2 = Instruction::new_send(1, 2, 0, 0, b"two ".to_vec());
+        let i3 = Instruction::new_send(2, 3, 0, 0, b"three".to_vec());
+
+        assert_eq!(receiver.recv_instruction(&i1, 1000), Some(b"one ".to_vec()));
+        // #2 がロスし #3 が先に届く → 保持して渡さない
+        assert_eq!(receiver.recv_instruction(&i3, 1010), None);
+        assert_eq!(receiver.stats().recv_num, 1, "欠落があるので ACK は #1 のまま");
+        assert_eq!(receiver.stats().reorder_count, 1);
+
+        // #2 の再送が届く → #2 と #3 をまとめて渡す
+        assert_eq!(
+            receiver.recv_instruction(&i2, 2000),
+            Some(b"two three".to_vec())
+        );
+        assert_eq!(receiver.stats().recv_num, 3);
+        assert_eq!(receiver.stats().reorder_count, 0);
+
+        // 遅れて届いた #3 の再送は重複として破棄
+        assert_eq!(receiver.recv_instruction(&i3, 2010), None);
+    }
+
+    /// 送信側が欠落分を再送し、受信側のバイトストリームが欠落・重複なく揃う
+    #[test]
+    fn test_byte_stream_gap_free_under_loss() {
+        let mut sender = unpaced();
+        let mut receiver = unpaced();
+        let mut received = Vec::new();
+
+        let mut now_ms = 0u64;
+        let mut wire = Vec::new();
+        for chunk in [b"aa".as_slice(), b"bb", b"cc", b"dd"] {
+            sender.push_payload(chunk.to_vec());
+            wire.extend(sender.tick(now_ms));
+            now_ms += 10;
+        }
+        assert_eq!(wire.len(), 4);
+
+        // #2 をロスさせる
+        for (i, bytes) in wire.iter().enumerate() {
+            if i == 1 {
+                continue;
+            }
+            let instr = Instruction::decode_from_bytes(bytes).unwrap();
+            if let Some(data) = receiver.recv_instruction(&instr, now_ms) {
+                received.extend(data);
+            }
+        }
+        assert_eq!(received, b"aa");
+
+        // 受信側の ACK（#1 まで）を送信側へ
+        let ack = receiver.make_ack(now_ms);
+        sender.recv_instruction(&ack, now_ms);
+
+        // RTO 経過後の再送をすべて受信側に届ける
+        let resend_ms = now_ms + RTO_INITIAL_MS + ACK_DELAY_MS;
+        for bytes in sender.tick(resend_ms) {
+            let instr = Instruction::decode_from_bytes(&bytes).unwrap();
+            if let Some(data) = receiver.recv_instruction(&instr, resend_ms) {
+                received.extend(data);
+            }
+        }
+
+        assert_eq!(received, b"aabbccdd");
+        assert_eq!(receiver.stats().recv_num, 4);
+    }
+
+    /// 再送は未 ACK 分をまとめた 1 つの Instruction になる
+    #[test]
+    fn test_cumulative_retransmission() {
+        let mut session = unpaced();
+
+        for (i, chunk) in [b"ab".as_slice(), b"cd", b"ef"].iter().enumerate() {
+            session.push_payload(chunk.to_vec());
+            assert_eq!(session.tick(i as u64 * 10).len(), 1);
+        }
+
+        // #1 のみ ACK
+        session.recv_instruction(&Instruction::new_ack(1, 0), 50);
+
+        let packets = session.tick(RTO_INITIAL_MS + ACK_DELAY_MS + 50);
+        assert_eq!(packets.len(), 1, "再送は 1 つの Instruction にまとめる");
+
+        let instr = Instruction::decode_from_bytes(&packets[0]).unwrap();
+        assert_eq!(instr.old_num_or_zero(), 1);
+        assert_eq!(instr.new_num_or_zero(), 3);
+        assert_eq!(instr.throwaway_num_or_zero(), 1);
+        assert_eq!(instr.diff_bytes(), b"cdef");
+    }
+
+    /// 再送タイミングで積まれた新しいデータも累積 Instruction に含める
+    #[test]
+    fn test_cumulative_retransmission_includes_new_data() {
+        let mut session = unpaced();
+        session.push_payload(b"old".to_vec());
+        let _ = session.tick(0);
+
+        session.push_payload(b"new".to_vec());
+        let packets = session.tick(RTO_INITIAL_MS + ACK_DELAY_MS);
+        assert_eq!(packets.len(), 1);
+
+        let instr = Instruction::decode_from_bytes(&packets[0]).unwrap();
+        assert_eq!(instr.old_num_or_zero(), 0);
+        assert_eq!(instr.new_num_or_zero(), 2);
+        assert_eq!(instr.diff_bytes(), b"oldnew");
+    }
+
+    /// 受信側は累積再送のうち既に受信した部分を読み飛ばす
+    #[test]
+    fn test_cumulative_skips_already_received() {
+        let mut receiver = unpaced();
+
+        let i1 = Instruction::new_send(0, 1, 0, 0, b"ab".to_vec());
+        let i2 = Instruction::new_send(1, 2, 0, 0, b"cd".to_vec());
+        assert_eq!(receiver.recv_instruction(&i1, 0), Some(b"ab".to_vec()));
+        assert_eq!(receiver.recv_instruction(&i2, 0), Some(b"cd".to_vec()));
+
+        // 送信側は ACK #2 を受け取れず、#1 → #3 を累積再送してきた
+        let cumulative = Instruction::new_send(1, 3, 0, 1, b"cdef".to_vec());
+        assert_eq!(receiver.recv_instruction(&cumulative, 10), Some(b"ef".to_vec()));
+        assert_eq!(receiver.stats().recv_num, 3);
+    }
+
+    /// ACK 待ちが上限に達したら新しい Instruction を作らずデータを保持する
+    #[test]
+    fn test_pending_cap_holds_outgoing_data() {
+        let mut session = unpaced();
+
+        for i in 0..MAX_PENDING_INSTRUCTIONS as u64 {
+            session.push_payload(alloc::vec![i as u8]);
+            assert_eq!(session.tick(i).len(), 1);
+        }
+        assert_eq!(session.stats().pending_count, MAX_PENDING_INSTRUCTIONS);
+
+        session.push_payload(b"held".to_vec());
+        assert!(session.tick(100).is_empty(), "上限到達中は新規送信しない");
+
+        // ACK が進むと保持していたデータを送信する
+        session.recv_instruction(&Instruction::new_ack(1, 0), 110);
+        let packets = session.tick(120);
+        assert_eq!(packets.len(), 1);
+        let instr = Instruction::decode_from_bytes(&packets[0]).unwrap();
+        assert_eq!(instr.diff_bytes(), b"held");
+    }
+
+    /// 未 ACK のバイト数は上限を超えず、超える分は ACK が進むまで保持する
+    #[test]
+    fn test_in_flight_bytes_capped() {
+        let mut session = unpaced();
+        session.set_max_in_flight_bytes(4);
+
+        session.push_payload(b"abcdef".to_vec());
+        let packets = session.tick(0);
+        assert_eq!(packets.len(), 1);
+        assert_eq!(Instruction::decode_from_bytes(&packets[0]).unwrap().diff_bytes(), b"abcd");
+        assert_eq!(session.in_flight_bytes(), 4);
+        assert_eq!(session.queued_bytes(), 2);
+        assert_eq!(session.send_window_room(), 0);
+        assert_eq!(session.next_send_slot_ms(10), None);
+        assert!(session.tick(10).is_empty(), "ウィンドウが満杯の間は新規送信しない");
+
+        // ACK で空いた分だけ残りを送る
+        session.recv_instruction(&Instruction::new_ack(1, 0), 20);
+        let packets = session.tick(30);
+        assert_eq!(packets.len(), 1);
+        assert_eq!(Instruction::decode_from_bytes(&packets[0]).unwrap().diff_bytes(), b"ef");
+        assert_eq!(session.send_window_room(), 2);
+    }
+
+    /// データ受信後、ACK_DELAY_MS 経過で ACK のみの Instruction を送る
+    #[test]
+    fn test_delayed_ack_sent_after_delay() {
+        let mut session = unpaced();
+        let _ = session.tick(10_000); // 初回ハートビートを済ませる
+
+        let instr = Instruction::new_send(0, 1, 0, 0, b"data".to_vec());
+        session.recv_instruction(&instr, 10_100);
+
+        assert!(session.tick(10_100 + ACK_DELAY_MS - 1).is_empty(), "期限前は ACK しない");
+
+        let packets = session.tick(10_100 + ACK_DELAY_MS);
+        assert_eq!(packets.len(), 1);
+        let ack = Instruction::decode_from_bytes(&packets[0]).unwrap();
+        assert_eq!(ack.new_num_or_zero(), 0, "ACK のみ");
+        assert_eq!(ack.ack_num_or_zero(), 1);
+
+        // 一度 ACK したら次のデータ受信まで送らない
+        assert!(session.tick(10_100 + ACK_DELAY_MS * 2).is_empty());
+    }
+
+    /// 期限前にデータを送れば ACK はそれに相乗りし、ACK のみの Instruction は送らない
+    #[test]
+    fn test_delayed_ack_piggybacks_on_data() {
+        let mut session = unpaced();
+        let _ = session.tick(10_000);
+
+        session.recv_instruction(&Instruction::new_send(0, 1, 0, 0, b"req".to_vec()), 10_100);
+
+        session.push_payload(b"resp".to_vec());
+        let packets = session.tick(10_120);
+        assert_eq!(packets.len(), 1);
+        let instr = Instruction::decode_from_bytes(&packets[0]).unwrap();
+        assert_eq!(instr.ack_num_or_zero(), 1);
+        assert_eq!(instr.diff_bytes(), b"resp");
+
+        assert!(session.tick(10_100 + ACK_DELAY_MS).is_empty(), "ACK 済みなので追加送信なし");
+    }
+
+    /// ハートビート（ACK のみ）の受信では ACK を返さない（ACK の応酬を防ぐ）
+    #[test]
+    fn test_ack_only_does_not_arm_delayed_ack() {
+        let mut session = unpaced();
+        let _ = session.tick(10_000);
+
+        session.recv_instruction(&Instruction::new_ack(0, 0), 10_100);
+        assert!(!session.ack_due(10_100 + ACK_DELAY_MS));
+        assert!(session.tick(10_100 + ACK_DELAY_MS).is_empty());
+    }
+
+    /// 次の期限はハートビート・再送・遅延 ACK・送信待ちのうち最も早いもの
+    #[test]
+    fn test_next_deadline() {
+        let mut session = unpaced();
+        let _ = session.tick(10_000);
+
+        // 何もなければハートビート
+        assert_eq!(session.next_deadline_ms(10_000), 10_000 + HEARTBEAT_INTERVAL_MS);
+
+        // 送信待ちデータがあれば即時
+        session.push_payload(b"x".to_vec());
+        assert_eq!(session.next_deadline_ms(10_010), 10_010);
+
+        // 送信後は再送タイマー（RTO + 相手の遅延 ACK の猶予）
+        let _ = session.tick(10_010);
+        assert_eq!(session.next_deadline_ms(10_010), 10_010 + RTO_INITIAL_MS + ACK_DELAY_MS);
+
+        // データを受信したら遅延 ACK の期限
+        session.recv_instruction(&Instruction::new_send(0, 1, 0, 0, b"y".to_vec()), 10_020);
+        assert_eq!(session.next_deadline_ms(10_020), 10_020 + ACK_DELAY_MS);
+
+        // 期限を過ぎていれば now を返す
+        assert_eq!(session.next_deadline_ms(20_000), 20_000);
+    }
+
+    /// next_deadline_ms の時刻に tick すると何かが送信される
+    #[test]
+    fn test_tick_at_next_deadline_sends() {
+        let mut session = unpaced();
+        let _ = session.tick(10_000);
+        session.push_payload(b"data".to_vec());
+        let _ = session.tick(10_000);
+
+        let deadline = session.next_deadline_ms(10_000);
+        assert!(session.tick(deadline - 1).is_empty());
+        assert!(!session.tick(deadline).is_empty(), "期限で再送される");
+    }
+
+    /// 再送のたびに待ち時間が倍になり、上限で頭打ちになる
+    #[test]
+    fn test_retransmit_exponential_backoff() {
+        let mut session = unpaced().with_max_backoff(4 * RTO_INITIAL_MS);
+        session.push_payload(b"lost".to_vec());
+        let _ = session.tick(0);
+
+        // 再送間隔: 1000 → 2000 → 4000 → 4000（上限）に、それぞれ遅延 ACK の猶予を足す
+        let mut expected = 0;
+        for (i, interval) in [1000, 2000, 4000, 4000].into_iter().enumerate() {
+            expected += interval + ACK_DELAY_MS;
+
+            // 期限前はハートビート（ACK のみ）しか送らない
+            let before = session.tick(expected - 1);
+            assert!(before.iter().all(|b| {
+                Instruction::decode_from_bytes(b).unwrap().new_num_or_zero() == 0
+            }), "期限前は再送しない");
+
+            let packets = session.tick(expected);
+            assert_eq!(packets.len(), 1);
+            assert_eq!(Instruction::decode_from_bytes(&packets[0]).unwrap().new_num_or_zero(), 1);
+            assert_eq!(session.consecutive_timeouts(), i as u32 + 1);
+        }
+    }
+
+    /// 新しい ACK でバックオフと連続タイムアウト回数がリセットされる
+    #[test]
+    fn test_backoff_reset_on_ack() {
+        let mut session = unpaced();
+        session.push_payload(b"a".to_vec());
+        let _ = session.tick(0);
+        session.push_payload(b"b".to_vec());
+        let _ = session.tick(10);
+
+        let _ = session.tick(1100);
+        let _ = session.tick(3200);
+        assert_eq!(session.stats().consecutive_timeouts, 2);
+
+        // #1 の ACK が届く（#2 は未 ACK のまま）
+        session.recv_instruction(&Instruction::new_ack(1, 0), 3300);
+        assert_eq!(session.stats().consecutive_timeouts, 0);
+
+        // #2 は元の RTO で再送される
+        assert_eq!(session.next_deadline_ms(3300), 3200 + session.stats().rto_ms + ACK_DELAY_MS);
+    }
+
+    /// 受信・無受信の経過に応じて接続状態が遷移し、変化が一度だけ通知される
+    #[test]
+    fn test_connection_state_transitions() {
+        let mut session = unpaced();
+        assert_eq!(session.state(0), ConnectionState::Connecting);
+        assert_eq!(session.poll_state_change(0), None);
+
+        session.recv_instruction(&Instruction::new_ack(0, 0), 1000);
+        assert_eq!(session.last_recv_ms(), Some(1000));
+        assert_eq!(session.poll_state_change(1000), Some(ConnectionState::Connected));
+        assert_eq!(session.poll_state_change(1001), None, "変化がなければ通知しない");
+
+        let stalled_at = 1000 + crate::STALLED_AFTER_MS;
+        assert_eq!(session.poll_state_change(stalled_at), Some(ConnectionState::Stalled));
+        assert_eq!(session.poll_state_change(1000 + crate::LOST_AFTER_MS), Some(ConnectionState::Lost));
+
+        // 受信が再開すれば Connected に戻る
+        session.recv_instruction(&Instruction::new_ack(0, 0), 70_000);
+        assert_eq!(session.poll_state_change(70_000), Some(ConnectionState::Connected));
+    }
+
+    /// 再送が続くと受信があっても Stalled になる
+    #[test]
+    fn test_consecutive_timeouts_mark_stalled() {
+        let mut session = unpaced();
+        session.set_connection_thresholds(ConnectionThresholds {
+            stalled_after_timeouts: 2,
+            ..ConnectionThresholds::default()
+        });
+        session.recv_instruction(&Instruction::new_ack(0, 0), 0);
+        session.push_payload(b"x".to_vec());
+        let _ = session.tick(0);
+
+        let _ = session.tick(1100);
+        assert_eq!(session.state(1100), ConnectionState::Connected);
+        session.recv_instruction(&Instruction::new_ack(0, 0), 2000); // ハートビートは届くが ACK は進まない
+        let _ = session.tick(3200);
+        assert_eq!(session.state(3200), ConnectionState::Stalled);
+
+        session.recv_instruction(&Instruction::new_ack(1, 0), 3300);
+        assert_eq!(session.state(3300), ConnectionState::Connected);
+    }
+
+    /// タイムスタンプのエコーは保持時間を補正して 1 度だけ返す
+    #[test]
+    fn test_timestamp_reply_hold_time_correction() {
+        let mut session = unpaced();
+        assert_eq!(session.timestamp_reply(0), Timestamp16::INIT);
+
+        session.recv_timestamps(Timestamp16(5000), Timestamp16::INIT, 10_000);
+        assert_eq!(session.timestamp_reply(10_040), Timestamp16(5040));
+        assert_eq!(session.timestamp_reply(10_050), Timestamp16::INIT, "エコーは 1 度だけ");
+
+        // 保持しすぎたタイムスタンプはエコーしない
+        session.recv_timestamps(Timestamp16(6000), Timestamp16::INIT, 20_000);
+        assert_eq!(session.timestamp_reply(20_000 + TIMESTAMP_ECHO_MAX_HOLD_MS), Timestamp16::INIT);
+    }
+
+    /// エコーされたタイムスタンプから RTT を計測する（ACK が進まなくても）
+    #[test]
+    fn test_rtt_from_timestamp_echo() {
+        let mut a = unpaced();
+        let mut b = unpaced();
+
+        // a が 65_500 に送信 → b が 65_540 に受信、60ms 保持して 65_600 に返信 → a が 65_620 に受信
+        // 時刻は 16 ビットをまたぐ
+        let sent = Timestamp16::now_from_ms(65_500);
+        b.recv_timestamps(sent, Timestamp16::INIT, 65_540);
+        let reply = b.timestamp_reply(65_600);
+        a.recv_timestamps(Timestamp16::now_from_ms(65_600), reply, 65_620);
+
+        // RTT = 120ms - 保持時間 60ms
+        assert_eq!(a.stats().srtt_ms, 60.0);
+    }
+
+    /// INIT のエコーや大きすぎるサンプルは RTT に使わない
+    #[test]
+    fn test_rtt_ignores_init_and_outliers() {
+        let mut session = unpaced();
+        session.recv_timestamps(Timestamp16::INIT, Timestamp16::INIT, 1000);
+        session.recv_timestamps(Timestamp16::INIT, Timestamp16::now_from_ms(1000), 1000 + RTT_SAMPLE_MAX_MS);
+        assert_eq!(session.stats().srtt_ms, 0.0);
+        assert_eq!(session.stats().rto_ms, RTO_INITIAL_MS);
+    }
+
+    /// 書き込みは SEND_MINDELAY_MS まとめて 1 つの Instruction にする
+    #[test]
+    fn test_pacing_batches_writes() {
+        let mut session = SspSession::new();
+        let _ = session.tick(10_000); // ハートビートを済ませておく
+
+        session.push_payload(b"a".to_vec());
+        assert!(session.tick(10_000).is_empty(), "min delay の間は送らない");
+        session.push_payload(b"b".to_vec());
+        assert!(session.tick(10_005).is_empty());
+        session.push_payload(b"c".to_vec());
+
+        assert_eq!(session.next_send_slot_ms(10_005), Some(10_000 + SEND_MINDELAY_MS));
+        let packets = session.tick(10_000 + SEND_MINDELAY_MS);
+        assert_eq!(packets.len(), 1);
+        assert_eq!(Instruction::decode_from_bytes(&packets[0]).unwrap().diff_bytes(), b"abc");
+    }
+
+    /// 新しい Instruction は SRTT / 2（下限 20ms）の間隔で作る
+    #[test]
+    fn test_pacing_send_interval() {
+        let mut session = SspSession::new();
+        let _ = session.tick(10_000);
+
+        session.push_payload(b"1".to_vec());
+        let _ = session.tick(10_000);
+        assert_eq!(session.tick(10_008).len(), 1);
+
+        // RTT 未計測なので間隔は SEND_INTERVAL_MIN_MS
+        session.push_payload(b"2".to_vec());
+        let _ = session.tick(10_010);
+        assert_eq!(session.next_send_slot_ms(10_010), Some(10_008 + SEND_INTERVAL_MIN_MS));
+        assert_eq!(session.next_deadline_ms(10_010), 10_008 + SEND_INTERVAL_MIN_MS);
+        assert!(session.tick(10_027).is_empty());
+        assert_eq!(session.tick(10_028).len(), 1);
+
+        // SRTT 200ms → 間隔 100ms
+        session.update_rtt(200, 10_028);
+        assert_eq!(session.send_interval_ms(), 100);
+        session.push_payload(b"3".to_vec());
+        let _ = session.tick(10_030);
+        assert_eq!(session.next_send_slot_ms(10_030), Some(10_128));
+    }
+
+    /// 再送のタイミングでは送信枠を待たずに新しいデータを同乗させる
+    #[test]
+    fn test_pacing_data_rides_on_retransmission() {
+        let mut session = SspSession::new();
+        session.push_payload(b"old".to_vec());
+        let _ = session.tick(0);
+        let _ = session.tick(SEND_MINDELAY_MS);
+
+        session.push_payload(b"new".to_vec());
+        let packets = session.tick(SEND_MINDELAY_MS + RTO_INITIAL_MS + ACK_DELAY_MS);
+        assert_eq!(packets.len(), 1);
+        assert_eq!(Instruction::decode_from_bytes(&packets[0]).unwrap().diff_bytes(), b"oldnew");
+    }
+
+    /// 新しいデータは輻輳ウィンドウに収まる分だけ送り、ACK でウィンドウが広がったら残りを送る
+    #[test]
+    fn test_congestion_window_limits_new_data() {
+        let mut session = unpaced();
+        let cwnd = session.stats().congestion.window_bytes;
+
+        session.push_payload(alloc::vec![0u8; cwnd + 600]);
+        let packets = session.tick(0);
+        assert_eq!(packets.len(), 1);
+        assert_eq!(Instruction::decode_from_bytes(&packets[0]).unwrap().diff_bytes().len(), cwnd);
+        assert_eq!(session.queued_bytes(), 600);
+        assert!(session.tick(10).is_empty(), "ウィンドウが満杯の間は新規送信しない");
+
+        // スロースタート: ACK されたバイト数だけウィンドウが広がる
+        session.recv_instruction(&Instruction::new_ack(1, 0), 50);
+        assert_eq!(session.stats().congestion.window_bytes, 2 * cwnd);
+        let packets = session.tick(60);
+        assert_eq!(Instruction::decode_from_bytes(&packets[0]).unwrap().diff_bytes().len(), 600);
+    }
+
+    /// 再送タイムアウトでウィンドウが縮み、失われた分はウィンドウに収まる単位で再送する
+    #[test]
+    fn test_timeout_resends_within_congestion_window() {
+        let mut session = unpaced();
+        for i in 0..3u64 {
+            session.push_payload(alloc::vec![i as u8; 1000]);
+            let _ = session.tick(i);
+        }
+
+        // 1 セグメントのウィンドウには最初の 1 つだけが収まる
+        let timeout_ms = RTO_INITIAL_MS + ACK_DELAY_MS;
+        let packets = session.tick(timeout_ms);
+        assert_eq!(packets.len(), 1);
+        let instr = Instruction::decode_from_bytes(&packets[0]).unwrap();
+        assert_eq!((instr.old_num_or_zero(), instr.new_num_or_zero()), (0, 1));
+        let stats = session.stats().congestion;
+        assert_eq!(stats.window_bytes, CWND_SEGMENT_BYTES);
+        assert_eq!(stats.ssthresh_bytes, Some(CWND_MIN_SEGMENTS * CWND_SEGMENT_BYTES));
+        assert!(session.tick(timeout_ms + 10).is_empty());
+
+        // ACK でウィンドウが空いたら続きから再送する
+        session.recv_instruction(&Instruction::new_ack(1, 0), timeout_ms + 100);
+        assert_eq!(session.next_deadline_ms(timeout_ms + 100), timeout_ms + 100);
+        let packets = session.tick(timeout_ms + 100);
+        assert_eq!(packets.len(), 1);
+        let instr = Instruction::decode_from_bytes(&packets[0]).unwrap();
+        assert_eq!((instr.old_num_or_zero(), instr.new_num_or_zero()), (1, 3));
+        assert_eq!(instr.diff_bytes().len(), 2000);
+    }
+
+    /// push_message で積んだメッセージは Instruction の途中で分割しない
+    #[test]
+    fn test_messages_not_split_across_instructions() {
+        let mut session = unpaced();
+        session.set_max_in_flight_bytes(1000);
+
+        session.push_message(alloc::vec![1u8; 600]);
+        session.push_message(alloc::vec![2u8; 600]);
+        let packets = session.tick(0);
+        assert_eq!(Instruction::decode_from_bytes(&packets[0]).unwrap().diff_bytes(), [1u8; 600]);
+        assert_eq!(session.queued_bytes(), 600);
+        assert!(session.tick(10).is_empty(), "残りの 400 バイトには 2 つ目が収まらない");
+
+        // 何も送信中でなければ、ウィンドウを超えるメッセージも 1 つは送る
+        session.recv_instruction(&Instruction::new_ack(1, 0), 50);
+        session.push_message(alloc::vec![3u8; 1200]);
+        let packets = session.tick(60);
+        assert_eq!(Instruction::decode_from_bytes(&packets[0]).unwrap().diff_bytes(), [2u8; 600]);
+        session.recv_instruction(&Instruction::new_ack(2, 0), 100);
+        let packets = session.tick(110);
+        assert_eq!(Instruction::decode_from_bytes(&packets[0]).unwrap().diff_bytes().len(), 1200);
+    }
+
+    /// 状態同期: 差分を起点の状態に適用し、保持している最新の状態番号を ACK する
+    #[test]
+    fn test_recv_state_instruction() {
+        #[derive(Debug, Clone, Default)]
+        struct Log(Vec<u8>);
+
+        impl SyncState for Log {
+            type Error = ();
+
+            fn apply_diff(&mut self, diff: &[u8]) -> Result<(), ()> {
+                self.0.extend_from_slice(diff);
+                Ok(())
+            }
+        }
+
+        let mut session = unpaced();
+        let mut states = ReceivedStates::new(Log::default());
+        let mut recv = |session: &mut SspSession, old, new, throwaway, diff: &[u8]| {
+            let instr = Instruction::new_send(old, new, 0, throwaway, diff.to_vec());
+            session.recv_state_instruction(&instr, &mut states, 0).unwrap()
+        };
+
+        assert_eq!(recv(&mut session, 0, 1, 0, b"a"), Some(1));
+        // 相手がまだ ACK を受け取っていなければ、同じ起点からの差分が来る
+        assert_eq!(recv(&mut session, 0, 2, 0, b"b"), Some(2));
+        assert_eq!(recv(&mut session, 0, 2, 0, b"b"), None, "重複");
+        assert_eq!(recv(&mut session, 5, 6, 0, b"x"), None, "起点なし");
+        assert_eq!(session.make_ack(0).ack_num_or_zero(), 2);
+
+        assert_eq!(recv(&mut session, 2, 3, 2, b"c"), Some(3));
+        assert_eq!(session.make_ack(0).ack_num_or_zero(), 3);
+        assert!(session.ack_due(ACK_DELAY_MS));
+        assert_eq!(recv(&mut session, 1, 4, 2, b"y"), None, "throwaway_num より古い起点");
+        assert_eq!(states.latest().0, b"bc");
+    }
 }
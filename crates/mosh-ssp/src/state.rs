@@ -0,0 +1,149 @@
+//! 接続状態（生存確認）
+//!
+//! 最後に相手から受信した時刻と、ACK が進まないままの連続再送タイムアウト回数から
+//! 接続の状態を判定する。mosh の「Last contact N seconds ago」表示に相当する情報を提供する。
+//!
+//! ```text
+//! Connecting ──(初回受信)──→ Connected ──(無受信 stalled_after_ms / 連続タイムアウト)──→ Stalled
+//!                               ↑                                                      │
+//!                               └──────────────(受信・ACK の前進)──────────────────────┤
+//!                                                                                      ↓
+//!                                                          (無受信 lost_after_ms)   Lost
+//! ```
+//!
+//! Lost も最終状態ではなく、再び受信すれば Connected に戻る（mosh はローミングを前提とする）。
+
+use crate::{LOST_AFTER_MS, STALLED_AFTER_MS, STALLED_AFTER_TIMEOUTS};
+
+/// 接続状態
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    /// まだ相手から一度も受信していない
+    Connecting,
+    /// 相手から定期的に受信している
+    Connected,
+    /// しばらく受信がない、または再送が続いている（一時的な断の可能性）
+    Stalled,
+    /// 長時間受信がない（経路が切れている可能性が高い）
+    Lost,
+}
+
+impl ConnectionState {
+    /// 小文字の状態名（JS への受け渡し用）
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConnectionState::Connecting => "connecting",
+            ConnectionState::Connected => "connected",
+            ConnectionState::Stalled => "stalled",
+            ConnectionState::Lost => "lost",
+        }
+    }
+}
+
+impl core::fmt::Display for ConnectionState {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// 接続状態の判定しきい値
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConnectionThresholds {
+    /// 最終受信からこの時間（ミリ秒）が経過したら Stalled
+    pub stalled_after_ms: u64,
+    /// 最終受信からこの時間（ミリ秒）が経過したら Lost
+    pub lost_after_ms: u64,
+    /// ACK が進まないままの連続再送タイムアウトがこの回数に達したら Stalled
+    pub stalled_after_timeouts: u32,
+}
+
+impl ConnectionThresholds {
+    /// 最終受信時刻と連続タイムアウト回数から状態を判定する
+    ///
+    /// # 引数
+    /// - `last_recv_ms`: 最後に受信した時刻（未受信なら `None`）
+    /// - `consecutive_timeouts`: ACK が進まないままの連続再送タイムアウト回数
+    /// - `now_ms`: 現在時刻
+    pub fn classify(
+        &self,
+        last_recv_ms: Option<u64>,
+        consecutive_timeouts: u32,
+        now_ms: u64,
+    ) -> ConnectionState {
+        let Some(last_recv_ms) = last_recv_ms else {
+            return ConnectionState::Connecting;
+        };
+
+        let silent_ms = now_ms.saturating_sub(last_recv_ms);
+        if silent_ms >= self.lost_after_ms {
+            ConnectionState::Lost
+        } else if silent_ms >= self.stalled_after_ms
+            || consecutive_timeouts >= self.stalled_after_timeouts
+        {
+            ConnectionState::Stalled
+        } else {
+            ConnectionState::Connected
+        }
+    }
+
+    /// 受信がないまま経過した場合に、次に状態が変わる時刻（ミリ秒）
+    ///
+    /// 未受信、または既に Lost なら `None`（時間経過では変わらない）。
+    pub fn next_transition_ms(&self, last_recv_ms: Option<u64>, now_ms: u64) -> Option<u64> {
+        let last_recv_ms = last_recv_ms?;
+        [
+            last_recv_ms + self.stalled_after_ms,
+            last_recv_ms + self.lost_after_ms,
+        ]
+        .into_iter()
+        .find(|&t| t > now_ms)
+    }
+}
+
+impl Default for ConnectionThresholds {
+    fn default() -> Self {
+        ConnectionThresholds {
+            stalled_after_ms: STALLED_AFTER_MS,
+            lost_after_ms: LOST_AFTER_MS,
+            stalled_after_timeouts: STALLED_AFTER_TIMEOUTS,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify() {
+        let t = ConnectionThresholds::default();
+        assert_eq!(t.classify(None, 0, 100_000), ConnectionState::Connecting);
+        assert_eq!(t.classify(Some(1000), 0, 1000), ConnectionState::Connected);
+        assert_eq!(
+            t.classify(Some(1000), 0, 1000 + STALLED_AFTER_MS),
+            ConnectionState::Stalled
+        );
+        assert_eq!(
+            t.classify(Some(1000), 0, 1000 + LOST_AFTER_MS),
+            ConnectionState::Lost
+        );
+    }
+
+    #[test]
+    fn test_timeouts_mark_stalled() {
+        let t = ConnectionThresholds::default();
+        assert_eq!(
+            t.classify(Some(1000), STALLED_AFTER_TIMEOUTS, 1000),
+            ConnectionState::Stalled
+        );
+    }
+
+    #[test]
+    fn test_next_transition() {
+        let t = ConnectionThresholds::default();
+        assert_eq!(t.next_transition_ms(None, 0), None);
+        assert_eq!(t.next_transition_ms(Some(0), 0), Some(STALLED_AFTER_MS));
+        assert_eq!(t.next_transition_ms(Some(0), STALLED_AFTER_MS), Some(LOST_AFTER_MS));
+        assert_eq!(t.next_transition_ms(Some(0), LOST_AFTER_MS), None);
+    }
+}
@@ -0,0 +1,154 @@
+//! 状態同期（端末モード）
+//!
+//! バイトストリームモードの差分は連結できる（old_num → new_num の差分は途中の差分を
+//! つないだもの）ので、[`SspSession::recv_instruction`](crate::SspSession::recv_instruction) は
+//! 既に受信した部分を読み飛ばして返す。端末モードの差分（mosh-server の HostMessage）は
+//! 「状態 old_num の画面 → 状態 new_num の画面」を表し、連結できない。
+//!
+//! 受信側は受信した状態を番号ごとに [`ReceivedStates`] に保持し、届いた差分を起点の状態の
+//! 複製に適用して新しい状態を作る（mosh C++ 実装の `Transport<MyState, RemoteState>` と同じ）。
+//!
+//! ```text
+//! 受信済み: {4: S4, 5: S5}
+//! 4 → 6 の差分が届く → S6 = S4.clone().apply_diff(diff)   （5 を経由しない）
+//! 7 → 8 の差分が届く → 起点 7 を持っていないので捨てる（相手は ACK を見て 6 から送り直す）
+//! ```
+
+use alloc::collections::BTreeMap;
+
+use crate::RECEIVED_STATES_MAX;
+
+/// 状態同期で受信側が保持する状態（mosh の `RemoteState` 相当）
+pub trait SyncState: Clone {
+    /// 差分を適用できなかったときのエラー
+    type Error;
+
+    /// 差分を適用してこの状態を更新する
+    fn apply_diff(&mut self, diff: &[u8]) -> Result<(), Self::Error>;
+}
+
+/// 受信した状態の保持領域
+///
+/// 状態番号 0（初期状態）から始まり、差分を適用した状態を番号順に保持する。
+/// 最新の状態は常に残す。
+#[derive(Debug, Clone)]
+pub struct ReceivedStates<S> {
+    /// 状態番号 → 状態
+    states: BTreeMap<u64, S>,
+}
+
+impl<S: SyncState> ReceivedStates<S> {
+    /// 初期状態（番号 0）だけを持つ保持領域を生成する
+    pub fn new(initial: S) -> Self {
+        ReceivedStates {
+            states: BTreeMap::from([(0, initial)]),
+        }
+    }
+
+    /// 最新（番号が最大）の状態の番号
+    pub fn latest_num(&self) -> u64 {
+        *self.states.keys().next_back().unwrap()
+    }
+
+    /// 最新（番号が最大）の状態
+    pub fn latest(&self) -> &S {
+        self.states.values().next_back().unwrap()
+    }
+
+    /// 番号 `num` の状態。保持していなければ `None`
+    pub fn get(&self, num: u64) -> Option<&S> {
+        self.states.get(&num)
+    }
+
+    /// 保持している状態の数
+    pub fn len(&self) -> usize {
+        self.states.len()
+    }
+
+    /// 保持している状態がないか（最新の状態は常に残すので常に `false`）
+    pub fn is_empty(&self) -> bool {
+        self.states.is_empty()
+    }
+
+    /// 状態 `old_num` に差分を適用して状態 `new_num` を作る
+    ///
+    /// # 戻り値
+    /// - `Ok(true)`: 新しい状態を保持した
+    /// - `Ok(false)`: 受信済みの状態・起点の状態を持っていない・保持数が上限
+    ///   （`RECEIVED_STATES_MAX`）に達している、のいずれかで何もしなかった
+    /// - `Err(e)`: 差分を適用できなかった（何も保持しない）
+    pub fn apply(&mut self, old_num: u64, new_num: u64, diff: &[u8]) -> Result<bool, S::Error> {
+        if self.states.contains_key(&new_num) || self.states.len() >= RECEIVED_STATES_MAX {
+            return Ok(false);
+        }
+        let Some(base) = self.states.get(&old_num) else {
+            return Ok(false);
+        };
+
+        let mut state = base.clone();
+        state.apply_diff(diff)?;
+        self.states.insert(new_num, state);
+        Ok(true)
+    }
+
+    /// `throwaway_num` より古い状態を捨てる（相手がもう差分の起点に使わない）
+    ///
+    /// 最新の状態は番号に関係なく残す。
+    pub fn discard_before(&mut self, throwaway_num: u64) {
+        let keep_from = throwaway_num.min(self.latest_num());
+        self.states.retain(|&num, _| num >= keep_from);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    /// 差分を末尾に足していくだけの状態（`0xFF` は適用できない差分）
+    #[derive(Debug, Clone, Default, PartialEq)]
+    struct Log(Vec<u8>);
+
+    impl SyncState for Log {
+        type Error = ();
+
+        fn apply_diff(&mut self, diff: &[u8]) -> Result<(), ()> {
+            if diff.contains(&0xFF) {
+                return Err(());
+            }
+            self.0.extend_from_slice(diff);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_apply_from_any_retained_state() {
+        let mut states = ReceivedStates::new(Log::default());
+        assert!(states.apply(0, 1, b"a").unwrap());
+        assert!(states.apply(1, 2, b"b").unwrap());
+
+        // 2 を経由せず 1 から 3 を作る
+        assert!(states.apply(1, 3, b"c").unwrap());
+        assert_eq!(states.latest_num(), 3);
+        assert_eq!(states.latest().0, b"ac");
+
+        // 受信済み・起点なしは無視
+        assert!(!states.apply(0, 2, b"x").unwrap());
+        assert!(!states.apply(9, 10, b"x").unwrap());
+        assert!(states.apply(3, 4, &[0xFF]).is_err());
+        assert_eq!(states.latest_num(), 3);
+    }
+
+    #[test]
+    fn test_discard_keeps_latest() {
+        let mut states = ReceivedStates::new(Log::default());
+        states.apply(0, 1, b"a").unwrap();
+        states.apply(1, 2, b"b").unwrap();
+        states.discard_before(2);
+        assert!(states.get(1).is_none());
+        assert_eq!(states.len(), 1);
+
+        states.discard_before(100);
+        assert_eq!(states.latest_num(), 2);
+    }
+}
@@ -3,6 +3,9 @@
 use alloc::collections::VecDeque;
 use alloc::vec::Vec;
 
+use crate::error::StreamError;
+use crate::SEND_BUFFER_MAX_BYTES;
+
 /// バイトストリームチャンネル
 ///
 /// VS Code RPC プロトコルのバイトストリームを mosh SSP の diff フィールドで転送するための
@@ -23,6 +26,8 @@ pub struct StreamChannel {
     /// 送信バッファ（まだ SSP に渡していないデータ）
     /// 上位レイヤーが write() で積み、tick 時に take_pending_diff() で取得される
     send_buffer: Vec<u8>,
+    /// 送信バッファの上限（バイト）
+    send_capacity: usize,
     /// 受信した総バイト数（統計用）
     total_received: u64,
     /// 送信した総バイト数（統計用）
@@ -35,19 +40,42 @@ impl StreamChannel {
         StreamChannel {
             recv_buffer: VecDeque::new(),
             send_buffer: Vec::new(),
+            send_capacity: SEND_BUFFER_MAX_BYTES,
             total_received: 0,
             total_sent: 0,
         }
     }
 
+    /// 送信バッファの上限（バイト）を設定する
+    ///
+    /// 既にバッファにあるデータは上限を超えていても破棄しない。
+    pub fn set_send_capacity(&mut self, send_capacity: usize) {
+        self.send_capacity = send_capacity;
+    }
+
     /// 上位レイヤー（VS Code RPC）から送信データを積む
     ///
     /// このデータは次の `take_pending_diff()` 呼び出し時に SSP に渡される。
+    /// 送信バッファの空きを超える分は積まない。
     ///
     /// # 引数
     /// - `data`: 送信するバイト列
-    pub fn write(&mut self, data: &[u8]) {
-        self.send_buffer.extend_from_slice(data);
+    ///
+    /// # 戻り値
+    /// - `Ok(n)`: 先頭から `n` バイトを積んだ（`n < data.len()` なら残りは呼び出し側で再送する）
+    /// - `Err(StreamError::WouldBlock)`: バッファが満杯で 1 バイトも積めなかった
+    pub fn write(&mut self, data: &[u8]) -> Result<usize, StreamError> {
+        let accepted = data.len().min(self.writable_bytes());
+        if accepted == 0 && !data.is_empty() {
+            return Err(StreamError::WouldBlock);
+        }
+        self.send_buffer.extend_from_slice(&data[..accepted]);
+        Ok(accepted)
+    }
+
+    /// 送信バッファにあと何バイト積めるか
+    pub fn writable_bytes(&self) -> usize {
+        self.send_capacity.saturating_sub(self.send_buffer.len())
     }
 
     /// SSP に渡す送信データを取得し、バッファをクリアする
@@ -58,7 +86,15 @@ impl StreamChannel {
     /// # 戻り値
     /// 送信待ちのバイト列（空の場合は `Vec::new()`）
     pub fn take_pending_diff(&mut self) -> Vec<u8> {
-        let diff = core::mem::take(&mut self.send_buffer);
+        self.take_pending_diff_up_to(usize::MAX)
+    }
+
+    /// SSP に渡す送信データを先頭から最大 `max_len` バイト取得する
+    ///
+    /// SSP の送信ウィンドウの空きに合わせて取り出すのに使う。残りはバッファに保持される。
+    pub fn take_pending_diff_up_to(&mut self, max_len: usize) -> Vec<u8> {
+        let rest = self.send_buffer.split_off(max_len.min(self.send_buffer.len()));
+        let diff = core::mem::replace(&mut self.send_buffer, rest);
         self.total_sent += diff.len() as u64;
         diff
     }
@@ -127,8 +163,8 @@ mod tests {
     #[test]
     fn test_write_and_take_pending() {
         let mut ch = StreamChannel::new();
-        ch.write(b"hello");
-        ch.write(b" world");
+        ch.write(b"hello").unwrap();
+        ch.write(b" world").unwrap();
 
         let diff = ch.take_pending_diff();
         assert_eq!(diff, b"hello world");
@@ -173,7 +209,7 @@ mod tests {
     #[test]
     fn test_stats() {
         let mut ch = StreamChannel::new();
-        ch.write(b"send data");
+        ch.write(b"send data").unwrap();
         let _ = ch.take_pending_diff();
 
         ch.apply_diff(b"recv data");
@@ -187,7 +223,7 @@ mod tests {
     fn test_buffer_independence() {
         // 送信バッファと受信バッファが独立していることを確認
         let mut ch = StreamChannel::new();
-        ch.write(b"outgoing");
+        ch.write(b"outgoing").unwrap();
         ch.apply_diff(b"incoming");
 
         assert!(ch.has_pending_write());
@@ -200,4 +236,21 @@ mod tests {
         let send = ch.take_pending_diff();
         assert_eq!(send, b"outgoing");
     }
+
+    #[test]
+    fn test_write_respects_send_capacity() {
+        let mut ch = StreamChannel::new();
+        ch.set_send_capacity(8);
+
+        assert_eq!(ch.write(b"12345"), Ok(5));
+        assert_eq!(ch.write(b"6789"), Ok(3), "空きの分だけ受け付ける");
+        assert_eq!(ch.writable_bytes(), 0);
+        assert_eq!(ch.write(b"9"), Err(StreamError::WouldBlock));
+        assert_eq!(ch.write(b""), Ok(0));
+
+        // 取り出した分だけ空きができる
+        assert_eq!(ch.take_pending_diff_up_to(3), b"123");
+        assert_eq!(ch.writable_bytes(), 3);
+        assert_eq!(ch.take_pending_diff(), b"45678");
+    }
 }
@@ -0,0 +1,16 @@
+//! mosh-stream エラー型
+
+/// バイトストリームのエラー
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StreamError {
+    /// 送信バッファが満杯で 1 バイトも書き込めない（送信が進むまで待つ）
+    WouldBlock,
+}
+
+impl core::fmt::Display for StreamError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            StreamError::WouldBlock => write!(f, "Send buffer full, write would block"),
+        }
+    }
+}
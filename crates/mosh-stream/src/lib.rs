@@ -18,6 +18,9 @@
 //!   2. stream.take_pending_diff() → SSP の Instruction.diff に設定
 //!   3. SSP → Fragment 分割 → 暗号化 → UDP
 //!
+//!   送信バッファには上限（SEND_BUFFER_MAX_BYTES）があり、満杯なら write() は
+//!   受け付けたバイト数だけを返す（1 バイトも入らなければ StreamError::WouldBlock）。
+//!
 //! 受信:
 //!   1. UDP → 復号 → Fragment 再組み立て → SSP
 //!   2. SSP の Instruction.diff → stream.apply_diff(diff)
@@ -28,5 +31,11 @@
 extern crate alloc;
 
 pub mod channel;
+pub mod error;
 
 pub use channel::StreamChannel;
+pub use error::StreamError;
+
+/// 送信バッファ（まだ SSP に渡していないデータ）の上限（バイト）のデフォルト値
+/// 上位レイヤーはこれを超えて書き込めず、送信が進むのを待つ必要がある
+pub const SEND_BUFFER_MAX_BYTES: usize = 1024 * 1024;
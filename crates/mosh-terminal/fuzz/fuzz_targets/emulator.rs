@@ -0,0 +1,72 @@
+//! 任意のバイト列を端末エミュレーターに流してもパニックせず、表示の差分が画面を再現することを確かめる
+//!
+//! 先頭 2 バイトで画面の大きさを、次の 2 バイトで途中のリサイズ後の大きさを決め、
+//! 残りを 2 回に分けて書き込む（シーケンスの途中で切れた入力と、リサイズをまたぐ状態も通す）。
+//! 1 回目の書き込み後の画面 `last` を全体描画した端末に、`last` から最終画面 `f` への差分を流し、
+//! `f` と同じ画面（セル・折り返し・カーソル・属性・モード）になることを確かめる。
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use mosh_terminal::{Display, Emulator, Framebuffer};
+
+fuzz_target!(|data: &[u8]| {
+    let [width, height, new_width, new_height, rest @ ..] = data else {
+        return;
+    };
+    let mut emulator = Emulator::new(usize::from(*width % 200), usize::from(*height % 100));
+    let (first, second) = rest.split_at(rest.len() / 2);
+    emulator.write(first);
+    let last = emulator.framebuffer().clone();
+    emulator.resize(
+        usize::from(*new_width % 200),
+        usize::from(*new_height % 100),
+    );
+    emulator.write(second);
+    let _ = emulator.take_replies();
+
+    let fb = emulator.framebuffer();
+    let (row, col) = fb.cursor();
+    assert!(row < fb.height() && col < fb.width());
+    assert_eq!(fb.rows().len(), fb.height());
+    assert!(fb.rows().iter().all(|r| r.cells().len() == fb.width()));
+
+    let display = Display::default();
+    let mut terminal = Emulator::new(last.width(), last.height());
+    terminal.write(display.new_frame(false, &last, &last).as_bytes());
+    assert_same_screen(terminal.framebuffer(), &last);
+    terminal.resize(fb.width(), fb.height());
+    terminal.write(display.new_frame(true, &last, fb).as_bytes());
+    assert_same_screen(terminal.framebuffer(), fb);
+});
+
+/// 端末に表示される内容が同じか確かめる
+fn assert_same_screen(actual: &Framebuffer, expected: &Framebuffer) {
+    for row in 0..expected.height() {
+        assert_eq!(
+            actual.row(row).cells(),
+            expected.row(row).cells(),
+            "{} 行目",
+            row
+        );
+    }
+    // 最下行の折り返しは再現しない（次の文字で画面がスクロールする）
+    for row in 0..expected.height() - 1 {
+        assert_eq!(
+            actual.row(row).wrap,
+            expected.row(row).wrap,
+            "{} 行目の折り返し",
+            row
+        );
+    }
+    assert_eq!(actual.cursor(), expected.cursor());
+    assert_eq!(actual.renditions(), expected.renditions());
+    let (a, e) = (actual.modes(), expected.modes());
+    assert_eq!(a.cursor_visible, e.cursor_visible);
+    assert_eq!(a.reverse_video, e.reverse_video);
+    assert_eq!(a.bracketed_paste, e.bracketed_paste);
+    assert_eq!(a.mouse_tracking, e.mouse_tracking);
+    assert_eq!(a.mouse_encoding, e.mouse_encoding);
+    assert_eq!(a.application_cursor_keys, e.application_cursor_keys);
+    assert_eq!(actual.title(), expected.title());
+}
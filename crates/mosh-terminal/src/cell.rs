@@ -0,0 +1,147 @@
+//! 画面の 1 マス（セル）と文字属性
+//!
+//! mosh C++ 実装の `Terminal::Cell` / `Terminal::Renditions` に対応する。
+
+use alloc::vec::Vec;
+
+/// 文字色・背景色
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Color {
+    /// 端末の既定の色
+    #[default]
+    Default,
+    /// 256 色パレットの番号（0〜7: 標準色、8〜15: 明るい色、16〜255: 拡張色）
+    Indexed(u8),
+    /// 24 ビットカラー（truecolor）
+    Rgb(u8, u8, u8),
+}
+
+/// 文字属性（SGR で設定する）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Renditions {
+    /// 文字色
+    pub fg: Color,
+    /// 背景色
+    pub bg: Color,
+    /// 太字（SGR 1）
+    pub bold: bool,
+    /// 淡色（SGR 2）
+    pub faint: bool,
+    /// 斜体（SGR 3）
+    pub italic: bool,
+    /// 下線（SGR 4）
+    pub underline: bool,
+    /// 点滅（SGR 5）
+    pub blink: bool,
+    /// 反転（SGR 7）
+    pub inverse: bool,
+    /// 不可視（SGR 8）
+    pub invisible: bool,
+    /// 取り消し線（SGR 9）
+    pub strikethrough: bool,
+}
+
+impl Renditions {
+    /// 背景色だけを持つ属性（消去したセルに使う）
+    pub fn with_background(bg: Color) -> Self {
+        Renditions {
+            bg,
+            ..Default::default()
+        }
+    }
+}
+
+/// 画面の 1 マス
+///
+/// 全角文字は左側のセルに `wide = true` で入り、右側のセルは空白のまま描画しない。
+/// 結合文字（濃点・アクセントなど）は直前に書いたセルの `combining` に足す。
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Cell {
+    /// 基底文字（空白なら `' '`）
+    ch: char,
+    /// 基底文字に続く結合文字
+    combining: Vec<char>,
+    /// 文字属性
+    pub renditions: Renditions,
+    /// 全角文字（右隣のセルも占める）
+    pub wide: bool,
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Cell::blank(Renditions::default())
+    }
+}
+
+impl Cell {
+    /// 指定した属性の空白セル
+    pub fn blank(renditions: Renditions) -> Self {
+        Cell {
+            ch: ' ',
+            combining: Vec::new(),
+            renditions,
+            wide: false,
+        }
+    }
+
+    /// 半角文字 `ch` を属性 `renditions` で書いたセル
+    pub fn new(ch: char, renditions: Renditions) -> Self {
+        Cell {
+            ch,
+            combining: Vec::new(),
+            renditions,
+            wide: false,
+        }
+    }
+
+    /// 基底文字
+    pub fn ch(&self) -> char {
+        self.ch
+    }
+
+    /// 結合文字
+    pub fn combining(&self) -> &[char] {
+        &self.combining
+    }
+
+    /// 空白セルか（結合文字もない空白）
+    pub fn is_blank(&self) -> bool {
+        self.ch == ' ' && self.combining.is_empty()
+    }
+
+    /// 基底文字と結合文字を古い順に返す
+    pub fn chars(&self) -> impl Iterator<Item = char> + '_ {
+        core::iter::once(self.ch).chain(self.combining.iter().copied())
+    }
+
+    /// 内容（基底文字と結合文字）が同じか（属性は比べない）
+    pub fn same_contents(&self, other: &Cell) -> bool {
+        self.ch == other.ch && self.combining == other.combining
+    }
+
+    /// 背景色 `bg` で消去する
+    pub fn reset(&mut self, bg: Color) {
+        *self = Cell::blank(Renditions::with_background(bg));
+    }
+
+    /// 文字を書き込む（結合文字は消える）
+    pub(crate) fn set(&mut self, ch: char, renditions: Renditions, wide: bool) {
+        self.ch = ch;
+        self.combining.clear();
+        self.renditions = renditions;
+        self.wide = wide;
+    }
+
+    /// 結合文字を足す
+    ///
+    /// 基底文字のない（空白の）セルには、mosh と同じく U+00A0 を基底文字にして足す。
+    /// 結合文字は [`COMBINING_CHARS_MAX`](crate::COMBINING_CHARS_MAX) 個まで保持する。
+    pub(crate) fn push_combining(&mut self, ch: char) {
+        if self.is_blank() {
+            self.ch = '\u{A0}';
+        }
+        if self.combining.len() < crate::COMBINING_CHARS_MAX {
+            self.combining.push(ch);
+        }
+    }
+}
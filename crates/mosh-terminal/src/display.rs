@@ -0,0 +1,849 @@
+//! 画面の差分をエスケープシーケンスにする
+//!
+//! mosh C++ 実装の `Terminal::Display` に対応する。端末が表示している画面 `last` と
+//! 新しい画面 `f` を比べ、`last` を表示している端末に流すと `f` になるバイト列を作る。
+//!
+//! 出力を小さくするための工夫（mosh と同じ）:
+//!
+//! - 内容が同じセルは飛ばし、カーソル移動は CR / LF / BS で済むならそれを使う
+//! - 画面が上にスクロールしただけなら、LF（必要ならスクロール領域を設定して）で送る
+//! - 空白が続く部分は ECH（`CSI n X`）・EL（`CSI K`）で消す
+//! - 文字属性は変わった分だけ送る（全部送り直すほうが短ければそちら）
+//! - 自動折り返しした行は、端末でも実際に折り返させる（単語選択が行をまたげるように）
+
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::Write as _;
+
+use crate::cell::{Cell, Color, Renditions};
+use crate::framebuffer::{Framebuffer, MouseEncoding, MouseTracking, Row};
+
+/// 差分を流す端末の機能
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Display {
+    /// ECH（`CSI n X`）で文字を消去できる
+    pub has_ech: bool,
+    /// 消去したセルに現在の背景色が付く（background color erase）
+    pub has_bce: bool,
+    /// OSC でウィンドウタイトルを設定できる
+    pub has_title: bool,
+}
+
+impl Default for Display {
+    /// xterm 互換の端末（xterm.js を含む）
+    fn default() -> Self {
+        Display {
+            has_ech: true,
+            has_bce: true,
+            has_title: true,
+        }
+    }
+}
+
+/// 差分を作っている間の端末の状態（出力を流し終えた時点の状態を追う）
+struct FrameState {
+    out: String,
+    /// カーソル位置（行, 桁）。不明なら `None`。桁が画面の幅なら、行末で折り返し待ち
+    cursor: Option<(usize, usize)>,
+    /// 端末の現在の文字属性
+    renditions: Renditions,
+    /// 端末がカーソルを表示しているか
+    cursor_visible: bool,
+    /// 画面の幅
+    width: usize,
+}
+
+impl FrameState {
+    /// カーソルを移動する（CR / LF / BS で済むならそれを使う）
+    fn append_move(&mut self, row: usize, col: usize) {
+        let last = self.cursor.replace((row, col));
+        if let Some((last_row, last_col)) = last {
+            if col == 0 && row >= last_row && row - last_row < 5 {
+                if last_col != 0 {
+                    self.out.push('\r');
+                }
+                self.out.extend(core::iter::repeat_n('\n', row - last_row));
+                return;
+            }
+            // 折り返し待ちの位置からの BS は端末によって結果が違うので使わない
+            if row == last_row && col < last_col && last_col - col < 5 && last_col < self.width {
+                self.out
+                    .extend(core::iter::repeat_n('\x08', last_col - col));
+                return;
+            }
+        }
+        let _ = write!(self.out, "\x1b[{};{}H", row + 1, col + 1);
+    }
+
+    /// 描画のためにカーソルを移動する（移動が必要なら、その間はカーソルを隠す）
+    fn append_silent_move(&mut self, row: usize, col: usize) {
+        if self.cursor == Some((row, col)) {
+            return;
+        }
+        if self.cursor_visible {
+            self.out.push_str("\x1b[?25l");
+            self.cursor_visible = false;
+        }
+        self.append_move(row, col);
+    }
+
+    /// 文字属性を `renditions` にする。`force` なら同じでも送り直す
+    fn update_renditions(&mut self, renditions: Renditions, force: bool) {
+        if force {
+            push_sgr(&mut self.out, &full_sgr_params(&renditions));
+        } else if self.renditions != renditions {
+            let incremental = incremental_sgr_params(&self.renditions, &renditions);
+            let full = full_sgr_params(&renditions);
+            push_sgr(
+                &mut self.out,
+                if incremental.len() < full.len() {
+                    &incremental
+                } else {
+                    &full
+                },
+            );
+        }
+        self.renditions = renditions;
+    }
+
+    /// 同じ行の少し右へ進むだけなら、カーソル移動の代わりに間のセルを書き直す
+    ///
+    /// 間のセルは端末と同じ内容なので、現在の属性のままの半角文字なら書き直しても変わらない。
+    fn catch_up(&mut self, cells: &[Cell], row: usize, col: usize) {
+        let Some((cursor_row, cursor_col)) = self.cursor else {
+            return;
+        };
+        if cursor_row != row || cursor_col >= col || col - cursor_col > 4 {
+            return;
+        }
+        // カーソルが全角文字の右半分にあるなら書けない
+        if cursor_col > 0 && cells[cursor_col - 1].wide {
+            return;
+        }
+        let gap = &cells[cursor_col..col];
+        if gap
+            .iter()
+            .all(|cell| !cell.wide && cell.renditions == self.renditions)
+        {
+            for cell in gap {
+                self.append_cell(cell);
+            }
+            self.cursor = Some((row, col));
+        }
+    }
+
+    /// セルの文字を書く（カーソルは呼び出し側が進める）
+    fn append_cell(&mut self, cell: &Cell) {
+        self.out.extend(cell.chars());
+    }
+}
+
+impl Display {
+    /// `last` を表示している端末を `f` にするバイト列を作る
+    ///
+    /// `initialized` が `false` なら端末の内容は不明とみなし、画面を消去してから全体を描く
+    /// （モード・タイトルなども送り直す）。`last` と `f` の大きさが違う場合も画面全体を描く。
+    pub fn new_frame(&self, initialized: bool, last: &Framebuffer, f: &Framebuffer) -> String {
+        let mut frame = FrameState {
+            out: String::new(),
+            cursor: Some(last.cursor()),
+            renditions: *last.renditions(),
+            cursor_visible: if initialized {
+                last.modes().cursor_visible
+            } else {
+                true
+            },
+            width: f.width(),
+        };
+        let (width, height) = (f.width(), f.height());
+
+        // ベル
+        if initialized && f.bell_count() != last.bell_count() {
+            frame.out.push('\x07');
+        }
+
+        // ウィンドウタイトル・アイコン名
+        if self.has_title
+            && (!initialized || f.title() != last.title() || f.icon_name() != last.icon_name())
+        {
+            if f.title() == f.icon_name() {
+                push_osc(&mut frame.out, 0, f.title());
+            } else {
+                push_osc(&mut frame.out, 1, f.icon_name());
+                push_osc(&mut frame.out, 2, f.title());
+            }
+        }
+
+        // 画面全体の反転表示
+        if !initialized || f.modes().reverse_video != last.modes().reverse_video {
+            push_dec_mode(&mut frame.out, 5, f.modes().reverse_video);
+        }
+
+        // 端末に表示されている行（スクロールを送ったら一緒に動かす）
+        let blank_row = Row::new(width, Color::Default);
+        // 最下行の折り返しは再現しないので、端末側の最下行は折り返していない
+        let mut last_bottom = last.row(last.height() - 1).clone();
+        last_bottom.wrap = false;
+        let mut old_rows: Vec<&Row>;
+        if initialized && last.width() == width && last.height() == height {
+            old_rows = last.rows().iter().collect();
+            old_rows[height - 1] = &last_bottom;
+            self.scroll(&mut frame, f, &mut old_rows, &blank_row);
+        } else {
+            // スクロール領域を戻して画面を消去する
+            frame.out.push_str("\x1b[r\x1b[0m\x1b[H\x1b[2J");
+            frame.cursor = Some((0, 0));
+            frame.renditions = Renditions::default();
+            old_rows = vec![&blank_row; height];
+        }
+
+        // 各行を描く
+        let mut wrap = false;
+        for (row, old_row) in old_rows.iter().enumerate() {
+            wrap = self.put_row(&mut frame, f, row, old_row, wrap);
+        }
+
+        // カーソル位置
+        if !initialized || frame.cursor != Some(f.cursor()) {
+            let (row, col) = f.cursor();
+            frame.append_move(row, col);
+        }
+
+        // カーソルの表示
+        if !initialized || frame.cursor_visible != f.modes().cursor_visible {
+            push_dec_mode(&mut frame.out, 25, f.modes().cursor_visible);
+            frame.cursor_visible = f.modes().cursor_visible;
+        }
+
+        // 次に書く文字の属性
+        frame.update_renditions(*f.renditions(), !initialized);
+
+        let (modes, last_modes) = (f.modes(), last.modes());
+
+        // ブラケットペースト
+        if !initialized || modes.bracketed_paste != last_modes.bracketed_paste {
+            push_dec_mode(&mut frame.out, 2004, modes.bracketed_paste);
+        }
+
+        // マウスイベントの報告
+        if !initialized || modes.mouse_tracking != last_modes.mouse_tracking {
+            match mouse_tracking_mode(modes.mouse_tracking) {
+                None => {
+                    for mode in [1003, 1002, 1000, 9] {
+                        push_dec_mode(&mut frame.out, mode, false);
+                    }
+                }
+                Some(mode) => {
+                    if let Some(last_mode) = mouse_tracking_mode(last_modes.mouse_tracking) {
+                        push_dec_mode(&mut frame.out, last_mode, false);
+                    }
+                    push_dec_mode(&mut frame.out, mode, true);
+                }
+            }
+        }
+        if !initialized || modes.focus_events != last_modes.focus_events {
+            push_dec_mode(&mut frame.out, 1004, modes.focus_events);
+        }
+        if !initialized || modes.mouse_encoding != last_modes.mouse_encoding {
+            match mouse_encoding_mode(modes.mouse_encoding) {
+                None => {
+                    for mode in [1015, 1006, 1005] {
+                        push_dec_mode(&mut frame.out, mode, false);
+                    }
+                }
+                Some(mode) => {
+                    if let Some(last_mode) = mouse_encoding_mode(last_modes.mouse_encoding) {
+                        push_dec_mode(&mut frame.out, last_mode, false);
+                    }
+                    push_dec_mode(&mut frame.out, mode, true);
+                }
+            }
+        }
+
+        // カーソルキー・テンキーのアプリケーションモード
+        if !initialized || modes.application_cursor_keys != last_modes.application_cursor_keys {
+            push_dec_mode(&mut frame.out, 1, modes.application_cursor_keys);
+        }
+        if !initialized || modes.application_keypad != last_modes.application_keypad {
+            frame.out.push_str(if modes.application_keypad {
+                "\x1b="
+            } else {
+                "\x1b>"
+            });
+        }
+
+        frame.out
+    }
+
+    /// 画面が上にスクロールしただけなら、スクロールを送って `old_rows` も動かす
+    ///
+    /// 新しい画面の先頭行が、表示中の画面の何行目に一致するかで判断する（空行は手がかりにしない）。
+    fn scroll<'a>(
+        &self,
+        frame: &mut FrameState,
+        f: &Framebuffer,
+        old_rows: &mut [&'a Row],
+        blank_row: &'a Row,
+    ) {
+        let height = f.height();
+        let first = f.row(0);
+        if first.cells().iter().all(Cell::is_blank) {
+            return;
+        }
+        let Some(lines_scrolled) = (0..height).find(|&row| *old_rows[row] == *first) else {
+            return;
+        };
+        if lines_scrolled == 0 {
+            return;
+        }
+        // スクロールした範囲の高さ
+        let scroll_height = (1..height - lines_scrolled)
+            .take_while(|&row| *f.row(row) == *old_rows[lines_scrolled + row])
+            .count()
+            + 1;
+        let bottom = lines_scrolled + scroll_height - 1;
+
+        // 入ってくる空行は既定の背景色にする
+        frame.update_renditions(Renditions::default(), false);
+        if bottom == height - 1 && frame.cursor.is_some_and(|(row, _)| row == height - 1) {
+            // 最下行にいて画面全体をスクロールするなら CR と LF だけで済む
+            frame.out.push('\r');
+            frame.out.extend(core::iter::repeat_n('\n', lines_scrolled));
+            frame.cursor = Some((height - 1, 0));
+        } else {
+            let _ = write!(frame.out, "\x1b[1;{}r", bottom + 1);
+            frame.cursor = None;
+            frame.append_silent_move(bottom, 0);
+            frame.out.extend(core::iter::repeat_n('\n', lines_scrolled));
+            frame.out.push_str("\x1b[r");
+            // スクロール領域の設定でカーソルは原点に戻る
+            frame.cursor = None;
+        }
+
+        for row in 0..=bottom {
+            old_rows[row] = if row + lines_scrolled <= bottom {
+                old_rows[row + lines_scrolled]
+            } else {
+                blank_row
+            };
+        }
+    }
+
+    /// 1 行を描く
+    ///
+    /// `wrap` が `true` なら前の行の最後のセルを書いて折り返し待ちなので、先頭のセルを
+    /// カーソルを動かさずに書く。
+    ///
+    /// # 戻り値
+    /// この行の最後のセルを書いて、次の行へ折り返し待ちになったら `true`
+    fn put_row(
+        &self,
+        frame: &mut FrameState,
+        f: &Framebuffer,
+        y: usize,
+        old_row: &Row,
+        wrap: bool,
+    ) -> bool {
+        let row = f.row(y);
+        let cells = row.cells();
+        let old_cells = old_row.cells();
+        let width = f.width();
+        let mut x = 0;
+
+        if wrap {
+            let cell = &cells[0];
+            frame.update_renditions(cell.renditions, false);
+            frame.append_cell(cell);
+            x = cell_width(cell);
+            frame.cursor = Some((y, x));
+        } else if row == old_row {
+            return false;
+        }
+
+        let mut clear_count = 0;
+        let mut blank_renditions = Renditions::default();
+        // 折り返し待ちから書いた先頭のセルで行が埋まることもある（幅 2 の行の全角文字）
+        let mut wrote_last_cell = x >= width;
+        let mut erased_to_end = false;
+        // ここより左のセルは内容が同じでも描く（全角文字の片割れを上書きして端末側で消えたセル）
+        let mut force_until = forced_after(old_cells, x);
+
+        while x < width {
+            let cell = &cells[x];
+            // 折り返す行は最後のセルまで書く（書かないと端末側で折り返しが起きない）
+            // 最下行の折り返しは画面がスクロールしないと起きないので再現しない
+            let wraps_here = row.wrap && y + 1 < f.height() && x + cell_width(cell) >= width;
+            if x >= force_until && clear_count == 0 && *cell == old_cells[x] && !wraps_here {
+                x += cell_width(cell);
+                continue;
+            }
+
+            // 同じ属性の空白はまとめて消す
+            if cell.is_blank() {
+                if clear_count == 0 {
+                    blank_renditions = cell.renditions;
+                }
+                if cell.renditions == blank_renditions {
+                    clear_count += 1;
+                    x += 1;
+                    continue;
+                }
+            }
+
+            if clear_count > 0 {
+                frame.catch_up(cells, y, x - clear_count);
+                frame.append_silent_move(y, x - clear_count);
+                frame.update_renditions(blank_renditions, false);
+                if self.can_erase(&blank_renditions) && self.has_ech && clear_count > 4 {
+                    let _ = write!(frame.out, "\x1b[{}X", clear_count);
+                } else {
+                    frame.out.extend(core::iter::repeat_n(' ', clear_count));
+                    frame.cursor = Some((y, x));
+                    force_until = force_until.max(forced_after(old_cells, x));
+                }
+                clear_count = 0;
+
+                // 属性の違う空白が続くなら数え直す
+                if cell.is_blank() {
+                    blank_renditions = cell.renditions;
+                    clear_count = 1;
+                    x += 1;
+                    continue;
+                }
+            }
+
+            let cell_width = cell_width(cell);
+            // 折り返す行の最後のセルを書く前は位置を明示する（折り返し待ちの扱いが端末によって違う）
+            if row.wrap && x + cell_width >= width {
+                frame.cursor = None;
+            }
+            frame.catch_up(cells, y, x);
+            frame.append_silent_move(y, x);
+            frame.update_renditions(cell.renditions, false);
+            frame.append_cell(cell);
+            x += cell_width;
+            frame.cursor = Some((y, x));
+            force_until = force_until.max(forced_after(old_cells, x));
+            if x >= width {
+                wrote_last_cell = true;
+            }
+        }
+
+        // 行末の空白
+        if clear_count > 0 {
+            frame.catch_up(cells, y, x - clear_count);
+            frame.append_silent_move(y, x - clear_count);
+            frame.update_renditions(blank_renditions, false);
+            if self.can_erase(&blank_renditions) && !row.wrap {
+                frame.out.push_str("\x1b[K");
+                erased_to_end = true;
+            } else {
+                frame.out.extend(core::iter::repeat_n(' ', clear_count));
+                frame.cursor = Some((y, x));
+                wrote_last_cell = true;
+            }
+        }
+
+        // 折り返しが解けた行は、行末の消去で端末側の折り返しも解く
+        if old_row.wrap && !row.wrap && !erased_to_end {
+            let col = if width >= 2 && cells[width - 2].wide {
+                width - 2
+            } else {
+                width - 1
+            };
+            let cell = &cells[col];
+            frame.append_silent_move(y, col);
+            frame.out.push_str("\x1b[K");
+            frame.update_renditions(cell.renditions, false);
+            if cell.is_blank() {
+                frame.out.push(' ');
+            } else {
+                frame.append_cell(cell);
+            }
+            frame.cursor = Some((y, width));
+            wrote_last_cell = true;
+        }
+
+        if wrote_last_cell && y + 1 < f.height() {
+            if row.wrap {
+                // 端末でも実際に折り返させる
+                frame.cursor = Some((y + 1, 0));
+                return true;
+            }
+            frame.out.push_str("\r\n");
+            frame.cursor = Some((y + 1, 0));
+        }
+        false
+    }
+
+    /// 属性 `renditions` の空白を消去のシーケンスで作れるか
+    fn can_erase(&self, renditions: &Renditions) -> bool {
+        *renditions == Renditions::with_background(renditions.bg)
+            && (self.has_bce || renditions.bg == Color::Default)
+    }
+}
+
+/// セルの表示幅
+fn cell_width(cell: &Cell) -> usize {
+    if cell.wide {
+        2
+    } else {
+        1
+    }
+}
+
+/// `x` の直前まで書いたとき、端末側で消える全角文字の右半分のセルがあれば、その次の桁を返す
+fn forced_after(old_cells: &[Cell], x: usize) -> usize {
+    match x.checked_sub(1).map(|col| &old_cells[col]) {
+        Some(cell) if cell.wide => x + 1,
+        _ => 0,
+    }
+}
+
+/// DEC プライベートモードの設定・解除
+fn push_dec_mode(out: &mut String, mode: u16, on: bool) {
+    let _ = write!(out, "\x1b[?{}{}", mode, if on { 'h' } else { 'l' });
+}
+
+/// OSC（制御文字は取り除く）
+fn push_osc(out: &mut String, kind: u8, text: &str) {
+    let _ = write!(out, "\x1b]{};", kind);
+    out.extend(text.chars().filter(|ch| !ch.is_control()));
+    out.push('\x07');
+}
+
+/// SGR
+fn push_sgr(out: &mut String, params: &str) {
+    let _ = write!(out, "\x1b[{}m", params);
+}
+
+fn mouse_tracking_mode(tracking: MouseTracking) -> Option<u16> {
+    match tracking {
+        MouseTracking::Off => None,
+        MouseTracking::X10 => Some(9),
+        MouseTracking::Normal => Some(1000),
+        MouseTracking::ButtonEvent => Some(1002),
+        MouseTracking::AnyEvent => Some(1003),
+    }
+}
+
+fn mouse_encoding_mode(encoding: MouseEncoding) -> Option<u16> {
+    match encoding {
+        MouseEncoding::Default => None,
+        MouseEncoding::Utf8 => Some(1005),
+        MouseEncoding::Sgr => Some(1006),
+        MouseEncoding::Urxvt => Some(1015),
+    }
+}
+
+/// 属性を既定に戻してから `to` にする SGR のパラメーター
+fn full_sgr_params(to: &Renditions) -> String {
+    let mut params = String::from("0");
+    let flags = [
+        (to.bold, 1),
+        (to.faint, 2),
+        (to.italic, 3),
+        (to.underline, 4),
+        (to.blink, 5),
+        (to.inverse, 7),
+        (to.invisible, 8),
+        (to.strikethrough, 9),
+    ];
+    for (_, code) in flags.iter().filter(|(on, _)| *on) {
+        let _ = write!(params, ";{}", code);
+    }
+    if to.fg != Color::Default {
+        push_color_params(&mut params, to.fg, 30);
+    }
+    if to.bg != Color::Default {
+        push_color_params(&mut params, to.bg, 40);
+    }
+    params
+}
+
+/// `from` から `to` へ変わった分だけの SGR のパラメーター
+fn incremental_sgr_params(from: &Renditions, to: &Renditions) -> String {
+    let mut params = String::new();
+    let (mut bold, mut faint) = (from.bold, from.faint);
+    // 太字と淡色は SGR 22 で一緒に解除される
+    if (bold && !to.bold) || (faint && !to.faint) {
+        params.push_str(";22");
+        bold = false;
+        faint = false;
+    }
+    let flags = [
+        (bold, to.bold, 1, 22),
+        (faint, to.faint, 2, 22),
+        (from.italic, to.italic, 3, 23),
+        (from.underline, to.underline, 4, 24),
+        (from.blink, to.blink, 5, 25),
+        (from.inverse, to.inverse, 7, 27),
+        (from.invisible, to.invisible, 8, 28),
+        (from.strikethrough, to.strikethrough, 9, 29),
+    ];
+    for (was, is, on, off) in flags {
+        if was != is {
+            let _ = write!(params, ";{}", if is { on } else { off });
+        }
+    }
+    if from.fg != to.fg {
+        push_color_params(&mut params, to.fg, 30);
+    }
+    if from.bg != to.bg {
+        push_color_params(&mut params, to.bg, 40);
+    }
+    // 先頭の `;` を除く
+    params.split_off(1.min(params.len()))
+}
+
+/// 色の SGR パラメーター（`;` 付き）。`base` は文字色なら 30、背景色なら 40
+fn push_color_params(params: &mut String, color: Color, base: u8) {
+    let _ = match color {
+        Color::Default => write!(params, ";{}", base + 9),
+        Color::Indexed(i @ 0..=7) => write!(params, ";{}", base + i),
+        Color::Indexed(i @ 8..=15) => write!(params, ";{}", base + 60 + i - 8),
+        Color::Indexed(i) => write!(params, ";{};5;{}", base + 8, i),
+        Color::Rgb(r, g, b) => write!(params, ";{};2;{};{};{}", base + 8, r, g, b),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Emulator;
+
+    /// `initial` を表示している端末に差分を流すと、`initial` + `update` の画面になることを確かめる
+    ///
+    /// # 戻り値
+    /// 差分
+    fn check(width: usize, height: usize, initial: &[u8], update: &[u8]) -> String {
+        let mut last = Emulator::new(width, height);
+        last.write(initial);
+        let mut next = last.clone();
+        next.write(update);
+
+        let display = Display::default();
+        let mut terminal = Emulator::new(width, height);
+        terminal.write(
+            display
+                .new_frame(false, last.framebuffer(), last.framebuffer())
+                .as_bytes(),
+        );
+        assert_same_screen(terminal.framebuffer(), last.framebuffer());
+
+        let diff = display.new_frame(true, last.framebuffer(), next.framebuffer());
+        terminal.write(diff.as_bytes());
+        assert_same_screen(terminal.framebuffer(), next.framebuffer());
+        diff
+    }
+
+    fn assert_same_screen(actual: &Framebuffer, expected: &Framebuffer) {
+        for row in 0..expected.height() {
+            assert_eq!(
+                actual.row(row).cells(),
+                expected.row(row).cells(),
+                "{} 行目:\n{}\n---\n{}",
+                row,
+                actual.text(),
+                expected.text()
+            );
+        }
+        // 最下行の折り返しは再現しない（次の文字で画面がスクロールする）
+        for row in 0..expected.height() - 1 {
+            assert_eq!(
+                actual.row(row).wrap,
+                expected.row(row).wrap,
+                "{} 行目の折り返し",
+                row
+            );
+        }
+        assert_eq!(actual.cursor(), expected.cursor());
+        assert_eq!(actual.renditions(), expected.renditions());
+        let (a, e) = (actual.modes(), expected.modes());
+        assert_eq!(a.cursor_visible, e.cursor_visible);
+        assert_eq!(a.reverse_video, e.reverse_video);
+        assert_eq!(a.bracketed_paste, e.bracketed_paste);
+        assert_eq!(a.mouse_tracking, e.mouse_tracking);
+        assert_eq!(a.mouse_encoding, e.mouse_encoding);
+        assert_eq!(a.application_cursor_keys, e.application_cursor_keys);
+        assert_eq!(actual.title(), expected.title());
+    }
+
+    #[test]
+    fn test_typing_sends_only_new_chars() {
+        assert_eq!(check(20, 3, b"$ ", b"abc"), "abc");
+        // カーソルを動かす間は隠す（mosh と同じ）
+        assert_eq!(
+            check(20, 3, b"$ abc", b"\x08 \x08"),
+            "\x1b[?25l\x08\x1b[K\x1b[?25h"
+        );
+        assert_eq!(check(20, 3, b"$ ", b""), "");
+    }
+
+    #[test]
+    fn test_scroll_detected() {
+        let mut initial = String::new();
+        for i in 0..10 {
+            let _ = write!(initial, "line {}\r\n", i);
+        }
+        let initial = initial.as_bytes();
+        let diff = check(
+            10,
+            10,
+            &initial[..initial.len() - 2],
+            b"\r\nline 10\r\nline 11",
+        );
+        // 全行を描き直さず、LF 2 つと新しい 2 行だけを送る
+        assert_eq!(diff, "\r\n\n\x1b[?25l\x1b[9;1Hline 10\r\nline 11\x1b[?25h");
+
+        // スクロール領域の中だけのスクロール
+        check(10, 10, initial, b"\x1b[2;5r\x1b[5;1H\nnew\x1b[r\x1b[10;1H");
+    }
+
+    #[test]
+    fn test_renditions_minimised() {
+        let diff = check(20, 1, b"\x1b[1;31mab", b"\x1b[32mc");
+        assert_eq!(diff, "\x1b[32mc");
+        let diff = check(20, 1, b"\x1b[1;2;4mab", b"\x1b[22;2mc");
+        assert_eq!(diff, "\x1b[22;2mc");
+        let diff = check(20, 1, b"\x1b[1;4;7;38;5;100mab", b"\x1b[0;3mc");
+        assert_eq!(diff, "\x1b[0;3mc");
+    }
+
+    #[test]
+    fn test_blank_runs_and_wrapped_rows() {
+        check(20, 3, b"0123456789012345", b"\x1b[1;3H\x1b[K");
+        check(
+            20,
+            3,
+            b"0123456789012345",
+            b"\x1b[1;3H\x1b[44m\x1b[8X\x1b[0m",
+        );
+        check(20, 3, b"0123456789012345", b"\x1b[1;3H\x1b[7m      \x1b[0m");
+        // 折り返した行・全角文字
+        check(5, 3, b"", "abcde漢字かな".as_bytes());
+        check(6, 3, "漢字かな".as_bytes(), b"\x1b[1;2Hx\x1b[1;5Hab");
+        // 最後のセルが空白の折り返した行
+        assert_eq!(check(5, 3, b"", b" aba a"), " aba a");
+        // 内容は同じまま折り返しが解けた行
+        check(5, 3, b"abcdef", b"\x1b[1;5H\x1b[Ke\x1b[2;2H");
+    }
+
+    #[test]
+    fn test_modes_and_title() {
+        let diff = check(
+            10,
+            2,
+            b"",
+            b"\x1b]0;vim\x07\x1b[?25l\x1b[?1000h\x1b[?1006h\x1b[?2004h\x1b[?1h\x1b[?5h",
+        );
+        assert!(diff.contains("\x1b]0;vim\x07"));
+        check(
+            10,
+            2,
+            b"\x1b[?1000h\x1b[?1006h",
+            b"\x1b[?1002h\x1b[?1015h\x1b]1;i\x07",
+        );
+        check(10, 2, b"\x1b[?1003h", b"\x1b[?1003l\x1b[?1006l");
+    }
+
+    #[test]
+    fn test_resize_repaints_whole_screen() {
+        let mut last = Emulator::new(10, 3);
+        last.write(b"hello\r\nworld");
+        let mut next = last.clone();
+        next.resize(8, 4);
+        next.write(b"!");
+
+        let display = Display::default();
+        let mut terminal = Emulator::new(8, 4);
+        terminal.write(
+            display
+                .new_frame(true, last.framebuffer(), next.framebuffer())
+                .as_bytes(),
+        );
+        assert_same_screen(terminal.framebuffer(), next.framebuffer());
+    }
+
+    /// 疑似乱数で作った出力の差分が、どれも画面を正しく再現する
+    #[test]
+    fn test_random_updates_reproduce_screen() {
+        let mut seed: u64 = 0x9E37_79B9_7F4A_7C15;
+        let mut next = move || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed
+        };
+        let pieces: [&[u8]; 27] = [
+            b"a",
+            b"xyz",
+            "漢".as_bytes(),
+            "e\u{301}".as_bytes(),
+            "\u{301}".as_bytes(),
+            b" ",
+            b"     ",
+            b"\r\n",
+            b"\r",
+            b"\x08",
+            b"\t",
+            b"\x1b[K",
+            b"\x1b[1K",
+            b"\x1b[J",
+            b"\x1b[3X",
+            b"\x1b[2P",
+            b"\x1b[2@",
+            b"\x1b[L",
+            b"\x1b[M",
+            b"\x1b[1;31m",
+            b"\x1b[7;44m",
+            b"\x1b[0m",
+            b"\x1b[2;4r",
+            b"\x1b[r",
+            b"\x1bM",
+            b"\x1b[T",
+            b"\x1b[2S",
+        ];
+        for round in 0..300 {
+            let (width, height) = (2 + round % 9, 1 + round % 6);
+            let mut screen = Emulator::new(width, height);
+            let mut terminal = Emulator::new(width, height);
+            let display = Display::default();
+            terminal.write(
+                display
+                    .new_frame(false, screen.framebuffer(), screen.framebuffer())
+                    .as_bytes(),
+            );
+            for _ in 0..20 {
+                let last = screen.framebuffer().clone();
+                for _ in 0..(next() % 8) {
+                    let r = next() as usize;
+                    if r.is_multiple_of(5) {
+                        let _ = write!(
+                            &mut ByteWriter(&mut screen),
+                            "\x1b[{};{}H",
+                            (r >> 8) % (height + 1) + 1,
+                            (r >> 16) % (width + 1) + 1
+                        );
+                    } else {
+                        screen.write(pieces[(r >> 8) % pieces.len()]);
+                    }
+                }
+                let diff = display.new_frame(true, &last, screen.framebuffer());
+                terminal.write(diff.as_bytes());
+                assert_same_screen(terminal.framebuffer(), screen.framebuffer());
+            }
+        }
+    }
+
+    /// `write!` の出力をエミュレーターに流す
+    struct ByteWriter<'a>(&'a mut Emulator);
+
+    impl core::fmt::Write for ByteWriter<'_> {
+        fn write_str(&mut self, s: &str) -> core::fmt::Result {
+            self.0.write(s.as_bytes());
+            Ok(())
+        }
+    }
+}
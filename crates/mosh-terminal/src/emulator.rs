@@ -0,0 +1,574 @@
+//! 端末エミュレーター
+//!
+//! mosh C++ 実装の `Terminal::Emulator` に対応する。
+//! [`Parser`] が取り出した指示を解釈し、[`Framebuffer`] を更新する。
+//! 端末への問い合わせ（DA / DSR）の応答は [`Emulator::take_replies`] で取り出し、ホストに送り返す。
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write as _;
+
+use unicode_width::UnicodeWidthChar;
+
+use crate::cell::Color;
+use crate::framebuffer::{Charset, Framebuffer, MouseEncoding, MouseTracking};
+use crate::parser::{Action, Csi, Intermediates, Params, Parser};
+
+/// 端末エミュレーター
+#[derive(Debug, Clone)]
+pub struct Emulator {
+    parser: Parser,
+    fb: Framebuffer,
+    /// ホストに送り返す応答（DA / DSR）
+    replies: Vec<u8>,
+}
+
+impl Emulator {
+    /// `width` 桁 × `height` 行の空白の画面で初期化する（幅・高さは 1 以上に切り上げる）
+    pub fn new(width: usize, height: usize) -> Self {
+        Emulator {
+            parser: Parser::new(),
+            fb: Framebuffer::new(width, height),
+            replies: Vec::new(),
+        }
+    }
+
+    /// ホストの出力を解釈して画面に反映する
+    ///
+    /// エスケープシーケンスや UTF-8 の途中で切れたバイト列は、次の呼び出しで続きから読む。
+    pub fn write(&mut self, bytes: &[u8]) {
+        let Emulator {
+            parser,
+            fb,
+            replies,
+        } = self;
+        parser.feed(bytes, |action| dispatch(fb, replies, action));
+    }
+
+    /// 画面の大きさを変える
+    pub fn resize(&mut self, width: usize, height: usize) {
+        self.fb.resize(width, height);
+    }
+
+    /// 現在の画面
+    pub fn framebuffer(&self) -> &Framebuffer {
+        &self.fb
+    }
+
+    /// 現在の画面（書き換え用）
+    ///
+    /// 予測したエコーを重ねるなど、表示用の複製に手を加えるときに使う。
+    pub fn framebuffer_mut(&mut self) -> &mut Framebuffer {
+        &mut self.fb
+    }
+
+    /// ホストに送り返す応答を取り出す（なければ空）
+    pub fn take_replies(&mut self) -> Vec<u8> {
+        core::mem::take(&mut self.replies)
+    }
+}
+
+fn dispatch(fb: &mut Framebuffer, replies: &mut Vec<u8>, action: Action) {
+    match action {
+        Action::Print(ch) => print(fb, ch),
+        Action::Execute(ch) => execute(fb, ch),
+        Action::Esc {
+            intermediates,
+            final_byte,
+        } => esc(fb, intermediates, final_byte),
+        Action::Csi(csi) => csi_dispatch(fb, replies, &csi),
+        Action::Osc(osc) => osc_dispatch(fb, &osc),
+    }
+}
+
+/// 文字を書く。表示幅 0 は結合文字、2 は全角文字として扱う
+fn print(fb: &mut Framebuffer, ch: char) {
+    // 表示幅が定まらない文字（制御文字など）は半角として扱う
+    let width = ch.width().unwrap_or(1);
+    fb.print(ch, width);
+}
+
+/// C0 / C1 制御文字
+fn execute(fb: &mut Framebuffer, ch: char) {
+    match ch {
+        '\x07' => fb.ring_bell(),
+        '\x08' => fb.backspace(),
+        '\x09' => fb.tab_forward(1),
+        '\x0A'..='\x0C' => fb.line_feed(),
+        '\x0D' => fb.carriage_return(),
+        '\x0E' => fb.shift_charset(1),
+        '\x0F' => fb.shift_charset(0),
+        '\u{84}' => fb.line_feed(),
+        '\u{85}' => {
+            fb.carriage_return();
+            fb.line_feed();
+        }
+        '\u{88}' => fb.set_tab(),
+        '\u{8D}' => fb.reverse_index(),
+        _ => {}
+    }
+}
+
+/// ESC シーケンス
+fn esc(fb: &mut Framebuffer, intermediates: Intermediates, final_byte: u8) {
+    match (intermediates.as_bytes(), final_byte) {
+        ([], b'7') => fb.save_cursor(),
+        ([], b'8') => fb.restore_cursor(),
+        ([], b'D') => fb.line_feed(),
+        ([], b'E') => {
+            fb.carriage_return();
+            fb.line_feed();
+        }
+        ([], b'H') => fb.set_tab(),
+        ([], b'M') => fb.reverse_index(),
+        ([], b'c') => fb.reset(),
+        ([], b'=') => fb.modes_mut().application_keypad = true,
+        ([], b'>') => fb.modes_mut().application_keypad = false,
+        ([b'#'], b'8') => fb.fill_with_e(),
+        ([b'('], charset) => fb.designate_charset(0, charset_for(charset)),
+        ([b')'], charset) => fb.designate_charset(1, charset_for(charset)),
+        _ => {}
+    }
+}
+
+/// 文字集合の指定（`ESC ( 0` など）の終端文字に対応する文字集合
+fn charset_for(final_byte: u8) -> Charset {
+    match final_byte {
+        b'0' => Charset::DecSpecialGraphics,
+        _ => Charset::Ascii,
+    }
+}
+
+/// CSI シーケンス
+fn csi_dispatch(fb: &mut Framebuffer, replies: &mut Vec<u8>, csi: &Csi) {
+    let params = &csi.params;
+    // 回数・位置のパラメーター（省略・0 は 1）
+    let n = |i: usize| usize::from(params.get_or(i, 1));
+
+    match (csi.private, csi.intermediates.as_bytes(), csi.final_byte) {
+        (None, [], b'@') => fb.insert_cells(n(0)),
+        (None, [], b'A') => fb.cursor_up(n(0)),
+        (None, [], b'B' | b'e') => fb.cursor_down(n(0)),
+        (None, [], b'C' | b'a') => fb.cursor_forward(n(0) as isize),
+        (None, [], b'D') => fb.cursor_forward(-(n(0) as isize)),
+        (None, [], b'E') => {
+            fb.cursor_down(n(0));
+            fb.carriage_return();
+        }
+        (None, [], b'F') => {
+            fb.cursor_up(n(0));
+            fb.carriage_return();
+        }
+        (None, [], b'G' | b'`') => fb.set_col(n(0) - 1),
+        (None, [], b'H' | b'f') => fb.move_to(n(0) - 1, n(1) - 1),
+        (None, [], b'I') => fb.tab_forward(n(0)),
+        (None, [], b'J') => fb.erase_in_display(params.get(0)),
+        (None, [], b'K') => fb.erase_in_line(params.get(0)),
+        (None, [], b'L') => fb.insert_lines(n(0)),
+        (None, [], b'M') => fb.delete_lines(n(0)),
+        (None, [], b'P') => fb.delete_cells(n(0)),
+        (None, [], b'S') => fb.scroll_up(n(0)),
+        (None, [], b'T') => fb.scroll_down(n(0)),
+        (None, [], b'X') => fb.erase_cells(n(0)),
+        (None, [], b'Z') => fb.tab_backward(n(0)),
+        (None, [], b'c') if params.get(0) == 0 => {
+            // VT220 として応答する（mosh C++ 実装と同じ）
+            replies.extend_from_slice(b"\x1b[?62c");
+        }
+        (Some(b'>'), [], b'c') if params.get(0) == 0 => {
+            replies.extend_from_slice(b"\x1b[>1;10;0c");
+        }
+        (None, [], b'd') => fb.set_row(n(0) - 1),
+        (None, [], b'g') => fb.clear_tabs(params.get(0)),
+        (None, [], b'h') => set_ansi_modes(fb, params, true),
+        (None, [], b'l') => set_ansi_modes(fb, params, false),
+        (Some(b'?'), [], b'h') => set_dec_modes(fb, params, true),
+        (Some(b'?'), [], b'l') => set_dec_modes(fb, params, false),
+        (None, [], b'm') => select_graphic_rendition(fb, params),
+        (None, [], b'n') => device_status_report(fb, replies, params.get(0)),
+        (None, [], b'r') => {
+            let top = n(0) - 1;
+            let bottom = match params.get(1) {
+                0 => fb.height(),
+                bottom => usize::from(bottom),
+            };
+            fb.set_scrolling_region(top, bottom - 1);
+        }
+        (None, [], b's') => fb.save_cursor(),
+        (None, [], b'u') => fb.restore_cursor(),
+        (None, [b'!'], b'p') => fb.soft_reset(),
+        _ => {}
+    }
+}
+
+/// ANSI モード（SM / RM）
+fn set_ansi_modes(fb: &mut Framebuffer, params: &Params, on: bool) {
+    for mode in params.iter() {
+        if mode == 4 {
+            fb.modes_mut().insert = on;
+        }
+    }
+}
+
+/// DEC プライベートモード（DECSET / DECRST）
+fn set_dec_modes(fb: &mut Framebuffer, params: &Params, on: bool) {
+    for mode in params.iter() {
+        match mode {
+            1 => fb.modes_mut().application_cursor_keys = on,
+            5 => fb.modes_mut().reverse_video = on,
+            6 => fb.set_origin_mode(on),
+            7 => fb.modes_mut().auto_wrap = on,
+            25 => fb.modes_mut().cursor_visible = on,
+            9 => set_mouse_tracking(fb, MouseTracking::X10, on),
+            1000 => set_mouse_tracking(fb, MouseTracking::Normal, on),
+            1002 => set_mouse_tracking(fb, MouseTracking::ButtonEvent, on),
+            1003 => set_mouse_tracking(fb, MouseTracking::AnyEvent, on),
+            1004 => fb.modes_mut().focus_events = on,
+            1005 => set_mouse_encoding(fb, MouseEncoding::Utf8, on),
+            1006 => set_mouse_encoding(fb, MouseEncoding::Sgr, on),
+            1015 => set_mouse_encoding(fb, MouseEncoding::Urxvt, on),
+            2004 => fb.modes_mut().bracketed_paste = on,
+            47 | 1047 => {
+                if on {
+                    fb.enter_alternate_screen();
+                } else {
+                    if mode == 1047 && fb.is_alternate_screen() {
+                        // 1047 は主画面に戻る前に代替画面を消去する
+                        fb.erase_in_display(2);
+                    }
+                    fb.leave_alternate_screen();
+                }
+            }
+            1048 => {
+                if on {
+                    fb.save_cursor();
+                } else {
+                    fb.restore_cursor();
+                }
+            }
+            1049 => {
+                if on {
+                    fb.save_cursor();
+                    fb.enter_alternate_screen();
+                    fb.erase_in_display(2);
+                } else {
+                    fb.leave_alternate_screen();
+                    fb.restore_cursor();
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// マウス報告の方式を切り替える。解除は現在の方式と一致するときだけ効く
+fn set_mouse_tracking(fb: &mut Framebuffer, tracking: MouseTracking, on: bool) {
+    let modes = fb.modes_mut();
+    if on {
+        modes.mouse_tracking = tracking;
+    } else if modes.mouse_tracking == tracking {
+        modes.mouse_tracking = MouseTracking::Off;
+    }
+}
+
+/// マウス報告の符号化を切り替える。解除は現在の符号化と一致するときだけ効く
+fn set_mouse_encoding(fb: &mut Framebuffer, encoding: MouseEncoding, on: bool) {
+    let modes = fb.modes_mut();
+    if on {
+        modes.mouse_encoding = encoding;
+    } else if modes.mouse_encoding == encoding {
+        modes.mouse_encoding = MouseEncoding::Default;
+    }
+}
+
+/// 端末の状態の問い合わせ（DSR: 5 = 動作状態、6 = カーソル位置）
+fn device_status_report(fb: &Framebuffer, replies: &mut Vec<u8>, kind: u16) {
+    match kind {
+        5 => replies.extend_from_slice(b"\x1b[0n"),
+        6 => {
+            let mut reply = String::new();
+            let _ = write!(
+                reply,
+                "\x1b[{};{}R",
+                fb.reported_row() + 1,
+                fb.cursor().1 + 1
+            );
+            replies.extend_from_slice(reply.as_bytes());
+        }
+        _ => {}
+    }
+}
+
+/// 文字属性を設定する（SGR）
+fn select_graphic_rendition(fb: &mut Framebuffer, params: &Params) {
+    let r = fb.renditions_mut();
+    if params.is_empty() {
+        *r = Default::default();
+        return;
+    }
+
+    let mut i = 0;
+    while i < params.len() {
+        let code = params.get(i);
+        i += 1;
+        match code {
+            0 => *r = Default::default(),
+            1 => r.bold = true,
+            2 => r.faint = true,
+            3 => r.italic = true,
+            // `4:0` は下線なし、`4:3`（波線）などは下線として扱う
+            4 => r.underline = !(params.is_subparam(i) && params.get(i) == 0),
+            5 | 6 => r.blink = true,
+            7 => r.inverse = true,
+            8 => r.invisible = true,
+            9 => r.strikethrough = true,
+            21 => r.underline = true,
+            22 => {
+                r.bold = false;
+                r.faint = false;
+            }
+            23 => r.italic = false,
+            24 => r.underline = false,
+            25 => r.blink = false,
+            27 => r.inverse = false,
+            28 => r.invisible = false,
+            29 => r.strikethrough = false,
+            30..=37 => r.fg = Color::Indexed((code - 30) as u8),
+            38 => {
+                if let Some(color) = extended_color(params, &mut i) {
+                    r.fg = color;
+                }
+            }
+            39 => r.fg = Color::Default,
+            40..=47 => r.bg = Color::Indexed((code - 40) as u8),
+            48 => {
+                if let Some(color) = extended_color(params, &mut i) {
+                    r.bg = color;
+                }
+            }
+            49 => r.bg = Color::Default,
+            90..=97 => r.fg = Color::Indexed((code - 90 + 8) as u8),
+            100..=107 => r.bg = Color::Indexed((code - 100 + 8) as u8),
+            _ => {}
+        }
+        // 残りのサブパラメーターは読み捨てる
+        while params.is_subparam(i) {
+            i += 1;
+        }
+    }
+}
+
+/// 拡張色（SGR 38 / 48 の続き）を読む。`i` は 38 / 48 の次を指し、読んだ分だけ進める
+///
+/// - `38;5;N` / `38:5:N`: 256 色パレット
+/// - `38;2;R;G;B` / `38:2:R:G:B` / `38:2:CS:R:G:B`: 24 ビットカラー（CS は色空間の ID で無視する）
+fn extended_color(params: &Params, i: &mut usize) -> Option<Color> {
+    let channel = |index: usize| params.get(index).min(255) as u8;
+
+    if params.is_subparam(*i) {
+        // `:` 区切り: サブパラメーターの数で色空間 ID の有無を判断する
+        let start = *i;
+        let mut end = start;
+        while params.is_subparam(end) {
+            end += 1;
+        }
+        *i = end;
+        return match (params.get(start), end - start) {
+            (5, 2..) => Some(Color::Indexed(channel(start + 1))),
+            (2, 5..) => Some(Color::Rgb(
+                channel(start + 2),
+                channel(start + 3),
+                channel(start + 4),
+            )),
+            (2, 4) => Some(Color::Rgb(
+                channel(start + 1),
+                channel(start + 2),
+                channel(start + 3),
+            )),
+            _ => None,
+        };
+    }
+
+    match params.get(*i) {
+        5 if *i + 1 < params.len() => {
+            let color = Color::Indexed(channel(*i + 1));
+            *i += 2;
+            Some(color)
+        }
+        2 if *i + 3 < params.len() => {
+            let color = Color::Rgb(channel(*i + 1), channel(*i + 2), channel(*i + 3));
+            *i += 4;
+            Some(color)
+        }
+        _ => {
+            *i = params.len();
+            None
+        }
+    }
+}
+
+/// OSC 文字列（`番号;内容`）。0 = タイトルとアイコン名、1 = アイコン名、2 = タイトル
+fn osc_dispatch(fb: &mut Framebuffer, osc: &[u8]) {
+    let Some(split) = osc.iter().position(|&b| b == b';') else {
+        return;
+    };
+    let text = String::from_utf8_lossy(&osc[split + 1..]).into_owned();
+    match &osc[..split] {
+        b"0" => {
+            fb.set_icon_name(text.clone());
+            fb.set_title(text);
+        }
+        b"1" => fb.set_icon_name(text),
+        b"2" => fb.set_title(text),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    fn emulate(width: usize, height: usize, bytes: &[u8]) -> Emulator {
+        let mut emulator = Emulator::new(width, height);
+        emulator.write(bytes);
+        emulator
+    }
+
+    #[test]
+    fn test_cursor_movement_and_erase() {
+        let emulator = emulate(10, 3, b"abcdef\x1b[1;3H\x1b[K\x1b[2;2Hxy\x1b[3D!");
+        let fb = emulator.framebuffer();
+        assert_eq!(fb.text(), "ab\n!xy\n");
+        assert_eq!(fb.cursor(), (1, 1));
+    }
+
+    #[test]
+    fn test_sgr_colors() {
+        let emulator = emulate(
+            10,
+            1,
+            b"\x1b[1;4;31;42ma\x1b[38;5;200;48;2;1;2;3mb\x1b[38:2::10:20:30;4:0mc\x1b[0md",
+        );
+        let fb = emulator.framebuffer();
+        let a = fb.cell(0, 0).renditions;
+        assert!(a.bold && a.underline);
+        assert_eq!((a.fg, a.bg), (Color::Indexed(1), Color::Indexed(2)));
+        let b = fb.cell(0, 1).renditions;
+        assert_eq!((b.fg, b.bg), (Color::Indexed(200), Color::Rgb(1, 2, 3)));
+        let c = fb.cell(0, 2).renditions;
+        assert_eq!(c.fg, Color::Rgb(10, 20, 30));
+        assert!(c.bold && !c.underline);
+        assert_eq!(fb.cell(0, 3).renditions, Default::default());
+    }
+
+    #[test]
+    fn test_wide_and_combining_chars() {
+        let emulator = emulate(6, 2, "漢字e\u{301}\u{1F600}".as_bytes());
+        let fb = emulator.framebuffer();
+        assert!(fb.cell(0, 0).wide && fb.cell(0, 2).wide);
+        assert_eq!(fb.cell(0, 4).combining(), &['\u{301}']);
+        assert_eq!(fb.row(0).text(), "漢字e\u{301}");
+        assert_eq!(fb.row(1).text(), "\u{1F600}");
+        assert_eq!(fb.cursor(), (1, 2));
+    }
+
+    #[test]
+    fn test_combining_char_without_base() {
+        // カーソル移動の後の結合文字は U+00A0 を基底文字にして書き、カーソルを進める
+        let emulator = emulate(4, 2, "ab\x1b[2;2H\u{301}\u{308}x".as_bytes());
+        let fb = emulator.framebuffer();
+        assert_eq!(
+            fb.cell(1, 1).chars().collect::<String>(),
+            "\u{A0}\u{301}\u{308}"
+        );
+        assert_eq!(fb.row(1).text(), " \u{A0}\u{301}\u{308}x");
+        assert_eq!(fb.row(0).text(), "ab");
+        assert_eq!(fb.cursor(), (1, 3));
+    }
+
+    #[test]
+    fn test_combining_char_without_base_on_wide_right_half() {
+        // 全角文字の右半分に書くと、左半分も消える
+        let emulator = emulate(4, 1, "漢\x1b[1;2H\u{301}".as_bytes());
+        let fb = emulator.framebuffer();
+        assert!(!fb.cell(0, 0).wide);
+        assert!(fb.cell(0, 0).is_blank());
+        assert_eq!(fb.cell(0, 1).chars().collect::<String>(), "\u{A0}\u{301}");
+        assert_eq!(fb.cursor(), (0, 2));
+    }
+
+    #[test]
+    fn test_alternate_screen_1049() {
+        let mut emulator = emulate(5, 2, b"shell\x1b[?1049h\x1b[Hvim");
+        assert!(emulator.framebuffer().is_alternate_screen());
+        assert_eq!(emulator.framebuffer().text(), "vim\n");
+        emulator.write(b"\x1b[?1049l");
+        let fb = emulator.framebuffer();
+        assert!(!fb.is_alternate_screen());
+        assert_eq!(fb.text(), "shell\n");
+        assert_eq!(fb.cursor(), (0, 4));
+    }
+
+    #[test]
+    fn test_modes_title_and_replies() {
+        let mut emulator = emulate(
+            10,
+            5,
+            b"\x1b[?1h\x1b[?25l\x1b[?1002h\x1b[?1006h\x1b[?2004h\x1b]2;title\x07\x1b]1;icon\x1b\\",
+        );
+        let modes = *emulator.framebuffer().modes();
+        assert!(modes.application_cursor_keys && !modes.cursor_visible && modes.bracketed_paste);
+        assert_eq!(modes.mouse_tracking, MouseTracking::ButtonEvent);
+        assert_eq!(modes.mouse_encoding, MouseEncoding::Sgr);
+        assert_eq!(emulator.framebuffer().title(), "title");
+        assert_eq!(emulator.framebuffer().icon_name(), "icon");
+
+        emulator.write(b"\x1b[2;4r\x1b[?6h\x1b[2;3H\x1b[6n\x1b[c");
+        assert_eq!(emulator.framebuffer().cursor(), (2, 2));
+        assert_eq!(emulator.take_replies(), b"\x1b[2;3R\x1b[?62c");
+        assert!(emulator.take_replies().is_empty());
+    }
+
+    #[test]
+    fn test_dec_special_graphics() {
+        let emulator = emulate(10, 1, b"\x1b(0lqk\x1b(Bx\x0eq\x0fq\x1b)0");
+        assert_eq!(emulator.framebuffer().row(0).text(), "┌─┐xqq");
+    }
+
+    /// 任意のバイト列でパニックしない（決定的な疑似乱数で生成）
+    #[test]
+    fn test_random_bytes_do_not_panic() {
+        let mut seed: u64 = 0x2545_F491_4F6C_DD1D;
+        let mut next = move || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed
+        };
+        // エスケープシーケンスの断片を多く含めて、状態遷移を広く通す
+        let alphabet: Vec<u8> = b"\x1b[]?;:0123456789mHJKLMPr@hlABCDE\x07\x08\x09\n\r\x0e\x0f(0#8"
+            .iter()
+            .copied()
+            .chain("漢\u{301}é".bytes())
+            .collect();
+        for round in 0..200 {
+            let mut emulator = Emulator::new(1 + round % 13, 1 + round % 7);
+            let mut bytes = vec![0u8; 512];
+            for byte in &mut bytes {
+                let r = next();
+                *byte = if r % 4 == 0 {
+                    r as u8
+                } else {
+                    alphabet[(r >> 8) as usize % alphabet.len()]
+                };
+            }
+            emulator.write(&bytes);
+            emulator.resize((next() % 20) as usize, (next() % 10) as usize);
+            emulator.write(&bytes);
+            let fb = emulator.framebuffer();
+            let (row, col) = fb.cursor();
+            assert!(row < fb.height() && col < fb.width());
+        }
+    }
+}
@@ -0,0 +1,1007 @@
+//! 画面の状態（フレームバッファ）
+//!
+//! mosh C++ 実装の `Terminal::Framebuffer` / `Terminal::DrawState` に対応する。
+//! セルの格子・カーソル・スクロール領域・タブストップ・端末モード・代替画面を保持し、
+//! [`Emulator`](crate::Emulator) がエスケープシーケンスに従って操作する。
+//!
+//! 2 つのフレームバッファは `==` で比較でき、状態同期の差分生成の入力になる。
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::cell::{Cell, Color, Renditions};
+use crate::TAB_WIDTH;
+
+/// 画面の 1 行
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Row {
+    cells: Vec<Cell>,
+    /// 行末で自動折り返しして次の行に続いている
+    pub wrap: bool,
+}
+
+impl Row {
+    /// 背景色 `bg` の空白で埋めた行
+    pub fn new(width: usize, bg: Color) -> Self {
+        Row {
+            cells: alloc::vec![Cell::blank(Renditions::with_background(bg)); width],
+            wrap: false,
+        }
+    }
+
+    /// 行のセル
+    pub fn cells(&self) -> &[Cell] {
+        &self.cells
+    }
+
+    /// 行の内容（全角文字の右側のセルを除き、末尾の空白を削る）
+    pub fn text(&self) -> String {
+        let mut text = String::new();
+        let mut skip = false;
+        for cell in &self.cells {
+            if core::mem::take(&mut skip) {
+                continue;
+            }
+            text.extend(cell.chars());
+            skip = cell.wide;
+        }
+        text.truncate(text.trim_end_matches(' ').len());
+        text
+    }
+
+    /// 背景色 `bg` で消去する
+    fn reset(&mut self, bg: Color) {
+        for cell in &mut self.cells {
+            cell.reset(bg);
+        }
+        self.wrap = false;
+    }
+
+    /// `col` に空白を `n` 個挿入し、右端からはみ出した分を捨てる
+    fn insert_cells(&mut self, col: usize, n: usize, bg: Color) {
+        self.clear_wide_before(col);
+        let width = self.cells.len();
+        let n = n.min(width - col);
+        self.cells.truncate(width - n);
+        self.cells.splice(
+            col..col,
+            core::iter::repeat_n(Cell::blank(Renditions::with_background(bg)), n),
+        );
+        self.fix_split_wide(width);
+    }
+
+    /// `col` から `n` 個削除し、右端を空白で埋める
+    fn delete_cells(&mut self, col: usize, n: usize, bg: Color) {
+        self.clear_wide_before(col);
+        let width = self.cells.len();
+        let n = n.min(width - col);
+        self.cells.drain(col..col + n);
+        self.cells
+            .resize(width, Cell::blank(Renditions::with_background(bg)));
+    }
+
+    /// `from..to` のセルを背景色 `bg` で消去する
+    fn erase(&mut self, from: usize, to: usize, bg: Color) {
+        let to = to.min(self.cells.len());
+        if from < to {
+            self.clear_wide_before(from);
+        }
+        for cell in self.cells.get_mut(from..to).into_iter().flatten() {
+            cell.reset(bg);
+        }
+    }
+
+    /// 幅を変える（右端で切れた全角文字は消す）
+    fn resize(&mut self, width: usize) {
+        self.cells.resize(width, Cell::default());
+        self.fix_split_wide(width);
+    }
+
+    /// `col` の直前のセルが全角文字なら（右半分を動かす・消すと片割れになるので）消す
+    fn clear_wide_before(&mut self, col: usize) {
+        if let Some(cell) = col.checked_sub(1).and_then(|col| self.cells.get_mut(col)) {
+            if cell.wide {
+                let bg = cell.renditions.bg;
+                cell.reset(bg);
+            }
+        }
+    }
+
+    /// 右端のセルが全角文字の左側なら（右側がないので）消す
+    fn fix_split_wide(&mut self, width: usize) {
+        if let Some(last) = self.cells.get_mut(width - 1) {
+            if last.wide {
+                let bg = last.renditions.bg;
+                last.reset(bg);
+            }
+        }
+    }
+}
+
+/// マウスイベントの報告方式（DECSET 9 / 1000 / 1002 / 1003）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum MouseTracking {
+    /// 報告しない
+    #[default]
+    Off,
+    /// ボタンを押したときだけ（X10 互換、DECSET 9）
+    X10,
+    /// ボタンの押下・解放（DECSET 1000）
+    Normal,
+    /// ボタンを押したままの移動も（DECSET 1002）
+    ButtonEvent,
+    /// すべての移動（DECSET 1003）
+    AnyEvent,
+}
+
+/// マウスイベントの座標の符号化（DECSET 1005 / 1006 / 1015）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum MouseEncoding {
+    /// 既定（1 バイトずつ）
+    #[default]
+    Default,
+    /// UTF-8（DECSET 1005）
+    Utf8,
+    /// SGR 形式（DECSET 1006）
+    Sgr,
+    /// urxvt 形式（DECSET 1015）
+    Urxvt,
+}
+
+/// 端末モード（SM / RM・DECSET / DECRST で切り替える）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Modes {
+    /// 挿入モード（IRM、SM 4）
+    pub insert: bool,
+    /// カーソルキーのアプリケーションモード（DECCKM、DECSET 1）
+    pub application_cursor_keys: bool,
+    /// テンキーのアプリケーションモード（DECKPAM / DECKPNM）
+    pub application_keypad: bool,
+    /// 画面全体の反転表示（DECSCNM、DECSET 5）
+    pub reverse_video: bool,
+    /// 行番号をスクロール領域の上端から数える（DECOM、DECSET 6）
+    pub origin: bool,
+    /// 行末で自動折り返しする（DECAWM、DECSET 7）
+    pub auto_wrap: bool,
+    /// カーソルを表示する（DECTCEM、DECSET 25）
+    pub cursor_visible: bool,
+    /// マウスイベントの報告方式
+    pub mouse_tracking: MouseTracking,
+    /// マウスイベントの座標の符号化
+    pub mouse_encoding: MouseEncoding,
+    /// フォーカスの変化を報告する（DECSET 1004）
+    pub focus_events: bool,
+    /// 貼り付けを括弧で囲む（DECSET 2004）
+    pub bracketed_paste: bool,
+}
+
+impl Default for Modes {
+    fn default() -> Self {
+        Modes {
+            insert: false,
+            application_cursor_keys: false,
+            application_keypad: false,
+            reverse_video: false,
+            origin: false,
+            auto_wrap: true,
+            cursor_visible: true,
+            mouse_tracking: MouseTracking::Off,
+            mouse_encoding: MouseEncoding::Default,
+            focus_events: false,
+            bracketed_paste: false,
+        }
+    }
+}
+
+/// 文字集合（G0 / G1 に割り当てる）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub(crate) enum Charset {
+    /// US-ASCII
+    #[default]
+    Ascii,
+    /// DEC 特殊図形（罫線）
+    DecSpecialGraphics,
+}
+
+impl Charset {
+    /// この文字集合で `ch` を表示する文字に変換する
+    fn map(self, ch: char) -> char {
+        const DEC_SPECIAL_GRAPHICS: [char; 32] = [
+            ' ', '◆', '▒', '␉', '␌', '␍', '␊', '°', '±', '␤', '␋', '┘', '┐', '┌', '└', '┼', //
+            '⎺', '⎻', '─', '⎼', '⎽', '├', '┤', '┴', '┬', '│', '≤', '≥', 'π', '≠', '£', '·',
+        ];
+        match (self, ch) {
+            (Charset::DecSpecialGraphics, '\x5F'..='\x7E') => {
+                DEC_SPECIAL_GRAPHICS[ch as usize - 0x5F]
+            }
+            _ => ch,
+        }
+    }
+}
+
+/// DECSC で保存するカーソルの状態
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+struct SavedCursor {
+    row: usize,
+    col: usize,
+    renditions: Renditions,
+    origin: bool,
+    auto_wrap: bool,
+    charsets: [Charset; 2],
+    shift: usize,
+}
+
+/// 画面の状態
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Framebuffer {
+    /// 表示中の画面の行
+    rows: Vec<Row>,
+    /// 代替画面を表示している間の主画面の行
+    primary_rows: Option<Vec<Row>>,
+    width: usize,
+    height: usize,
+    cursor_row: usize,
+    cursor_col: usize,
+    /// 最後の桁に書いた直後で、次の文字は次の行の先頭に書く
+    next_print_will_wrap: bool,
+    /// 結合文字を足すセル（最後に文字を書いたセル）
+    combining_cell: Option<(usize, usize)>,
+    /// スクロール領域（両端を含む行番号）
+    scroll_top: usize,
+    scroll_bottom: usize,
+    tabs: Vec<bool>,
+    renditions: Renditions,
+    modes: Modes,
+    charsets: [Charset; 2],
+    /// 使用中の文字集合（0 = G0, 1 = G1）
+    shift: usize,
+    saved_cursor: SavedCursor,
+    title: String,
+    icon_name: String,
+    bell_count: u64,
+}
+
+impl Framebuffer {
+    /// 空白の画面を生成する（幅・高さは 1 以上に切り上げる）
+    pub fn new(width: usize, height: usize) -> Self {
+        let width = width.max(1);
+        let height = height.max(1);
+        Framebuffer {
+            rows: alloc::vec![Row::new(width, Color::Default); height],
+            primary_rows: None,
+            width,
+            height,
+            cursor_row: 0,
+            cursor_col: 0,
+            next_print_will_wrap: false,
+            combining_cell: None,
+            scroll_top: 0,
+            scroll_bottom: height - 1,
+            tabs: default_tabs(0, width).collect(),
+            renditions: Renditions::default(),
+            modes: Modes::default(),
+            charsets: [Charset::Ascii; 2],
+            shift: 0,
+            saved_cursor: SavedCursor {
+                auto_wrap: true,
+                ..Default::default()
+            },
+            title: String::new(),
+            icon_name: String::new(),
+            bell_count: 0,
+        }
+    }
+
+    /// 幅（桁数）
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// 高さ（行数）
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// 表示中の画面の行
+    pub fn rows(&self) -> &[Row] {
+        &self.rows
+    }
+
+    /// `row` 行目（0 始まり）
+    pub fn row(&self, row: usize) -> &Row {
+        &self.rows[row]
+    }
+
+    /// `row` 行 `col` 桁のセル（0 始まり）
+    pub fn cell(&self, row: usize, col: usize) -> &Cell {
+        &self.rows[row].cells[col]
+    }
+
+    /// カーソル位置（行, 桁）（0 始まり）
+    pub fn cursor(&self) -> (usize, usize) {
+        (self.cursor_row, self.cursor_col)
+    }
+
+    /// 次に書く文字に付ける属性
+    pub fn renditions(&self) -> &Renditions {
+        &self.renditions
+    }
+
+    /// 端末モード
+    pub fn modes(&self) -> &Modes {
+        &self.modes
+    }
+
+    /// スクロール領域（上端, 下端）（両端を含む、0 始まり）
+    pub fn scrolling_region(&self) -> (usize, usize) {
+        (self.scroll_top, self.scroll_bottom)
+    }
+
+    /// ウィンドウタイトル（OSC 0 / 2）
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    /// アイコン名（OSC 0 / 1）
+    pub fn icon_name(&self) -> &str {
+        &self.icon_name
+    }
+
+    /// これまでに鳴らしたベルの回数
+    pub fn bell_count(&self) -> u64 {
+        self.bell_count
+    }
+
+    /// 代替画面を表示しているか
+    pub fn is_alternate_screen(&self) -> bool {
+        self.primary_rows.is_some()
+    }
+
+    /// 画面の内容（各行の [`Row::text`] を改行でつないだもの）
+    pub fn text(&self) -> String {
+        let mut text = String::new();
+        for (i, row) in self.rows.iter().enumerate() {
+            if i > 0 {
+                text.push('\n');
+            }
+            text.push_str(&row.text());
+        }
+        text
+    }
+
+    // ===== 表示用の上書き =====
+
+    /// `(row, col)` のセルを `cell` に置き換える（画面外なら何もしない）
+    ///
+    /// 重なる全角文字の片割れは消す（全角文字の右半分に置く空白はそのまま右半分とみなす）。
+    /// 予測したエコーを画面に重ねるのに使う。
+    pub fn put_cell(&mut self, row: usize, col: usize, mut cell: Cell) {
+        if row >= self.height || col >= self.width {
+            return;
+        }
+        cell.wide &= col + 1 < self.width;
+        let cells = &mut self.rows[row].cells;
+        if col > 0 && cells[col - 1].wide && !cell.is_blank() {
+            let bg = cells[col - 1].renditions.bg;
+            cells[col - 1].reset(bg);
+        }
+        let end = col + if cell.wide { 2 } else { 1 };
+        if end < cells.len() && cells[end - 1].wide {
+            let bg = cells[end].renditions.bg;
+            cells[end].reset(bg);
+        }
+        cells[col] = cell;
+    }
+
+    /// カーソルを画面上の `(row, col)` に置く（原点モードを無視し、画面内に収める）
+    pub fn set_cursor(&mut self, row: usize, col: usize) {
+        self.cursor_row = row.min(self.height - 1);
+        self.cursor_col = col.min(self.width - 1);
+        self.next_print_will_wrap = false;
+    }
+
+    // ===== エミュレーターからの操作 =====
+
+    /// 文字を書く（`width` は表示幅: 0 = 結合文字、1 = 半角、2 = 全角）
+    pub(crate) fn print(&mut self, ch: char, width: usize) {
+        let ch = self.charsets[self.shift].map(ch);
+        if width == 0 {
+            self.print_combining(ch);
+            return;
+        }
+        if width > self.width {
+            return;
+        }
+
+        if self.next_print_will_wrap && self.modes.auto_wrap {
+            self.wrap_to_next_line();
+        }
+        if self.cursor_col + width > self.width {
+            // 全角文字が右端に収まらない
+            if self.modes.auto_wrap {
+                let (row, col, bg) = (self.cursor_row, self.cursor_col, self.renditions.bg);
+                self.rows[row].erase(col, self.width, bg);
+                self.wrap_to_next_line();
+            } else {
+                self.cursor_col = self.width - width;
+            }
+        }
+
+        let (row, col) = (self.cursor_row, self.cursor_col);
+        if self.modes.insert {
+            self.rows[row].insert_cells(col, width, self.renditions.bg);
+        }
+        self.clear_wide_overlap(row, col, width);
+        let renditions = self.renditions;
+        let cells = &mut self.rows[row].cells;
+        cells[col].set(ch, renditions, width == 2);
+        if width == 2 {
+            cells[col + 1] = Cell::blank(renditions);
+        }
+        self.combining_cell = Some((row, col));
+
+        if col + width < self.width {
+            self.cursor_col = col + width;
+            self.next_print_will_wrap = false;
+        } else {
+            self.next_print_will_wrap = true;
+        }
+    }
+
+    /// 結合文字を直前に書いたセルに足す
+    ///
+    /// 足すセルがない（カーソル移動の後など）ときは、mosh と同じく U+00A0 を基底文字にした
+    /// 半角の文字として書く（カーソルも 1 桁進む）。
+    fn print_combining(&mut self, ch: char) {
+        if self.combining_cell.is_none() {
+            self.print('\u{A0}', 1);
+        }
+        if let Some((row, col)) = self.combining_cell {
+            self.rows[row].cells[col].push_combining(ch);
+        }
+    }
+
+    /// 書き込む範囲にかかる全角文字の片割れを消す
+    fn clear_wide_overlap(&mut self, row: usize, col: usize, width: usize) {
+        let cells = &mut self.rows[row].cells;
+        if col > 0 && cells[col - 1].wide {
+            let bg = cells[col - 1].renditions.bg;
+            cells[col - 1].reset(bg);
+        }
+        let end = col + width;
+        if end < cells.len() && cells[end - 1].wide {
+            let bg = cells[end].renditions.bg;
+            cells[end].reset(bg);
+        }
+    }
+
+    /// 行末から次の行の先頭へ自動折り返しする
+    fn wrap_to_next_line(&mut self) {
+        self.rows[self.cursor_row].wrap = true;
+        self.cursor_col = 0;
+        self.next_print_will_wrap = false;
+        self.line_feed();
+    }
+
+    /// ベルを鳴らす
+    pub(crate) fn ring_bell(&mut self) {
+        self.bell_count = self.bell_count.wrapping_add(1);
+    }
+
+    /// 行頭に戻る（CR）
+    pub(crate) fn carriage_return(&mut self) {
+        self.cursor_col = 0;
+        self.next_print_will_wrap = false;
+    }
+
+    /// 1 桁戻る（BS）
+    pub(crate) fn backspace(&mut self) {
+        if self.next_print_will_wrap {
+            self.next_print_will_wrap = false;
+        } else {
+            self.cursor_col = self.cursor_col.saturating_sub(1);
+        }
+    }
+
+    /// 次の行へ（LF / IND）。スクロール領域の下端ならスクロールする
+    pub(crate) fn line_feed(&mut self) {
+        if self.cursor_row == self.scroll_bottom {
+            self.scroll_up(1);
+        } else if self.cursor_row + 1 < self.height {
+            self.cursor_row += 1;
+        }
+        self.next_print_will_wrap = false;
+        self.combining_cell = None;
+    }
+
+    /// 前の行へ（RI）。スクロール領域の上端なら逆スクロールする
+    pub(crate) fn reverse_index(&mut self) {
+        if self.cursor_row == self.scroll_top {
+            self.scroll_down(1);
+        } else {
+            self.cursor_row = self.cursor_row.saturating_sub(1);
+        }
+        self.next_print_will_wrap = false;
+        self.combining_cell = None;
+    }
+
+    /// スクロール領域を `n` 行上にスクロールする（SU）
+    pub(crate) fn scroll_up(&mut self, n: usize) {
+        let (top, bottom) = (self.scroll_top, self.scroll_bottom);
+        let n = n.min(bottom - top + 1);
+        let blank = Row::new(self.width, self.renditions.bg);
+        self.rows.drain(top..top + n);
+        self.rows.splice(
+            bottom + 1 - n..bottom + 1 - n,
+            core::iter::repeat_n(blank, n),
+        );
+        self.combining_cell = None;
+    }
+
+    /// スクロール領域を `n` 行下にスクロールする（SD）
+    pub(crate) fn scroll_down(&mut self, n: usize) {
+        let (top, bottom) = (self.scroll_top, self.scroll_bottom);
+        let n = n.min(bottom - top + 1);
+        let blank = Row::new(self.width, self.renditions.bg);
+        self.rows.drain(bottom + 1 - n..bottom + 1);
+        self.rows.splice(top..top, core::iter::repeat_n(blank, n));
+        self.combining_cell = None;
+    }
+
+    /// カーソル位置に空行を `n` 行挿入する（IL）。スクロール領域の外では何もしない
+    pub(crate) fn insert_lines(&mut self, n: usize) {
+        if self.cursor_in_scrolling_region() {
+            let top = core::mem::replace(&mut self.scroll_top, self.cursor_row);
+            self.scroll_down(n);
+            self.scroll_top = top;
+            self.carriage_return();
+        }
+    }
+
+    /// カーソル位置から `n` 行削除する（DL）。スクロール領域の外では何もしない
+    pub(crate) fn delete_lines(&mut self, n: usize) {
+        if self.cursor_in_scrolling_region() {
+            let top = core::mem::replace(&mut self.scroll_top, self.cursor_row);
+            self.scroll_up(n);
+            self.scroll_top = top;
+            self.carriage_return();
+        }
+    }
+
+    fn cursor_in_scrolling_region(&self) -> bool {
+        (self.scroll_top..=self.scroll_bottom).contains(&self.cursor_row)
+    }
+
+    /// カーソル位置に空白を `n` 個挿入する（ICH）
+    pub(crate) fn insert_cells(&mut self, n: usize) {
+        let (row, col, bg) = (self.cursor_row, self.cursor_col, self.renditions.bg);
+        self.rows[row].insert_cells(col, n, bg);
+        self.next_print_will_wrap = false;
+    }
+
+    /// カーソル位置から `n` 個削除する（DCH）
+    pub(crate) fn delete_cells(&mut self, n: usize) {
+        let (row, col, bg) = (self.cursor_row, self.cursor_col, self.renditions.bg);
+        self.rows[row].delete_cells(col, n, bg);
+        self.next_print_will_wrap = false;
+    }
+
+    /// カーソル位置から `n` 個消去する（ECH）
+    pub(crate) fn erase_cells(&mut self, n: usize) {
+        let (row, col, bg) = (self.cursor_row, self.cursor_col, self.renditions.bg);
+        self.rows[row].erase(col, col.saturating_add(n), bg);
+    }
+
+    /// 行を消去する（EL: 0 = カーソルから行末、1 = 行頭からカーソル、2 = 行全体）
+    pub(crate) fn erase_in_line(&mut self, mode: u16) {
+        let (row, col, bg) = (self.cursor_row, self.cursor_col, self.renditions.bg);
+        let width = self.width;
+        match mode {
+            0 => self.rows[row].erase(col, width, bg),
+            1 => self.rows[row].erase(0, col + 1, bg),
+            2 => self.rows[row].erase(0, width, bg),
+            _ => return,
+        }
+        if mode != 1 {
+            self.rows[row].wrap = false;
+        }
+    }
+
+    /// 画面を消去する（ED: 0 = カーソルから画面末尾、1 = 画面先頭からカーソル、2 / 3 = 画面全体）
+    pub(crate) fn erase_in_display(&mut self, mode: u16) {
+        let bg = self.renditions.bg;
+        match mode {
+            0 => {
+                self.erase_in_line(0);
+                for row in &mut self.rows[self.cursor_row + 1..] {
+                    row.reset(bg);
+                }
+            }
+            1 => {
+                for row in &mut self.rows[..self.cursor_row] {
+                    row.reset(bg);
+                }
+                self.erase_in_line(1);
+            }
+            2 | 3 => {
+                for row in &mut self.rows {
+                    row.reset(bg);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// カーソルを絶対位置に移動する（0 始まり。原点モードでは行をスクロール領域の上端から数える）
+    pub(crate) fn move_to(&mut self, row: usize, col: usize) {
+        self.set_row(row);
+        self.set_col(col);
+    }
+
+    /// カーソルの行を絶対位置に移動する（原点モードではスクロール領域の上端から数える）
+    pub(crate) fn set_row(&mut self, row: usize) {
+        self.cursor_row = if self.modes.origin {
+            self.scroll_top.saturating_add(row).min(self.scroll_bottom)
+        } else {
+            row.min(self.height - 1)
+        };
+        self.next_print_will_wrap = false;
+        self.combining_cell = None;
+    }
+
+    /// カーソルの桁を絶対位置に移動する
+    pub(crate) fn set_col(&mut self, col: usize) {
+        self.cursor_col = col.min(self.width - 1);
+        self.next_print_will_wrap = false;
+        self.combining_cell = None;
+    }
+
+    /// カーソルを上に `n` 行移動する（CUU）。スクロール領域の中では上端で止まる
+    pub(crate) fn cursor_up(&mut self, n: usize) {
+        let limit = if self.cursor_row >= self.scroll_top {
+            self.scroll_top
+        } else {
+            0
+        };
+        self.cursor_row = self.cursor_row.saturating_sub(n).max(limit);
+        self.next_print_will_wrap = false;
+        self.combining_cell = None;
+    }
+
+    /// カーソルを下に `n` 行移動する（CUD）。スクロール領域の中では下端で止まる
+    pub(crate) fn cursor_down(&mut self, n: usize) {
+        let limit = if self.cursor_row <= self.scroll_bottom {
+            self.scroll_bottom
+        } else {
+            self.height - 1
+        };
+        self.cursor_row = self.cursor_row.saturating_add(n).min(limit);
+        self.next_print_will_wrap = false;
+        self.combining_cell = None;
+    }
+
+    /// カーソルを右（`n` > 0）・左（`n` < 0）に移動する（CUF / CUB）
+    pub(crate) fn cursor_forward(&mut self, n: isize) {
+        let col = self.cursor_col.saturating_add_signed(n);
+        self.set_col(col);
+    }
+
+    /// カーソルの行（原点モードを考慮した 0 始まりの値。CPR の応答に使う）
+    pub(crate) fn reported_row(&self) -> usize {
+        if self.modes.origin {
+            self.cursor_row.saturating_sub(self.scroll_top)
+        } else {
+            self.cursor_row
+        }
+    }
+
+    /// 次のタブストップへ `n` 回進む（HT / CHT）
+    pub(crate) fn tab_forward(&mut self, n: usize) {
+        for _ in 0..n {
+            self.cursor_col = (self.cursor_col + 1..self.width)
+                .find(|&col| self.tabs[col])
+                .unwrap_or(self.width - 1);
+        }
+        self.next_print_will_wrap = false;
+    }
+
+    /// 前のタブストップへ `n` 回戻る（CBT）
+    pub(crate) fn tab_backward(&mut self, n: usize) {
+        for _ in 0..n {
+            self.cursor_col = (0..self.cursor_col)
+                .rev()
+                .find(|&col| self.tabs[col])
+                .unwrap_or(0);
+        }
+        self.next_print_will_wrap = false;
+    }
+
+    /// カーソル位置にタブストップを設定する（HTS）
+    pub(crate) fn set_tab(&mut self) {
+        self.tabs[self.cursor_col] = true;
+    }
+
+    /// タブストップを解除する（TBC: 0 = カーソル位置、3 = すべて）
+    pub(crate) fn clear_tabs(&mut self, mode: u16) {
+        match mode {
+            0 => self.tabs[self.cursor_col] = false,
+            3 => self.tabs.iter_mut().for_each(|tab| *tab = false),
+            _ => {}
+        }
+    }
+
+    /// スクロール領域を設定し、カーソルを原点に移動する（DECSTBM、0 始まり）
+    ///
+    /// 上端が下端以上なら何もしない。
+    pub(crate) fn set_scrolling_region(&mut self, top: usize, bottom: usize) {
+        let bottom = bottom.min(self.height - 1);
+        if top >= bottom {
+            return;
+        }
+        self.scroll_top = top;
+        self.scroll_bottom = bottom;
+        self.move_to(0, 0);
+    }
+
+    /// 次に書く文字に付ける属性
+    pub(crate) fn renditions_mut(&mut self) -> &mut Renditions {
+        &mut self.renditions
+    }
+
+    /// 端末モード
+    pub(crate) fn modes_mut(&mut self) -> &mut Modes {
+        &mut self.modes
+    }
+
+    /// 原点モードを切り替え、カーソルを原点に移動する（DECOM）
+    pub(crate) fn set_origin_mode(&mut self, origin: bool) {
+        self.modes.origin = origin;
+        self.move_to(0, 0);
+    }
+
+    /// G0 / G1 に文字集合を割り当てる
+    pub(crate) fn designate_charset(&mut self, slot: usize, charset: Charset) {
+        self.charsets[slot] = charset;
+    }
+
+    /// 使用する文字集合を切り替える（SI = 0 / SO = 1）
+    pub(crate) fn shift_charset(&mut self, slot: usize) {
+        self.shift = slot;
+    }
+
+    /// カーソルの状態を保存する（DECSC）
+    pub(crate) fn save_cursor(&mut self) {
+        self.saved_cursor = SavedCursor {
+            row: self.cursor_row,
+            col: self.cursor_col,
+            renditions: self.renditions,
+            origin: self.modes.origin,
+            auto_wrap: self.modes.auto_wrap,
+            charsets: self.charsets,
+            shift: self.shift,
+        };
+    }
+
+    /// 保存したカーソルの状態に戻す（DECRC）
+    pub(crate) fn restore_cursor(&mut self) {
+        let saved = self.saved_cursor;
+        self.cursor_row = saved.row.min(self.height - 1);
+        self.cursor_col = saved.col.min(self.width - 1);
+        self.renditions = saved.renditions;
+        self.modes.origin = saved.origin;
+        self.modes.auto_wrap = saved.auto_wrap;
+        self.charsets = saved.charsets;
+        self.shift = saved.shift;
+        self.next_print_will_wrap = false;
+        self.combining_cell = None;
+    }
+
+    /// 代替画面に切り替える（既に代替画面なら何もしない）
+    pub(crate) fn enter_alternate_screen(&mut self) {
+        if self.primary_rows.is_none() {
+            let blank = alloc::vec![Row::new(self.width, Color::Default); self.height];
+            self.primary_rows = Some(core::mem::replace(&mut self.rows, blank));
+            self.combining_cell = None;
+        }
+    }
+
+    /// 主画面に戻る（代替画面でなければ何もしない）
+    pub(crate) fn leave_alternate_screen(&mut self) {
+        if let Some(rows) = self.primary_rows.take() {
+            self.rows = rows;
+            self.combining_cell = None;
+        }
+    }
+
+    /// ウィンドウタイトルを設定する
+    pub(crate) fn set_title(&mut self, title: String) {
+        self.title = title;
+    }
+
+    /// アイコン名を設定する
+    pub(crate) fn set_icon_name(&mut self, icon_name: String) {
+        self.icon_name = icon_name;
+    }
+
+    /// 画面を `E` で埋める（DECALN）
+    pub(crate) fn fill_with_e(&mut self) {
+        for row in &mut self.rows {
+            for cell in &mut row.cells {
+                cell.set('E', Renditions::default(), false);
+            }
+            row.wrap = false;
+        }
+        self.scroll_top = 0;
+        self.scroll_bottom = self.height - 1;
+        self.move_to(0, 0);
+    }
+
+    /// モード・属性・スクロール領域を初期値に戻す（DECSTR）。画面の内容は残す
+    pub(crate) fn soft_reset(&mut self) {
+        self.modes = Modes {
+            mouse_tracking: self.modes.mouse_tracking,
+            mouse_encoding: self.modes.mouse_encoding,
+            focus_events: self.modes.focus_events,
+            bracketed_paste: self.modes.bracketed_paste,
+            reverse_video: self.modes.reverse_video,
+            ..Modes::default()
+        };
+        self.renditions = Renditions::default();
+        self.charsets = [Charset::Ascii; 2];
+        self.shift = 0;
+        self.scroll_top = 0;
+        self.scroll_bottom = self.height - 1;
+        self.saved_cursor = SavedCursor {
+            auto_wrap: true,
+            ..Default::default()
+        };
+        self.next_print_will_wrap = false;
+    }
+
+    /// 端末を初期状態に戻す（RIS）。タイトルとベルの回数は残す
+    pub(crate) fn reset(&mut self) {
+        let mut fresh = Framebuffer::new(self.width, self.height);
+        fresh.title = core::mem::take(&mut self.title);
+        fresh.icon_name = core::mem::take(&mut self.icon_name);
+        fresh.bell_count = self.bell_count;
+        *self = fresh;
+    }
+
+    /// 画面の大きさを変える（幅・高さは 1 以上に切り上げる）
+    ///
+    /// 高さを縮めるときは、カーソルの行が残るよう上の行から捨てる。
+    /// スクロール領域は画面全体に戻る。
+    pub(crate) fn resize(&mut self, width: usize, height: usize) {
+        let width = width.max(1);
+        let height = height.max(1);
+
+        if self.cursor_row >= height {
+            self.rows.drain(..self.cursor_row + 1 - height);
+            self.cursor_row = height - 1;
+        }
+        for rows in core::iter::once(&mut self.rows).chain(self.primary_rows.as_mut()) {
+            rows.resize(height, Row::new(width, Color::Default));
+            for row in rows.iter_mut() {
+                row.resize(width);
+            }
+        }
+
+        if width > self.width {
+            self.tabs.extend(default_tabs(self.width, width));
+        } else {
+            self.tabs.truncate(width);
+        }
+        self.width = width;
+        self.height = height;
+        self.cursor_col = self.cursor_col.min(width - 1);
+        self.scroll_top = 0;
+        self.scroll_bottom = height - 1;
+        self.next_print_will_wrap = false;
+        self.combining_cell = None;
+    }
+}
+
+/// `from..to` 桁の既定のタブストップ（[`TAB_WIDTH`] 桁ごと）
+fn default_tabs(from: usize, to: usize) -> impl Iterator<Item = bool> {
+    (from..to).map(|col| col > 0 && col % TAB_WIDTH == 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(fb: &mut Framebuffer, text: &str) {
+        for ch in text.chars() {
+            let width = if ('\u{3000}'..='\u{9FFF}').contains(&ch) {
+                2
+            } else {
+                1
+            };
+            fb.print(ch, width);
+        }
+    }
+
+    #[test]
+    fn test_auto_wrap_and_scroll() {
+        let mut fb = Framebuffer::new(4, 2);
+        write(&mut fb, "abcd");
+        assert_eq!(fb.cursor(), (0, 3), "最後の桁ではカーソルは止まる");
+        write(&mut fb, "efghij");
+        assert_eq!(fb.text(), "efgh\nij");
+        assert!(fb.row(0).wrap);
+        assert_eq!(fb.cursor(), (1, 2));
+    }
+
+    #[test]
+    fn test_wide_char_at_right_edge_wraps() {
+        let mut fb = Framebuffer::new(5, 2);
+        write(&mut fb, "abcd漢");
+        assert_eq!(fb.text(), "abcd\n漢");
+        assert!(fb.cell(1, 0).wide);
+
+        // 全角文字の右半分を上書きすると左半分も消える
+        fb.move_to(1, 1);
+        write(&mut fb, "x");
+        assert_eq!(fb.row(1).text(), " x");
+    }
+
+    #[test]
+    fn test_scrolling_region_and_lines() {
+        let mut fb = Framebuffer::new(3, 4);
+        for (i, line) in ["a", "b", "c", "d"].iter().enumerate() {
+            fb.move_to(i, 0);
+            write(&mut fb, line);
+        }
+        fb.set_scrolling_region(1, 2);
+        fb.move_to(2, 0);
+        fb.line_feed();
+        assert_eq!(fb.text(), "a\nc\n\nd");
+
+        fb.move_to(1, 0);
+        fb.insert_lines(1);
+        assert_eq!(fb.text(), "a\n\nc\nd");
+        fb.delete_lines(5);
+        assert_eq!(fb.text(), "a\n\n\nd");
+    }
+
+    #[test]
+    fn test_alternate_screen_and_resize() {
+        let mut fb = Framebuffer::new(4, 3);
+        write(&mut fb, "main");
+        fb.enter_alternate_screen();
+        fb.move_to(0, 0);
+        write(&mut fb, "alt");
+        assert_eq!(fb.text(), "alt\n\n");
+
+        fb.resize(2, 2);
+        fb.leave_alternate_screen();
+        assert_eq!(fb.text(), "ma\n");
+        assert_eq!(fb.rows().len(), 2);
+    }
+
+    #[test]
+    fn test_put_cell_clears_wide_half() {
+        let mut fb = Framebuffer::new(4, 1);
+        write(&mut fb, "漢a");
+        fb.put_cell(0, 1, Cell::new('x', Renditions::default()));
+        assert_eq!(fb.text(), " xa");
+        fb.put_cell(0, 4, Cell::new('y', Renditions::default()));
+        fb.set_cursor(5, 9);
+        assert_eq!(fb.cursor(), (0, 3));
+    }
+
+    #[test]
+    fn test_resize_keeps_cursor_line() {
+        let mut fb = Framebuffer::new(3, 3);
+        for line in ["1", "2", "3"] {
+            write(&mut fb, line);
+            fb.carriage_return();
+            if line != "3" {
+                fb.line_feed();
+            }
+        }
+        fb.resize(3, 2);
+        assert_eq!(fb.text(), "2\n3");
+        assert_eq!(fb.cursor(), (1, 0));
+    }
+}
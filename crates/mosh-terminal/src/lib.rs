@@ -0,0 +1,81 @@
+//! # mosh-terminal
+//!
+//! VT100 / xterm 互換の端末エミュレーター（no_std + alloc）。
+//!
+//! mosh C++ 実装の `Terminal::Emulator` / `Terminal::Framebuffer` に対応し、
+//! ホストの出力（エスケープシーケンスを含むバイト列）を解釈してセルの格子に描く。
+//! 端末モードのセッションで、サーバー側では画面の状態を作り、
+//! クライアント側では受け取った状態を表示・予測するために使う。
+//!
+//! ## 構成
+//!
+//! ```text
+//! バイト列 → Parser（UTF-8 デコード + DEC 互換の状態機械）
+//!          → Action（Print / Execute / Esc / Csi / Osc）
+//!          → Emulator（指示を解釈）
+//!          → Framebuffer（セル・カーソル・スクロール領域・モード・代替画面）
+//!
+//! Framebuffer × 2 → Display（差分を最小限のエスケープシーケンスにする）
+//! ```
+//!
+//! サーバーは確認済みの画面から最新の画面への差分を [`Display::new_frame`] で作って送り、
+//! クライアントは表示中の画面から受け取った画面への差分で端末を描き替える。
+//!
+//! ## 対応している主な機能
+//!
+//! - カーソル移動・消去・行/文字の挿入削除（CSI）
+//! - スクロール領域（DECSTBM）・原点モード・自動折り返し
+//! - SGR の文字属性と色（16 色・256 色・24 ビットカラー、`;` / `:` 区切りの両方）
+//! - DEC プライベートモード（カーソルキー、マウス報告、ブラケットペースト、代替画面 47/1047/1049 など）
+//! - 全角文字・結合文字（表示幅は `unicode-width` で判定）
+//! - DEC 特殊図形（罫線）の文字集合
+//! - OSC 0 / 1 / 2（ウィンドウタイトル・アイコン名）
+//! - 端末への問い合わせ（DA / DSR）への応答
+//!
+//! ## 使用例
+//!
+//! ```
+//! use mosh_terminal::Emulator;
+//!
+//! let mut emulator = Emulator::new(80, 24);
+//! emulator.write(b"hello\r\n\x1b[1;31mworld\x1b[0m");
+//!
+//! let fb = emulator.framebuffer();
+//! assert_eq!(fb.row(0).text(), "hello");
+//! assert!(fb.cell(1, 0).renditions.bold);
+//! assert_eq!(fb.cursor(), (1, 5));
+//! ```
+
+#![no_std]
+extern crate alloc;
+
+pub mod cell;
+pub mod display;
+pub mod emulator;
+pub mod framebuffer;
+pub mod parser;
+
+pub use cell::{Cell, Color, Renditions};
+pub use display::Display;
+pub use emulator::Emulator;
+pub use framebuffer::{Framebuffer, Modes, MouseEncoding, MouseTracking, Row};
+pub use parser::{Action, Csi, Intermediates, Params, Parser};
+
+/// CSI シーケンスで保持する数値パラメーターの最大数
+/// これを超えるパラメーターは読み捨てる
+pub const CSI_MAX_PARAMS: usize = 16;
+
+/// CSI / ESC シーケンスで保持する中間文字の最大数
+/// これを超えるシーケンスは全体を無視する
+pub const CSI_MAX_INTERMEDIATES: usize = 2;
+
+/// OSC 文字列の最大バイト数
+/// これを超える分は切り捨てる（終端が来ないまま大量のデータが流れてもメモリを使い切らない）
+pub const OSC_STRING_MAX_BYTES: usize = 4096;
+
+/// 1 つのセルに保持する結合文字の最大数
+/// これを超える結合文字は捨てる（mosh C++ 実装と同じく、際限なく積み上がるのを防ぐ）
+pub const COMBINING_CHARS_MAX: usize = 16;
+
+/// 既定のタブストップの間隔（桁数）
+pub const TAB_WIDTH: usize = 8;
@@ -0,0 +1,568 @@
+//! エスケープシーケンスのパーサー
+//!
+//! UTF-8 をデコードし、Paul Williams の DEC 互換パーサーの状態機械
+//! （<https://vt100.net/emu/dec_ansi_parser>）で文字・制御文字・シーケンスに分ける。
+//! mosh C++ 実装の `Parser::UTF8Parser` と同じく、C1 制御文字は UTF-8 の
+//! U+0080〜U+009F として解釈する（8 ビットの生バイトは不正な UTF-8 として U+FFFD になる）。
+//!
+//! どんなバイト列を与えてもパニックせず、保持するメモリも上限
+//! （[`CSI_MAX_PARAMS`]・[`OSC_STRING_MAX_BYTES`]）を超えない。
+
+use alloc::vec::Vec;
+
+use crate::{CSI_MAX_INTERMEDIATES, CSI_MAX_PARAMS, OSC_STRING_MAX_BYTES};
+
+/// CSI シーケンスの数値パラメーター
+///
+/// 省略されたパラメーターは 0。値は `u16::MAX` で頭打ちになる。
+/// `:` で区切られたサブパラメーター（`38:2::255:0:0` など）も 1 つのパラメーターとして数え、
+/// 区切りが `:` だったかを [`Params::is_subparam`] で区別する。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Params {
+    values: [u16; CSI_MAX_PARAMS],
+    /// i ビット目: i 番目のパラメーターの直前の区切りが `:`
+    subparams: u32,
+    len: usize,
+    /// 上限を超えたパラメーターを読み捨てている
+    overflow: bool,
+}
+
+impl Params {
+    /// パラメーターの数
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// パラメーターがないか
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// i 番目のパラメーター。省略・範囲外なら 0
+    pub fn get(&self, i: usize) -> u16 {
+        if i < self.len {
+            self.values[i]
+        } else {
+            0
+        }
+    }
+
+    /// i 番目のパラメーター。省略・範囲外・0 なら `default`（カーソル移動の回数などに使う）
+    pub fn get_or(&self, i: usize, default: u16) -> u16 {
+        match self.get(i) {
+            0 => default,
+            value => value,
+        }
+    }
+
+    /// i 番目のパラメーターの直前の区切りが `:` か
+    pub fn is_subparam(&self, i: usize) -> bool {
+        i < self.len && self.subparams & (1 << i) != 0
+    }
+
+    /// パラメーターを古い順に返す
+    pub fn iter(&self) -> impl Iterator<Item = u16> + '_ {
+        self.values[..self.len].iter().copied()
+    }
+
+    /// 現在のパラメーターに数字を 1 桁足す
+    fn push_digit(&mut self, digit: u8) {
+        if self.overflow {
+            return;
+        }
+        if self.len == 0 {
+            self.len = 1;
+        }
+        let value = &mut self.values[self.len - 1];
+        *value = value.saturating_mul(10).saturating_add(u16::from(digit));
+    }
+
+    /// 区切り文字で次のパラメーターに進む。上限を超える分は捨てる
+    fn next(&mut self, subparam: bool) {
+        if self.len == 0 {
+            self.len = 1;
+        }
+        if self.len >= CSI_MAX_PARAMS {
+            self.overflow = true;
+            return;
+        }
+        if subparam {
+            self.subparams |= 1 << self.len;
+        }
+        self.len += 1;
+    }
+}
+
+/// 中間文字（0x20〜0x2F）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Intermediates {
+    bytes: [u8; CSI_MAX_INTERMEDIATES],
+    len: usize,
+}
+
+impl Intermediates {
+    /// 中間文字のバイト列
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+
+    /// 中間文字がないか
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// 中間文字を足す
+    ///
+    /// # 戻り値
+    /// 上限を超えたら `false`（シーケンス全体を無視する）
+    fn push(&mut self, byte: u8) -> bool {
+        if self.len >= CSI_MAX_INTERMEDIATES {
+            return false;
+        }
+        self.bytes[self.len] = byte;
+        self.len += 1;
+        true
+    }
+}
+
+/// CSI シーケンス（`ESC [` … 終端文字）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Csi {
+    /// プライベートマーカー（`?` `>` `<` `=`）
+    pub private: Option<u8>,
+    /// 数値パラメーター
+    pub params: Params,
+    /// 中間文字
+    pub intermediates: Intermediates,
+    /// 終端文字（0x40〜0x7E）
+    pub final_byte: u8,
+}
+
+/// パーサーが取り出した端末への指示
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    /// 表示する文字
+    Print(char),
+    /// C0 / C1 制御文字
+    Execute(char),
+    /// ESC シーケンス（`ESC` 中間文字 終端文字）
+    Esc {
+        /// 中間文字
+        intermediates: Intermediates,
+        /// 終端文字（0x30〜0x7E）
+        final_byte: u8,
+    },
+    /// CSI シーケンス
+    Csi(Csi),
+    /// OSC 文字列（`ESC ]` … BEL / ST）。[`OSC_STRING_MAX_BYTES`] を超える分は切り捨てる
+    Osc(Vec<u8>),
+}
+
+/// パーサーの状態（DEC 互換パーサーの状態図に対応）
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Ground,
+    Escape,
+    EscapeIntermediate,
+    CsiEntry,
+    CsiParam,
+    CsiIntermediate,
+    CsiIgnore,
+    OscString,
+    /// DCS / SOS / PM / APC の文字列（内容は使わずに読み捨てる）
+    IgnoredString,
+}
+
+/// UTF-8 のデコード途中の状態
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Utf8Decoder {
+    /// デコード中の符号位置
+    code_point: u32,
+    /// あと何バイトの継続バイトが必要か
+    remaining: u8,
+    /// この長さのシーケンスで表せる最小の符号位置（冗長な表現の検出用）
+    min: u32,
+}
+
+impl Utf8Decoder {
+    /// 1 バイトを読み、確定した文字ごとに `emit` を呼ぶ
+    ///
+    /// 1 バイトで確定する文字は最大 2 つ（不正なシーケンスの U+FFFD と、新しく始まった文字）。
+    fn decode(&mut self, byte: u8, mut emit: impl FnMut(char)) {
+        if self.remaining > 0 {
+            if byte & 0xC0 == 0x80 {
+                self.code_point = (self.code_point << 6) | u32::from(byte & 0x3F);
+                self.remaining -= 1;
+                if self.remaining == 0 {
+                    let valid = self.code_point >= self.min;
+                    emit(
+                        char::from_u32(self.code_point)
+                            .filter(|_| valid)
+                            .unwrap_or(char::REPLACEMENT_CHARACTER),
+                    );
+                }
+                return;
+            }
+            // 継続バイトが足りない: それまでの分を U+FFFD にして、このバイトを読み直す
+            self.remaining = 0;
+            emit(char::REPLACEMENT_CHARACTER);
+        }
+
+        match byte {
+            0x00..=0x7F => emit(char::from(byte)),
+            0xC2..=0xDF => self.start(u32::from(byte & 0x1F), 1, 0x80),
+            0xE0..=0xEF => self.start(u32::from(byte & 0x0F), 2, 0x800),
+            0xF0..=0xF4 => self.start(u32::from(byte & 0x07), 3, 0x10000),
+            _ => emit(char::REPLACEMENT_CHARACTER),
+        }
+    }
+
+    fn start(&mut self, bits: u32, remaining: u8, min: u32) {
+        self.code_point = bits;
+        self.remaining = remaining;
+        self.min = min;
+    }
+}
+
+/// エスケープシーケンスのパーサー
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Parser {
+    state: State,
+    utf8: Utf8Decoder,
+    private: Option<u8>,
+    params: Params,
+    intermediates: Intermediates,
+    osc: Vec<u8>,
+}
+
+impl Default for Parser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Parser {
+    /// 初期状態のパーサーを生成する
+    pub fn new() -> Self {
+        Parser {
+            state: State::Ground,
+            utf8: Utf8Decoder::default(),
+            private: None,
+            params: Params::default(),
+            intermediates: Intermediates::default(),
+            osc: Vec::new(),
+        }
+    }
+
+    /// バイト列を読み、確定した指示ごとに `handler` を呼ぶ
+    ///
+    /// シーケンスの途中で終わったバイト列は、次の呼び出しで続きから読む。
+    pub fn feed(&mut self, bytes: &[u8], mut handler: impl FnMut(Action)) {
+        for &byte in bytes {
+            let mut utf8 = self.utf8;
+            utf8.decode(byte, |ch| self.advance(ch, &mut handler));
+            self.utf8 = utf8;
+        }
+    }
+
+    /// 1 文字を読む
+    fn advance(&mut self, ch: char, handler: &mut impl FnMut(Action)) {
+        let code = u32::from(ch);
+
+        // どの状態からでも遷移する文字
+        match code {
+            0x18 | 0x1A => {
+                self.state = State::Ground;
+                handler(Action::Execute(ch));
+                return;
+            }
+            0x1B => {
+                self.end_string(handler);
+                self.clear();
+                self.state = State::Escape;
+                return;
+            }
+            0x90 | 0x98 | 0x9E | 0x9F => {
+                self.end_string(handler);
+                self.state = State::IgnoredString;
+                return;
+            }
+            0x9B => {
+                self.end_string(handler);
+                self.clear();
+                self.state = State::CsiEntry;
+                return;
+            }
+            0x9C => {
+                self.end_string(handler);
+                self.state = State::Ground;
+                return;
+            }
+            0x9D => {
+                self.end_string(handler);
+                self.osc.clear();
+                self.state = State::OscString;
+                return;
+            }
+            0x80..=0x9F => {
+                self.end_string(handler);
+                self.state = State::Ground;
+                handler(Action::Execute(ch));
+                return;
+            }
+            _ => {}
+        }
+
+        // C0 制御文字はシーケンスの途中でも実行する（文字列の中では BEL だけが意味を持つ）
+        if code < 0x20 || code == 0x7F {
+            match self.state {
+                State::OscString => {
+                    if code == 0x07 {
+                        self.end_string(handler);
+                        self.state = State::Ground;
+                    }
+                }
+                State::IgnoredString => {}
+                _ if code == 0x7F => {}
+                _ => handler(Action::Execute(ch)),
+            }
+            return;
+        }
+
+        match self.state {
+            State::Ground => handler(Action::Print(ch)),
+            State::Escape => self.escape(ch, handler),
+            State::EscapeIntermediate => {
+                if (0x20..=0x2F).contains(&code) {
+                    self.intermediate(code as u8);
+                } else {
+                    self.esc_dispatch(ch, handler);
+                }
+            }
+            State::CsiEntry | State::CsiParam => self.csi_param(ch, handler),
+            State::CsiIntermediate => match code {
+                0x20..=0x2F => self.intermediate(code as u8),
+                0x40..=0x7E => self.csi_dispatch(code as u8, handler),
+                _ => self.state = State::CsiIgnore,
+            },
+            State::CsiIgnore => {
+                if (0x40..=0x7E).contains(&code) {
+                    self.state = State::Ground;
+                }
+            }
+            State::OscString => {
+                if self.osc.len() + ch.len_utf8() <= OSC_STRING_MAX_BYTES {
+                    let mut buf = [0u8; 4];
+                    self.osc
+                        .extend_from_slice(ch.encode_utf8(&mut buf).as_bytes());
+                }
+            }
+            State::IgnoredString => {}
+        }
+    }
+
+    /// `ESC` の次の文字
+    fn escape(&mut self, ch: char, handler: &mut impl FnMut(Action)) {
+        match ch {
+            '[' => self.state = State::CsiEntry,
+            ']' => {
+                self.osc.clear();
+                self.state = State::OscString;
+            }
+            'P' | 'X' | '^' | '_' => self.state = State::IgnoredString,
+            '\x20'..='\x2F' => self.intermediate(ch as u8),
+            _ => self.esc_dispatch(ch, handler),
+        }
+    }
+
+    /// CSI のパラメーター部分
+    fn csi_param(&mut self, ch: char, handler: &mut impl FnMut(Action)) {
+        match ch {
+            '0'..='9' => {
+                self.params.push_digit(ch as u8 - b'0');
+                self.state = State::CsiParam;
+            }
+            ';' | ':' => {
+                self.params.next(ch == ':');
+                self.state = State::CsiParam;
+            }
+            '<'..='?' if self.state == State::CsiEntry => {
+                self.private = Some(ch as u8);
+                self.state = State::CsiParam;
+            }
+            '<'..='?' => self.state = State::CsiIgnore,
+            '\x20'..='\x2F' => self.intermediate(ch as u8),
+            '\x40'..='\x7E' => self.csi_dispatch(ch as u8, handler),
+            _ => self.state = State::CsiIgnore,
+        }
+    }
+
+    /// 中間文字を足す（ESC・CSI 共通）
+    fn intermediate(&mut self, byte: u8) {
+        let in_csi = matches!(
+            self.state,
+            State::CsiEntry | State::CsiParam | State::CsiIntermediate
+        );
+        if !self.intermediates.push(byte) {
+            self.state = if in_csi {
+                State::CsiIgnore
+            } else {
+                State::Ground
+            };
+            return;
+        }
+        self.state = if in_csi {
+            State::CsiIntermediate
+        } else {
+            State::EscapeIntermediate
+        };
+    }
+
+    fn esc_dispatch(&mut self, ch: char, handler: &mut impl FnMut(Action)) {
+        self.state = State::Ground;
+        if ('\x30'..='\x7E').contains(&ch) {
+            handler(Action::Esc {
+                intermediates: self.intermediates,
+                final_byte: ch as u8,
+            });
+        }
+    }
+
+    fn csi_dispatch(&mut self, final_byte: u8, handler: &mut impl FnMut(Action)) {
+        self.state = State::Ground;
+        handler(Action::Csi(Csi {
+            private: self.private,
+            params: self.params,
+            intermediates: self.intermediates,
+            final_byte,
+        }));
+    }
+
+    /// OSC 文字列の途中なら確定させて渡す
+    fn end_string(&mut self, handler: &mut impl FnMut(Action)) {
+        if self.state == State::OscString {
+            handler(Action::Osc(core::mem::take(&mut self.osc)));
+        }
+    }
+
+    /// シーケンスの途中の状態を捨てる
+    fn clear(&mut self) {
+        self.private = None;
+        self.params = Params::default();
+        self.intermediates = Intermediates::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    fn parse(bytes: &[u8]) -> Vec<Action> {
+        let mut actions = Vec::new();
+        Parser::new().feed(bytes, |action| actions.push(action));
+        actions
+    }
+
+    fn csi(bytes: &[u8]) -> Csi {
+        match parse(bytes).as_slice() {
+            [Action::Csi(csi)] => *csi,
+            other => panic!("CSI ではない: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_utf8_and_invalid_bytes() {
+        assert_eq!(
+            parse("aあ🎉".as_bytes()),
+            vec![Action::Print('a'), Action::Print('あ'), Action::Print('🎉')]
+        );
+        // 途切れたシーケンス・冗長な表現・8 ビットの生バイトは U+FFFD
+        assert_eq!(
+            parse(&[0xE3, 0x81, b'x', 0xC0, 0x80, 0x9B]),
+            vec![
+                Action::Print('\u{FFFD}'),
+                Action::Print('x'),
+                Action::Print('\u{FFFD}'),
+                Action::Print('\u{FFFD}'),
+                Action::Print('\u{FFFD}'),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_csi_params() {
+        let seq = csi(b"\x1b[1;;38:2::255:0:0m");
+        assert_eq!(seq.final_byte, b'm');
+        assert_eq!(
+            seq.params.iter().collect::<Vec<_>>(),
+            [1, 0, 38, 2, 0, 255, 0, 0]
+        );
+        assert!(!seq.params.is_subparam(2));
+        assert!(seq.params.is_subparam(3));
+        assert_eq!(seq.params.get_or(1, 1), 1);
+
+        let seq = csi(b"\x1b[?1049h");
+        assert_eq!((seq.private, seq.params.get(0)), (Some(b'?'), 1049));
+
+        let seq = csi(b"\x1b[99999999A");
+        assert_eq!(seq.params.get(0), u16::MAX);
+
+        // C1 の CSI（UTF-8 の U+009B）
+        assert_eq!(csi("\u{9b}2J".as_bytes()).final_byte, b'J');
+    }
+
+    #[test]
+    fn test_control_inside_sequence_executes() {
+        assert_eq!(
+            parse(b"\x1b[1\r;2H"),
+            vec![Action::Execute('\r'), Action::Csi(csi(b"\x1b[1;2H"))]
+        );
+    }
+
+    #[test]
+    fn test_osc_terminators() {
+        assert_eq!(
+            parse(b"\x1b]0;title\x07"),
+            vec![Action::Osc(b"0;title".to_vec())]
+        );
+        assert_eq!(
+            parse(b"\x1b]2;t\x1b\\x"),
+            vec![
+                Action::Osc(b"2;t".to_vec()),
+                Action::Esc {
+                    intermediates: Intermediates::default(),
+                    final_byte: b'\\',
+                },
+                Action::Print('x'),
+            ]
+        );
+        let long = [b'a'; OSC_STRING_MAX_BYTES * 2];
+        let mut input = b"\x1b]0;".to_vec();
+        input.extend_from_slice(&long);
+        input.push(0x07);
+        match parse(&input).as_slice() {
+            [Action::Osc(osc)] => assert_eq!(osc.len(), OSC_STRING_MAX_BYTES),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_dcs_ignored_and_split_feed() {
+        assert_eq!(
+            parse(b"\x1bPq#0;2;0;0;0\x1b\\z").last(),
+            Some(&Action::Print('z'))
+        );
+
+        // シーケンスが複数回の feed にまたがっても同じ結果になる
+        let mut parser = Parser::new();
+        let mut actions = Vec::new();
+        for chunk in [&b"\x1b["[..], b"3", b"1m\xE3\x81", b"\x82"] {
+            parser.feed(chunk, |action| actions.push(action));
+        }
+        assert_eq!(
+            actions,
+            vec![Action::Csi(csi(b"\x1b[31m")), Action::Print('あ')]
+        );
+    }
+}
@@ -0,0 +1,97 @@
+//! Instruction の圧縮レイヤー
+//!
+//! mosh C++ 実装はシリアライズ済みの `TransportBuffers.Instruction` を zlib で圧縮してから
+//! Fragment に分割する（受信側は再組み立て後に展開する）。
+//!
+//! ```text
+//! 送信: Instruction::encode_to_bytes → Compression::compress   → Fragmenter
+//! 受信: FragmentAssembly             → Compression::decompress → Instruction::decode_from_bytes
+//! ```
+//!
+//! 無改造の mosh-server と通信するには [`Compression::Zlib`] が必要。
+//! 自前のピア同士では [`Compression::Raw`]（無圧縮）も使える。両端で揃えること。
+
+use alloc::vec::Vec;
+
+use miniz_oxide::deflate::compress_to_vec_zlib;
+use miniz_oxide::inflate::decompress_to_vec_zlib_with_limit;
+
+use crate::error::TransportError;
+
+/// zlib の圧縮レベル（mosh C++ 実装の Z_DEFAULT_COMPRESSION 相当）
+const ZLIB_LEVEL: u8 = 6;
+
+/// 展開後の Instruction の最大サイズ（バイト）
+/// 不正な圧縮データ（zip bomb）でメモリを使い切らないための上限
+pub const MAX_DECOMPRESSED_SIZE: usize = 4 * 1024 * 1024;
+
+/// Instruction バイト列の圧縮方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Compression {
+    /// zlib 圧縮（mosh C++ 実装と互換）
+    #[default]
+    Zlib,
+    /// 無圧縮（自前のピア同士でのみ使用可）
+    Raw,
+}
+
+impl Compression {
+    /// 送信前に Instruction バイト列を圧縮する
+    pub fn compress(&self, instruction_bytes: &[u8]) -> Vec<u8> {
+        match self {
+            Compression::Zlib => compress_to_vec_zlib(instruction_bytes, ZLIB_LEVEL),
+            Compression::Raw => instruction_bytes.to_vec(),
+        }
+    }
+
+    /// 再組み立て済みのバイト列を展開する
+    ///
+    /// # エラー
+    /// - `TransportError::DecompressionFailed`: zlib データとして不正、
+    ///   または展開後のサイズが [`MAX_DECOMPRESSED_SIZE`] を超える
+    pub fn decompress(&self, bytes: &[u8]) -> Result<Vec<u8>, TransportError> {
+        match self {
+            Compression::Zlib => decompress_to_vec_zlib_with_limit(bytes, MAX_DECOMPRESSED_SIZE)
+                .map_err(|_| TransportError::DecompressionFailed),
+            Compression::Raw => Ok(bytes.to_vec()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    #[test]
+    fn test_zlib_round_trip() {
+        let data: Vec<u8> = b"hello mosh ".iter().copied().cycle().take(1000).collect();
+        let compressed = Compression::Zlib.compress(&data);
+        assert!(compressed.len() < data.len());
+        // zlib ヘッダー（CMF = 0x78）
+        assert_eq!(compressed[0], 0x78);
+        assert_eq!(Compression::Zlib.decompress(&compressed).unwrap(), data);
+    }
+
+    #[test]
+    fn test_raw_passthrough() {
+        let data = b"raw bytes".to_vec();
+        assert_eq!(Compression::Raw.compress(&data), data);
+        assert_eq!(Compression::Raw.decompress(&data).unwrap(), data);
+    }
+
+    #[test]
+    fn test_invalid_zlib_rejected() {
+        let result = Compression::Zlib.decompress(&[0x01, 0x02, 0x03]);
+        assert_eq!(result, Err(TransportError::DecompressionFailed));
+    }
+
+    #[test]
+    fn test_oversized_output_rejected() {
+        let bomb = Compression::Zlib.compress(&vec![0u8; MAX_DECOMPRESSED_SIZE + 1]);
+        assert_eq!(
+            Compression::Zlib.decompress(&bomb),
+            Err(TransportError::DecompressionFailed)
+        );
+    }
+}
@@ -9,6 +9,14 @@ pub enum TransportError {
     InvalidFragmentFormat,
     /// 再組み立てエラー
     AssemblyError,
+    /// Instruction の展開に失敗（zlib データ不正・展開後サイズ超過）
+    DecompressionFailed,
+    /// fragment_num が 1 Instruction あたりの最大 Fragment 数を超える
+    TooManyFragments,
+    /// 再組み立て後の Instruction が最大サイズを超える
+    InstructionTooLarge,
+    /// 再組み立て中の合計バッファ量が上限を超える
+    BufferLimitExceeded,
 }
 
 impl core::fmt::Display for TransportError {
@@ -17,6 +25,10 @@ impl core::fmt::Display for TransportError {
             TransportError::TooShort => write!(f, "Packet or fragment too short"),
             TransportError::InvalidFragmentFormat => write!(f, "Invalid fragment format"),
             TransportError::AssemblyError => write!(f, "Fragment reassembly error"),
+            TransportError::DecompressionFailed => write!(f, "Instruction decompression failed"),
+            TransportError::TooManyFragments => write!(f, "Too many fragments in one instruction"),
+            TransportError::InstructionTooLarge => write!(f, "Reassembled instruction too large"),
+            TransportError::BufferLimitExceeded => write!(f, "Fragment reassembly buffer limit exceeded"),
         }
     }
 }
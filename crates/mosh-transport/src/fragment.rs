@@ -9,10 +9,14 @@
 //! [payload: variable]
 //! ```
 
-use alloc::collections::BTreeMap;
+use alloc::collections::{BTreeMap, VecDeque};
 use alloc::vec::Vec;
 
 use crate::error::TransportError;
+use crate::{
+    ASSEMBLY_MAX_FRAGMENTS, ASSEMBLY_MAX_INSTRUCTION_SIZE, ASSEMBLY_MAX_SLOTS, ASSEMBLY_MAX_TOTAL_BYTES,
+    ASSEMBLY_TIMEOUT_MS,
+};
 
 /// mosh Fragment（ネットワーク上の最小送受信単位）
 ///
@@ -87,12 +91,17 @@ impl Fragment {
 ///
 /// MTU を超える Instruction を複数の Fragment に分割する。
 /// mosh のデフォルト MTU は 500 バイト（モバイル向け保守的設定）。
+///
+/// mosh C++ 実装の Fragmenter と同じく、直前と同一内容の Instruction（再送）には
+/// 同じ instruction_id を使う。受信側は別々の送信で届いた Fragment を組み合わせて完成できる。
 pub struct Fragmenter {
     /// 次に使う instruction_id
     next_instruction_id: u64,
     /// アプリケーション MTU（Fragment ペイロードの最大バイト数）
     /// = ネットワーク MTU - 暗号オーバーヘッド(24) - Fragment ヘッダー(10)
     app_payload_mtu: usize,
+    /// 直前に分割した Instruction（instruction_id, バイト列）
+    last_instruction: Option<(u64, Vec<u8>)>,
 }
 
 impl Fragmenter {
@@ -106,6 +115,7 @@ impl Fragmenter {
         Fragmenter {
             next_instruction_id: 1, // 1 始まり（0 は未初期化扱い）
             app_payload_mtu: app_mtu,
+            last_instruction: None,
         }
     }
 
@@ -114,11 +124,20 @@ impl Fragmenter {
     /// # 引数
     /// - `instruction_bytes`: Instruction の Protocol Buffer エンコード済みバイト列
     ///
+    /// 直前と同一内容の Instruction には直前と同じ instruction_id を割り当てる。
+    ///
     /// # 戻り値
     /// Fragment のベクタ。1 つに収まる場合でも常に Vec で返す。
     pub fn make_fragments(&mut self, instruction_bytes: &[u8]) -> Vec<Fragment> {
-        let id = self.next_instruction_id;
-        self.next_instruction_id = self.next_instruction_id.wrapping_add(1);
+        let id = match &self.last_instruction {
+            Some((id, last)) if last.as_slice() == instruction_bytes => *id,
+            _ => {
+                let id = self.next_instruction_id;
+                self.next_instruction_id = self.next_instruction_id.wrapping_add(1);
+                self.last_instruction = Some((id, instruction_bytes.to_vec()));
+                id
+            }
+        };
 
         if instruction_bytes.is_empty() {
             // 空 Instruction → Fragment 1 つ（ハートビート用）
@@ -151,96 +170,273 @@ impl Fragmenter {
     }
 }
 
+/// [`FragmentAssembly::add_fragment`] の結果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Reassembly {
+    /// すべての Fragment が揃い、再組み立てした Instruction バイト列
+    Complete(Vec<u8>),
+    /// まだ Fragment が足りない
+    Incomplete,
+    /// 組み立て終えた Instruction の Fragment（再送の重複）
+    ///
+    /// 相手が ACK を受け取れずに再送している合図なので、受信側は ACK を送り直す。
+    Duplicate,
+}
+
+impl Reassembly {
+    /// 再組み立てした Instruction バイト列（揃っていなければ `None`）
+    pub fn into_complete(self) -> Option<Vec<u8>> {
+        match self {
+            Reassembly::Complete(bytes) => Some(bytes),
+            Reassembly::Incomplete | Reassembly::Duplicate => None,
+        }
+    }
+}
+
+/// 組み立て中の Instruction（1 つの instruction_id 分）
+#[derive(Debug)]
+struct PartialInstruction {
+    /// 受信済み Fragment のペイロード（fragment_num → payload）
+    arrived: BTreeMap<u16, Vec<u8>>,
+    /// 最後の Fragment（is_final=true）の fragment_num
+    final_fragment_num: Option<u16>,
+    /// 最後に Fragment を受信した時刻（ミリ秒）。LRU 破棄とタイムアウトに使う
+    last_update_ms: u64,
+    /// 受信済みペイロードの合計バイト数
+    bytes: usize,
+}
+
+impl PartialInstruction {
+    /// すべての Fragment が揃っていれば Instruction バイト列を返す
+    fn try_assemble(&self) -> Option<Vec<u8>> {
+        let final_num = self.final_fragment_num?;
+
+        // fragment_num が 0..=final_num のすべてが揃っているか
+        if self.arrived.len() != final_num as usize + 1
+            || self.arrived.keys().any(|&num| num > final_num)
+        {
+            return None;
+        }
+
+        // 揃ったので順番に結合する（BTreeMap なので fragment_num 順）
+        Some(self.arrived.values().flatten().copied().collect())
+    }
+}
+
 /// Fragment を受け取り、Instruction に再組み立てするクラス
 ///
 /// 複数の Fragment を順番通りではなく受け取り、すべて揃った時点で
 /// 元の Instruction バイト列を返す。
 ///
-/// mosh では、より新しい instruction_id の Fragment が来たら
-/// 古い instruction_id の Fragment は破棄する。
+/// 再送と新しいデータが混在して届いても取りこぼさないよう、複数の Instruction を
+/// instruction_id ごとのスロットで並行して組み立てる。
+/// - スロット数か合計バッファ量が上限に達したら、最も長く更新のないスロットから破棄する（LRU）
+/// - 最後の受信からタイムアウト時間が経過したスロットは破棄する
+/// - 組み立て終えた instruction_id の遅れて届いた Fragment（再送の残り）にはスロットを作らない
+///
+/// バッファするメモリ量は [`AssemblyLimits`] で制限し、他のスロットを破棄しても収まらない
+/// Fragment はエラーとして拒否する。
 pub struct FragmentAssembly {
-    /// 現在組み立て中の instruction_id
-    current_id: Option<u64>,
-    /// 受信済み Fragment（fragment_num → Fragment）
-    arrived: BTreeMap<u16, Fragment>,
-    /// 最後の Fragment（is_final=true）の fragment_num
-    final_fragment_num: Option<u16>,
+    /// 組み立て中の Instruction（instruction_id → 受信状況）
+    slots: BTreeMap<u64, PartialInstruction>,
+    /// 最近組み立て終えた instruction_id（古い順、最大 `max_slots` 個）
+    completed: VecDeque<u64>,
+    /// 同時に組み立てる Instruction の最大数
+    max_slots: usize,
+    /// 組み立て中の Instruction を破棄するまでの時間（ミリ秒）
+    timeout_ms: u64,
+    /// バッファ量の上限
+    limits: AssemblyLimits,
+}
+
+/// 再組み立てでバッファするメモリ量の上限
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AssemblyLimits {
+    /// 1 つの Instruction の最大 Fragment 数（fragment_num はこれ未満でなければならない）
+    pub max_fragments: u16,
+    /// 1 つの Instruction の再組み立て後の最大バイト数
+    pub max_instruction_size: usize,
+    /// 組み立て中の全 Instruction の合計バッファバイト数の上限
+    pub max_total_bytes: usize,
+}
+
+impl Default for AssemblyLimits {
+    fn default() -> Self {
+        AssemblyLimits {
+            max_fragments: ASSEMBLY_MAX_FRAGMENTS,
+            max_instruction_size: ASSEMBLY_MAX_INSTRUCTION_SIZE,
+            max_total_bytes: ASSEMBLY_MAX_TOTAL_BYTES,
+        }
+    }
 }
 
 impl FragmentAssembly {
     /// 新しい FragmentAssembly を生成する
     pub fn new() -> Self {
         FragmentAssembly {
-            current_id: None,
-            arrived: BTreeMap::new(),
-            final_fragment_num: None,
+            slots: BTreeMap::new(),
+            completed: VecDeque::new(),
+            max_slots: ASSEMBLY_MAX_SLOTS,
+            timeout_ms: ASSEMBLY_TIMEOUT_MS,
+            limits: AssemblyLimits::default(),
         }
     }
 
+    /// バッファ量の上限を変更する（ビルダー）
+    pub fn with_limits(mut self, limits: AssemblyLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// 同時に組み立てる Instruction の最大数を変更する（ビルダー、最小 1）
+    pub fn with_max_slots(mut self, max_slots: usize) -> Self {
+        self.max_slots = max_slots.max(1);
+        self
+    }
+
+    /// 組み立て中の Instruction のタイムアウトを変更する（ビルダー）
+    pub fn with_timeout(mut self, timeout_ms: u64) -> Self {
+        self.timeout_ms = timeout_ms;
+        self
+    }
+
     /// Fragment を追加する
     ///
+    /// # 引数
+    /// - `frag`: 受信した Fragment
+    /// - `now_ms`: 受信時刻（タイムアウト判定用）
+    ///
     /// # 戻り値
-    /// - `Some(Vec<u8>)`: すべての Fragment が揃い、再組み立てした Instruction バイト列
-    /// - `None`: まだ Fragment が足りない
-    pub fn add_fragment(&mut self, frag: Fragment) -> Option<Vec<u8>> {
-        // 新しい instruction_id が来たら古いものを破棄
-        self.reset_if_new_id(frag.instruction_id);
-
-        if self.current_id.is_none() {
-            self.current_id = Some(frag.instruction_id);
+    /// - `Ok(Reassembly::Complete(bytes))`: すべての Fragment が揃い、再組み立てした Instruction バイト列
+    /// - `Ok(Reassembly::Incomplete)`: まだ Fragment が足りない
+    /// - `Ok(Reassembly::Duplicate)`: 組み立て終えた Instruction の Fragment（スロットは作らない）
+    ///
+    /// 1 つで完結する Fragment は組み立て終えた instruction_id でもそのまま `Complete` で返す
+    /// （重複した Instruction は SSP が ACK を送り直す合図に使う）。
+    ///
+    /// # エラー
+    /// 上限を超える Fragment は破棄してエラーを返す。
+    /// - `TransportError::TooManyFragments`: fragment_num が `max_fragments` 以上
+    /// - `TransportError::InstructionTooLarge`: Instruction が `max_instruction_size` を超える
+    ///   （その Instruction の組み立て中の Fragment もすべて破棄する）
+    /// - `TransportError::BufferLimitExceeded`: 他のスロットをすべて破棄しても
+    ///   合計バッファ量が `max_total_bytes` を超える
+    pub fn add_fragment(&mut self, frag: Fragment, now_ms: u64) -> Result<Reassembly, TransportError> {
+        self.expire_stale(now_ms);
+
+        if frag.fragment_num >= self.limits.max_fragments {
+            return Err(TransportError::TooManyFragments);
         }
 
-        if frag.is_final {
-            self.final_fragment_num = Some(frag.fragment_num);
+        let id = frag.instruction_id;
+        if frag.fragment_num == 0 && frag.is_final {
+            // 1 つで完結する Instruction はスロットを使わない
+            self.slots.remove(&id);
+            if frag.payload.len() > self.limits.max_instruction_size {
+                return Err(TransportError::InstructionTooLarge);
+            }
+            self.mark_completed(id);
+            return Ok(Reassembly::Complete(frag.payload));
+        }
+        if self.completed.contains(&id) {
+            return Ok(Reassembly::Duplicate);
         }
 
-        let frag_num = frag.fragment_num;
-        self.arrived.insert(frag_num, frag);
+        let replaced = self
+            .slots
+            .get(&id)
+            .and_then(|slot| slot.arrived.get(&frag.fragment_num))
+            .map_or(0, |payload| payload.len());
+        let slot_bytes = self.slots.get(&id).map_or(0, |slot| slot.bytes) - replaced + frag.payload.len();
+
+        if slot_bytes > self.limits.max_instruction_size {
+            self.slots.remove(&id);
+            return Err(TransportError::InstructionTooLarge);
+        }
+        while self.buffered_bytes() - replaced + frag.payload.len() > self.limits.max_total_bytes {
+            if !self.evict_least_recent(Some(id)) {
+                return Err(TransportError::BufferLimitExceeded);
+            }
+        }
+
+        if !self.slots.contains_key(&id) && self.slots.len() >= self.max_slots {
+            self.evict_least_recent(None);
+        }
+
+        let slot = self.slots.entry(id).or_insert_with(|| PartialInstruction {
+            arrived: BTreeMap::new(),
+            final_fragment_num: None,
+            last_update_ms: now_ms,
+            bytes: 0,
+        });
+
+        if frag.is_final {
+            slot.final_fragment_num = Some(frag.fragment_num);
+        }
+        slot.last_update_ms = now_ms;
+        slot.bytes = slot_bytes;
+        slot.arrived.insert(frag.fragment_num, frag.payload);
 
         // すべての Fragment が揃ったか確認
-        self.try_assemble()
+        let Some(assembled) = slot.try_assemble() else {
+            return Ok(Reassembly::Incomplete);
+        };
+        self.slots.remove(&id);
+        self.mark_completed(id);
+        Ok(Reassembly::Complete(assembled))
     }
 
-    /// 新しい instruction_id が来たら古い状態をリセットする
+    /// タイムアウトした組み立て中の Instruction を破棄する
     ///
-    /// # 戻り値
-    /// - `true`: リセットが行われた（新しい ID だった）
-    /// - `false`: 同じ ID（リセットなし）
-    pub fn reset_if_new_id(&mut self, id: u64) -> bool {
-        match self.current_id {
-            Some(current) if current == id => false,
-            _ => {
-                self.arrived.clear();
-                self.final_fragment_num = None;
-                self.current_id = Some(id);
-                true
-            }
-        }
+    /// `add_fragment` からも呼ばれるが、受信が途絶えた間のメモリを解放するために
+    /// ホストのタイマーから呼んでもよい。
+    pub fn expire_stale(&mut self, now_ms: u64) {
+        let timeout_ms = self.timeout_ms;
+        self.slots
+            .retain(|_, slot| now_ms.saturating_sub(slot.last_update_ms) < timeout_ms);
     }
 
-    /// すべての Fragment が揃っていれば Instruction バイト列を返す
-    fn try_assemble(&self) -> Option<Vec<u8>> {
-        let final_num = self.final_fragment_num?;
+    /// 組み立て中の Instruction 数
+    pub fn pending_count(&self) -> usize {
+        self.slots.len()
+    }
 
-        // fragment_num が 0..=final_num のすべてが揃っているか
-        for num in 0..=final_num {
-            if !self.arrived.contains_key(&num) {
-                return None;
-            }
-        }
+    /// 組み立て中の全 Instruction の合計バッファバイト数
+    pub fn buffered_bytes(&self) -> usize {
+        self.slots.values().map(|slot| slot.bytes).sum()
+    }
 
-        // 揃ったので順番に結合する
-        let mut assembled = Vec::new();
-        for num in 0..=final_num {
-            assembled.extend_from_slice(&self.arrived[&num].payload);
-        }
+    /// 指定した instruction_id を組み立て中か
+    pub fn is_pending(&self, id: u64) -> bool {
+        self.slots.contains_key(&id)
+    }
 
-        Some(assembled)
+    /// 最も長く更新のないスロット（`keep` を除く）を破棄する。破棄できるスロットがなければ false
+    fn evict_least_recent(&mut self, keep: Option<u64>) -> bool {
+        let oldest = self
+            .slots
+            .iter()
+            .filter(|(&id, _)| Some(id) != keep)
+            .min_by_key(|(&id, slot)| (slot.last_update_ms, id))
+            .map(|(&id, _)| id);
+        match oldest {
+            Some(id) => {
+                self.slots.remove(&id);
+                true
+            }
+            None => false,
+        }
     }
 
-    /// 現在組み立て中の instruction_id を返す
-    pub fn current_id(&self) -> Option<u64> {
-        self.current_id
+    /// 組み立て終えた instruction_id を記録する
+    fn mark_completed(&mut self, id: u64) {
+        if self.completed.contains(&id) {
+            return;
+        }
+        if self.completed.len() >= self.max_slots {
+            self.completed.pop_front();
+        }
+        self.completed.push_back(id);
     }
 }
 
@@ -299,6 +495,36 @@ mod tests {
         assert_eq!(frags[2].fragment_num, 2);
     }
 
+    #[test]
+    fn test_fragmenter_reuses_id_for_identical_instruction() {
+        let mut fragmenter = Fragmenter::new(10);
+        let data = alloc::vec![7u8; 25];
+
+        let first = fragmenter.make_fragments(&data);
+        let retransmit = fragmenter.make_fragments(&data);
+        assert_eq!(first[0].instruction_id, retransmit[0].instruction_id);
+
+        // 内容が変われば新しい ID
+        let other = fragmenter.make_fragments(&[1, 2, 3]);
+        assert_ne!(other[0].instruction_id, first[0].instruction_id);
+        assert_eq!(fragmenter.current_id(), other[0].instruction_id + 1);
+    }
+
+    #[test]
+    fn test_fragments_from_different_transmissions_combine() {
+        let mut fragmenter = Fragmenter::new(10);
+        let data: Vec<u8> = (0u8..25).collect();
+
+        // 1 回目は Fragment 0 だけ、再送では Fragment 1, 2 だけが届く
+        let first = fragmenter.make_fragments(&data);
+        let retransmit = fragmenter.make_fragments(&data);
+
+        let mut assembly = FragmentAssembly::new();
+        assert_eq!(assembly.add_fragment(first[0].clone(), 0).unwrap(), Reassembly::Incomplete);
+        assert_eq!(assembly.add_fragment(retransmit[1].clone(), 1000).unwrap(), Reassembly::Incomplete);
+        assert_eq!(assembly.add_fragment(retransmit[2].clone(), 1000).unwrap(), Reassembly::Complete(data));
+    }
+
     #[test]
     fn test_assembly_single_fragment() {
         let mut assembly = FragmentAssembly::new();
@@ -311,8 +537,8 @@ mod tests {
             payload: payload.clone(),
         };
 
-        let result = assembly.add_fragment(frag);
-        assert_eq!(result, Some(payload));
+        let result = assembly.add_fragment(frag, 0).unwrap();
+        assert_eq!(result, Reassembly::Complete(payload));
     }
 
     #[test]
@@ -333,34 +559,59 @@ mod tests {
         };
 
         // 順不同で追加
-        assert_eq!(assembly.add_fragment(frag1), None);
-        let result = assembly.add_fragment(frag0);
-        assert_eq!(result, Some(alloc::vec![1, 2, 3, 4, 5, 6]));
+        assert_eq!(assembly.add_fragment(frag1, 0).unwrap(), Reassembly::Incomplete);
+        let result = assembly.add_fragment(frag0, 0).unwrap();
+        assert_eq!(result, Reassembly::Complete(alloc::vec![1, 2, 3, 4, 5, 6]));
+    }
+
+    fn frag(id: u64, num: u16, is_final: bool, payload: &[u8]) -> Fragment {
+        Fragment {
+            instruction_id: id,
+            fragment_num: num,
+            is_final,
+            payload: payload.to_vec(),
+        }
     }
 
     #[test]
-    fn test_assembly_new_id_resets() {
+    fn test_assembly_interleaved_instructions() {
         let mut assembly = FragmentAssembly::new();
 
-        // 古い ID の Fragment を追加
-        let old_frag = Fragment {
-            instruction_id: 1,
-            fragment_num: 0,
-            is_final: false,
-            payload: alloc::vec![1, 2, 3],
-        };
-        assembly.add_fragment(old_frag);
+        // 2 つの Instruction の Fragment が交互に届いても両方完成する
+        assert_eq!(assembly.add_fragment(frag(1, 0, false, &[1, 2, 3]), 0).unwrap(), Reassembly::Incomplete);
+        assert_eq!(assembly.add_fragment(frag(2, 0, false, &[9, 8]), 0).unwrap(), Reassembly::Incomplete);
+        assert_eq!(assembly.pending_count(), 2);
 
-        // 新しい ID の Fragment が来たら古いのはリセットされ、
-        // is_final の Fragment のみなので即座に完成する
-        let new_frag = Fragment {
-            instruction_id: 2,
-            fragment_num: 0,
-            is_final: true,
-            payload: alloc::vec![9, 8, 7],
-        };
-        let result = assembly.add_fragment(new_frag);
-        assert_eq!(result, Some(alloc::vec![9, 8, 7]));
+        assert_eq!(assembly.add_fragment(frag(2, 1, true, &[7]), 0).unwrap(), Reassembly::Complete(alloc::vec![9, 8, 7]));
+        assert_eq!(assembly.add_fragment(frag(1, 1, true, &[4]), 0).unwrap(), Reassembly::Complete(alloc::vec![1, 2, 3, 4]));
+        assert_eq!(assembly.pending_count(), 0);
+    }
+
+    #[test]
+    fn test_assembly_evicts_least_recent_slot() {
+        let mut assembly = FragmentAssembly::new().with_max_slots(2);
+
+        assembly.add_fragment(frag(1, 0, false, &[1]), 0).unwrap();
+        assembly.add_fragment(frag(2, 0, false, &[2]), 10).unwrap();
+        assembly.add_fragment(frag(1, 1, false, &[1]), 20).unwrap(); // #1 を更新
+        assembly.add_fragment(frag(3, 0, false, &[3]), 30).unwrap(); // #2 が最も古い
+
+        assert!(assembly.is_pending(1));
+        assert!(!assembly.is_pending(2));
+        assert!(assembly.is_pending(3));
+    }
+
+    #[test]
+    fn test_assembly_stale_partial_expires() {
+        let mut assembly = FragmentAssembly::new().with_timeout(1000);
+
+        assembly.add_fragment(frag(1, 0, false, &[1]), 0).unwrap();
+        assembly.expire_stale(999);
+        assert!(assembly.is_pending(1));
+
+        // タイムアウト後の残りの Fragment では完成しない
+        assert_eq!(assembly.add_fragment(frag(1, 1, true, &[2]), 1000).unwrap(), Reassembly::Incomplete);
+        assert_eq!(assembly.pending_count(), 1);
     }
 
     #[test]
@@ -372,12 +623,12 @@ mod tests {
         assert!(frags.len() > 1);
 
         let mut assembly = FragmentAssembly::new();
-        let mut result = None;
+        let mut result = Reassembly::Incomplete;
         for frag in frags {
-            result = assembly.add_fragment(frag);
+            result = assembly.add_fragment(frag, 0).unwrap();
         }
 
-        assert_eq!(result.unwrap(), original);
+        assert_eq!(result, Reassembly::Complete(original));
     }
 
     #[test]
@@ -405,4 +656,96 @@ mod tests {
         assert_eq!(frag_word2 >> 15, 0); // MSB が 0
         assert_eq!(frag_word2 & 0x7FFF, 3); // fragment_num = 3
     }
+
+    fn limited(max_fragments: u16, max_instruction_size: usize, max_total_bytes: usize) -> FragmentAssembly {
+        FragmentAssembly::new().with_limits(AssemblyLimits {
+            max_fragments,
+            max_instruction_size,
+            max_total_bytes,
+        })
+    }
+
+    #[test]
+    fn test_assembly_rejects_fragment_num_over_limit() {
+        let mut assembly = limited(4, 1024, 1024);
+        assert_eq!(
+            assembly.add_fragment(frag(1, 4, false, &[0]), 0),
+            Err(TransportError::TooManyFragments)
+        );
+        assert_eq!(assembly.pending_count(), 0);
+    }
+
+    #[test]
+    fn test_assembly_rejects_oversized_instruction() {
+        let mut assembly = limited(16, 8, 1024);
+        assembly.add_fragment(frag(1, 0, false, &[0; 5]), 0).unwrap();
+        assert_eq!(
+            assembly.add_fragment(frag(1, 1, false, &[0; 5]), 0),
+            Err(TransportError::InstructionTooLarge)
+        );
+        // 完成し得ない Instruction は破棄される
+        assert!(!assembly.is_pending(1));
+        assert_eq!(assembly.buffered_bytes(), 0);
+    }
+
+    #[test]
+    fn test_assembly_evicts_to_fit_total_budget() {
+        let mut assembly = limited(16, 16, 10);
+        assembly.add_fragment(frag(1, 0, false, &[0; 4]), 0).unwrap();
+        assembly.add_fragment(frag(2, 0, false, &[0; 4]), 10).unwrap();
+        assembly.add_fragment(frag(1, 1, false, &[0; 1]), 20).unwrap(); // #1 を更新
+
+        // 合計が上限を超えるので、最も古い #2 を破棄して受け入れる
+        assert_eq!(assembly.add_fragment(frag(3, 0, false, &[0; 4]), 30), Ok(Reassembly::Incomplete));
+        assert!(assembly.is_pending(1));
+        assert!(!assembly.is_pending(2));
+        assert!(assembly.is_pending(3));
+        assert_eq!(assembly.buffered_bytes(), 9);
+    }
+
+    #[test]
+    fn test_assembly_rejects_over_total_budget() {
+        let mut assembly = limited(16, 16, 10);
+        assembly.add_fragment(frag(1, 0, false, &[0; 6]), 0).unwrap();
+        assembly.add_fragment(frag(2, 0, false, &[0; 6]), 0).unwrap();
+        assert!(!assembly.is_pending(1));
+
+        // 他のスロットをすべて破棄しても収まらない
+        assert_eq!(
+            assembly.add_fragment(frag(2, 1, false, &[0; 6]), 0),
+            Err(TransportError::BufferLimitExceeded)
+        );
+        assert_eq!(assembly.buffered_bytes(), 6);
+
+        // 同じ Fragment の再受信は二重に数えない
+        assert_eq!(assembly.add_fragment(frag(2, 0, false, &[0; 6]), 0), Ok(Reassembly::Incomplete));
+        assert_eq!(assembly.buffered_bytes(), 6);
+    }
+
+    #[test]
+    fn test_assembly_reports_late_fragments_of_completed_instruction() {
+        let mut fragmenter = Fragmenter::new(10);
+        let data: Vec<u8> = (0u8..25).collect();
+        let first = fragmenter.make_fragments(&data);
+        let retransmit = fragmenter.make_fragments(&data);
+
+        let mut assembly = FragmentAssembly::new();
+        let mut result = Reassembly::Incomplete;
+        for frag in first {
+            result = assembly.add_fragment(frag, 0).unwrap();
+        }
+        assert_eq!(result, Reassembly::Complete(data));
+
+        // 再送が遅れて届いたら重複と報告し、スロットは作らない
+        assert_eq!(assembly.add_fragment(retransmit[1].clone(), 10).unwrap(), Reassembly::Duplicate);
+        assert_eq!(assembly.add_fragment(retransmit[0].clone(), 20).unwrap(), Reassembly::Duplicate);
+        assert_eq!(assembly.pending_count(), 0);
+        assert_eq!(assembly.buffered_bytes(), 0);
+
+        // 1 つで完結する重複はそのまま返す
+        let ack = fragmenter.make_fragments(&[]);
+        assert_eq!(assembly.add_fragment(ack[0].clone(), 30).unwrap(), Reassembly::Complete(Vec::new()));
+        assert_eq!(assembly.add_fragment(ack[0].clone(), 40).unwrap(), Reassembly::Complete(Vec::new()));
+        assert_eq!(assembly.pending_count(), 0);
+    }
 }
@@ -14,6 +14,16 @@
 //!   bit 0..14    = fragment_num（0 始まり）
 //! ```
 //!
+//! ## 再組み立て
+//!
+//! [`FragmentAssembly`] は instruction_id ごとのスロットで複数の Instruction を並行して組み立てる。
+//! スロット数の上限（LRU で破棄）とタイムアウトで、揃わない Fragment を溜め込まない。
+//! バッファ量は [`AssemblyLimits`] で制限し、超過は `TransportError` として返す。
+//!
+//! ## 圧縮
+//!
+//! Fragment に分割するのは zlib 圧縮済みの Instruction バイト列（[`Compression`]）。
+//!
 //! ## UDP ペイロードの全体構造
 //!
 //! ```text
@@ -24,12 +34,31 @@
 #![no_std]
 extern crate alloc;
 
+pub mod compress;
 pub mod error;
 pub mod fragment;
 pub mod packet;
 pub mod timestamp;
 
+pub use compress::Compression;
 pub use error::TransportError;
-pub use fragment::{Fragment, FragmentAssembly, Fragmenter};
+pub use fragment::{AssemblyLimits, Fragment, FragmentAssembly, Fragmenter, Reassembly};
 pub use packet::UdpPacket;
 pub use timestamp::Timestamp16;
+
+/// 同時に組み立てる Instruction の最大数（デフォルト値）
+pub const ASSEMBLY_MAX_SLOTS: usize = 16;
+
+/// 組み立て中の Instruction を破棄するまでの時間（ミリ秒、デフォルト値）
+/// 最後の Fragment 受信からこの時間内に揃わなければ、相手の再送に任せる
+pub const ASSEMBLY_TIMEOUT_MS: u64 = 10_000;
+
+/// 1 つの Instruction の最大 Fragment 数（デフォルト値）
+pub const ASSEMBLY_MAX_FRAGMENTS: u16 = 8192;
+
+/// 1 つの Instruction の再組み立て後の最大バイト数（デフォルト値）
+pub const ASSEMBLY_MAX_INSTRUCTION_SIZE: usize = 4 * 1024 * 1024;
+
+/// 再組み立て中の全 Instruction の合計バッファバイト数の上限（デフォルト値）
+/// VS Code の Extension Host 内で動くため、相手の不正な送信でメモリを使い切らないようにする
+pub const ASSEMBLY_MAX_TOTAL_BYTES: usize = 8 * 1024 * 1024;
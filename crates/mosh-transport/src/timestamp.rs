@@ -2,6 +2,9 @@
 //!
 //! mosh はミリ秒単位の時刻の下位 16 ビットをタイムスタンプとして使用する。
 //! RTT 計算のために送信側のタイムスタンプを受信側がエコーバックする。
+//!
+//! `0xFFFF` は「タイムスタンプなし」を表す [`Timestamp16::INIT`] として予約されており、
+//! 時刻から生成する値がこれと衝突する場合は 0 に丸める（mosh C++ 実装と同じ）。
 
 /// mosh パケットのタイムスタンプ（16ビット、ミリ秒の下位16ビット）
 ///
@@ -16,7 +19,14 @@ impl Timestamp16 {
     /// # 引数
     /// - `now_ms`: 現在時刻（ミリ秒、WASM 環境では JS の Date.now() から注入）
     pub fn now_from_ms(now_ms: u64) -> Self {
-        Timestamp16((now_ms & 0xFFFF) as u16)
+        Self::skip_init((now_ms & 0xFFFF) as u16)
+    }
+
+    /// 経過時間を加算する（mod 2^16、INIT とは衝突しない）
+    ///
+    /// エコーバック時の保持時間補正に使う。
+    pub fn add_ms(self, elapsed_ms: u64) -> Self {
+        Self::skip_init(self.0.wrapping_add((elapsed_ms & 0xFFFF) as u16))
     }
 
     /// 2 つのタイムスタンプの差を計算する（newer - older）
@@ -39,6 +49,15 @@ impl Timestamp16 {
     pub fn is_initialized(&self) -> bool {
         self.0 != u16::MAX
     }
+
+    /// INIT と衝突する値を 0 に丸める
+    fn skip_init(raw: u16) -> Self {
+        if raw == u16::MAX {
+            Timestamp16(0)
+        } else {
+            Timestamp16(raw)
+        }
+    }
 }
 
 impl From<u16> for Timestamp16 {
@@ -88,4 +107,11 @@ mod tests {
         assert!(Timestamp16(0).is_initialized());
         assert!(Timestamp16(100).is_initialized());
     }
+
+    #[test]
+    fn test_timestamp_never_collides_with_init() {
+        assert_eq!(Timestamp16::now_from_ms(65535), Timestamp16(0));
+        assert_eq!(Timestamp16(65530).add_ms(5), Timestamp16(0));
+        assert_eq!(Timestamp16(65530).add_ms(10), Timestamp16(4));
+    }
 }
@@ -22,6 +22,12 @@
  * AES-128-OCB3 暗号化 + SSP プロトコル + Fragment 管理を統合した
  * wasm-bindgen エクスポートクラス。
  *
+ * ## 時刻
+ *
+ * 時刻を取るメソッドの `now_ms` は省略でき、省略時は単調な `performance.now()` を使う。
+ * `Date.now()` などを渡すこともできるが、時刻合わせで巻き戻った分は吸収され、
+ * 内部の時刻は常に前に進む（逆行の回数は `getStats()` の `clock_backward_jumps`）。
+ *
  * ## 使用例
  *
  * ```typescript
@@ -35,7 +41,7 @@
  *
  * // UDP 受信処理
  * socket.on('message', (msg: Buffer) => {
- *     const data = client.recvUdpPacket(new Uint8Array(msg.buffer), Date.now());
+ *     const data = client.recvUdpPacket(new Uint8Array(msg.buffer));
  *     if (data.length > 0) {
  *         onDataReceived(data); // VS Code RPC に渡す
  *     }
@@ -43,7 +49,7 @@
  *
  * // 定期タイマー（ハートビート・再送）
  * setInterval(() => {
- *     const packets = client.tick(Date.now());
+ *     const packets = client.tick();
  *     for (const pkt of packets) {
  *         socket.send(Buffer.from(pkt));
  *     }
@@ -51,7 +57,7 @@
  *
  * // VS Code からのデータ送信
  * function sendToMosh(data: Uint8Array) {
- *     const packets = client.sendData(data, Date.now());
+ *     const packets = client.sendData(data);
  *     for (const pkt of packets) {
  *         socket.send(Buffer.from(pkt));
  *     }
@@ -62,16 +68,18 @@ export class MoshClient {
     /**
      * mosh クライアントを初期化する
      *
-     * @param key_base64 - mosh-server が出力した Base64 鍵（22文字）
+     * @param key_base64 - mosh-server が出力した Base64 鍵（22文字）。URL-safe・パディング付きの表記も可
      *   例: `"4NeCCgvZFe2RnPgrcU1PQw"`
      *   SSH 接続後に `mosh-server new` の標準出力 `"MOSH CONNECT <PORT> <KEY>"` から取得
      * @param mtu - UDP の実効 MTU（バイト）。省略時は 500（モバイル向け推奨値）。
      *   - 有線 LAN や高品質な接続: 1400 を推奨
      *   - モバイル・混雑した Wi-Fi: 500（デフォルト）を推奨
+     * @param compress - Instruction を zlib 圧縮するか。省略時は `true`（無改造の mosh-server と互換）。
+     *   `false`（無圧縮）は相手も mosh-wasm の場合のみ使える。
      *
      * @throws {Error} - Base64 鍵のデコード失敗または鍵長不正
      */
-    constructor(key_base64: string, mtu?: number);
+    constructor(key_base64: string, mtu?: number, compress?: boolean);
 
     /**
      * 受信した UDP ペイロード（生バイト）を処理する
@@ -79,7 +87,7 @@ export class MoshClient {
      * 処理フロー:
      * 1. AES-128-OCB3 復号
      * 2. Fragment ヘッダーを解析
-     * 3. Fragment が揃ったら Instruction に再組み立て
+     * 3. Fragment が揃ったら再組み立てし、展開して Instruction をデコード
      * 4. SSP プロトコル処理（ACK、状態更新）
      * 5. ペイロードをバッファに積む
      *
@@ -87,10 +95,10 @@ export class MoshClient {
      *   ```typescript
      *   socket.on('message', (msg: Buffer) => {
      *       const bytes = new Uint8Array(msg.buffer, msg.byteOffset, msg.byteLength);
-     *       const data = client.recvUdpPacket(bytes, Date.now());
+     *       const data = client.recvUdpPacket(bytes);
      *   });
      *   ```
-     * @param now_ms - 現在時刻（`Date.now()` の値）
+     * @param now_ms - 現在時刻（ミリ秒）。省略時は `performance.now()`（推奨）
      *
      * @returns 上位レイヤー（VS Code RPC）に渡すバイト列。
      *   データがない場合は長さ 0 の Uint8Array。
@@ -100,7 +108,7 @@ export class MoshClient {
      *   注: mosh ではパケットロスが起こりうるため、エラーは catch して警告ログに留める。
      *   接続が切れたわけではない。
      */
-    recvUdpPacket(udp_bytes: Uint8Array, now_ms: number): Uint8Array;
+    recvUdpPacket(udp_bytes: Uint8Array, now_ms?: number): Uint8Array;
 
     /**
      * 上位レイヤー（VS Code RPC）からのデータを mosh で送信する
@@ -112,20 +120,73 @@ export class MoshClient {
      * 4. UDP ペイロードのリストを返す
      *
      * @param data - `ManagedMessagePassing.send()` で来た Uint8Array
-     * @param now_ms - 現在時刻（`Date.now()`）
+     * @param now_ms - 現在時刻（ミリ秒）。省略時は `performance.now()`（推奨）
      *
      * @returns 送信すべき UDP ペイロードの配列。
      *   各要素を `socket.send()` で送信する。
+     *   細かい書き込みをまとめるため、データは次の送信枠まで保留されることがある
+     *   （その場合は空の配列）。保留分は `nextTimeoutMs()` の後の `tick()` で送られる。
      *   ```typescript
-     *   const packets = client.sendData(data, Date.now());
+     *   const packets = client.sendData(data);
      *   for (const pkt of packets) {
      *       socket.send(Buffer.from(pkt));
      *   }
      *   ```
      *
+     * @throws {Error} - 送信バッファの空き（`writableBytes()`）が足りない。
+     *   何も積まないので、`onWritable` のコールバックを待ってから送り直す。
      * @throws {Error} - 暗号化失敗（通常は起こらない）
      */
-    sendData(data: Uint8Array, now_ms: number): Uint8Array[];
+    sendData(data: Uint8Array, now_ms?: number): Uint8Array[];
+
+    /**
+     * 上位レイヤーからのデータを送信バッファに積む（送信は次の `tick()` で行う）
+     *
+     * 送信バッファの空きを超える分は積まない。大きなデータを流すときに使い、
+     * 戻り値が `data.length` より小さければ生産側を止めて `onWritable` を待つ。
+     *
+     * @param data - 送信するバイト列
+     *
+     * @returns 積んだバイト数（先頭から）。バッファが満杯なら 0。
+     *
+     * @example
+     * ```typescript
+     * const n = client.write(chunk);
+     * if (n < chunk.length) {
+     *     pending = chunk.subarray(n);
+     *     producer.pause();
+     * }
+     * schedule(); // nextTimeoutMs() で tick を予約
+     * ```
+     */
+    write(data: Uint8Array): number;
+
+    /**
+     * 送信バッファにあと何バイト積めるか
+     */
+    writableBytes(): number;
+
+    /**
+     * 送信バッファが空いたときに呼ばれるコールバックを登録する
+     *
+     * `write` / `sendData` がデータを受け付けなかった後、`tick` 等で送信バッファが
+     * 空になったら 1 度だけ呼ばれる（Node.js の `'drain'` に相当）。
+     * `undefined` を渡すと登録を解除する。
+     *
+     * @example
+     * ```typescript
+     * client.onWritable(() => producer.resume());
+     * ```
+     */
+    onWritable(callback?: () => void): void;
+
+    /**
+     * 送信ウィンドウ（フロー制御）を設定する
+     *
+     * @param max_in_flight_bytes - 送信済み・未 ACK のバイト数の上限（デフォルト 262144）
+     * @param max_queued_bytes - 送信待ちのバイト数の上限（デフォルト 1048576）
+     */
+    setSendWindow(max_in_flight_bytes: number, max_queued_bytes: number): void;
 
     /**
      * 定期タイマー tick（ハートビート・再送管理）
@@ -133,9 +194,10 @@ export class MoshClient {
      * Node.js の `setInterval` から **50ms ごと** に呼び出す。
      * - 送信待ちデータがあれば送信 Instruction を生成
      * - 再送タイムアウト（RTO）を超えた未 ACK パケットを再送
+     * - データ受信から 100ms 以内に送信データがなければ ACK のみを送信（遅延 ACK）
      * - 3000ms 以上何も送っていなければハートビートを送信
      *
-     * @param now_ms - 現在時刻（`Date.now()`）
+     * @param now_ms - 現在時刻（ミリ秒）。省略時は `performance.now()`（推奨）
      *
      * @returns 送信すべき UDP ペイロードの配列（空の場合もある）
      *
@@ -144,14 +206,92 @@ export class MoshClient {
      * @example
      * ```typescript
      * setInterval(() => {
-     *     const packets = client.tick(Date.now());
+     *     const packets = client.tick();
      *     for (const pkt of packets) {
      *         socket.send(Buffer.from(pkt));
      *     }
      * }, 50);
      * ```
      */
-    tick(now_ms: number): Uint8Array[];
+    tick(now_ms?: number): Uint8Array[];
+
+    /**
+     * 次に `tick` を呼ぶべきまでの時間（ミリ秒）を返す
+     *
+     * 再送（RTO）・ハートビート・遅延 ACK・送信待ちのうち最も早い期限までの残り時間。
+     * 既に期限が来ていれば 0。固定間隔の `setInterval` の代わりに、
+     * `tick` / `sendData` / `recvUdpPacket` の後で毎回 `setTimeout` を張り直す。
+     *
+     * @param now_ms - 現在時刻（ミリ秒）。省略時は `performance.now()`（推奨）
+     *
+     * @example
+     * ```typescript
+     * let timer: NodeJS.Timeout | undefined;
+     * function schedule() {
+     *     clearTimeout(timer);
+     *     timer = setTimeout(() => {
+     *         for (const pkt of client.tick()) {
+     *             socket.send(Buffer.from(pkt));
+     *         }
+     *         schedule();
+     *     }, client.nextTimeoutMs());
+     * }
+     * ```
+     */
+    nextTimeoutMs(now_ms?: number): number;
+
+    /**
+     * 現在の接続状態を返す
+     *
+     * - `"connecting"`: まだサーバーから一度も受信していない
+     * - `"connected"`: 定期的に受信している
+     * - `"stalled"`: しばらく受信がない、または再送が続いている
+     * - `"lost"`: 長時間受信がない
+     *
+     * @param now_ms - 現在時刻（ミリ秒）。省略時は `performance.now()`（推奨）
+     */
+    connectionState(now_ms?: number): ConnectionState;
+
+    /**
+     * 最後にサーバーから受信してからの経過時間（ミリ秒）。未受信なら -1。
+     *
+     * @param now_ms - 現在時刻（ミリ秒）。省略時は `performance.now()`（推奨）
+     *
+     * @example
+     * ```typescript
+     * if (client.connectionState() === "stalled") {
+     *     const sec = Math.floor(client.lastContactMs() / 1000);
+     *     statusBar.text = `Last contact ${sec} seconds ago`;
+     * }
+     * ```
+     */
+    lastContactMs(now_ms?: number): number;
+
+    /**
+     * 接続状態が変化したときに呼ばれるコールバックを登録する
+     *
+     * `recvUdpPacket` / `sendData` / `tick` の処理後に状態が変わっていれば呼ばれる。
+     * `undefined` を渡すと登録を解除する。
+     *
+     * @example
+     * ```typescript
+     * client.onStateChange((state) => console.log(`mosh: ${state}`));
+     * ```
+     */
+    onStateChange(callback?: (state: ConnectionState) => void): void;
+
+    /**
+     * 接続状態の判定しきい値を設定する
+     *
+     * @param stalled_after_ms - 最終受信からこの時間が経過したら `"stalled"`（デフォルト 6500）
+     * @param lost_after_ms - 最終受信からこの時間が経過したら `"lost"`（デフォルト 60000）
+     * @param stalled_after_timeouts - 連続再送タイムアウトがこの回数に達したら `"stalled"`（デフォルト 3）
+     */
+    setConnectionThresholds(
+        stalled_after_ms: number,
+        lost_after_ms: number,
+        stalled_after_timeouts: number,
+    ): void;
 
     /**
      * 上位レイヤーが読み取れるデータがあるかチェック
@@ -180,8 +320,18 @@ export class MoshClient {
      *   "send_num": 42,
      *   "recv_num": 38,
      *   "pending_count": 2,
+     *   "consecutive_timeouts": 0,
+     *   "send_interval_ms": 22,
+     *   "in_flight_bytes": 4096,
+     *   "queued_bytes": 0,
+     *   "cwnd_bytes": 28000,
+     *   "ssthresh_bytes": null,
+     *   "congestion_phase": "slow_start",
      *   "total_sent_bytes": 102400,
-     *   "total_recv_bytes": 98304
+     *   "total_recv_bytes": 98304,
+     *   "replay_window": 1024,
+     *   "replay_rejected": 0,
+     *   "clock_backward_jumps": 0
      * }
      * ```
      *
@@ -211,6 +361,166 @@ export class MoshClient {
     free(): void;
 }
 
+/**
+ * 端末モードの mosh クライアントセッション
+ *
+ * 無改造の mosh-server に対話シェルとして接続する。キー入力と端末サイズの変更を送り、
+ * サーバーから届いた画面の更新を端末（xterm.js など）に流すバイト列として返す。
+ * 暗号化・Fragment・SSP の層は `MoshClient` と共通で、時刻の扱いも同じ。
+ *
+ * キー入力のエコーは予測して先に画面に重ね（遅い回線では下線付き）、サーバーの画面で確かめる。
+ * 予測の表示・取り消しも画面の更新なので、`sendKeys()` / `tick()` の後に `takeScreenUpdate()` を流す。
+ *
+ * ## 使用例
+ *
+ * ```typescript
+ * const term = new MoshTerminalClient(key, 500, xterm.cols, xterm.rows);
+ * const send = (packets: Uint8Array[]) => packets.forEach((p) => socket.send(p));
+ *
+ * xterm.onData((keys) => {
+ *     send(term.sendKeys(new TextEncoder().encode(keys)));
+ *     xterm.write(term.takeScreenUpdate());
+ * });
+ * xterm.onResize(({ cols, rows }) => send(term.resize(cols, rows)));
+ * term.onResize((cols, rows) => xterm.resize(cols, rows));
+ *
+ * socket.on('message', (msg: Buffer) => {
+ *     const screen = term.recvUdpPacket(new Uint8Array(msg.buffer, msg.byteOffset, msg.byteLength));
+ *     if (screen.length > 0) {
+ *         xterm.write(screen);
+ *     }
+ * });
+ * ```
+ */
+export class MoshTerminalClient {
+    /**
+     * 端末モードの mosh クライアントを初期化する
+     *
+     * @param key_base64 - mosh-server が出力した Base64 鍵（22文字）
+     * @param mtu - UDP の実効 MTU（バイト）。省略時は 500
+     * @param width - 端末の幅（桁数）。`height` と両方を渡すと最初の `tick()` で送る
+     * @param height - 端末の高さ（行数）。mosh-server は端末サイズを受け取るまで画面を送らない
+     *
+     * @throws {Error} - Base64 鍵のデコード失敗または鍵長不正
+     */
+    constructor(key_base64: string, mtu?: number, width?: number, height?: number);
+
+    /**
+     * キー入力を送信する
+     *
+     * @param keys - 端末に入力されたバイト列（UTF-8）
+     * @param now_ms - 現在時刻（ミリ秒）。省略時は `performance.now()`（推奨）
+     *
+     * @returns 送信すべき UDP ペイロードの配列。入力は次の送信枠まで保留されることがある
+     *   （その場合は空の配列）。保留分は `nextTimeoutMs()` の後の `tick()` で送られる。
+     */
+    sendKeys(keys: Uint8Array, now_ms?: number): Uint8Array[];
+
+    /**
+     * 端末サイズの変更を送信する
+     *
+     * @returns 送信すべき UDP ペイロードの配列
+     */
+    resize(width: number, height: number, now_ms?: number): Uint8Array[];
+
+    /**
+     * 受信した UDP ペイロード（生バイト）を処理する
+     *
+     * 新しい画面の状態が届いていれば、表示中の画面からの描き替えを返す。
+     * サーバーが端末サイズを変えていれば、先に `onResize` のコールバックが呼ばれる。
+     *
+     * @returns 端末に流すバイト列。画面の更新がなければ長さ 0 の Uint8Array。
+     *
+     * @throws {Error} - 復号失敗（パケット破損）。catch して警告ログに留める。
+     * @throws {Error} - 画面差分のデコード失敗（ACK しないのでサーバーが送り直す）
+     */
+    recvUdpPacket(udp_bytes: Uint8Array, now_ms?: number): Uint8Array;
+
+    /**
+     * 表示中の画面からの描き替えを取り出す
+     *
+     * キー入力のエコーの予測を重ねたり取り消したりするので、`sendKeys()` / `tick()` の後に呼ぶ。
+     * サーバーが端末サイズを変えていれば、先に `onResize` のコールバックが呼ばれる。
+     *
+     * @returns 端末に流すバイト列。画面の更新がなければ長さ 0 の Uint8Array。
+     */
+    takeScreenUpdate(): Uint8Array;
+
+    /**
+     * キー入力のエコーの予測の表示モードを設定する（mosh の `--predict` と同じ）
+     *
+     * @param mode - `"adaptive"`（既定: 往復時間が長いときだけ表示）/ `"always"` / `"never"`
+     *
+     * @throws {Error} - 不明なモード名
+     */
+    setPredictionMode(mode: PredictionMode): void;
+
+    /**
+     * キー入力のエコーの予測の表示モード
+     */
+    predictionMode(): PredictionMode;
+
+    /**
+     * サーバーが端末サイズを変えたときに呼ばれるコールバックを登録する
+     *
+     * `undefined` を渡すと登録を解除する。
+     */
+    onResize(callback?: (width: number, height: number) => void): void;
+
+    /**
+     * 定期タイマー tick（入力の送信・再送・ハートビート）
+     *
+     * @returns 送信すべき UDP ペイロードの配列（空の場合もある）
+     */
+    tick(now_ms?: number): Uint8Array[];
+
+    /**
+     * 次に `tick` を呼ぶべきまでの時間（ミリ秒）を返す（`MoshClient.nextTimeoutMs` と同じ）
+     */
+    nextTimeoutMs(now_ms?: number): number;
+
+    /**
+     * 現在の接続状態を返す
+     */
+    connectionState(now_ms?: number): ConnectionState;
+
+    /**
+     * 最後にサーバーから受信してからの経過時間（ミリ秒）。未受信なら -1。
+     */
+    lastContactMs(now_ms?: number): number;
+
+    /**
+     * 接続状態が変化したときに呼ばれるコールバックを登録する
+     */
+    onStateChange(callback?: (state: ConnectionState) => void): void;
+
+    /**
+     * 接続状態の判定しきい値を設定する（`MoshClient.setConnectionThresholds` と同じ）
+     */
+    setConnectionThresholds(
+        stalled_after_ms: number,
+        lost_after_ms: number,
+        stalled_after_timeouts: number,
+    ): void;
+
+    /**
+     * サーバーがキー入力のエコーを画面に反映済みのクライアント状態番号
+     */
+    echoAck(): number;
+
+    /**
+     * セッション統計を JSON 文字列で返す（形式は `MoshClient.getStats` と同じ `MoshStats`）
+     *
+     * `total_sent_bytes` / `total_recv_bytes` は送受信したメッセージ（UserMessage / HostMessage）のバイト数。
+     */
+    getStats(): string;
+
+    /**
+     * WASM メモリを解放する（接続終了時に呼ぶ）
+     */
+    free(): void;
+}
+
 /**
  * デバッグ用: コンソールにパニックスタックトレースを出力するよう設定する
  *
@@ -243,6 +553,16 @@ export function init_panic_hook(): void;
  */
 export function decodeBase64Key(key_b64: string): Uint8Array;
 
+/**
+ * 接続状態（`connectionState()` / `onStateChange` の値）
+ */
+export type ConnectionState = "connecting" | "connected" | "stalled" | "lost";
+
+/**
+ * キー入力のエコーの予測の表示モード（`setPredictionMode()` / `predictionMode()` の値）
+ */
+export type PredictionMode = "adaptive" | "always" | "never";
+
 /**
  * セッション統計の型定義
  * `JSON.parse(client.getStats())` の結果に使う
@@ -258,8 +578,28 @@ export interface MoshStats {
     recv_num: number;
     /** ACK 待ちの Instruction 数 */
     pending_count: number;
+    /** ACK が進まないまま連続で再送タイムアウトした回数（新しい ACK で 0 に戻る） */
+    consecutive_timeouts: number;
+    /** 新しい Instruction を作る間隔（ミリ秒）。SRTT / 2 を 20〜250 に制限した値。 */
+    send_interval_ms: number;
+    /** 送信済み・未 ACK のバイト数 */
+    in_flight_bytes: number;
+    /** 送信待ち（まだ送信していない）バイト数 */
+    queued_bytes: number;
+    /** 輻輳ウィンドウ（バイト）。未 ACK のデータはこれを超えない。 */
+    cwnd_bytes: number;
+    /** スロースタートのしきい値（バイト）。最初の輻輳の合図までは null。 */
+    ssthresh_bytes: number | null;
+    /** 輻輳制御のフェーズ */
+    congestion_phase: "slow_start" | "congestion_avoidance";
     /** セッション開始からの送信総バイト数 */
     total_sent_bytes: number;
     /** セッション開始からの受信総バイト数 */
     total_recv_bytes: number;
+    /** リプレイウィンドウの幅（パケット数） */
+    replay_window: number;
+    /** リプレイ（重複・古すぎる seq）として拒否したパケット数 */
+    replay_rejected: number;
+    /** 時刻の逆行（`now_ms` の巻き戻り）を検出して吸収した回数 */
+    clock_backward_jumps: number;
 }
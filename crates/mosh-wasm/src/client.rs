@@ -1,7 +1,8 @@
 //! MoshClient wasm-bindgen エクスポート
 //!
 //! VS Code Extension（Node.js）から呼び出す mosh クライアントの主エントリポイント。
-//! 暗号化・SSP プロトコル・Fragment 管理を統合する。
+//! 送受信パイプラインは `mosh-endpoint` のクライアント役割の実装を使い、
+//! ここでは JS 型（Uint8Array / Array）との変換のみを担当する。
 
 extern crate alloc;
 
@@ -11,24 +12,13 @@ use alloc::vec::Vec;
 use wasm_bindgen::prelude::*;
 use js_sys::Uint8Array;
 
-use mosh_crypto::{CryptoSession, Direction};
-use mosh_proto::Instruction;
-use mosh_ssp::SspSession;
-use mosh_stream::StreamChannel;
-use mosh_transport::{Fragment, FragmentAssembly, Fragmenter, Timestamp16};
+use mosh_crypto::MoshKey;
+use mosh_endpoint::{EndpointError, EndpointStats, MoshClientEndpoint, SendWindow};
+use mosh_stream::StreamError;
+use mosh_ssp::{Clock, ConnectionThresholds, MonotonicClock};
+use mosh_transport::Compression;
 
-/// mosh プロトコルのデフォルト MTU（バイト）
-/// モバイル環境向けの保守的な設定
-const DEFAULT_MTU: u32 = 500;
-
-/// Fragment ヘッダーのオーバーヘッド（バイト）
-/// - nonce_tail: 8
-/// - auth_tag: 16
-/// - direction_seq: 8
-/// - timestamp: 2
-/// - timestamp_reply: 2
-/// - fragment_header: 10
-const CRYPTO_OVERHEAD: usize = 46;
+use crate::clock::PerformanceClock;
 
 /// mosh クライアントセッション
 ///
@@ -39,31 +29,33 @@ const CRYPTO_OVERHEAD: usize = 46;
 ///
 /// ```text
 /// MoshClient
-///   ├── CryptoSession  (mosh-crypto) - AES-128-OCB3 暗号化/復号
-///   ├── Fragmenter     (mosh-transport) - Instruction を Fragment に分割
-///   ├── FragmentAssembly (mosh-transport) - Fragment を再組み立て
-///   ├── SspSession     (mosh-ssp) - SSP 状態機械
-///   └── StreamChannel  (mosh-stream) - バイトストリームバッファ
+///   └── MoshEndpoint<ClientRole> (mosh-endpoint)
+///         ├── CryptoSession  (mosh-crypto) - AES-128-OCB3 暗号化/復号
+///         ├── Fragmenter     (mosh-transport) - Instruction を Fragment に分割
+///         ├── FragmentAssembly (mosh-transport) - Fragment を再組み立て
+///         ├── SspSession     (mosh-ssp) - SSP 状態機械
+///         └── StreamChannel  (mosh-stream) - バイトストリームバッファ
 /// ```
 ///
+/// ## 時刻
+///
+/// 時刻を取る API の `now_ms` は省略でき、省略時は `performance.now()` を使う（推奨）。
+/// 渡した時刻も含めて逆行は吸収され、内部の時刻は常に単調に進む。
+///
 /// ## スレッド安全性
 ///
 /// WASM は シングルスレッドのため、`!Send + !Sync` を満たす。
 /// JS からは単一スレッドで呼び出される前提。
 #[wasm_bindgen]
 pub struct MoshClient {
-    /// 暗号セッション（AES-128-OCB3）
-    crypto: CryptoSession,
-    /// Fragment 分割器
-    fragmenter: Fragmenter,
-    /// Fragment 再組み立て器
-    assembly: FragmentAssembly,
-    /// SSP 状態機械
-    ssp: SspSession,
-    /// バイトストリームチャンネル
-    stream: StreamChannel,
-    /// 最後に受信したタイムスタンプ（RTT 計算用にエコーバック）
-    last_remote_timestamp: u16,
+    /// クライアント役割のエンドポイント
+    endpoint: MoshClientEndpoint,
+    /// 逆行を吸収する時刻源（`now_ms` 省略時は `performance.now()`）
+    clock: MonotonicClock<PerformanceClock>,
+    /// 接続状態の変化を通知する JS コールバック（`onStateChange` で登録）
+    state_callback: Option<js_sys::Function>,
+    /// 送信バッファが空いたことを通知する JS コールバック（`onWritable` で登録）
+    writable_callback: Option<js_sys::Function>,
 }
 
 #[wasm_bindgen]
@@ -72,8 +64,10 @@ impl MoshClient {
     ///
     /// # 引数
     /// - `key_base64`: mosh-server が出力した Base64 鍵（22文字）
-    ///   例: `"4NeCCgvZFe2RnPgrcU1PQw"`
+    ///   例: `"4NeCCgvZFe2RnPgrcU1PQw"`。URL-safe（`-` `_`）やパディング付きの表記も受け付ける。
     /// - `mtu`: UDP の実効 MTU（バイト）。省略時は 500（モバイル推奨値）。
+    /// - `compress`: Instruction を zlib 圧縮するか。省略時は `true`（mosh-server と互換）。
+    ///   `false` は相手も mosh-wasm / mosh-endpoint の場合のみ使える。
     ///
     /// # エラー
     /// - Base64 鍵のデコード失敗
@@ -85,21 +79,24 @@ impl MoshClient {
     /// const client2 = new MoshClient("4NeCCgvZFe2RnPgrcU1PQw", 1400); // 大きい MTU
     /// ```
     #[wasm_bindgen(constructor)]
-    pub fn new(key_base64: &str, mtu: Option<u32>) -> Result<MoshClient, JsError> {
-        let crypto = CryptoSession::from_base64_key(key_base64)
-            .map_err(|e| JsError::new(&format!("Invalid mosh key: {}", e)))?;
-
-        let effective_mtu = mtu.unwrap_or(DEFAULT_MTU) as usize;
-        // Fragment のペイロード MTU = UDP MTU - 暗号オーバーヘッド
-        let app_payload_mtu = effective_mtu.saturating_sub(CRYPTO_OVERHEAD).max(64);
-
+    pub fn new(
+        key_base64: &str,
+        mtu: Option<u32>,
+        compress: Option<bool>,
+    ) -> Result<MoshClient, JsError> {
+        let key = MoshKey::parse(key_base64).map_err(|e| to_js_error(EndpointError::InvalidKey(e)))?;
+        let compression = if compress.unwrap_or(true) {
+            Compression::Zlib
+        } else {
+            Compression::Raw
+        };
+        let endpoint = MoshClientEndpoint::from_mosh_key(&key, mtu.map(|m| m as usize))
+            .with_compression(compression);
         Ok(MoshClient {
-            crypto,
-            fragmenter: Fragmenter::new(app_payload_mtu),
-            assembly: FragmentAssembly::new(),
-            ssp: SspSession::new(),
-            stream: StreamChannel::new(),
-            last_remote_timestamp: Timestamp16::INIT.raw(),
+            endpoint,
+            clock: MonotonicClock::new(PerformanceClock),
+            state_callback: None,
+            writable_callback: None,
         })
     }
 
@@ -108,13 +105,13 @@ impl MoshClient {
     /// 処理フロー:
     /// 1. AES-128-OCB3 復号
     /// 2. Fragment ヘッダーを解析
-    /// 3. Fragment が揃ったら Instruction に再組み立て
+    /// 3. Fragment が揃ったら再組み立てし、展開して Instruction をデコード
     /// 4. SSP プロトコル処理（ACK、状態更新）
     /// 5. ペイロードをストリームバッファに積む
     ///
     /// # 引数
     /// - `udp_bytes`: Node.js `socket.on('message', msg)` の `msg` を Uint8Array に変換したもの
-    /// - `now_ms`: 現在時刻（`Date.now()` の値）
+    /// - `now_ms`: 現在時刻（ミリ秒）。省略時は `performance.now()`
     ///
     /// # 戻り値
     /// 上位レイヤー（VS Code RPC）に渡すバイト列。空の場合は長さ 0 の Uint8Array。
@@ -126,52 +123,15 @@ impl MoshClient {
     pub fn recv_udp_packet(
         &mut self,
         udp_bytes: &[u8],
-        now_ms: f64,
+        now_ms: Option<f64>,
     ) -> Result<Uint8Array, JsError> {
-        let now_ms = now_ms as u64;
-
-        // 復号
-        let decrypted = self
-            .crypto
-            .decrypt_packet(udp_bytes)
-            .map_err(|e| JsError::new(&format!("Decryption failed: {}", e)))?;
-
-        // タイムスタンプを記録（エコーバック用）
-        self.last_remote_timestamp = decrypted.timestamp;
-
-        // Fragment の再組み立て
-        let frag = Fragment::from_bytes(&decrypted.payload)
-            .map_err(|e| JsError::new(&format!("Fragment parse failed: {}", e)))?;
-
-        let instruction_bytes = match self.assembly.add_fragment(frag) {
-            Some(bytes) => bytes,
-            None => {
-                // まだ Fragment が揃っていない
-                return Ok(Uint8Array::new_with_length(0));
-            }
-        };
-
-        // Instruction のデコード
-        let instr = Instruction::decode_from_bytes(&instruction_bytes)
-            .map_err(|e| JsError::new(&format!("Instruction decode failed: {}", e)))?;
-
-        // SSP 処理
-        let payload = self.ssp.recv_instruction(&instr, now_ms);
-
-        // ストリームバッファに積む
-        if let Some(data) = payload {
-            self.stream.apply_diff(&data);
-        }
-
-        // 読み取り可能なデータを返す
-        if self.stream.has_pending_read() {
-            let data = self.stream.read_available();
-            let arr = Uint8Array::new_with_length(data.len() as u32);
-            arr.copy_from(&data);
-            Ok(arr)
-        } else {
-            Ok(Uint8Array::new_with_length(0))
-        }
+        let now_ms = self.now(now_ms);
+        let data = self
+            .endpoint
+            .recv_udp_packet(udp_bytes, now_ms)
+            .map_err(to_js_error)?;
+        self.notify_state_change(now_ms);
+        Ok(to_uint8_array(&data))
     }
 
     /// 上位レイヤー（VS Code RPC）からのデータを mosh で送信する
@@ -185,26 +145,76 @@ impl MoshClient {
     ///
     /// # 引数
     /// - `data`: `ManagedMessagePassing.send()` で来た Uint8Array
-    /// - `now_ms`: 現在時刻（`Date.now()`）
+    /// - `now_ms`: 現在時刻（ミリ秒）。省略時は `performance.now()`
     ///
     /// # 戻り値
     /// 送信すべき UDP ペイロードの配列。各要素を `socket.send()` で送信する。
+    /// 細かい書き込みをまとめるため、データは次の送信枠（`nextTimeoutMs`）まで
+    /// 保留されることがある（その場合は空の配列）。
     ///
     /// # エラー
+    /// - 送信バッファの空き（`writableBytes`）が足りない。何も積まないので、
+    ///   `onWritable` のコールバックを待ってから送り直す
     /// - 暗号化失敗（通常は起こらない）
     #[wasm_bindgen(js_name = "sendData")]
     pub fn send_data(
         &mut self,
         data: &[u8],
-        now_ms: f64,
+        now_ms: Option<f64>,
     ) -> Result<js_sys::Array, JsError> {
-        let now_ms = now_ms as u64;
+        let now_ms = self.now(now_ms);
+        let packets = self
+            .endpoint
+            .send_data(data, now_ms)
+            .map_err(to_js_error)?;
+        self.notify_state_change(now_ms);
+        self.notify_writable();
+        Ok(to_js_packets(packets))
+    }
 
-        // ストリームバッファにデータを積む
-        self.stream.write(data);
+    /// 上位レイヤーからのデータを送信バッファに積む（送信は次の `tick` で行う）
+    ///
+    /// 送信バッファの空きを超える分は積まない。大きなデータを流すときに使い、
+    /// 戻り値が `data.length` より小さければ生産側を止めて `onWritable` を待つ。
+    ///
+    /// # 戻り値
+    /// 積んだバイト数（先頭から）。バッファが満杯なら 0。
+    #[wasm_bindgen]
+    pub fn write(&mut self, data: &[u8]) -> Result<u32, JsError> {
+        match self.endpoint.write(data) {
+            Ok(accepted) => Ok(accepted as u32),
+            Err(EndpointError::Write(StreamError::WouldBlock)) => Ok(0),
+            Err(e) => Err(to_js_error(e)),
+        }
+    }
+
+    /// 送信バッファにあと何バイト積めるか
+    #[wasm_bindgen(js_name = "writableBytes")]
+    pub fn writable_bytes(&self) -> f64 {
+        self.endpoint.writable_bytes() as f64
+    }
+
+    /// 送信バッファが空いたときに呼ばれるコールバックを登録する
+    ///
+    /// `write` / `sendData` がデータを受け付けなかった後、`tick` 等で送信バッファが
+    /// 空になったら 1 度だけ呼ばれる（Node.js の `'drain'` に相当）。
+    /// `undefined` を渡すと登録を解除する。
+    #[wasm_bindgen(js_name = "onWritable")]
+    pub fn on_writable(&mut self, callback: Option<js_sys::Function>) {
+        self.writable_callback = callback;
+    }
 
-        // 送信 Instruction を生成して UDP ペイロードに変換
-        self.flush_to_udp(now_ms)
+    /// 送信ウィンドウ（フロー制御）を設定する
+    ///
+    /// # 引数
+    /// - `max_in_flight_bytes`: 送信済み・未 ACK のバイト数の上限（デフォルト 262144）
+    /// - `max_queued_bytes`: 送信待ちのバイト数の上限（デフォルト 1048576）
+    #[wasm_bindgen(js_name = "setSendWindow")]
+    pub fn set_send_window(&mut self, max_in_flight_bytes: f64, max_queued_bytes: f64) {
+        self.endpoint.set_send_window(SendWindow {
+            max_in_flight_bytes: max_in_flight_bytes as usize,
+            max_queued_bytes: max_queued_bytes as usize,
+        });
     }
 
     /// 定期タイマー tick（ハートビート・再送管理）
@@ -212,41 +222,89 @@ impl MoshClient {
     /// Node.js の `setInterval(50)` から定期的に呼び出す。
     /// - ペイロードがあれば送信
     /// - 再送が必要な Instruction を再送
-    /// - ハートビートが必要なら ACK のみのパケットを送信
+    /// - 遅延 ACK の期限切れ、またはハートビートが必要なら ACK のみのパケットを送信
     ///
     /// # 引数
-    /// - `now_ms`: 現在時刻（`Date.now()`）
+    /// - `now_ms`: 現在時刻（ミリ秒）。省略時は `performance.now()`
     ///
     /// # 戻り値
     /// 送信すべき UDP ペイロードの配列
     #[wasm_bindgen]
-    pub fn tick(&mut self, now_ms: f64) -> Result<js_sys::Array, JsError> {
-        let now_ms = now_ms as u64;
+    pub fn tick(&mut self, now_ms: Option<f64>) -> Result<js_sys::Array, JsError> {
+        let now_ms = self.now(now_ms);
+        let packets = self.endpoint.tick(now_ms).map_err(to_js_error)?;
+        self.notify_state_change(now_ms);
+        self.notify_writable();
+        Ok(to_js_packets(packets))
+    }
 
-        // ストリームバッファに溜まっているデータを SSP に渡す
-        let pending_diff = self.stream.take_pending_diff();
-        if !pending_diff.is_empty() {
-            self.ssp.push_payload(pending_diff);
-        }
+    /// 次に `tick` を呼ぶべきまでの時間（ミリ秒）を返す
+    ///
+    /// 再送・ハートビート・遅延 ACK・送信待ちのうち最も早い期限までの残り時間。
+    /// 既に期限が来ていれば 0。`setTimeout` を 1 回設定すれば固定間隔のポーリングは不要。
+    ///
+    /// # 引数
+    /// - `now_ms`: 現在時刻（ミリ秒）。省略時は `performance.now()`
+    #[wasm_bindgen(js_name = "nextTimeoutMs")]
+    pub fn next_timeout_ms(&self, now_ms: Option<f64>) -> f64 {
+        let now_ms = self.now(now_ms);
+        self.endpoint.next_deadline_ms(now_ms).saturating_sub(now_ms) as f64
+    }
 
-        // SSP の tick で送信すべき Instruction 列を取得
-        let instructions = self.ssp.tick(now_ms);
-        let result = js_sys::Array::new();
+    /// 現在の接続状態を返す
+    ///
+    /// # 戻り値
+    /// `"connecting"` / `"connected"` / `"stalled"` / `"lost"` のいずれか
+    #[wasm_bindgen(js_name = "connectionState")]
+    pub fn connection_state(&self, now_ms: Option<f64>) -> String {
+        self.endpoint.connection_state(self.now(now_ms)).as_str().into()
+    }
 
-        for instr_bytes in instructions {
-            let packets = self.encrypt_and_fragment(&instr_bytes, now_ms)?;
-            for pkt in packets {
-                result.push(&pkt);
-            }
+    /// 最後にサーバーから受信してからの経過時間（ミリ秒）
+    ///
+    /// 「Last contact N seconds ago」表示に使う。未受信なら -1。
+    #[wasm_bindgen(js_name = "lastContactMs")]
+    pub fn last_contact_ms(&self, now_ms: Option<f64>) -> f64 {
+        match self.endpoint.last_recv_ms() {
+            Some(last) => self.now(now_ms).saturating_sub(last) as f64,
+            None => -1.0,
         }
+    }
+
+    /// 接続状態が変化したときに呼ばれるコールバックを登録する
+    ///
+    /// `recvUdpPacket` / `sendData` / `tick` の処理後に状態が変わっていれば、
+    /// 新しい状態名（`connectionState` と同じ文字列）を引数に呼び出される。
+    /// `undefined` を渡すと登録を解除する。
+    #[wasm_bindgen(js_name = "onStateChange")]
+    pub fn on_state_change(&mut self, callback: Option<js_sys::Function>) {
+        self.state_callback = callback;
+    }
 
-        Ok(result)
+    /// 接続状態の判定しきい値を設定する
+    ///
+    /// # 引数
+    /// - `stalled_after_ms`: 最終受信からこの時間が経過したら `"stalled"`（デフォルト 6500）
+    /// - `lost_after_ms`: 最終受信からこの時間が経過したら `"lost"`（デフォルト 60000）
+    /// - `stalled_after_timeouts`: 連続再送タイムアウトがこの回数に達したら `"stalled"`（デフォルト 3）
+    #[wasm_bindgen(js_name = "setConnectionThresholds")]
+    pub fn set_connection_thresholds(
+        &mut self,
+        stalled_after_ms: f64,
+        lost_after_ms: f64,
+        stalled_after_timeouts: u32,
+    ) {
+        self.endpoint.set_connection_thresholds(ConnectionThresholds {
+            stalled_after_ms: stalled_after_ms as u64,
+            lost_after_ms: lost_after_ms as u64,
+            stalled_after_timeouts,
+        });
     }
 
     /// 上位レイヤーが読み取れるデータがあるか
     #[wasm_bindgen(js_name = "hasPendingRead")]
     pub fn has_pending_read(&self) -> bool {
-        self.stream.has_pending_read()
+        self.endpoint.has_pending_read()
     }
 
     /// バッファのデータをすべて読み出す
@@ -254,10 +312,7 @@ impl MoshClient {
     /// `recv_udp_packet` の戻り値を使わずに、後から呼び出すこともできる。
     #[wasm_bindgen(js_name = "readPending")]
     pub fn read_pending(&mut self) -> Uint8Array {
-        let data = self.stream.read_available();
-        let arr = Uint8Array::new_with_length(data.len() as u32);
-        arr.copy_from(&data);
-        arr
+        to_uint8_array(&self.endpoint.read_pending())
     }
 
     /// セッション統計を JSON 文字列で返す
@@ -271,71 +326,102 @@ impl MoshClient {
     ///   "send_num": 42,
     ///   "recv_num": 38,
     ///   "pending_count": 2,
+    ///   "consecutive_timeouts": 0,
+    ///   "send_interval_ms": 22,
+    ///   "in_flight_bytes": 4096,
+    ///   "queued_bytes": 0,
+    ///   "cwnd_bytes": 28000,
+    ///   "ssthresh_bytes": null,
+    ///   "congestion_phase": "slow_start",
     ///   "total_sent_bytes": 102400,
-    ///   "total_recv_bytes": 98304
+    ///   "total_recv_bytes": 98304,
+    ///   "replay_window": 1024,
+    ///   "replay_rejected": 0,
+    ///   "clock_backward_jumps": 0
     /// }
     /// ```
     #[wasm_bindgen(js_name = "getStats")]
     pub fn get_stats(&self) -> String {
-        let stats = self.ssp.stats();
-        format!(
-            r#"{{"srtt_ms":{:.1},"rto_ms":{},"send_num":{},"recv_num":{},"pending_count":{},"total_sent_bytes":{},"total_recv_bytes":{}}}"#,
-            stats.srtt_ms,
-            stats.rto_ms,
-            stats.send_num,
-            stats.recv_num,
-            stats.pending_count,
-            self.stream.total_sent_bytes(),
-            self.stream.total_received_bytes(),
-        )
+        stats_json(&self.endpoint.stats(), self.clock.backward_jumps())
     }
 }
 
 impl MoshClient {
-    /// Instruction バイト列を Fragment 分割 → 暗号化 → UDP ペイロード変換する
-    fn encrypt_and_fragment(
-        &mut self,
-        instruction_bytes: &[u8],
-        now_ms: u64,
-    ) -> Result<Vec<Uint8Array>, JsError> {
-        let fragments = self.fragmenter.make_fragments(instruction_bytes);
-        let timestamp = Timestamp16::now_from_ms(now_ms).raw();
-        let timestamp_reply = self.last_remote_timestamp;
-
-        let mut result = Vec::new();
-        for frag in fragments {
-            let frag_bytes = frag.to_bytes();
-            let packet = self
-                .crypto
-                .encrypt_packet(Direction::ToServer, timestamp, timestamp_reply, &frag_bytes)
-                .map_err(|e| JsError::new(&format!("Encryption failed: {}", e)))?;
-
-            let arr = Uint8Array::new_with_length(packet.len() as u32);
-            arr.copy_from(&packet);
-            result.push(arr);
+    /// 現在時刻（ミリ秒）を返す。`now_ms` が渡されればそれを、なければ `performance.now()` を使う
+    ///
+    /// どちらの場合も逆行は吸収される（2 つを混ぜても時刻は単調に進む）。
+    fn now(&self, now_ms: Option<f64>) -> u64 {
+        match now_ms {
+            Some(now_ms) => self.clock.observe(now_ms as u64),
+            None => self.clock.now_ms(),
         }
-
-        Ok(result)
     }
 
-    /// ストリームバッファのデータを SSP → Fragment → 暗号化 → UDP ペイロードに変換する
-    fn flush_to_udp(&mut self, now_ms: u64) -> Result<js_sys::Array, JsError> {
-        // ストリームバッファから送信待ちデータを取得
-        let pending = self.stream.take_pending_diff();
-        if !pending.is_empty() {
-            self.ssp.push_payload(pending);
+    /// 接続状態が変わっていれば登録済みのコールバックを呼び出す
+    fn notify_state_change(&mut self, now_ms: u64) {
+        if let Some(state) = self.endpoint.poll_state_change(now_ms) {
+            if let Some(callback) = &self.state_callback {
+                // コールバック内の例外は送受信処理に影響させない
+                let _ = callback.call1(&JsValue::NULL, &JsValue::from_str(state.as_str()));
+            }
         }
+    }
 
-        let instructions = self.ssp.tick(now_ms);
-        let result = js_sys::Array::new();
-
-        for instr_bytes in instructions {
-            let packets = self.encrypt_and_fragment(&instr_bytes, now_ms)?;
-            for pkt in packets {
-                result.push(&pkt);
+    /// 書き込みを断った後に送信バッファが空いていれば登録済みのコールバックを呼び出す
+    fn notify_writable(&mut self) {
+        if self.endpoint.poll_writable() {
+            if let Some(callback) = &self.writable_callback {
+                let _ = callback.call0(&JsValue::NULL);
             }
         }
+    }
+}
+
+/// エンドポイントの統計を `getStats` の JSON 文字列にする
+pub(crate) fn stats_json(stats: &EndpointStats, backward_jumps: u32) -> String {
+    format!(
+        r#"{{"srtt_ms":{:.1},"rto_ms":{},"send_num":{},"recv_num":{},"pending_count":{},"consecutive_timeouts":{},"send_interval_ms":{},"in_flight_bytes":{},"queued_bytes":{},"cwnd_bytes":{},"ssthresh_bytes":{},"congestion_phase":"{}","total_sent_bytes":{},"total_recv_bytes":{},"replay_window":{},"replay_rejected":{},"clock_backward_jumps":{}}}"#,
+        stats.ssp.srtt_ms,
+        stats.ssp.rto_ms,
+        stats.ssp.send_num,
+        stats.ssp.recv_num,
+        stats.ssp.pending_count,
+        stats.ssp.consecutive_timeouts,
+        stats.ssp.send_interval_ms,
+        stats.ssp.in_flight_bytes,
+        stats.queued_bytes,
+        stats.ssp.congestion.window_bytes,
+        stats
+            .ssp
+            .congestion
+            .ssthresh_bytes
+            .map_or_else(|| "null".into(), |b| format!("{}", b)),
+        stats.ssp.congestion.phase.as_str(),
+        stats.total_sent_bytes,
+        stats.total_recv_bytes,
+        stats.replay_window,
+        stats.replay.rejected(),
+        backward_jumps,
+    )
+}
+
+/// エンドポイントのエラーを JS 例外に変換する
+pub(crate) fn to_js_error(e: EndpointError) -> JsError {
+    JsError::new(&format!("{}", e))
+}
+
+/// バイト列を Uint8Array にコピーする
+pub(crate) fn to_uint8_array(data: &[u8]) -> Uint8Array {
+    let arr = Uint8Array::new_with_length(data.len() as u32);
+    arr.copy_from(data);
+    arr
+}
 
-        Ok(result)
+/// UDP ペイロードのリストを JS の Array<Uint8Array> に変換する
+pub(crate) fn to_js_packets(packets: Vec<Vec<u8>>) -> js_sys::Array {
+    let result = js_sys::Array::new();
+    for pkt in packets {
+        result.push(&to_uint8_array(&pkt));
     }
+    result
 }
@@ -0,0 +1,24 @@
+//! WASM 用の時刻源
+//!
+//! `performance.now()` は壁時計の変更に影響されない単調な時刻を返す。
+//! `MoshClient` は `now_ms` 引数が省略されたときにこの時刻源を使う。
+
+use wasm_bindgen::prelude::*;
+
+use mosh_ssp::Clock;
+
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(js_namespace = performance, js_name = now)]
+    fn performance_now() -> f64;
+}
+
+/// `performance.now()` による時刻源（ページ・プロセスの起動時刻を 0 とする）
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PerformanceClock;
+
+impl Clock for PerformanceClock {
+    fn now_ms(&self) -> u64 {
+        performance_now() as u64
+    }
+}
@@ -13,27 +13,39 @@
 //! // クライアント初期化
 //! const client = new MoshClient("4NeCCgvZFe2RnPgrcU1PQw", 500);
 //!
-//! // 受信 UDP パケットを処理
-//! const data = client.recvUdpPacket(udpBuffer, Date.now());
+//! // 受信 UDP パケットを処理（時刻は省略すると performance.now() を使う）
+//! const data = client.recvUdpPacket(udpBuffer);
 //! if (data.length > 0) {
 //!     managedMessagePassing.emit(data);
 //! }
 //!
 //! // VS Code からのデータを送信
-//! const packets = client.sendData(rpcData, Date.now());
+//! const packets = client.sendData(rpcData);
 //! for (const pkt of packets) {
 //!     socket.send(Buffer.from(pkt));
 //! }
 //!
-//! // 定期タイマー（50ms ごと）
-//! const packets = client.tick(Date.now());
+//! // 定期タイマー（nextTimeoutMs() の後）
+//! const packets = client.tick();
+//!
+//! // 無改造の mosh-server に対話シェルとして接続する（端末モード）
+//! const term = new MoshTerminalClient("4NeCCgvZFe2RnPgrcU1PQw", 500, 80, 24);
+//! xterm.onData((keys) => send(term.sendKeys(encoder.encode(keys))));
+//! const screen = term.recvUdpPacket(udpBuffer);
+//! if (screen.length > 0) {
+//!     xterm.write(screen);
+//! }
 //! ```
 
 use wasm_bindgen::prelude::*;
 
 pub mod client;
+pub mod clock;
+pub mod terminal;
 
 pub use client::MoshClient;
+pub use clock::PerformanceClock;
+pub use terminal::MoshTerminalClient;
 
 /// パニック時にブラウザコンソールにスタックトレースを出力する
 ///
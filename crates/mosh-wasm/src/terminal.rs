@@ -0,0 +1,276 @@
+//! MoshTerminalClient wasm-bindgen エクスポート
+//!
+//! 無改造の mosh-server に対話シェルとして接続する端末モードのクライアント。
+//! 送受信パイプラインは `mosh-endpoint` の [`MoshTerminalEndpoint`] を使い、
+//! ここでは JS 型（Uint8Array / Array）との変換とコールバックのみを担当する。
+
+extern crate alloc;
+
+use wasm_bindgen::prelude::*;
+use js_sys::Uint8Array;
+
+use mosh_crypto::MoshKey;
+use mosh_endpoint::{EndpointError, MoshTerminalEndpoint};
+use mosh_prediction::PredictionMode;
+use mosh_ssp::{Clock, ConnectionThresholds, MonotonicClock};
+
+use crate::client::{stats_json, to_js_error, to_js_packets, to_uint8_array};
+use crate::clock::PerformanceClock;
+
+/// 端末モードの mosh クライアントセッション
+///
+/// キー入力と端末サイズの変更を UserMessage として送り、mosh-server から届いた
+/// HostMessage を画面の状態に適用して、端末（xterm.js など）に流すバイト列を返す。
+///
+/// ## 内部アーキテクチャ
+///
+/// ```text
+/// MoshTerminalClient
+///   └── MoshTerminalEndpoint (mosh-endpoint)
+///         ├── PacketCodec<ClientRole> - 暗号化・圧縮・Fragment 分割/再組み立て
+///         ├── SspSession     (mosh-ssp) - SSP 状態機械
+///         ├── ReceivedStates (mosh-ssp) - 受信した画面の状態
+///         │     └── TerminalScreen       - 端末エミュレーター (mosh-terminal) で描いた画面
+///         └── PredictionEngine (mosh-prediction) - キー入力のエコーの予測
+/// ```
+///
+/// 画面の更新は、表示中の画面との差分を最小限のエスケープシーケンスにしたもの。
+/// キー入力のエコーの予測も画面の更新として返すので、`sendKeys` / `tick` の後は
+/// `takeScreenUpdate` を呼ぶ。
+///
+/// 時刻の扱いは [`MoshClient`](crate::MoshClient) と同じ（`now_ms` は省略可能）。
+#[wasm_bindgen]
+pub struct MoshTerminalClient {
+    /// 端末モードのエンドポイント
+    endpoint: MoshTerminalEndpoint,
+    /// 逆行を吸収する時刻源（`now_ms` 省略時は `performance.now()`）
+    clock: MonotonicClock<PerformanceClock>,
+    /// 接続状態の変化を通知する JS コールバック（`onStateChange` で登録）
+    state_callback: Option<js_sys::Function>,
+    /// サーバーが端末サイズを変えたことを通知する JS コールバック（`onResize` で登録）
+    resize_callback: Option<js_sys::Function>,
+}
+
+#[wasm_bindgen]
+impl MoshTerminalClient {
+    /// 端末モードの mosh クライアントを初期化する
+    ///
+    /// # 引数
+    /// - `key_base64`: mosh-server が出力した Base64 鍵（22文字）
+    /// - `mtu`: UDP の実効 MTU（バイト）。省略時は 500（モバイル推奨値）。
+    /// - `width` / `height`: 端末サイズ（桁数・行数）。両方を渡すと最初の `tick` で送る。
+    ///   mosh-server は端末サイズを受け取るまで画面を送らないので、通常は渡す。
+    ///
+    /// # エラー
+    /// - Base64 鍵のデコード失敗
+    /// - 鍵長が不正
+    #[wasm_bindgen(constructor)]
+    pub fn new(
+        key_base64: &str,
+        mtu: Option<u32>,
+        width: Option<u16>,
+        height: Option<u16>,
+    ) -> Result<MoshTerminalClient, JsError> {
+        let key = MoshKey::parse(key_base64).map_err(|e| to_js_error(EndpointError::InvalidKey(e)))?;
+        let mut endpoint = MoshTerminalEndpoint::from_mosh_key(&key, mtu.map(|m| m as usize));
+        if let (Some(width), Some(height)) = (width, height) {
+            endpoint.resize(width, height);
+        }
+        Ok(MoshTerminalClient {
+            endpoint,
+            clock: MonotonicClock::new(PerformanceClock),
+            state_callback: None,
+            resize_callback: None,
+        })
+    }
+
+    /// キー入力を送信する
+    ///
+    /// # 引数
+    /// - `keys`: 端末に入力されたバイト列（xterm.js の `onData` を UTF-8 にしたもの）
+    /// - `now_ms`: 現在時刻（ミリ秒）。省略時は `performance.now()`
+    ///
+    /// # 戻り値
+    /// 送信すべき UDP ペイロードの配列。入力は次の送信枠（`nextTimeoutMs`）まで
+    /// 保留されることがある（その場合は空の配列）。
+    #[wasm_bindgen(js_name = "sendKeys")]
+    pub fn send_keys(&mut self, keys: &[u8], now_ms: Option<f64>) -> Result<js_sys::Array, JsError> {
+        self.endpoint.keystroke(keys);
+        self.tick(now_ms)
+    }
+
+    /// 端末サイズの変更を送信する
+    ///
+    /// # 戻り値
+    /// 送信すべき UDP ペイロードの配列
+    #[wasm_bindgen]
+    pub fn resize(
+        &mut self,
+        width: u16,
+        height: u16,
+        now_ms: Option<f64>,
+    ) -> Result<js_sys::Array, JsError> {
+        self.endpoint.resize(width, height);
+        self.tick(now_ms)
+    }
+
+    /// 受信した UDP ペイロード（生バイト）を処理する
+    ///
+    /// 新しい画面の状態が届いていれば、表示中の画面からの描き替えを返す。
+    /// サーバーが端末サイズを変えていれば、先に `onResize` のコールバックを呼ぶ。
+    ///
+    /// # 戻り値
+    /// 端末に流すバイト列。画面の更新がなければ長さ 0 の Uint8Array。
+    ///
+    /// # エラー
+    /// - 復号失敗（タグ不一致、パケット破損）。パケットロスと同様に軽微として扱う
+    /// - 画面差分（HostMessage）のデコード失敗
+    #[wasm_bindgen(js_name = "recvUdpPacket")]
+    pub fn recv_udp_packet(
+        &mut self,
+        udp_bytes: &[u8],
+        now_ms: Option<f64>,
+    ) -> Result<Uint8Array, JsError> {
+        let now_ms = self.now(now_ms);
+        self.endpoint
+            .recv_udp_packet(udp_bytes, now_ms)
+            .map_err(to_js_error)?;
+        self.notify_state_change(now_ms);
+        Ok(self.take_screen_update())
+    }
+
+    /// 表示中の画面からの描き替えを取り出す
+    ///
+    /// キー入力のエコーの予測を画面に重ねたり取り消したりするので、
+    /// `sendKeys` / `tick` の後に呼ぶ。端末サイズの扱いは `recvUdpPacket` と同じ。
+    ///
+    /// # 戻り値
+    /// 端末に流すバイト列。画面の更新がなければ長さ 0 の Uint8Array。
+    #[wasm_bindgen(js_name = "takeScreenUpdate")]
+    pub fn take_screen_update(&mut self) -> Uint8Array {
+        let update = self.endpoint.take_screen_update();
+        if let (Some((width, height)), Some(callback)) = (update.resize, &self.resize_callback) {
+            // コールバック内の例外は送受信処理に影響させない
+            let _ = callback.call2(&JsValue::NULL, &width.into(), &height.into());
+        }
+        to_uint8_array(&update.bytes)
+    }
+
+    /// キー入力のエコーの予測の表示モードを設定する（mosh の `--predict` と同じ）
+    ///
+    /// # 引数
+    /// - `mode`: `"adaptive"`（既定: 遅い回線でだけ表示）/ `"always"` / `"never"`
+    ///
+    /// # エラー
+    /// - 不明なモード名
+    #[wasm_bindgen(js_name = "setPredictionMode")]
+    pub fn set_prediction_mode(&mut self, mode: &str) -> Result<(), JsError> {
+        let mode = PredictionMode::from_name(mode)
+            .ok_or_else(|| JsError::new(&alloc::format!("unknown prediction mode: {}", mode)))?;
+        self.endpoint.set_prediction_mode(mode);
+        Ok(())
+    }
+
+    /// キー入力のエコーの予測の表示モード（`"adaptive"` / `"always"` / `"never"`）
+    #[wasm_bindgen(js_name = "predictionMode")]
+    pub fn prediction_mode(&self) -> String {
+        self.endpoint.prediction().mode().as_str().into()
+    }
+
+    /// サーバーが端末サイズを変えたときに呼ばれるコールバックを登録する
+    ///
+    /// 引数は新しい幅（桁数）と高さ（行数）。`undefined` を渡すと登録を解除する。
+    #[wasm_bindgen(js_name = "onResize")]
+    pub fn on_resize(&mut self, callback: Option<js_sys::Function>) {
+        self.resize_callback = callback;
+    }
+
+    /// 定期タイマー tick（入力の送信・再送・ハートビート）
+    ///
+    /// # 戻り値
+    /// 送信すべき UDP ペイロードの配列
+    #[wasm_bindgen]
+    pub fn tick(&mut self, now_ms: Option<f64>) -> Result<js_sys::Array, JsError> {
+        let now_ms = self.now(now_ms);
+        let packets = self.endpoint.tick(now_ms).map_err(to_js_error)?;
+        self.notify_state_change(now_ms);
+        Ok(to_js_packets(packets))
+    }
+
+    /// 次に `tick` を呼ぶべきまでの時間（ミリ秒）を返す
+    #[wasm_bindgen(js_name = "nextTimeoutMs")]
+    pub fn next_timeout_ms(&self, now_ms: Option<f64>) -> f64 {
+        let now_ms = self.now(now_ms);
+        self.endpoint.next_deadline_ms(now_ms).saturating_sub(now_ms) as f64
+    }
+
+    /// 現在の接続状態を返す
+    ///
+    /// # 戻り値
+    /// `"connecting"` / `"connected"` / `"stalled"` / `"lost"` のいずれか
+    #[wasm_bindgen(js_name = "connectionState")]
+    pub fn connection_state(&self, now_ms: Option<f64>) -> String {
+        self.endpoint.connection_state(self.now(now_ms)).as_str().into()
+    }
+
+    /// 最後にサーバーから受信してからの経過時間（ミリ秒）。未受信なら -1
+    #[wasm_bindgen(js_name = "lastContactMs")]
+    pub fn last_contact_ms(&self, now_ms: Option<f64>) -> f64 {
+        match self.endpoint.last_recv_ms() {
+            Some(last) => self.now(now_ms).saturating_sub(last) as f64,
+            None => -1.0,
+        }
+    }
+
+    /// 接続状態が変化したときに呼ばれるコールバックを登録する
+    #[wasm_bindgen(js_name = "onStateChange")]
+    pub fn on_state_change(&mut self, callback: Option<js_sys::Function>) {
+        self.state_callback = callback;
+    }
+
+    /// 接続状態の判定しきい値を設定する（`MoshClient` と同じ）
+    #[wasm_bindgen(js_name = "setConnectionThresholds")]
+    pub fn set_connection_thresholds(
+        &mut self,
+        stalled_after_ms: f64,
+        lost_after_ms: f64,
+        stalled_after_timeouts: u32,
+    ) {
+        self.endpoint.set_connection_thresholds(ConnectionThresholds {
+            stalled_after_ms: stalled_after_ms as u64,
+            lost_after_ms: lost_after_ms as u64,
+            stalled_after_timeouts,
+        });
+    }
+
+    /// サーバーがエコーを画面に反映済みのクライアント状態番号
+    #[wasm_bindgen(js_name = "echoAck")]
+    pub fn echo_ack(&self) -> f64 {
+        self.endpoint.echo_ack() as f64
+    }
+
+    /// セッション統計を JSON 文字列で返す（形式は `MoshClient.getStats` と同じ）
+    #[wasm_bindgen(js_name = "getStats")]
+    pub fn get_stats(&self) -> String {
+        stats_json(&self.endpoint.stats(), self.clock.backward_jumps())
+    }
+}
+
+impl MoshTerminalClient {
+    /// 現在時刻（ミリ秒）を返す。`now_ms` が渡されればそれを、なければ `performance.now()` を使う
+    fn now(&self, now_ms: Option<f64>) -> u64 {
+        match now_ms {
+            Some(now_ms) => self.clock.observe(now_ms as u64),
+            None => self.clock.now_ms(),
+        }
+    }
+
+    /// 接続状態が変わっていれば登録済みのコールバックを呼び出す
+    fn notify_state_change(&mut self, now_ms: u64) {
+        if let Some(state) = self.endpoint.poll_state_change(now_ms) {
+            if let Some(callback) = &self.state_callback {
+                let _ = callback.call1(&JsValue::NULL, &JsValue::from_str(state.as_str()));
+            }
+        }
+    }
+}
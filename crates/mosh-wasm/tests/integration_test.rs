@@ -3,9 +3,9 @@
 //! crypto + transport + SSP + stream の完全なパイプラインをテストする。
 //! mosh プロトコルの実際の動作をシミュレートする。
 
-use mosh_crypto::{CryptoSession, Direction};
+use mosh_crypto::{CryptoError, CryptoSession, Direction, Role};
 use mosh_proto::Instruction;
-use mosh_ssp::SspSession;
+use mosh_ssp::{SendPacing, SspSession};
 use mosh_stream::StreamChannel;
 use mosh_transport::{Fragment, FragmentAssembly, Fragmenter, Timestamp16};
 
@@ -33,9 +33,10 @@ struct Receiver {
 impl Sender {
     fn new(key: [u8; 16], mtu: usize) -> Self {
         Sender {
-            crypto: CryptoSession::from_key(key).unwrap(),
+            crypto: CryptoSession::from_key(key, Role::Client).unwrap(),
             fragmenter: Fragmenter::new(mtu.saturating_sub(46).max(64)),
-            ssp: SspSession::new(),
+            // send() ごとに 1 つの Instruction を送る（ペーシングはエンドポイントのテストで確認）
+            ssp: SspSession::new().with_send_pacing(SendPacing::DISABLED),
             stream: StreamChannel::new(),
             last_remote_ts: Timestamp16::INIT.raw(),
         }
@@ -43,7 +44,7 @@ impl Sender {
 
     /// データを送信して UDP ペイロード列を返す
     fn send(&mut self, data: &[u8], now_ms: u64) -> Vec<Vec<u8>> {
-        self.stream.write(data);
+        self.stream.write(data).unwrap();
         let pending = self.stream.take_pending_diff();
         if !pending.is_empty() {
             self.ssp.push_payload(pending);
@@ -60,7 +61,7 @@ impl Sender {
             for frag in frags {
                 let frag_bytes = frag.to_bytes();
                 let udp_payload = self.crypto
-                    .encrypt_packet(Direction::ToServer, ts, ts_reply, &frag_bytes)
+                    .encrypt_packet(ts, ts_reply, &frag_bytes)
                     .unwrap();
                 udp_packets.push(udp_payload);
             }
@@ -82,7 +83,7 @@ impl Sender {
             for frag in frags {
                 let frag_bytes = frag.to_bytes();
                 let udp_payload = self.crypto
-                    .encrypt_packet(Direction::ToServer, ts, ts_reply, &frag_bytes)
+                    .encrypt_packet(ts, ts_reply, &frag_bytes)
                     .unwrap();
                 udp_packets.push(udp_payload);
             }
@@ -95,7 +96,7 @@ impl Sender {
 impl Receiver {
     fn new(key: [u8; 16]) -> Self {
         Receiver {
-            crypto: CryptoSession::from_key(key).unwrap(),
+            crypto: CryptoSession::from_key(key, Role::Server).unwrap(),
             assembly: FragmentAssembly::new(),
             ssp: SspSession::new(),
             stream: StreamChannel::new(),
@@ -111,7 +112,7 @@ impl Receiver {
         let frag = Fragment::from_bytes(&decrypted.payload).ok()?;
 
         // 3. 再組み立て
-        let instruction_bytes = self.assembly.add_fragment(frag)?;
+        let instruction_bytes = self.assembly.add_fragment(frag, now_ms).ok()?.into_complete()?;
 
         // 4. Instruction デコード
         let instr = Instruction::decode_from_bytes(&instruction_bytes).ok()?;
@@ -133,8 +134,8 @@ impl Receiver {
 #[test]
 fn test_crypto_roundtrip_full() {
     let key = [0xABu8; 16];
-    let mut sender = CryptoSession::from_key(key).unwrap();
-    let mut receiver = CryptoSession::from_key(key).unwrap();
+    let mut sender = CryptoSession::from_key(key, Role::Client).unwrap();
+    let mut receiver = CryptoSession::from_key(key, Role::Server).unwrap();
 
     let payloads = [
         b"".as_slice(),
@@ -145,7 +146,7 @@ fn test_crypto_roundtrip_full() {
 
     for (i, payload) in payloads.iter().enumerate() {
         let packet = sender
-            .encrypt_packet(Direction::ToServer, i as u16, 0, payload)
+            .encrypt_packet(i as u16, 0, payload)
             .expect("暗号化に失敗");
 
         let decrypted = receiver
@@ -162,38 +163,60 @@ fn test_crypto_roundtrip_full() {
 #[test]
 fn test_crypto_direction_bits() {
     let key = [0u8; 16];
-    let mut sender = CryptoSession::from_key(key).unwrap();
-    let mut receiver = CryptoSession::from_key(key).unwrap();
+    let mut client = CryptoSession::from_key(key, Role::Client).unwrap();
+    let mut server = CryptoSession::from_key(key, Role::Server).unwrap();
 
     // ToServer 方向
-    let pkt_to_server = sender
-        .encrypt_packet(Direction::ToServer, 100, 200, b"from client")
+    let pkt_to_server = client
+        .encrypt_packet(100, 200, b"from client")
         .unwrap();
-    let dec = receiver.decrypt_packet(&pkt_to_server).unwrap();
+    let dec = server.decrypt_packet(&pkt_to_server).unwrap();
     assert_eq!(dec.direction, Direction::ToServer);
     assert_eq!(dec.timestamp, 100);
     assert_eq!(dec.timestamp_reply, 200);
     assert_eq!(dec.payload, b"from client");
 
     // ToClient 方向
-    let pkt_to_client = sender
-        .encrypt_packet(Direction::ToClient, 300, 100, b"from server")
+    let pkt_to_client = server
+        .encrypt_packet(300, 100, b"from server")
         .unwrap();
-    let dec2 = receiver.decrypt_packet(&pkt_to_client).unwrap();
+    let dec2 = client.decrypt_packet(&pkt_to_client).unwrap();
     assert_eq!(dec2.direction, Direction::ToClient);
     assert_eq!(dec2.timestamp, 300);
     assert_eq!(dec2.payload, b"from server");
 }
 
+/// 反射されたパケット（自分が送った方向）の拒否テスト
+#[test]
+fn test_crypto_reflected_packets_rejected() {
+    let key = [0x5Au8; 16];
+    let mut client = CryptoSession::from_key(key, Role::Client).unwrap();
+    let mut server = CryptoSession::from_key(key, Role::Server).unwrap();
+
+    let to_server = client
+        .encrypt_packet(0, 0, b"keystrokes")
+        .unwrap();
+    let to_client = server
+        .encrypt_packet(0, 0, b"output")
+        .unwrap();
+
+    assert_eq!(client.decrypt_packet(&to_server), Err(CryptoError::UnexpectedDirection));
+    assert_eq!(server.decrypt_packet(&to_client), Err(CryptoError::UnexpectedDirection));
+
+    // 反射の拒否は正規のパケットの受理に影響しない
+    assert!(server.decrypt_packet(&to_server).is_ok());
+    assert!(client.decrypt_packet(&to_client).is_ok());
+}
+
 /// 改ざんされたパケットの復号失敗テスト
 #[test]
 fn test_crypto_tampered_packet_fails() {
     let key = [0x42u8; 16];
-    let mut sender = CryptoSession::from_key(key).unwrap();
-    let mut receiver = CryptoSession::from_key(key).unwrap();
+    let mut sender = CryptoSession::from_key(key, Role::Client).unwrap();
+    let mut receiver = CryptoSession::from_key(key, Role::Server).unwrap();
 
     let mut packet = sender
-        .encrypt_packet(Direction::ToServer, 0, 0, b"authentic data")
+        .encrypt_packet(0, 0, b"authentic data")
         .unwrap();
 
     // パケットを改ざん（ciphertext 部分を変更）
@@ -219,7 +242,7 @@ fn test_fragment_roundtrip_various_sizes() {
         let mut assembled = None;
 
         for frag in frags {
-            assembled = assembly.add_fragment(frag);
+            assembled = assembly.add_fragment(frag, 0).unwrap().into_complete();
         }
 
         let result = assembled.unwrap_or_default();
@@ -243,35 +266,35 @@ fn test_fragment_out_of_order_assembly() {
     let frag1 = frags[1].clone();
     let frag0 = frags[0].clone();
 
-    assert!(assembly.add_fragment(frag2).is_none(), "最後から追加して再組み立て完了しないはず");
-    assert!(assembly.add_fragment(frag1).is_none(), "中間追加で再組み立て完了しないはず");
-    let result = assembly.add_fragment(frag0);
+    assert!(assembly.add_fragment(frag2, 0).unwrap().into_complete().is_none(), "最後から追加して再組み立て完了しないはず");
+    assert!(assembly.add_fragment(frag1, 0).unwrap().into_complete().is_none(), "中間追加で再組み立て完了しないはず");
+    let result = assembly.add_fragment(frag0, 0).unwrap().into_complete();
 
     assert_eq!(result, Some(data), "逆順フラグメントの再組み立て失敗");
 }
 
-/// 新しい instruction_id が来たとき古いフラグメントが破棄されるテスト
+/// 別の instruction_id が割り込んでも組み立て中のフラグメントが破棄されないテスト
 #[test]
-fn test_fragment_id_reset_discards_old() {
+fn test_fragment_interleaved_ids_both_complete() {
     let mut fragmenter = Fragmenter::new(5); // 超小さい MTU
     let mut assembly = FragmentAssembly::new();
 
     // 最初の Instruction（3フラグメント分のデータ）
-    let data1: Vec<u8> = vec![0u8; 15];
+    let data1: Vec<u8> = vec![1u8; 15];
     let frags1 = fragmenter.make_fragments(&data1);
 
     // フラグメント 0 のみ追加（未完成）
-    assembly.add_fragment(frags1[0].clone());
-    assert_eq!(assembly.current_id(), Some(1));
+    assert!(assembly.add_fragment(frags1[0].clone(), 0).unwrap().into_complete().is_none());
 
-    // 新しい Instruction（完結する1フラグメント）
+    // 新しい Instruction（完結する1フラグメント）が割り込む
     let data2 = vec![9u8; 3];
     let frags2 = fragmenter.make_fragments(&data2);
-    let result = assembly.add_fragment(frags2[0].clone());
+    assert_eq!(assembly.add_fragment(frags2[0].clone(), 10).unwrap().into_complete(), Some(data2));
 
-    // 古いフラグメントが破棄されて新しいのが完結する
-    assert_eq!(result, Some(data2), "新しい ID で古いフラグメントが破棄されるべき");
-    assert_eq!(assembly.current_id(), Some(2));
+    // 最初の Instruction の残りが届けば完成する
+    assert!(assembly.add_fragment(frags1[1].clone(), 20).unwrap().into_complete().is_none());
+    assert_eq!(assembly.add_fragment(frags1[2].clone(), 30).unwrap().into_complete(), Some(data1));
+    assert_eq!(assembly.pending_count(), 0);
 }
 
 /// SSP 双方向通信の完全なパイプラインテスト（暗号化込み）
@@ -413,18 +436,18 @@ fn test_ssp_heartbeat_via_tick() {
 #[test]
 fn test_timestamp_wraparound_in_crypto() {
     let key = [0u8; 16];
-    let mut sender = CryptoSession::from_key(key).unwrap();
-    let mut receiver = CryptoSession::from_key(key).unwrap();
+    let mut sender = CryptoSession::from_key(key, Role::Client).unwrap();
+    let mut receiver = CryptoSession::from_key(key, Role::Server).unwrap();
 
     // ラップアラウンド付近のタイムスタンプ
     let ts_near_max = 65535u16;
     let ts_after_wrap = 100u16;
 
-    let pkt1 = sender.encrypt_packet(Direction::ToServer, ts_near_max, 0, b"near max").unwrap();
+    let pkt1 = sender.encrypt_packet(ts_near_max, 0, b"near max").unwrap();
     let dec1 = receiver.decrypt_packet(&pkt1).unwrap();
     assert_eq!(dec1.timestamp, ts_near_max);
 
-    let pkt2 = sender.encrypt_packet(Direction::ToServer, ts_after_wrap, ts_near_max, b"after wrap").unwrap();
+    let pkt2 = sender.encrypt_packet(ts_after_wrap, ts_near_max, b"after wrap").unwrap();
     let dec2 = receiver.decrypt_packet(&pkt2).unwrap();
     assert_eq!(dec2.timestamp, ts_after_wrap);
     assert_eq!(dec2.timestamp_reply, ts_near_max);
@@ -436,8 +459,8 @@ fn test_stream_channel_full_duplex() {
     let mut stream = StreamChannel::new();
 
     // 送信方向
-    stream.write(b"send 1");
-    stream.write(b"send 2");
+    stream.write(b"send 1").unwrap();
+    stream.write(b"send 2").unwrap();
     let diff = stream.take_pending_diff();
     assert_eq!(diff, b"send 1send 2");
     assert!(!stream.has_pending_write());